@@ -8,11 +8,15 @@ use solana_program::{
     msg,
     pubkey::Pubkey,
     program_error::ProgramError,
-    program::invoke_signed,
-    system_instruction,
+    program::{invoke, invoke_signed},
+    system_instruction, system_program,
     sysvar::{clock::Clock, rent::Rent, Sysvar},
 };
-use spl_token::instruction as spl_token_instruction;
+use spl_token_2022::{
+    extension::{transfer_fee::TransferFeeConfig, BaseStateWithExtensions, StateWithExtensions},
+    onchain,
+    state::{Account, Mint},
+};
 use borsh::{BorshDeserialize, BorshSerialize};
 use thiserror::Error;
 
@@ -21,8 +25,13 @@ use thiserror::Error;
 solana_program::declare_id!("3W5ebjB6bQkwSNNmK4amLwY47nEfmLztQwgMpa75ZPUt");
 
 // ------------------ Auction Struct ------------------
+/// First bytes of every [`Auction`] account: the first 8 bytes of
+/// `sha256("account:Auction")`.
+pub const AUCTION_DISCRIMINATOR: [u8; 8] = [218, 94, 247, 242, 126, 233, 131, 81];
+
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
 pub struct Auction {
+    pub discriminator: [u8; 8],
     pub property_mint: Pubkey,
     pub seller: Pubkey,
     pub start_price: u64,
@@ -35,12 +44,20 @@ pub struct Auction {
 }
 
 impl Auction {
-    pub const LEN: usize = 32 + 32 + 8 + 8 + 32 + 8 + 8 + 1 + 1;
+    pub const LEN: usize = 8 + 32 + 32 + 8 + 8 + 32 + 8 + 8 + 1 + 1;
 }
 
+/// Size of auctions created before accounts carried a discriminator: the
+/// current layout without it. They are recognised by size and at their PDA,
+/// and are read and written in that layout until they end.
+pub const LEGACY_AUCTION_LEN: usize = Auction::LEN - AUCTION_DISCRIMINATOR.len();
+
 // ------------------ Auction Instructions ------------------
 #[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq)]
 pub enum AuctionInstruction {
+    /// Accounts: seller, auction, property mint, system program. Creates the
+    /// auction account at its PDA, the seller paying its rent. Token-2022
+    /// property mints with a transfer fee are refused.
     InitializeAuction {
         start_price: u64,
         end_time: i64,
@@ -74,6 +91,12 @@ pub enum AuctionError {
     AuctionEnded,
     #[error("Invalid end time")]
     InvalidEndTime,
+    #[error("Math overflow")]
+    MathOverflow,
+    #[error("Account holds data of another type")]
+    InvalidAccountType,
+    #[error("Property mint charges a transfer fee")]
+    UnsupportedPropertyMint,
 }
 
 impl From<AuctionError> for ProgramError {
@@ -123,11 +146,14 @@ fn process_initialize_auction(
     let seller_account = next_account_info(account_info_iter)?;
     let auction_account = next_account_info(account_info_iter)?;
     let property_mint_account = next_account_info(account_info_iter)?;
-    let _system_program_account = next_account_info(account_info_iter)?; // unused
+    let system_program_account = next_account_info(account_info_iter)?;
 
     if !seller_account.is_signer {
         return Err(ProgramError::MissingRequiredSignature);
     }
+    if *system_program_account.key != system_program::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
 
     let (pda, bump_seed) = Pubkey::find_program_address(
         &[b"auction", property_mint_account.key.as_ref()],
@@ -137,13 +163,15 @@ fn process_initialize_auction(
     if pda != *auction_account.key {
         return Err(ProgramError::InvalidSeeds);
     }
+    validate_property_mint(property_mint_account)?;
 
-    if auction_account.data_len() > 0 {
-        return Err(ProgramError::AccountAlreadyInitialized);
-    }
-
-    if !Rent::get()?.is_exempt(auction_account.lamports(), Auction::LEN) {
-        return Err(AuctionError::NotRentExempt.into());
+    // The program creates the auction, so one it already owns is either this
+    // auction or some other data that must not be overwritten
+    if auction_account.owner == program_id {
+        if auction_account.data.borrow().starts_with(&AUCTION_DISCRIMINATOR) || auction_account.data_len() == LEGACY_AUCTION_LEN {
+            return Err(ProgramError::AccountAlreadyInitialized);
+        }
+        return Err(AuctionError::InvalidAccountType.into());
     }
 
     let current_timestamp = Clock::get()?.unix_timestamp;
@@ -151,7 +179,17 @@ fn process_initialize_auction(
         return Err(AuctionError::InvalidEndTime.into());
     }
 
+    create_pda_account(
+        seller_account,
+        auction_account,
+        system_program_account,
+        program_id,
+        Auction::LEN,
+        &[b"auction", property_mint_account.key.as_ref(), &[bump_seed]],
+    )?;
+
     let auction_data = Auction {
+        discriminator: AUCTION_DISCRIMINATOR,
         property_mint: *property_mint_account.key,
         seller: *seller_account.key,
         start_price,
@@ -170,7 +208,7 @@ fn process_initialize_auction(
 
 // ------------------ Place Bid ------------------
 fn process_place_bid(
-    _program_id: &Pubkey,
+    program_id: &Pubkey,
     accounts: &[AccountInfo],
     bid_amount: u64,
 ) -> ProgramResult {
@@ -185,7 +223,7 @@ fn process_place_bid(
         return Err(ProgramError::MissingRequiredSignature);
     }
 
-    let mut auction_data = Auction::try_from_slice(&auction_account.data.borrow())?;
+    let mut auction_data = load_auction(program_id, auction_account)?;
 
     if auction_data.ended {
         return Err(AuctionError::AuctionAlreadyEnded.into());
@@ -215,22 +253,25 @@ fn process_place_bid(
 
     // Refund previous highest bidder
     if auction_data.highest_bidder != Pubkey::default() {
-        **auction_account.try_borrow_mut_lamports()? -= auction_data.current_bid;
-        **previous_bidder_sol_account.try_borrow_mut_lamports()? += auction_data.current_bid;
+        transfer_lamports(auction_account, previous_bidder_sol_account, auction_data.current_bid)?;
         msg!("Refunded previous bidder: {}", auction_data.highest_bidder);
     }
 
     auction_data.current_bid = bid_amount;
     auction_data.highest_bidder = *bidder_account.key;
-    auction_data.serialize(&mut &mut auction_account.data.borrow_mut()[..])?;
+    store_auction(auction_account, &auction_data)?;
 
     msg!("Bid of {} placed on auction for property: {}", bid_amount, auction_data.property_mint);
     Ok(())
 }
 
 // ------------------ End Auction ------------------
+/// Accounts: seller, auction, seller SOL, highest bidder SOL, highest bidder
+/// property token, auction property token, seller property token, token
+/// program, then the property mint and its transfer-hook accounts when the
+/// mint is a Token-2022 mint with a transfer hook.
 fn process_end_auction(
-    _program_id: &Pubkey,
+    program_id: &Pubkey,
     accounts: &[AccountInfo],
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
@@ -243,8 +284,9 @@ fn process_end_auction(
     let auction_property_token_account = next_account_info(account_info_iter)?;
     let seller_property_token_account = next_account_info(account_info_iter)?;
     let token_program_account = next_account_info(account_info_iter)?;
+    let extra_accounts = account_info_iter.as_slice();
 
-    let mut auction_data = Auction::try_from_slice(&auction_account.data.borrow())?;
+    let mut auction_data = load_auction(program_id, auction_account)?;
 
     if auction_data.ended {
         return Err(AuctionError::AuctionAlreadyEnded.into());
@@ -259,56 +301,219 @@ fn process_end_auction(
         return Err(ProgramError::MissingRequiredSignature);
     }
 
+    if *token_program_account.key != spl_token::id() && *token_program_account.key != spl_token_2022::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let auction_seeds: &[&[u8]] = &[b"auction", auction_data.property_mint.as_ref(), &[auction_data.bump_seed]];
+
     if auction_data.highest_bidder != Pubkey::default() {
         // Transfer SOL to seller
-        **auction_account.try_borrow_mut_lamports()? -= auction_data.current_bid;
-        **seller_sol_account.try_borrow_mut_lamports()? += auction_data.current_bid;
+        transfer_lamports(auction_account, seller_sol_account, auction_data.current_bid)?;
 
         // Transfer property token to highest bidder
-        let transfer_ix = spl_token_instruction::transfer(
-            token_program_account.key,
-            auction_property_token_account.key,
-            highest_bidder_token_account.key,
-            auction_account.key,
-            &[auction_account.key],
-            1,
-        )?;
-
-        invoke_signed(
-            &transfer_ix,
-            &[
-                auction_property_token_account.clone(),
-                highest_bidder_token_account.clone(),
-                auction_account.clone(),
-                token_program_account.clone(),
-            ],
-            &[&[b"auction", auction_data.property_mint.as_ref(), &[auction_data.bump_seed]]],
+        transfer_property(
+            token_program_account,
+            auction_property_token_account,
+            highest_bidder_token_account,
+            auction_account,
+            extra_accounts,
+            &auction_data.property_mint,
+            auction_seeds,
         )?;
     } else {
         // No bids, return property token to seller
-        let transfer_ix = spl_token_instruction::transfer(
-            token_program_account.key,
-            auction_property_token_account.key,
-            seller_property_token_account.key,
-            auction_account.key,
-            &[auction_account.key],
-            1,
-        )?;
-
-        invoke_signed(
-            &transfer_ix,
-            &[
-                auction_property_token_account.clone(),
-                seller_property_token_account.clone(),
-                auction_account.clone(),
-                token_program_account.clone(),
-            ],
-            &[&[b"auction", auction_data.property_mint.as_ref(), &[auction_data.bump_seed]]],
+        transfer_property(
+            token_program_account,
+            auction_property_token_account,
+            seller_property_token_account,
+            auction_account,
+            extra_accounts,
+            &auction_data.property_mint,
+            auction_seeds,
         )?;
     }
 
     auction_data.ended = true;
-    auction_data.serialize(&mut &mut auction_account.data.borrow_mut()[..])?;
+    store_auction(auction_account, &auction_data)?;
     msg!("Auction ended for property: {}", auction_data.property_mint);
     Ok(())
 }
+
+// ------------------ Account Creation ------------------
+/// Creates `new_account` at the PDA `signer_seeds` derive, with `space` bytes
+/// owned by `owner` and rent paid by `payer`. An address that was already sent
+/// lamports cannot be passed to `create_account`, so it is topped up,
+/// allocated and assigned instead.
+fn create_pda_account<'a>(
+    payer: &AccountInfo<'a>,
+    new_account: &AccountInfo<'a>,
+    system_program_account: &AccountInfo<'a>,
+    owner: &Pubkey,
+    space: usize,
+    signer_seeds: &[&[u8]],
+) -> ProgramResult {
+    let rent = Rent::get()?.minimum_balance(space);
+    if new_account.lamports() == 0 {
+        return invoke_signed(
+            &system_instruction::create_account(payer.key, new_account.key, rent, space as u64, owner),
+            &[payer.clone(), new_account.clone(), system_program_account.clone()],
+            &[signer_seeds],
+        );
+    }
+
+    let top_up = rent.saturating_sub(new_account.lamports());
+    if top_up > 0 {
+        invoke(
+            &system_instruction::transfer(payer.key, new_account.key, top_up),
+            &[payer.clone(), new_account.clone(), system_program_account.clone()],
+        )?;
+    }
+    invoke_signed(
+        &system_instruction::allocate(new_account.key, space as u64),
+        &[new_account.clone(), system_program_account.clone()],
+        &[signer_seeds],
+    )?;
+    invoke_signed(
+        &system_instruction::assign(new_account.key, owner),
+        &[new_account.clone(), system_program_account.clone()],
+        &[signer_seeds],
+    )
+}
+
+// ------------------ Account Loading ------------------
+/// Loads the auction, checking that the account is owned by this program and
+/// holds an auction. A legacy auction must also sit at the PDA of its property
+/// mint, which only this program could have created it at.
+pub fn load_auction(program_id: &Pubkey, auction_account: &AccountInfo) -> Result<Auction, ProgramError> {
+    if auction_account.owner != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if auction_account.data_len() == LEGACY_AUCTION_LEN {
+        let mut data = AUCTION_DISCRIMINATOR.to_vec();
+        data.extend_from_slice(&auction_account.data.borrow());
+        let auction_data = Auction::try_from_slice(&data)?;
+        let pda = Pubkey::create_program_address(
+            &[b"auction", auction_data.property_mint.as_ref(), &[auction_data.bump_seed]],
+            program_id,
+        )
+        .map_err(|_| ProgramError::InvalidSeeds)?;
+        if pda != *auction_account.key {
+            return Err(ProgramError::InvalidSeeds);
+        }
+        return Ok(auction_data);
+    }
+    if !auction_account.data.borrow().starts_with(&AUCTION_DISCRIMINATOR) {
+        return Err(AuctionError::InvalidAccountType.into());
+    }
+    Ok(Auction::try_from_slice(&auction_account.data.borrow())?)
+}
+
+/// Writes the auction back in the layout its account was created with.
+fn store_auction(auction_account: &AccountInfo, auction_data: &Auction) -> ProgramResult {
+    let data = auction_data.try_to_vec()?;
+    let data = if auction_account.data_len() == LEGACY_AUCTION_LEN {
+        &data[AUCTION_DISCRIMINATOR.len()..]
+    } else {
+        &data[..]
+    };
+    auction_account.data.borrow_mut()[..data.len()].copy_from_slice(data);
+    Ok(())
+}
+
+// ------------------ Property Transfer ------------------
+/// Moves the property token out of the auction's escrow, signed by the auction
+/// PDA. Token-2022 requires a checked transfer, naming the mint, for mints
+/// with a transfer hook, so one is made whenever the property mint is
+/// among `extra_accounts`; the hook's accounts are looked up there too. Fails
+/// unless `destination` receives the whole token.
+fn transfer_property<'a>(
+    token_program_account: &AccountInfo<'a>,
+    source: &AccountInfo<'a>,
+    destination: &AccountInfo<'a>,
+    auction_account: &AccountInfo<'a>,
+    extra_accounts: &[AccountInfo<'a>],
+    property_mint: &Pubkey,
+    auction_seeds: &[&[u8]],
+) -> ProgramResult {
+    let balance_before = token_amount(destination)?;
+    match extra_accounts.iter().find(|account| account.key == property_mint) {
+        Some(mint_account) => {
+            validate_property_mint(mint_account)?;
+            let decimals = StateWithExtensions::<Mint>::unpack(&mint_account.data.borrow())?.base.decimals;
+            onchain::invoke_transfer_checked(
+                token_program_account.key,
+                source.clone(),
+                mint_account.clone(),
+                destination.clone(),
+                auction_account.clone(),
+                extra_accounts,
+                1,
+                decimals,
+                &[auction_seeds],
+            )
+        }
+        None => {
+            // Deprecated in Token-2022, but still valid for SPL Token mints
+            // and Token-2022 mints without a fee or hook.
+            #[allow(deprecated)]
+            let transfer_ix = spl_token_2022::instruction::transfer(
+                token_program_account.key,
+                source.key,
+                destination.key,
+                auction_account.key,
+                &[],
+                1,
+            )?;
+            invoke_signed(
+                &transfer_ix,
+                &[source.clone(), destination.clone(), auction_account.clone(), token_program_account.clone()],
+                &[auction_seeds],
+            )
+        }
+    }?;
+
+    if token_amount(destination)?.checked_sub(balance_before) != Some(1) {
+        return Err(AuctionError::UnsupportedPropertyMint.into());
+    }
+    Ok(())
+}
+
+/// Checks that a Token-2022 property mint has no transfer fee extension. Even a
+/// zero fee can be raised later, leaving the buyer less than the whole token.
+fn validate_property_mint(mint_account: &AccountInfo) -> ProgramResult {
+    if *mint_account.owner != spl_token_2022::id() {
+        return Ok(());
+    }
+    let mint_data = mint_account.data.borrow();
+    let mint = StateWithExtensions::<Mint>::unpack(&mint_data)?;
+    if mint.get_extension::<TransferFeeConfig>().is_ok() {
+        return Err(AuctionError::UnsupportedPropertyMint.into());
+    }
+    Ok(())
+}
+
+fn token_amount(token_account: &AccountInfo) -> Result<u64, ProgramError> {
+    Ok(StateWithExtensions::<Account>::unpack(&token_account.data.borrow())?.base.amount)
+}
+
+// ------------------ Checked Math ------------------
+/// Moves lamports out of a program-owned account, failing with `MathOverflow`
+/// instead of wrapping either balance.
+fn transfer_lamports(from: &AccountInfo, to: &AccountInfo, amount: u64) -> ProgramResult {
+    let from_balance = checked_sub(from.lamports(), amount)?;
+    let to_balance = checked_add(to.lamports(), amount)?;
+    **from.try_borrow_mut_lamports()? = from_balance;
+    **to.try_borrow_mut_lamports()? = to_balance;
+    Ok(())
+}
+
+/// `a + b`, failing with `MathOverflow` instead of wrapping.
+pub fn checked_add(a: u64, b: u64) -> Result<u64, ProgramError> {
+    a.checked_add(b).ok_or_else(|| AuctionError::MathOverflow.into())
+}
+
+/// `a - b`, failing with `MathOverflow` instead of wrapping.
+pub fn checked_sub(a: u64, b: u64) -> Result<u64, ProgramError> {
+    a.checked_sub(b).ok_or_else(|| AuctionError::MathOverflow.into())
+}
@@ -0,0 +1,139 @@
+mod common;
+
+use auction::{Auction, AuctionError, AuctionInstruction, AUCTION_DISCRIMINATOR, LEGACY_AUCTION_LEN};
+use borsh::{BorshDeserialize, BorshSerialize};
+use common::{auction_account, initialize_auction_accounts, run, TestAccount};
+use solana_program::{hash::hash, program_error::ProgramError, pubkey::Pubkey, system_program};
+
+fn initialize() -> AuctionInstruction {
+    AuctionInstruction::InitializeAuction { start_price: 1, end_time: 10_000 }
+}
+
+#[test]
+fn auction_discriminator_is_derived_from_its_name() {
+    assert_eq!(AUCTION_DISCRIMINATOR, hash(b"account:Auction").to_bytes()[..8]);
+}
+
+#[test]
+fn initializes_an_auction_once() {
+    let mut accounts = initialize_auction_accounts();
+    assert_eq!(run(&mut accounts, initialize()), Ok(()));
+    let auction = Auction::try_from_slice(&accounts[1].data).unwrap();
+    assert_eq!(auction.discriminator, AUCTION_DISCRIMINATOR);
+    assert_eq!(auction.property_mint, accounts[2].key);
+
+    assert_eq!(run(&mut accounts, initialize()), Err(ProgramError::AccountAlreadyInitialized));
+}
+
+#[test]
+fn initialize_rejects_an_account_holding_other_data() {
+    let mut accounts = initialize_auction_accounts();
+    accounts[1] = TestAccount { key: accounts[1].key, ..TestAccount::new(auction::id(), 1_000_000, vec![1; Auction::LEN]) };
+    assert_eq!(run(&mut accounts, initialize()), Err(AuctionError::InvalidAccountType.into()));
+}
+
+#[test]
+fn bids_require_an_auction_account() {
+    let mut accounts = initialize_auction_accounts();
+    assert_eq!(run(&mut accounts, initialize()), Ok(()));
+    accounts[0] = TestAccount::signer(1_000);
+    let bid = || AuctionInstruction::PlaceBid { bid_amount: 100 };
+
+    let mut foreign = accounts.clone();
+    foreign[1].owner = Pubkey::new_unique();
+    assert_eq!(run(&mut foreign, bid()), Err(ProgramError::IncorrectProgramId));
+
+    let mut mistyped = accounts.clone();
+    mistyped[1].data[..8].copy_from_slice(&[0; 8]);
+    assert_eq!(run(&mut mistyped, bid()), Err(AuctionError::InvalidAccountType.into()));
+
+    assert_eq!(run(&mut accounts, bid()), Ok(()));
+}
+
+/// `auction` as written before accounts carried a discriminator.
+fn legacy_auction_account(auction: &Auction) -> TestAccount {
+    let mut account = auction_account(auction);
+    account.data = auction.try_to_vec().unwrap()[AUCTION_DISCRIMINATOR.len()..].to_vec();
+    account
+}
+
+#[test]
+fn legacy_auctions_take_bids_and_settle() {
+    let mint = TestAccount::token_2022_mint(false);
+    let bidder = Pubkey::new_unique();
+    let auction = Auction {
+        discriminator: AUCTION_DISCRIMINATOR,
+        property_mint: mint.key,
+        seller: Pubkey::new_unique(),
+        start_price: 1,
+        current_bid: 100,
+        highest_bidder: bidder,
+        start_time: 0,
+        end_time: 10_000,
+        ended: false,
+        bump_seed: Pubkey::find_program_address(&[b"auction", mint.key.as_ref()], &auction::id()).1,
+    };
+    let mut auction_acc = legacy_auction_account(&auction);
+    auction_acc.lamports += 100;
+    assert_eq!(auction_acc.data.len(), LEGACY_AUCTION_LEN);
+
+    // Outbidding refunds the previous bidder.
+    let mut previous_bidder = TestAccount::wallet(0);
+    previous_bidder.key = bidder;
+    let mut accounts = vec![
+        TestAccount::signer(1_000),
+        auction_acc,
+        previous_bidder,
+        TestAccount::program(system_program::id()),
+    ];
+    assert_eq!(run(&mut accounts, AuctionInstruction::PlaceBid { bid_amount: 150 }), Ok(()));
+    assert_eq!(accounts[2].lamports, 100);
+    assert_eq!(accounts[1].data.len(), LEGACY_AUCTION_LEN);
+
+    let mut seller = TestAccount::signer(0);
+    seller.key = auction.seller;
+    let winner = accounts[0].key;
+    let mut ended = Auction::try_from_slice(&[&AUCTION_DISCRIMINATOR[..], &accounts[1].data].concat()).unwrap();
+    assert_eq!((ended.current_bid, ended.highest_bidder), (150, winner));
+    ended.end_time = 0;
+    let mut accounts = vec![
+        seller.clone(),
+        TestAccount { data: legacy_auction_account(&ended).data, ..accounts[1].clone() },
+        TestAccount::wallet(0),
+        TestAccount::wallet(0),
+        TestAccount::token_2022_account(&mint, winner, 0),
+        TestAccount::token_2022_account(&mint, accounts[1].key, 1),
+        TestAccount::token_2022_account(&mint, seller.key, 0),
+        TestAccount::program(spl_token_2022::id()),
+    ];
+    assert_eq!(run(&mut accounts, AuctionInstruction::EndAuction), Ok(()));
+    assert_eq!(accounts[2].lamports, 150);
+    assert_eq!(accounts[4].token_amount(), 1);
+    assert_eq!(accounts[1].data.len(), LEGACY_AUCTION_LEN);
+    assert_eq!(accounts[1].data[LEGACY_AUCTION_LEN - 2], 1);
+}
+
+#[test]
+fn legacy_auctions_must_sit_at_their_pda() {
+    let auction = Auction {
+        discriminator: AUCTION_DISCRIMINATOR,
+        property_mint: Pubkey::new_unique(),
+        seller: Pubkey::new_unique(),
+        start_price: 1,
+        current_bid: 1,
+        highest_bidder: Pubkey::default(),
+        start_time: 0,
+        end_time: 10_000,
+        ended: false,
+        bump_seed: 0,
+    };
+    let mut auction_acc = legacy_auction_account(&auction);
+    auction_acc.key = Pubkey::new_unique();
+    let mut accounts = vec![
+        TestAccount::signer(1_000),
+        auction_acc,
+        TestAccount::wallet(0),
+        TestAccount::program(system_program::id()),
+    ];
+    assert_eq!(run(&mut accounts, AuctionInstruction::PlaceBid { bid_amount: 100 }), Err(ProgramError::InvalidSeeds));
+}
@@ -0,0 +1,95 @@
+mod common;
+
+use auction::{Auction, AuctionError, AuctionInstruction, AUCTION_DISCRIMINATOR};
+use borsh::BorshDeserialize;
+use common::{auction_account, run, TestAccount};
+use solana_program::{pubkey::Pubkey, system_program};
+
+fn auction_with_bid(highest_bidder: Pubkey, current_bid: u64) -> Auction {
+    Auction {
+        discriminator: AUCTION_DISCRIMINATOR,
+        property_mint: Pubkey::new_unique(),
+        seller: Pubkey::new_unique(),
+        start_price: 1,
+        current_bid,
+        highest_bidder,
+        start_time: 0,
+        end_time: 10_000,
+        ended: false,
+        bump_seed: 0,
+    }
+}
+
+fn place_bid_accounts(auction: &Auction, bidder_lamports: u64, previous_bidder_lamports: u64) -> Vec<TestAccount> {
+    let mut auction_acc = auction_account(auction);
+    auction_acc.lamports = auction_acc.lamports.saturating_add(auction.current_bid);
+    let mut previous_bidder = TestAccount::wallet(previous_bidder_lamports);
+    previous_bidder.key = auction.highest_bidder;
+    vec![
+        TestAccount::signer(bidder_lamports),
+        auction_acc,
+        previous_bidder,
+        TestAccount::program(system_program::id()),
+    ]
+}
+
+#[test]
+fn refunds_previous_bidder() {
+    let auction = auction_with_bid(Pubkey::new_unique(), 100);
+    let mut accounts = place_bid_accounts(&auction, 1_000, 0);
+    let escrow_before = accounts[1].lamports;
+
+    assert_eq!(run(&mut accounts, AuctionInstruction::PlaceBid { bid_amount: 150 }), Ok(()));
+    assert_eq!(accounts[0].lamports, 850);
+    assert_eq!(accounts[1].lamports, escrow_before + 50);
+    assert_eq!(accounts[2].lamports, 100);
+
+    let updated = Auction::try_from_slice(&accounts[1].data).unwrap();
+    assert_eq!(updated.current_bid, 150);
+    assert_eq!(updated.highest_bidder, accounts[0].key);
+}
+
+#[test]
+fn refund_overflowing_previous_bidder_fails() {
+    let auction = auction_with_bid(Pubkey::new_unique(), 100);
+    let mut accounts = place_bid_accounts(&auction, 1_000, u64::MAX - 99);
+    assert_eq!(
+        run(&mut accounts, AuctionInstruction::PlaceBid { bid_amount: 101 }),
+        Err(AuctionError::MathOverflow.into())
+    );
+}
+
+#[test]
+fn payout_exceeding_escrow_fails() {
+    let mut auction = auction_with_bid(Pubkey::new_unique(), u64::MAX);
+    auction.end_time = 0;
+    let mut auction_acc = auction_account(&auction);
+    let mut seller = TestAccount::signer(0);
+    seller.key = auction.seller;
+
+    let mut accounts = vec![
+        seller,
+        auction_acc.clone(),
+        TestAccount::wallet(0),
+        TestAccount::wallet(0),
+        TestAccount::wallet(0),
+        TestAccount::wallet(0),
+        TestAccount::wallet(0),
+        TestAccount::program(spl_token::id()),
+    ];
+    assert_eq!(
+        run(&mut accounts, AuctionInstruction::EndAuction),
+        Err(AuctionError::MathOverflow.into())
+    );
+
+    // The seller's balance must not wrap either.
+    auction.current_bid = 100;
+    auction_acc = auction_account(&auction);
+    auction_acc.lamports += 100;
+    accounts[1] = auction_acc;
+    accounts[2].lamports = u64::MAX - 99;
+    assert_eq!(
+        run(&mut accounts, AuctionInstruction::EndAuction),
+        Err(AuctionError::MathOverflow.into())
+    );
+}
@@ -0,0 +1,383 @@
+//! Host-side harness for driving the program without a validator.
+//!
+//! System-program and SPL Token and Token-2022 CPIs are executed in-process
+//! against the same `AccountInfo`s, and the clock can be set per test binary.
+//! A CPI may only sign for the caller's signers and the PDAs it passes seeds
+//! for.
+
+#![allow(dead_code)]
+
+use auction::{process_instruction, Auction, AuctionInstruction};
+use borsh::BorshSerialize;
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::{ProgramResult, MAX_PERMITTED_DATA_INCREASE},
+    instruction::Instruction,
+    program_error::ProgramError,
+    program_option::COption,
+    program_utils::limited_deserialize,
+    program_stubs::{set_syscall_stubs, SyscallStubs},
+    pubkey::Pubkey,
+    rent::Rent,
+    system_instruction::SystemInstruction,
+    system_program,
+    sysvar::clock::Clock,
+};
+use spl_tlv_account_resolution::state::ExtraAccountMetaList;
+use spl_token_2022::{
+    extension::{
+        transfer_fee::{TransferFee, TransferFeeAmount, TransferFeeConfig},
+        transfer_hook::{TransferHook, TransferHookAccount},
+        BaseStateWithExtensions, BaseStateWithExtensionsMut, ExtensionType, StateWithExtensions, StateWithExtensionsMut,
+    },
+    state::{Account, AccountState, Mint},
+};
+use spl_transfer_hook_interface::{get_extra_account_metas_address, instruction::ExecuteInstruction};
+use std::cell::Cell;
+use std::sync::{
+    atomic::{AtomicI64, Ordering},
+    Once,
+};
+
+/// Unix timestamp reported by the `Clock` sysvar.
+pub static NOW: AtomicI64 = AtomicI64::new(1_000);
+
+/// Program id of the transfer hook simulated by the harness, which counts the
+/// transfers it is executed for.
+pub const TRANSFER_HOOK_ID: Pubkey = Pubkey::new_from_array([9; 32]);
+
+thread_local! {
+    static TRANSFER_HOOK_CALLS: Cell<u32> = const { Cell::new(0) };
+}
+
+/// Transfers the simulated transfer hook has seen on this thread.
+pub fn transfer_hook_calls() -> u32 {
+    TRANSFER_HOOK_CALLS.with(Cell::get)
+}
+
+struct TestSyscallStubs;
+
+impl SyscallStubs for TestSyscallStubs {
+    fn sol_log(&self, _message: &str) {}
+
+    fn sol_invoke_signed(
+        &self,
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+        signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        let pda_signers: Vec<Pubkey> = signers_seeds
+            .iter()
+            .map(|seeds| Pubkey::create_program_address(seeds, &auction::id()))
+            .collect::<Result<_, _>>()?;
+        let mut infos = Vec::with_capacity(instruction.accounts.len());
+        for meta in &instruction.accounts {
+            let mut info = account_infos
+                .iter()
+                .find(|info| *info.key == meta.pubkey)
+                .ok_or(ProgramError::NotEnoughAccountKeys)?
+                .clone();
+            // Signer privileges apply per key, as in the runtime.
+            info.is_signer |= instruction
+                .accounts
+                .iter()
+                .any(|other| other.pubkey == meta.pubkey && other.is_signer);
+            let caller_signed = account_infos.iter().any(|caller| caller.key == info.key && caller.is_signer);
+            if info.is_signer && !caller_signed && !pda_signers.contains(info.key) {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+            infos.push(info);
+        }
+
+        if instruction.program_id == spl_token::id() {
+            spl_token::processor::Processor::process(&instruction.program_id, &infos, &instruction.data)
+        } else if instruction.program_id == spl_token_2022::id() {
+            spl_token_2022::processor::Processor::process(&instruction.program_id, &infos, &instruction.data)
+        } else if instruction.program_id == TRANSFER_HOOK_ID {
+            TRANSFER_HOOK_CALLS.with(|calls| calls.set(calls.get() + 1));
+            Ok(())
+        } else if instruction.program_id == system_program::id() {
+            execute_system_instruction(&infos, &instruction.data)
+        } else {
+            Err(ProgramError::IncorrectProgramId)
+        }
+    }
+
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        let clock = Clock {
+            unix_timestamp: NOW.load(Ordering::SeqCst),
+            ..Clock::default()
+        };
+        unsafe { *(var_addr as *mut Clock) = clock };
+        0
+    }
+
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+        unsafe { *(var_addr as *mut Rent) = Rent::default() };
+        0
+    }
+}
+
+/// Executes the system program instructions the program issues.
+fn execute_system_instruction(infos: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    // `SystemError::AccountAlreadyInUse`
+    const ACCOUNT_ALREADY_IN_USE: ProgramError = ProgramError::Custom(0);
+    let unused = |info: &AccountInfo| info.data_is_empty() && *info.owner == system_program::id();
+
+    match limited_deserialize(data, data.len() as u64).map_err(|_| ProgramError::InvalidInstructionData)? {
+        SystemInstruction::Transfer { lamports } => transfer_lamports(&infos[0], &infos[1], lamports),
+        SystemInstruction::CreateAccount { lamports, space, owner } => {
+            let (from, to) = (&infos[0], &infos[1]);
+            if !to.is_signer {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+            if to.lamports() > 0 || !unused(to) {
+                return Err(ACCOUNT_ALREADY_IN_USE);
+            }
+            transfer_lamports(from, to, lamports)?;
+            to.realloc(space as usize, true)?;
+            to.assign(&owner);
+            Ok(())
+        }
+        SystemInstruction::Allocate { space } => {
+            if !infos[0].is_signer {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+            if !unused(&infos[0]) {
+                return Err(ACCOUNT_ALREADY_IN_USE);
+            }
+            infos[0].realloc(space as usize, true)
+        }
+        SystemInstruction::Assign { owner } => {
+            if !infos[0].is_signer {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+            if *infos[0].owner != system_program::id() {
+                return Err(ProgramError::IllegalOwner);
+            }
+            infos[0].assign(&owner);
+            Ok(())
+        }
+        _ => Err(ProgramError::InvalidInstructionData),
+    }
+}
+
+fn transfer_lamports(from: &AccountInfo, to: &AccountInfo, lamports: u64) -> ProgramResult {
+    if !from.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    let from_balance = from.lamports().checked_sub(lamports).ok_or(ProgramError::InsufficientFunds)?;
+    let to_balance = to.lamports().checked_add(lamports).ok_or(ProgramError::ArithmeticOverflow)?;
+    **from.try_borrow_mut_lamports()? = from_balance;
+    **to.try_borrow_mut_lamports()? = to_balance;
+    Ok(())
+}
+
+/// Installs the test syscall stubs once per test binary.
+pub fn setup() {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| {
+        set_syscall_stubs(Box::new(TestSyscallStubs));
+    });
+}
+
+/// An owned account that can be lent out as an `AccountInfo`.
+#[derive(Clone, Debug)]
+pub struct TestAccount {
+    pub key: Pubkey,
+    pub owner: Pubkey,
+    pub lamports: u64,
+    pub data: Vec<u8>,
+    pub is_signer: bool,
+}
+
+impl TestAccount {
+    pub fn new(owner: Pubkey, lamports: u64, data: Vec<u8>) -> Self {
+        Self {
+            key: Pubkey::new_unique(),
+            owner,
+            lamports,
+            data,
+            is_signer: false,
+        }
+    }
+
+    pub fn wallet(lamports: u64) -> Self {
+        Self::new(system_program::id(), lamports, vec![])
+    }
+
+    pub fn signer(lamports: u64) -> Self {
+        Self {
+            is_signer: true,
+            ..Self::wallet(lamports)
+        }
+    }
+
+    pub fn program(program_id: Pubkey) -> Self {
+        Self {
+            key: program_id,
+            ..Self::new(solana_program::bpf_loader::id(), 1, vec![])
+        }
+    }
+
+    /// A Token-2022 property mint with no decimals, running the simulated
+    /// transfer hook if `hooked`.
+    pub fn token_2022_mint(hooked: bool) -> Self {
+        let extensions: &[ExtensionType] = if hooked { &[ExtensionType::TransferHook] } else { &[] };
+        Self::token_2022_mint_with(extensions, |state| {
+            if hooked {
+                state.init_extension::<TransferHook>(true).unwrap().program_id = Some(TRANSFER_HOOK_ID).try_into().unwrap();
+            }
+        })
+    }
+
+    /// A Token-2022 property mint with no decimals charging `fee_bps` on
+    /// transfers.
+    pub fn transfer_fee_mint(fee_bps: u16) -> Self {
+        Self::token_2022_mint_with(&[ExtensionType::TransferFeeConfig], |state| {
+            let fee = TransferFee {
+                epoch: 0.into(),
+                maximum_fee: u64::MAX.into(),
+                transfer_fee_basis_points: fee_bps.into(),
+            };
+            let config = state.init_extension::<TransferFeeConfig>(true).unwrap();
+            config.older_transfer_fee = fee;
+            config.newer_transfer_fee = fee;
+        })
+    }
+
+    fn token_2022_mint_with(extensions: &[ExtensionType], init: impl FnOnce(&mut StateWithExtensionsMut<Mint>)) -> Self {
+        let mut data = vec![0; ExtensionType::try_calculate_account_len::<Mint>(extensions).unwrap()];
+        let mut state = StateWithExtensionsMut::<Mint>::unpack_uninitialized(&mut data).unwrap();
+        init(&mut state);
+        state.base = Mint {
+            mint_authority: COption::None,
+            supply: 1,
+            decimals: 0,
+            is_initialized: true,
+            freeze_authority: COption::None,
+        };
+        state.pack_base();
+        state.init_account_type().unwrap();
+        Self::new(spl_token_2022::id(), Rent::default().minimum_balance(data.len()), data)
+    }
+
+    /// A Token-2022 account of `mint` holding `amount`, with the extensions
+    /// the mint requires of its accounts.
+    pub fn token_2022_account(mint: &TestAccount, owner: Pubkey, amount: u64) -> Self {
+        let mint_state = StateWithExtensions::<Mint>::unpack(&mint.data).unwrap();
+        let extensions = ExtensionType::get_required_init_account_extensions(&mint_state.get_extension_types().unwrap());
+        let mut data = vec![0; ExtensionType::try_calculate_account_len::<Account>(&extensions).unwrap()];
+        let mut state = StateWithExtensionsMut::<Account>::unpack_uninitialized(&mut data).unwrap();
+        if extensions.contains(&ExtensionType::TransferHookAccount) {
+            state.init_extension::<TransferHookAccount>(true).unwrap();
+        }
+        if extensions.contains(&ExtensionType::TransferFeeAmount) {
+            state.init_extension::<TransferFeeAmount>(true).unwrap();
+        }
+        state.base = Account {
+            mint: mint.key,
+            owner,
+            amount,
+            delegate: COption::None,
+            state: AccountState::Initialized,
+            is_native: COption::None,
+            delegated_amount: 0,
+            close_authority: COption::None,
+        };
+        state.pack_base();
+        state.init_account_type().unwrap();
+        Self::new(spl_token_2022::id(), Rent::default().minimum_balance(data.len()), data)
+    }
+
+    /// The simulated transfer hook's validation account for `mint`, listing
+    /// no extra accounts.
+    pub fn transfer_hook_validation(mint: &Pubkey) -> Self {
+        let mut data = vec![0; ExtraAccountMetaList::size_of(0).unwrap()];
+        ExtraAccountMetaList::init::<ExecuteInstruction>(&mut data, &[]).unwrap();
+        Self {
+            key: get_extra_account_metas_address(mint, &TRANSFER_HOOK_ID),
+            ..Self::new(TRANSFER_HOOK_ID, Rent::default().minimum_balance(data.len()), data)
+        }
+    }
+
+    pub fn token_amount(&self) -> u64 {
+        StateWithExtensions::<Account>::unpack(&self.data).unwrap().base.amount
+    }
+}
+
+/// Offsets of the key, the owner and the data in a [`serialize_account`] buffer.
+const KEY_OFFSET: usize = 8;
+const OWNER_OFFSET: usize = KEY_OFFSET + 32;
+const DATA_OFFSET: usize = OWNER_OFFSET + 32 + 8;
+
+/// Lays out an account's key, owner and data as the runtime serializes them
+/// for a program, which `AccountInfo::realloc` and `AccountInfo::assign` rely
+/// on: the original data length right before the key, the current length
+/// right before the data, and room for the data to grow. Words keep the
+/// lengths aligned.
+fn serialize_account(account: &TestAccount) -> Vec<u64> {
+    let len = account.data.len();
+    let mut buffer = vec![0u64; (DATA_OFFSET + len + MAX_PERMITTED_DATA_INCREASE).div_ceil(8)];
+    let bytes = unsafe { std::slice::from_raw_parts_mut(buffer.as_mut_ptr() as *mut u8, buffer.len() * 8) };
+    bytes[KEY_OFFSET - 4..KEY_OFFSET].copy_from_slice(&(len as u32).to_le_bytes());
+    bytes[KEY_OFFSET..KEY_OFFSET + 32].copy_from_slice(account.key.as_ref());
+    bytes[OWNER_OFFSET..OWNER_OFFSET + 32].copy_from_slice(account.owner.as_ref());
+    bytes[DATA_OFFSET - 8..DATA_OFFSET].copy_from_slice(&(len as u64).to_le_bytes());
+    bytes[DATA_OFFSET..DATA_OFFSET + len].copy_from_slice(&account.data);
+    buffer
+}
+
+/// Runs one instruction against the given accounts, in order.
+pub fn run(accounts: &mut [TestAccount], instruction: AuctionInstruction) -> ProgramResult {
+    setup();
+    let mut buffers: Vec<Vec<u64>> = accounts.iter().map(serialize_account).collect();
+    let infos: Vec<AccountInfo> = accounts
+        .iter_mut()
+        .zip(buffers.iter_mut())
+        .map(|(account, buffer)| {
+            let base = buffer.as_mut_ptr() as *mut u8;
+            let (key, owner, data) = unsafe {
+                (
+                    &*(base.add(KEY_OFFSET) as *const Pubkey),
+                    &*(base.add(OWNER_OFFSET) as *const Pubkey),
+                    std::slice::from_raw_parts_mut(base.add(DATA_OFFSET), account.data.len()),
+                )
+            };
+            AccountInfo::new(key, account.is_signer, true, &mut account.lamports, data, owner, false, 0)
+        })
+        .collect();
+    let data = instruction.try_to_vec().unwrap();
+    let result = process_instruction(&auction::id(), &infos, &data);
+
+    let written: Vec<(Pubkey, Vec<u8>)> = infos.iter().map(|info| (*info.owner, info.data.borrow().to_vec())).collect();
+    drop(infos);
+    drop(buffers);
+    for (account, (owner, data)) in accounts.iter_mut().zip(written) {
+        account.owner = owner;
+        account.data = data;
+    }
+    result
+}
+
+/// `InitializeAuction` accounts for a new property mint, by a seller able to
+/// pay the auction's rent.
+pub fn initialize_auction_accounts() -> Vec<TestAccount> {
+    let property_mint = TestAccount::wallet(0);
+    let (key, _) = Pubkey::find_program_address(&[b"auction", property_mint.key.as_ref()], &auction::id());
+    vec![
+        TestAccount::signer(1_000_000_000),
+        TestAccount { key, ..TestAccount::wallet(0) },
+        property_mint,
+        TestAccount::program(system_program::id()),
+    ]
+}
+
+/// An auction account holding `escrow` lamports on top of its rent reserve.
+pub fn auction_account(auction: &Auction) -> TestAccount {
+    let (key, _) = Pubkey::find_program_address(&[b"auction", auction.property_mint.as_ref()], &auction::id());
+    let data = auction.try_to_vec().unwrap();
+    let mut account = TestAccount::new(auction::id(), Rent::default().minimum_balance(data.len()), data);
+    account.key = key;
+    account
+}
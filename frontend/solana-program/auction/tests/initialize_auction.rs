@@ -0,0 +1,45 @@
+mod common;
+
+use auction::{Auction, AuctionError, AuctionInstruction};
+use common::{initialize_auction_accounts, run, TestAccount};
+use solana_program::{program_error::ProgramError, pubkey::Pubkey, rent::Rent};
+
+fn initialize() -> AuctionInstruction {
+    AuctionInstruction::InitializeAuction { start_price: 1, end_time: 10_000 }
+}
+
+#[test]
+fn creates_the_auction_account_at_its_pda() {
+    let mut accounts = initialize_auction_accounts();
+    assert_eq!(run(&mut accounts, initialize()), Ok(()));
+
+    let rent = Rent::default().minimum_balance(Auction::LEN);
+    assert_eq!(accounts[1].owner, auction::id());
+    assert_eq!(accounts[1].data.len(), Auction::LEN);
+    assert_eq!(accounts[1].lamports, rent);
+    assert_eq!(accounts[0].lamports, 1_000_000_000 - rent);
+}
+
+#[test]
+fn creates_the_auction_at_an_address_already_sent_lamports() {
+    let mut accounts = initialize_auction_accounts();
+    accounts[1].lamports = 1;
+    assert_eq!(run(&mut accounts, initialize()), Ok(()));
+    assert_eq!(accounts[1].owner, auction::id());
+    assert_eq!(accounts[1].lamports, Rent::default().minimum_balance(Auction::LEN));
+}
+
+#[test]
+fn rejects_an_auction_account_not_at_its_pda() {
+    let mut accounts = initialize_auction_accounts();
+    accounts[1].key = Pubkey::new_unique();
+    assert_eq!(run(&mut accounts, initialize()), Err(ProgramError::InvalidSeeds));
+}
+
+#[test]
+fn rejects_an_end_time_in_the_past() {
+    let mut accounts = initialize_auction_accounts();
+    let instruction = AuctionInstruction::InitializeAuction { start_price: 1, end_time: 0 };
+    assert_eq!(run(&mut accounts, instruction), Err(AuctionError::InvalidEndTime.into()));
+    assert_eq!(accounts[1].owner, TestAccount::wallet(0).owner);
+}
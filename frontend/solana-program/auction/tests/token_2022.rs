@@ -0,0 +1,95 @@
+mod common;
+
+use auction::{Auction, AuctionError, AuctionInstruction, AUCTION_DISCRIMINATOR};
+use borsh::BorshDeserialize;
+use common::{auction_account, initialize_auction_accounts, run, transfer_hook_calls, TestAccount, TRANSFER_HOOK_ID};
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+use spl_token_2022::error::TokenError;
+
+/// An ended auction of `mint` and the `EndAuction` accounts, with the property
+/// token in escrow.
+fn ended_auction(mint: &TestAccount, highest_bidder: Pubkey, current_bid: u64) -> Vec<TestAccount> {
+    let auction = Auction {
+        discriminator: AUCTION_DISCRIMINATOR,
+        property_mint: mint.key,
+        seller: Pubkey::new_unique(),
+        start_price: 1,
+        current_bid,
+        highest_bidder,
+        start_time: 0,
+        end_time: 0,
+        ended: false,
+        bump_seed: Pubkey::find_program_address(&[b"auction", mint.key.as_ref()], &auction::id()).1,
+    };
+    let mut auction_acc = auction_account(&auction);
+    auction_acc.lamports += current_bid;
+    let mut seller = TestAccount::signer(0);
+    seller.key = auction.seller;
+
+    vec![
+        seller.clone(),
+        auction_acc.clone(),
+        TestAccount::wallet(0),
+        TestAccount::wallet(0),
+        TestAccount::token_2022_account(mint, highest_bidder, 0),
+        TestAccount::token_2022_account(mint, auction_acc.key, 1),
+        TestAccount::token_2022_account(mint, seller.key, 0),
+        TestAccount::program(spl_token_2022::id()),
+    ]
+}
+
+#[test]
+fn ends_with_a_token_2022_property() {
+    let mint = TestAccount::token_2022_mint(false);
+    let mut accounts = ended_auction(&mint, Pubkey::new_unique(), 500);
+    assert_eq!(run(&mut accounts, AuctionInstruction::EndAuction), Ok(()));
+
+    assert_eq!(accounts[2].lamports, 500);
+    assert_eq!(accounts[4].token_amount(), 1);
+    assert_eq!(accounts[5].token_amount(), 0);
+    assert!(Auction::try_from_slice(&accounts[1].data).unwrap().ended);
+}
+
+#[test]
+fn hooked_property_needs_its_mint_and_hook_accounts() {
+    let mint = TestAccount::token_2022_mint(true);
+    let mut accounts = ended_auction(&mint, Pubkey::default(), 1);
+    assert_eq!(
+        run(&mut accounts, AuctionInstruction::EndAuction),
+        Err(TokenError::MintRequiredForTransfer.into())
+    );
+
+    accounts.extend([mint.clone(), TestAccount::program(TRANSFER_HOOK_ID), TestAccount::transfer_hook_validation(&mint.key)]);
+    assert_eq!(run(&mut accounts, AuctionInstruction::EndAuction), Ok(()));
+    assert_eq!(transfer_hook_calls(), 1);
+    assert_eq!(accounts[6].token_amount(), 1);
+}
+
+#[test]
+fn refuses_a_property_mint_with_a_transfer_fee() {
+    let mint = TestAccount::transfer_fee_mint(100);
+    let mut accounts = initialize_auction_accounts();
+    accounts[2] = mint.clone();
+    accounts[1].key = Pubkey::find_program_address(&[b"auction", mint.key.as_ref()], &auction::id()).0;
+    assert_eq!(
+        run(&mut accounts, AuctionInstruction::InitializeAuction { start_price: 1, end_time: 10_000 }),
+        Err(AuctionError::UnsupportedPropertyMint.into())
+    );
+
+    // An auction created before the check cannot hand over less than the token.
+    let mut accounts = ended_auction(&mint, Pubkey::new_unique(), 500);
+    accounts.push(mint);
+    assert_eq!(
+        run(&mut accounts, AuctionInstruction::EndAuction),
+        Err(AuctionError::UnsupportedPropertyMint.into())
+    );
+    assert_eq!(accounts[5].token_amount(), 1);
+}
+
+#[test]
+fn rejects_other_token_programs() {
+    let mint = TestAccount::token_2022_mint(false);
+    let mut accounts = ended_auction(&mint, Pubkey::default(), 1);
+    accounts[7] = TestAccount::program(Pubkey::new_unique());
+    assert_eq!(run(&mut accounts, AuctionInstruction::EndAuction), Err(ProgramError::IncorrectProgramId));
+}
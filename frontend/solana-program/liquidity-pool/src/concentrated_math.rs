@@ -0,0 +1,251 @@
+//! Concentrated-liquidity math in the style of Uniswap v3.
+//!
+//! Prices are tracked as `sqrt(price)` in Q64.64 fixed point, where price is
+//! token B per token A, and discretised into ticks with `price = 1.0001^tick`.
+//! Within a tick range the pool behaves like a constant-product pool with
+//! virtual reserves set by its liquidity `L`. Outputs round down and inputs
+//! round up, as in [`crate::curve`].
+
+use crate::curve::{CurveError, BPS_DENOMINATOR};
+use crate::stable_swap::U256;
+
+/// Lowest usable tick; its sqrt price is about 2^32 in Q64.64.
+pub const MIN_TICK: i32 = -443_636;
+
+/// Highest usable tick; its sqrt price is about 2^96 in Q64.64.
+pub const MAX_TICK: i32 = 443_636;
+
+/// One in Q64.64.
+pub const Q64: u128 = 1 << 64;
+
+/// `sqrt(1.0001)^(2^i)` in Q64.64, for each bit of a tick's magnitude.
+const SQRT_RATIOS: [u128; 19] = [
+    18447666387855959851,
+    18448588748116922571,
+    18450433606991734263,
+    18454123878217468680,
+    18461506635090006702,
+    18476281010653910145,
+    18505865242158250042,
+    18565175891880433523,
+    18684368066214940583,
+    18925053041275764672,
+    19415764168677886927,
+    20435687552633177495,
+    22639080592224303007,
+    27784196929998399742,
+    41848122137994986129,
+    94936283578220370716,
+    488590176327622479861,
+    12941056668319229769860,
+    9078618265828848800676189,
+];
+
+fn to_u128(value: U256) -> Result<u128, CurveError> {
+    if value > U256::from(u128::MAX) {
+        return Err(CurveError::MathOverflow);
+    }
+    Ok(value.as_u128())
+}
+
+fn to_u64(value: U256) -> Result<u64, CurveError> {
+    if value > U256::from(u64::MAX) {
+        return Err(CurveError::MathOverflow);
+    }
+    Ok(value.as_u64())
+}
+
+fn div_ceil(numerator: U256, denominator: U256) -> U256 {
+    let quotient = numerator / denominator;
+    if quotient * denominator < numerator {
+        quotient + 1
+    } else {
+        quotient
+    }
+}
+
+/// `sqrt(1.0001^tick)` in Q64.64.
+pub fn sqrt_price_at_tick(tick: i32) -> Result<u128, CurveError> {
+    if !(MIN_TICK..=MAX_TICK).contains(&tick) {
+        return Err(CurveError::InvalidTick);
+    }
+
+    let magnitude = tick.unsigned_abs();
+    let mut ratio = U256::from(Q64);
+    for (bit, factor) in SQRT_RATIOS.iter().enumerate() {
+        if magnitude & (1 << bit) != 0 {
+            ratio = (ratio * U256::from(*factor)) >> 64;
+        }
+    }
+    if tick < 0 {
+        ratio = (U256::one() << 128) / ratio;
+    }
+    to_u128(ratio)
+}
+
+/// Greatest tick whose sqrt price is at most `sqrt_price`.
+pub fn tick_at_sqrt_price(sqrt_price: u128) -> Result<i32, CurveError> {
+    if sqrt_price < sqrt_price_at_tick(MIN_TICK)? || sqrt_price > sqrt_price_at_tick(MAX_TICK)? {
+        return Err(CurveError::InvalidTick);
+    }
+
+    let (mut low, mut high) = (MIN_TICK, MAX_TICK);
+    while low < high {
+        let middle = low + (high - low + 1) / 2;
+        if sqrt_price_at_tick(middle)? <= sqrt_price {
+            low = middle;
+        } else {
+            high = middle - 1;
+        }
+    }
+    Ok(low)
+}
+
+/// Token A held by `liquidity` between two sqrt prices:
+/// `L · (√Pu − √Pl) / (√Pu · √Pl)`.
+fn amount_a_delta_wide(sqrt_price_0: u128, sqrt_price_1: u128, liquidity: u128, round_up: bool) -> Result<U256, CurveError> {
+    let (lower, upper) = (sqrt_price_0.min(sqrt_price_1), sqrt_price_0.max(sqrt_price_1));
+    if lower == 0 {
+        return Err(CurveError::ZeroReserves);
+    }
+    let numerator = (U256::from(liquidity) << 64)
+        .checked_mul(U256::from(upper - lower))
+        .ok_or(CurveError::MathOverflow)?;
+    let denominator = U256::from(upper) * U256::from(lower);
+    Ok(if round_up { div_ceil(numerator, denominator) } else { numerator / denominator })
+}
+
+/// Token B held by `liquidity` between two sqrt prices: `L · (√Pu − √Pl)`.
+fn amount_b_delta_wide(sqrt_price_0: u128, sqrt_price_1: u128, liquidity: u128, round_up: bool) -> U256 {
+    let (lower, upper) = (sqrt_price_0.min(sqrt_price_1), sqrt_price_0.max(sqrt_price_1));
+    let product = U256::from(liquidity) * U256::from(upper - lower);
+    if round_up {
+        div_ceil(product, U256::from(Q64))
+    } else {
+        product >> 64
+    }
+}
+
+/// Token A held by `liquidity` between two sqrt prices.
+pub fn amount_a_delta(sqrt_price_0: u128, sqrt_price_1: u128, liquidity: u128, round_up: bool) -> Result<u64, CurveError> {
+    to_u64(amount_a_delta_wide(sqrt_price_0, sqrt_price_1, liquidity, round_up)?)
+}
+
+/// Token B held by `liquidity` between two sqrt prices.
+pub fn amount_b_delta(sqrt_price_0: u128, sqrt_price_1: u128, liquidity: u128, round_up: bool) -> Result<u64, CurveError> {
+    to_u64(amount_b_delta_wide(sqrt_price_0, sqrt_price_1, liquidity, round_up))
+}
+
+/// Tokens A and B needed to add `liquidity` over `[sqrt_price_lower, sqrt_price_upper)`
+/// at `sqrt_price`, or returned for removing it.
+pub fn amounts_for_liquidity(
+    sqrt_price: u128,
+    sqrt_price_lower: u128,
+    sqrt_price_upper: u128,
+    liquidity: u128,
+    round_up: bool,
+) -> Result<(u64, u64), CurveError> {
+    if sqrt_price < sqrt_price_lower {
+        Ok((amount_a_delta(sqrt_price_lower, sqrt_price_upper, liquidity, round_up)?, 0))
+    } else if sqrt_price < sqrt_price_upper {
+        Ok((
+            amount_a_delta(sqrt_price, sqrt_price_upper, liquidity, round_up)?,
+            amount_b_delta(sqrt_price_lower, sqrt_price, liquidity, round_up)?,
+        ))
+    } else {
+        Ok((0, amount_b_delta(sqrt_price_lower, sqrt_price_upper, liquidity, round_up)?))
+    }
+}
+
+/// Sqrt price after adding `amount` of token A, rounded up:
+/// `L · √P / (L + amount · √P)`.
+fn next_sqrt_price_from_a_in(sqrt_price: u128, liquidity: u128, amount: u64) -> Result<u128, CurveError> {
+    let scaled_liquidity = U256::from(liquidity) << 64;
+    let denominator = scaled_liquidity + U256::from(amount) * U256::from(sqrt_price);
+    to_u128(div_ceil(scaled_liquidity * U256::from(sqrt_price), denominator))
+}
+
+/// Sqrt price after adding `amount` of token B, rounded down: `√P + amount / L`.
+fn next_sqrt_price_from_b_in(sqrt_price: u128, liquidity: u128, amount: u64) -> Result<u128, CurveError> {
+    let delta = to_u128((U256::from(amount) << 64) / U256::from(liquidity))?;
+    sqrt_price.checked_add(delta).ok_or(CurveError::MathOverflow)
+}
+
+/// One step of a swap, within a single tick range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SwapStep {
+    pub sqrt_price_next: u128,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub fee_amount: u64,
+}
+
+/// Swaps up to `amount_remaining` (fee included) from `sqrt_price` towards
+/// `sqrt_price_target` with constant `liquidity`. Selling A moves the price
+/// down, selling B moves it up; the direction follows from the target.
+pub fn compute_swap_step(
+    sqrt_price: u128,
+    sqrt_price_target: u128,
+    liquidity: u128,
+    amount_remaining: u64,
+    fee_bps: u64,
+) -> Result<SwapStep, CurveError> {
+    if fee_bps >= BPS_DENOMINATOR {
+        return Err(CurveError::InvalidFee);
+    }
+
+    let a_to_b = sqrt_price_target <= sqrt_price;
+    let amount_remaining_less_fee =
+        (amount_remaining as u128 * (BPS_DENOMINATOR - fee_bps) as u128 / BPS_DENOMINATOR as u128) as u64;
+
+    let amount_in_to_target = if a_to_b {
+        amount_a_delta_wide(sqrt_price_target, sqrt_price, liquidity, true)?
+    } else {
+        amount_b_delta_wide(sqrt_price, sqrt_price_target, liquidity, true)
+    };
+
+    let (sqrt_price_next, amount_in) = if U256::from(amount_remaining_less_fee) >= amount_in_to_target {
+        (sqrt_price_target, amount_in_to_target.as_u64())
+    } else {
+        let sqrt_price_next = if a_to_b {
+            next_sqrt_price_from_a_in(sqrt_price, liquidity, amount_remaining_less_fee)?
+        } else {
+            next_sqrt_price_from_b_in(sqrt_price, liquidity, amount_remaining_less_fee)?
+        };
+        let amount_in = if a_to_b {
+            amount_a_delta(sqrt_price_next, sqrt_price, liquidity, true)?
+        } else {
+            amount_b_delta(sqrt_price, sqrt_price_next, liquidity, true)?
+        };
+        (sqrt_price_next, amount_in)
+    };
+
+    let amount_out = if a_to_b {
+        amount_b_delta(sqrt_price_next, sqrt_price, liquidity, false)?
+    } else {
+        amount_a_delta(sqrt_price, sqrt_price_next, liquidity, false)?
+    };
+
+    // A step that stops short of its target consumes the whole remainder, so
+    // whatever is left over after the input is the fee.
+    let fee_amount = if sqrt_price_next == sqrt_price_target {
+        (amount_in as u128 * fee_bps as u128).div_ceil((BPS_DENOMINATOR - fee_bps) as u128) as u64
+    } else {
+        amount_remaining.checked_sub(amount_in).ok_or(CurveError::MathOverflow)?
+    };
+
+    Ok(SwapStep { sqrt_price_next, amount_in, amount_out, fee_amount })
+}
+
+/// Fee growth per unit of liquidity for `fee_amount`, in Q64.64.
+pub fn fee_growth(fee_amount: u64, liquidity: u128) -> u128 {
+    if liquidity == 0 {
+        return 0;
+    }
+    ((U256::from(fee_amount) << 64) / U256::from(liquidity)).low_u128()
+}
+
+/// Fees earned by `liquidity` over a fee-growth delta, rounded down.
+pub fn fees_owed(fee_growth_delta: u128, liquidity: u128) -> Result<u64, CurveError> {
+    to_u64((U256::from(fee_growth_delta) * U256::from(liquidity)) >> 64)
+}
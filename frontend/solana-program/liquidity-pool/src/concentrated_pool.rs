@@ -0,0 +1,884 @@
+//! Concentrated-liquidity pools, where each LP position provides liquidity only
+//! within its own tick range.
+//!
+//! Initialized ticks are kept sorted in the pool account itself, up to
+//! [`MAX_TICKS`] of them, so a swap can walk across them without extra
+//! accounts. A position holds either no liquidity or at least
+//! [`MIN_TICK_LIQUIDITY`], so every initialized tick carries that much and
+//! filling the tick array ties up real capital rather than dust. Each position is its own program-owned account, created from a
+//! new keypair by `OpenPosition`, that tracks its liquidity and the tokens
+//! owed to it. Swap fees are credited to in-range positions through
+//! per-liquidity fee growth, as in Uniswap v3; fee-growth counters are allowed
+//! to wrap, and only their differences are meaningful. The pool also tracks
+//! its vault balances as reserves, with time-weighted accumulators like a
+//! constant-product pool's, so distributions can pay it on its average reserve.
+//!
+//! As for constant-product pools, the mints must be in canonical order, the
+//! program creates the vaults at `[b"vault", pool, mint]`, and the pool is
+//! recorded in the [`registry`], with no LP mint.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_program,
+    sysvar::{clock::Clock, Sysvar},
+};
+use crate::{
+    concentrated_math::{self, MAX_TICK, MIN_TICK},
+    create_account,
+    registry::{self, RegistryEntry},
+    token::TokenAccounts,
+    validate_mint, validate_pool_vault, validate_token_program, vault_address, LiquidityPoolError, SWAP_FEE_BPS,
+};
+
+/// Most initialized ticks a pool can hold.
+pub const MAX_TICKS: usize = 64;
+
+/// Least liquidity a position may hold once it holds any.
+pub const MIN_TICK_LIQUIDITY: u128 = 1_000_000;
+
+// ------------------ ConcentratedPoolState Struct ------------------
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct TickState {
+    pub index: i32,
+    /// Liquidity added when the price crosses this tick upwards.
+    pub liquidity_net: i128,
+    /// Total liquidity of positions using this tick as a bound.
+    pub liquidity_gross: u128,
+    pub fee_growth_outside_a_x64: u128,
+    pub fee_growth_outside_b_x64: u128,
+}
+
+impl TickState {
+    pub const LEN: usize = 4 + 16 + 16 + 16 + 16;
+}
+
+/// First bytes of every [`ConcentratedPoolState`] account: the first 8 bytes
+/// of `sha256("account:ConcentratedPoolState")`.
+pub const CONCENTRATED_POOL_STATE_DISCRIMINATOR: [u8; 8] = [72, 35, 170, 226, 140, 129, 232, 9];
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Default)]
+pub struct ConcentratedPoolState {
+    /// Always [`CONCENTRATED_POOL_STATE_DISCRIMINATOR`].
+    pub discriminator: [u8; 8],
+    pub is_initialized: u8,
+    pub bump_seed: u8,
+    pub creator: Pubkey,
+    pub token_a_mint: Pubkey,
+    pub token_b_mint: Pubkey,
+    pub token_a_vault: Pubkey,
+    pub token_b_vault: Pubkey,
+    pub tick_spacing: u16,
+    pub sqrt_price_x64: u128,
+    pub tick_current: i32,
+    /// Liquidity of the positions in range at the current price.
+    pub liquidity: u128,
+    pub fee_growth_global_a_x64: u128,
+    pub fee_growth_global_b_x64: u128,
+    /// Tokens in the vaults, all of which belong to positions: as liquidity,
+    /// as fees, or owed to them until collected.
+    pub reserve_a: u64,
+    pub reserve_b: u64,
+    /// Sums of each reserve times the seconds it was held, for time-weighted
+    /// balances; see [`distribution`](crate::distribution). Allowed to wrap.
+    pub reserve_a_cumulative: u128,
+    pub reserve_b_cumulative: u128,
+    pub last_update_ts: i64,
+    /// Initialized ticks, sorted by index.
+    pub ticks: Vec<TickState>,
+}
+
+impl ConcentratedPoolState {
+    pub const LEN: usize = 8 + 1 + 1 + 32 * 5 + 2 + 16 + 4 + 16 + 16 + 16 + 8 + 8 + 16 + 16 + 8 + 4 + MAX_TICKS * TickState::LEN;
+
+    /// Reserve accumulators as they stand at `now`, with the current reserves
+    /// held since the last update.
+    pub fn reserve_cumulatives(&self, now: i64) -> (u128, u128) {
+        let elapsed = u64::try_from(now.saturating_sub(self.last_update_ts)).unwrap_or(0) as u128;
+        (
+            self.reserve_a_cumulative.wrapping_add(self.reserve_a as u128 * elapsed),
+            self.reserve_b_cumulative.wrapping_add(self.reserve_b as u128 * elapsed),
+        )
+    }
+
+    /// Brings the reserve accumulators up to `now`. Called before every change
+    /// to the reserves, so each interval is weighted at the reserves in effect
+    /// during it.
+    pub fn update_reserve_accumulators(&mut self, now: i64) {
+        (self.reserve_a_cumulative, self.reserve_b_cumulative) = self.reserve_cumulatives(now);
+        self.last_update_ts = now.max(self.last_update_ts);
+    }
+
+    fn tick(&self, index: i32) -> Option<&TickState> {
+        self.ticks.iter().find(|tick| tick.index == index)
+    }
+
+    /// Fee growth per unit of liquidity inside `[tick_lower, tick_upper)` for
+    /// tokens A and B. Both ticks must be initialized.
+    fn fee_growth_inside(&self, tick_lower: i32, tick_upper: i32) -> Result<(u128, u128), ProgramError> {
+        let lower = self.tick(tick_lower).ok_or(LiquidityPoolError::InvalidTickRange)?;
+        let upper = self.tick(tick_upper).ok_or(LiquidityPoolError::InvalidTickRange)?;
+        let (global_a, global_b) = (self.fee_growth_global_a_x64, self.fee_growth_global_b_x64);
+
+        let (below_a, below_b) = if self.tick_current >= tick_lower {
+            (lower.fee_growth_outside_a_x64, lower.fee_growth_outside_b_x64)
+        } else {
+            (global_a.wrapping_sub(lower.fee_growth_outside_a_x64), global_b.wrapping_sub(lower.fee_growth_outside_b_x64))
+        };
+        let (above_a, above_b) = if self.tick_current < tick_upper {
+            (upper.fee_growth_outside_a_x64, upper.fee_growth_outside_b_x64)
+        } else {
+            (global_a.wrapping_sub(upper.fee_growth_outside_a_x64), global_b.wrapping_sub(upper.fee_growth_outside_b_x64))
+        };
+
+        Ok((
+            global_a.wrapping_sub(below_a).wrapping_sub(above_a),
+            global_b.wrapping_sub(below_b).wrapping_sub(above_b),
+        ))
+    }
+
+    /// Adds `liquidity_delta` to a tick used as a lower (`is_upper == false`) or
+    /// upper bound, initializing it or removing it as needed.
+    fn update_tick(&mut self, index: i32, liquidity_delta: i128, is_upper: bool) -> ProgramResult {
+        let position = match self.ticks.binary_search_by_key(&index, |tick| tick.index) {
+            Ok(position) => position,
+            Err(position) => {
+                if self.ticks.len() >= MAX_TICKS {
+                    return Err(LiquidityPoolError::TickCapacityExceeded.into());
+                }
+                // By convention all fee growth so far happened below an
+                // initialized tick at or under the current price.
+                let (outside_a, outside_b) = if index <= self.tick_current {
+                    (self.fee_growth_global_a_x64, self.fee_growth_global_b_x64)
+                } else {
+                    (0, 0)
+                };
+                self.ticks.insert(
+                    position,
+                    TickState {
+                        index,
+                        liquidity_net: 0,
+                        liquidity_gross: 0,
+                        fee_growth_outside_a_x64: outside_a,
+                        fee_growth_outside_b_x64: outside_b,
+                    },
+                );
+                position
+            }
+        };
+
+        let tick = &mut self.ticks[position];
+        tick.liquidity_gross = tick
+            .liquidity_gross
+            .checked_add_signed(liquidity_delta)
+            .ok_or(LiquidityPoolError::MathOverflow)?;
+        let net_delta = if is_upper { liquidity_delta.checked_neg() } else { Some(liquidity_delta) };
+        tick.liquidity_net = net_delta
+            .and_then(|delta| tick.liquidity_net.checked_add(delta))
+            .ok_or(LiquidityPoolError::MathOverflow)?;
+        if tick.liquidity_gross == 0 {
+            self.ticks.remove(position);
+        }
+        Ok(())
+    }
+
+    /// Next initialized tick in the swap direction: the greatest at or below
+    /// the current tick when selling A, the least above it when selling B.
+    fn next_initialized_tick(&self, a_to_b: bool) -> Option<i32> {
+        if a_to_b {
+            self.ticks.iter().rev().map(|tick| tick.index).find(|&index| index <= self.tick_current)
+        } else {
+            self.ticks.iter().map(|tick| tick.index).find(|&index| index > self.tick_current)
+        }
+    }
+
+    /// Moves the price across an initialized tick, updating in-range liquidity
+    /// and flipping the tick's fee growth to the other side.
+    fn cross_tick(&mut self, index: i32, a_to_b: bool) -> ProgramResult {
+        let (global_a, global_b) = (self.fee_growth_global_a_x64, self.fee_growth_global_b_x64);
+        let tick = self
+            .ticks
+            .iter_mut()
+            .find(|tick| tick.index == index)
+            .ok_or(LiquidityPoolError::InvalidTickRange)?;
+        tick.fee_growth_outside_a_x64 = global_a.wrapping_sub(tick.fee_growth_outside_a_x64);
+        tick.fee_growth_outside_b_x64 = global_b.wrapping_sub(tick.fee_growth_outside_b_x64);
+        let liquidity_net = if a_to_b { -tick.liquidity_net } else { tick.liquidity_net };
+        self.liquidity = self
+            .liquidity
+            .checked_add_signed(liquidity_net)
+            .ok_or(LiquidityPoolError::MathOverflow)?;
+        Ok(())
+    }
+}
+
+/// First bytes of every [`Position`] account: the first 8 bytes of
+/// `sha256("account:Position")`.
+pub const POSITION_DISCRIMINATOR: [u8; 8] = [170, 188, 143, 228, 122, 64, 247, 208];
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Default, PartialEq)]
+pub struct Position {
+    /// Always [`POSITION_DISCRIMINATOR`].
+    pub discriminator: [u8; 8],
+    pub is_initialized: u8,
+    pub pool: Pubkey,
+    pub owner: Pubkey,
+    pub tick_lower: i32,
+    pub tick_upper: i32,
+    pub liquidity: u128,
+    pub fee_growth_inside_a_last_x64: u128,
+    pub fee_growth_inside_b_last_x64: u128,
+    /// Principal from decreased liquidity plus fees, awaiting `CollectPosition`.
+    pub tokens_owed_a: u64,
+    pub tokens_owed_b: u64,
+}
+
+impl Position {
+    pub const LEN: usize = 8 + 1 + 32 + 32 + 4 + 4 + 16 + 16 + 16 + 8 + 8;
+}
+
+// ------------------ Initialize Concentrated Pool ------------------
+/// Accounts: creator, pool state, mint A, mint B, pool token A, pool token B,
+/// system program, token program, registry header, registry page, then any
+/// trailing token accounts. The vaults are created at their PDAs.
+pub(crate) fn process_initialize_concentrated_pool(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    tick_spacing: u16,
+    initial_sqrt_price_x64: u128,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let creator_account = next_account_info(account_info_iter)?; // 0
+    let pool_state_account = next_account_info(account_info_iter)?; // 1
+    let token_a_mint_account = next_account_info(account_info_iter)?; // 2
+    let token_b_mint_account = next_account_info(account_info_iter)?; // 3
+    let pool_token_a_account = next_account_info(account_info_iter)?; // 4
+    let pool_token_b_account = next_account_info(account_info_iter)?; // 5
+    let system_program_account = next_account_info(account_info_iter)?; // 6
+    let token_program_account = next_account_info(account_info_iter)?; // 7
+    let registry_header_account = next_account_info(account_info_iter)?; // 8
+    let registry_page_account = next_account_info(account_info_iter)?; // 9
+
+    if !creator_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if token_a_mint_account.key == token_b_mint_account.key {
+        return Err(LiquidityPoolError::SameTokenMints.into());
+    }
+    // One pool per pair: A/B and B/A would otherwise derive different PDAs.
+    if token_a_mint_account.key > token_b_mint_account.key {
+        return Err(LiquidityPoolError::NonCanonicalMintOrder.into());
+    }
+    if tick_spacing == 0 {
+        return Err(LiquidityPoolError::InvalidTickRange.into());
+    }
+    let tick_current = concentrated_math::tick_at_sqrt_price(initial_sqrt_price_x64)?;
+
+    let (pda, bump_seed) = Pubkey::find_program_address(
+        &[
+            b"concentrated_pool",
+            token_a_mint_account.key.as_ref(),
+            token_b_mint_account.key.as_ref(),
+        ],
+        program_id,
+    );
+    if pda != *pool_state_account.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let (vault_a_pda, vault_a_bump) = vault_address(program_id, &pda, token_a_mint_account.key);
+    let (vault_b_pda, vault_b_bump) = vault_address(program_id, &pda, token_b_mint_account.key);
+    if *pool_token_a_account.key != vault_a_pda || *pool_token_b_account.key != vault_b_pda {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    // The program creates the pool state, so one it already owns is either
+    // this pool or some other data that must not be overwritten
+    if pool_state_account.owner == program_id {
+        if pool_state_account.data.borrow().starts_with(&CONCENTRATED_POOL_STATE_DISCRIMINATOR) {
+            return Err(LiquidityPoolError::PoolAlreadyInitialized.into());
+        }
+        return Err(LiquidityPoolError::InvalidAccountType.into());
+    }
+    if *system_program_account.key != system_program::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let tokens = TokenAccounts::new(token_program_account, account_info_iter.as_slice())?;
+    validate_mint(token_a_mint_account)?;
+    validate_mint(token_b_mint_account)?;
+
+    create_account(
+        creator_account,
+        pool_state_account,
+        system_program_account,
+        program_id,
+        ConcentratedPoolState::LEN,
+        &[&[
+            b"concentrated_pool",
+            token_a_mint_account.key.as_ref(),
+            token_b_mint_account.key.as_ref(),
+            &[bump_seed],
+        ]],
+    )?;
+    for (vault_account, mint_account, vault_bump) in [
+        (pool_token_a_account, token_a_mint_account, vault_a_bump),
+        (pool_token_b_account, token_b_mint_account, vault_b_bump),
+    ] {
+        tokens.create_account(
+            creator_account,
+            vault_account,
+            mint_account,
+            &pda,
+            system_program_account,
+            &[&[b"vault", pda.as_ref(), mint_account.key.as_ref(), &[vault_bump]]],
+        )?;
+    }
+
+    let pool_state_data = ConcentratedPoolState {
+        discriminator: CONCENTRATED_POOL_STATE_DISCRIMINATOR,
+        is_initialized: 1,
+        bump_seed,
+        creator: *creator_account.key,
+        token_a_mint: *token_a_mint_account.key,
+        token_b_mint: *token_b_mint_account.key,
+        token_a_vault: vault_a_pda,
+        token_b_vault: vault_b_pda,
+        tick_spacing,
+        sqrt_price_x64: initial_sqrt_price_x64,
+        tick_current,
+        liquidity: 0,
+        fee_growth_global_a_x64: 0,
+        fee_growth_global_b_x64: 0,
+        reserve_a: 0,
+        reserve_b: 0,
+        reserve_a_cumulative: 0,
+        reserve_b_cumulative: 0,
+        last_update_ts: Clock::get()?.unix_timestamp,
+        ticks: Vec::new(),
+    };
+    pool_state_data.serialize(&mut &mut pool_state_account.data.borrow_mut()[..])?;
+
+    // Concentrated pools have no LP mint; positions stand in for LP tokens
+    registry::append_pool(
+        program_id,
+        creator_account,
+        registry_header_account,
+        registry_page_account,
+        system_program_account,
+        RegistryEntry {
+            token_a_mint: *token_a_mint_account.key,
+            token_b_mint: *token_b_mint_account.key,
+            pool: pda,
+            lp_mint: Pubkey::default(),
+            fee_bps: SWAP_FEE_BPS,
+            created_slot: Clock::get()?.slot,
+        },
+    )?;
+
+    msg!("Concentrated pool initialized at tick {}", tick_current);
+    Ok(())
+}
+
+// ------------------ Open Position ------------------
+/// Accounts: owner, pool state, position, system program. The position is a
+/// new keypair, signing so that it can be created with the owner paying its
+/// rent.
+pub(crate) fn process_open_position(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    tick_lower: i32,
+    tick_upper: i32,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let owner_account = next_account_info(account_info_iter)?; // 0
+    let pool_state_account = next_account_info(account_info_iter)?; // 1
+    let position_account = next_account_info(account_info_iter)?; // 2
+    let system_program_account = next_account_info(account_info_iter)?; // 3
+
+    if !owner_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if *system_program_account.key != system_program::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let pool_state_data = load_concentrated_pool(program_id, pool_state_account)?;
+    let spacing = pool_state_data.tick_spacing as i32;
+    if tick_lower >= tick_upper
+        || tick_lower < MIN_TICK
+        || tick_upper > MAX_TICK
+        || tick_lower % spacing != 0
+        || tick_upper % spacing != 0
+    {
+        return Err(LiquidityPoolError::InvalidTickRange.into());
+    }
+
+    if position_account.owner == program_id {
+        if position_account.data.borrow().starts_with(&POSITION_DISCRIMINATOR) {
+            return Err(LiquidityPoolError::PoolAlreadyInitialized.into());
+        }
+        return Err(LiquidityPoolError::InvalidAccountType.into());
+    }
+    create_account(owner_account, position_account, system_program_account, program_id, Position::LEN, &[])?;
+
+    let position = Position {
+        discriminator: POSITION_DISCRIMINATOR,
+        is_initialized: 1,
+        pool: *pool_state_account.key,
+        owner: *owner_account.key,
+        tick_lower,
+        tick_upper,
+        ..Position::default()
+    };
+    position.serialize(&mut &mut position_account.data.borrow_mut()[..])?;
+
+    msg!("Opened position over ticks [{}, {})", tick_lower, tick_upper);
+    Ok(())
+}
+
+// ------------------ Increase Liquidity ------------------
+/// Accounts: owner, pool state, position, pool token A, pool token B, owner
+/// token A, owner token B, token program.
+pub(crate) fn process_increase_liquidity(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    liquidity: u128,
+    max_amount_a: u64,
+    max_amount_b: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let owner_account = next_account_info(account_info_iter)?; // 0
+    let pool_state_account = next_account_info(account_info_iter)?; // 1
+    let position_account = next_account_info(account_info_iter)?; // 2
+    let pool_token_a_account = next_account_info(account_info_iter)?; // 3
+    let pool_token_b_account = next_account_info(account_info_iter)?; // 4
+    let owner_token_a_account = next_account_info(account_info_iter)?; // 5
+    let owner_token_b_account = next_account_info(account_info_iter)?; // 6
+    let token_program_account = next_account_info(account_info_iter)?; // 7
+
+    let mut pool_state_data = load_concentrated_pool(program_id, pool_state_account)?;
+    let mut position = load_position(program_id, owner_account, pool_state_account, position_account)?;
+    let tokens = TokenAccounts::new(token_program_account, &[])?;
+    validate_concentrated_vaults(&pool_state_data, pool_state_account.key, pool_token_a_account, pool_token_b_account)?;
+
+    let liquidity_delta = i128::try_from(liquidity).map_err(|_| LiquidityPoolError::MathOverflow)?;
+    if liquidity_delta == 0 {
+        return Err(LiquidityPoolError::ZeroAmountNotAllowed.into());
+    }
+
+    let (amount_a, amount_b) = modify_position(&mut pool_state_data, &mut position, liquidity_delta)?;
+    if amount_a > max_amount_a || amount_b > max_amount_b {
+        return Err(LiquidityPoolError::SlippageExceeded.into());
+    }
+    pool_state_data.update_reserve_accumulators(Clock::get()?.unix_timestamp);
+    pool_state_data.reserve_a = crate::checked_add(pool_state_data.reserve_a, amount_a)?;
+    pool_state_data.reserve_b = crate::checked_add(pool_state_data.reserve_b, amount_b)?;
+
+    for (source_account, vault_account, amount) in [
+        (owner_token_a_account, pool_token_a_account, amount_a),
+        (owner_token_b_account, pool_token_b_account, amount_b),
+    ] {
+        if amount == 0 {
+            continue;
+        }
+        tokens.transfer(source_account, vault_account, owner_account, amount, &[])?;
+    }
+
+    pool_state_data.serialize(&mut &mut pool_state_account.data.borrow_mut()[..])?;
+    position.serialize(&mut &mut position_account.data.borrow_mut()[..])?;
+
+    msg!("Added {} liquidity for {} Token A, {} Token B.", liquidity, amount_a, amount_b);
+    Ok(())
+}
+
+// ------------------ Decrease Liquidity ------------------
+/// Accounts: owner, pool state, position. The released tokens are owed to the
+/// position until collected.
+pub(crate) fn process_decrease_liquidity(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    liquidity: u128,
+    min_amount_a: u64,
+    min_amount_b: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let owner_account = next_account_info(account_info_iter)?; // 0
+    let pool_state_account = next_account_info(account_info_iter)?; // 1
+    let position_account = next_account_info(account_info_iter)?; // 2
+
+    let mut pool_state_data = load_concentrated_pool(program_id, pool_state_account)?;
+    let mut position = load_position(program_id, owner_account, pool_state_account, position_account)?;
+
+    if liquidity == 0 {
+        return Err(LiquidityPoolError::ZeroAmountNotAllowed.into());
+    }
+    if liquidity > position.liquidity {
+        return Err(LiquidityPoolError::InsufficientFunds.into());
+    }
+
+    let liquidity_delta = -i128::try_from(liquidity).map_err(|_| LiquidityPoolError::MathOverflow)?;
+    let (amount_a, amount_b) = modify_position(&mut pool_state_data, &mut position, liquidity_delta)?;
+    if amount_a < min_amount_a || amount_b < min_amount_b {
+        return Err(LiquidityPoolError::SlippageExceeded.into());
+    }
+    position.tokens_owed_a = crate::checked_add(position.tokens_owed_a, amount_a)?;
+    position.tokens_owed_b = crate::checked_add(position.tokens_owed_b, amount_b)?;
+
+    pool_state_data.serialize(&mut &mut pool_state_account.data.borrow_mut()[..])?;
+    position.serialize(&mut &mut position_account.data.borrow_mut()[..])?;
+
+    msg!("Removed {} liquidity for {} Token A, {} Token B.", liquidity, amount_a, amount_b);
+    Ok(())
+}
+
+// ------------------ Collect Position ------------------
+/// Accounts: as for `IncreaseLiquidity`. Pays out everything owed to the
+/// position, including fees earned so far.
+pub(crate) fn process_collect_position(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let owner_account = next_account_info(account_info_iter)?; // 0
+    let pool_state_account = next_account_info(account_info_iter)?; // 1
+    let position_account = next_account_info(account_info_iter)?; // 2
+    let pool_token_a_account = next_account_info(account_info_iter)?; // 3
+    let pool_token_b_account = next_account_info(account_info_iter)?; // 4
+    let owner_token_a_account = next_account_info(account_info_iter)?; // 5
+    let owner_token_b_account = next_account_info(account_info_iter)?; // 6
+    let token_program_account = next_account_info(account_info_iter)?; // 7
+
+    let mut pool_state_data = load_concentrated_pool(program_id, pool_state_account)?;
+    let mut position = load_position(program_id, owner_account, pool_state_account, position_account)?;
+    validate_token_program(token_program_account)?;
+    validate_concentrated_vaults(&pool_state_data, pool_state_account.key, pool_token_a_account, pool_token_b_account)?;
+
+    if position.liquidity > 0 {
+        accrue_fees(&pool_state_data, &mut position)?;
+    }
+    let (amount_a, amount_b) = (position.tokens_owed_a, position.tokens_owed_b);
+    position.tokens_owed_a = 0;
+    position.tokens_owed_b = 0;
+    pool_state_data.update_reserve_accumulators(Clock::get()?.unix_timestamp);
+    pool_state_data.reserve_a = crate::checked_sub(pool_state_data.reserve_a, amount_a)?;
+    pool_state_data.reserve_b = crate::checked_sub(pool_state_data.reserve_b, amount_b)?;
+
+    for (vault_account, destination_account, amount) in [
+        (pool_token_a_account, owner_token_a_account, amount_a),
+        (pool_token_b_account, owner_token_b_account, amount_b),
+    ] {
+        if amount == 0 {
+            continue;
+        }
+        transfer_out(&pool_state_data, token_program_account, vault_account, destination_account, pool_state_account, amount)?;
+    }
+
+    pool_state_data.serialize(&mut &mut pool_state_account.data.borrow_mut()[..])?;
+    position.serialize(&mut &mut position_account.data.borrow_mut()[..])?;
+
+    msg!("Collected {} Token A, {} Token B.", amount_a, amount_b);
+    Ok(())
+}
+
+// ------------------ Concentrated Swap ------------------
+/// Accounts: swapper, pool state, pool token A, pool token B, swapper token A,
+/// swapper token B, token program.
+pub(crate) fn process_concentrated_swap(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount_in: u64,
+    min_out: u64,
+    a_to_b: bool,
+    sqrt_price_limit_x64: u128,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let swapper_account = next_account_info(account_info_iter)?; // 0
+    let pool_state_account = next_account_info(account_info_iter)?; // 1
+    let pool_token_a_account = next_account_info(account_info_iter)?; // 2
+    let pool_token_b_account = next_account_info(account_info_iter)?; // 3
+    let swapper_token_a_account = next_account_info(account_info_iter)?; // 4
+    let swapper_token_b_account = next_account_info(account_info_iter)?; // 5
+    let token_program_account = next_account_info(account_info_iter)?; // 6
+
+    if !swapper_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut pool_state_data = load_concentrated_pool(program_id, pool_state_account)?;
+    let tokens = TokenAccounts::new(token_program_account, &[])?;
+    validate_concentrated_vaults(&pool_state_data, pool_state_account.key, pool_token_a_account, pool_token_b_account)?;
+
+    if amount_in == 0 {
+        return Err(LiquidityPoolError::ZeroAmountNotAllowed.into());
+    }
+
+    let (amount_in, amount_out) = swap(&mut pool_state_data, amount_in, a_to_b, sqrt_price_limit_x64)?;
+    if amount_out == 0 {
+        return Err(LiquidityPoolError::InvalidAmount.into());
+    }
+    if amount_out < min_out {
+        return Err(LiquidityPoolError::SlippageExceeded.into());
+    }
+    // The fee stays in the vault, credited to the positions in range
+    pool_state_data.update_reserve_accumulators(Clock::get()?.unix_timestamp);
+    if a_to_b {
+        pool_state_data.reserve_a = crate::checked_add(pool_state_data.reserve_a, amount_in)?;
+        pool_state_data.reserve_b = crate::checked_sub(pool_state_data.reserve_b, amount_out)?;
+    } else {
+        pool_state_data.reserve_b = crate::checked_add(pool_state_data.reserve_b, amount_in)?;
+        pool_state_data.reserve_a = crate::checked_sub(pool_state_data.reserve_a, amount_out)?;
+    }
+
+    let (pool_in_account, pool_out_account, swapper_in_account, swapper_out_account) = if a_to_b {
+        (pool_token_a_account, pool_token_b_account, swapper_token_a_account, swapper_token_b_account)
+    } else {
+        (pool_token_b_account, pool_token_a_account, swapper_token_b_account, swapper_token_a_account)
+    };
+
+    tokens.transfer(swapper_in_account, pool_in_account, swapper_account, amount_in, &[])?;
+    transfer_out(&pool_state_data, token_program_account, pool_out_account, swapper_out_account, pool_state_account, amount_out)?;
+
+    pool_state_data.serialize(&mut &mut pool_state_account.data.borrow_mut()[..])?;
+
+    msg!("Swapped {} in for {} out, now at tick {}.", amount_in, amount_out, pool_state_data.tick_current);
+    Ok(())
+}
+
+/// Runs an exact-in swap against the pool's ticks, stopping early if the price
+/// reaches `sqrt_price_limit_x64`. Returns the input actually used and the
+/// output.
+pub fn swap(
+    pool_state_data: &mut ConcentratedPoolState,
+    amount_in: u64,
+    a_to_b: bool,
+    sqrt_price_limit_x64: u128,
+) -> Result<(u64, u64), ProgramError> {
+    let price_limit_valid = if a_to_b {
+        sqrt_price_limit_x64 < pool_state_data.sqrt_price_x64
+            && sqrt_price_limit_x64 >= concentrated_math::sqrt_price_at_tick(MIN_TICK)?
+    } else {
+        sqrt_price_limit_x64 > pool_state_data.sqrt_price_x64
+            && sqrt_price_limit_x64 <= concentrated_math::sqrt_price_at_tick(MAX_TICK)?
+    };
+    if !price_limit_valid {
+        return Err(LiquidityPoolError::SlippageExceeded.into());
+    }
+
+    let mut amount_remaining = amount_in;
+    let mut amount_out: u64 = 0;
+    while amount_remaining > 0 && pool_state_data.sqrt_price_x64 != sqrt_price_limit_x64 {
+        let next_tick = pool_state_data.next_initialized_tick(a_to_b);
+        let sqrt_price_next_tick = concentrated_math::sqrt_price_at_tick(next_tick.unwrap_or(if a_to_b { MIN_TICK } else { MAX_TICK }))?;
+        let sqrt_price_target = if a_to_b {
+            sqrt_price_next_tick.max(sqrt_price_limit_x64)
+        } else {
+            sqrt_price_next_tick.min(sqrt_price_limit_x64)
+        };
+
+        let step = concentrated_math::compute_swap_step(
+            pool_state_data.sqrt_price_x64,
+            sqrt_price_target,
+            pool_state_data.liquidity,
+            amount_remaining,
+            SWAP_FEE_BPS,
+        )?;
+        amount_remaining -= step.amount_in + step.fee_amount;
+        amount_out = crate::checked_add(amount_out, step.amount_out)?;
+
+        let fee_growth = concentrated_math::fee_growth(step.fee_amount, pool_state_data.liquidity);
+        if a_to_b {
+            pool_state_data.fee_growth_global_a_x64 = pool_state_data.fee_growth_global_a_x64.wrapping_add(fee_growth);
+        } else {
+            pool_state_data.fee_growth_global_b_x64 = pool_state_data.fee_growth_global_b_x64.wrapping_add(fee_growth);
+        }
+
+        pool_state_data.sqrt_price_x64 = step.sqrt_price_next;
+        match next_tick {
+            Some(index) if step.sqrt_price_next == sqrt_price_next_tick => {
+                pool_state_data.cross_tick(index, a_to_b)?;
+                pool_state_data.tick_current = if a_to_b { index - 1 } else { index };
+            }
+            None if step.sqrt_price_next == sqrt_price_next_tick => {
+                // The price hit the end of the tick range with input left over.
+                break;
+            }
+            _ => {
+                pool_state_data.tick_current = concentrated_math::tick_at_sqrt_price(step.sqrt_price_next)?;
+            }
+        }
+    }
+
+    Ok((amount_in - amount_remaining, amount_out))
+}
+
+/// Credits the fees earned since the position was last touched.
+fn accrue_fees(pool_state_data: &ConcentratedPoolState, position: &mut Position) -> ProgramResult {
+    let (inside_a, inside_b) = pool_state_data.fee_growth_inside(position.tick_lower, position.tick_upper)?;
+    let fees_a = concentrated_math::fees_owed(inside_a.wrapping_sub(position.fee_growth_inside_a_last_x64), position.liquidity)?;
+    let fees_b = concentrated_math::fees_owed(inside_b.wrapping_sub(position.fee_growth_inside_b_last_x64), position.liquidity)?;
+    position.tokens_owed_a = crate::checked_add(position.tokens_owed_a, fees_a)?;
+    position.tokens_owed_b = crate::checked_add(position.tokens_owed_b, fees_b)?;
+    position.fee_growth_inside_a_last_x64 = inside_a;
+    position.fee_growth_inside_b_last_x64 = inside_b;
+    Ok(())
+}
+
+/// Adds `liquidity_delta` to a position and its ticks, returning the token
+/// amounts to deposit (rounded up) or withdraw (rounded down).
+fn modify_position(
+    pool_state_data: &mut ConcentratedPoolState,
+    position: &mut Position,
+    liquidity_delta: i128,
+) -> Result<(u64, u64), ProgramError> {
+    let (tick_lower, tick_upper) = (position.tick_lower, position.tick_upper);
+
+    // Fee growth is read while both ticks exist: before removing liquidity,
+    // which may clear them, and after adding it to an empty position, which
+    // starts from the current inside growth and earns nothing retroactively.
+    let was_empty = position.liquidity == 0;
+    if !was_empty {
+        accrue_fees(pool_state_data, position)?;
+    }
+    pool_state_data.update_tick(tick_lower, liquidity_delta, false)?;
+    pool_state_data.update_tick(tick_upper, liquidity_delta, true)?;
+    if was_empty {
+        let (inside_a, inside_b) = pool_state_data.fee_growth_inside(tick_lower, tick_upper)?;
+        position.fee_growth_inside_a_last_x64 = inside_a;
+        position.fee_growth_inside_b_last_x64 = inside_b;
+    }
+
+    position.liquidity = position
+        .liquidity
+        .checked_add_signed(liquidity_delta)
+        .ok_or(LiquidityPoolError::MathOverflow)?;
+    if position.liquidity != 0 && position.liquidity < MIN_TICK_LIQUIDITY {
+        return Err(LiquidityPoolError::LiquidityBelowMinimum.into());
+    }
+
+    let in_range = tick_lower <= pool_state_data.tick_current && pool_state_data.tick_current < tick_upper;
+    if in_range {
+        pool_state_data.liquidity = pool_state_data
+            .liquidity
+            .checked_add_signed(liquidity_delta)
+            .ok_or(LiquidityPoolError::MathOverflow)?;
+    }
+
+    let amounts = concentrated_math::amounts_for_liquidity(
+        pool_state_data.sqrt_price_x64,
+        concentrated_math::sqrt_price_at_tick(tick_lower)?,
+        concentrated_math::sqrt_price_at_tick(tick_upper)?,
+        liquidity_delta.unsigned_abs(),
+        liquidity_delta > 0,
+    )?;
+    Ok(amounts)
+}
+
+// ------------------ Account Validation ------------------
+/// Loads a concentrated pool's state, checking that the account is owned by
+/// this program, holds a concentrated pool, is initialized, and is the
+/// canonical PDA for its mint pair.
+pub fn load_concentrated_pool(program_id: &Pubkey, pool_state_account: &AccountInfo) -> Result<ConcentratedPoolState, ProgramError> {
+    if pool_state_account.owner != program_id {
+        return Err(LiquidityPoolError::InvalidOwner.into());
+    }
+    if !pool_state_account.data.borrow().starts_with(&CONCENTRATED_POOL_STATE_DISCRIMINATOR) {
+        return Err(LiquidityPoolError::InvalidAccountType.into());
+    }
+
+    let pool_state_data = ConcentratedPoolState::deserialize(&mut &pool_state_account.data.borrow()[..])?;
+    if pool_state_data.is_initialized == 0 {
+        return Err(LiquidityPoolError::PoolNotInitialized.into());
+    }
+
+    let pda = Pubkey::create_program_address(
+        &[
+            b"concentrated_pool",
+            pool_state_data.token_a_mint.as_ref(),
+            pool_state_data.token_b_mint.as_ref(),
+            &[pool_state_data.bump_seed],
+        ],
+        program_id,
+    )
+    .map_err(|_| ProgramError::InvalidSeeds)?;
+    if pda != *pool_state_account.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    Ok(pool_state_data)
+}
+
+/// Loads a position, checking that the account holds one, that it belongs to
+/// the pool and that its owner signed.
+pub fn load_position(
+    program_id: &Pubkey,
+    owner_account: &AccountInfo,
+    pool_state_account: &AccountInfo,
+    position_account: &AccountInfo,
+) -> Result<Position, ProgramError> {
+    if !owner_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if position_account.owner != program_id {
+        return Err(LiquidityPoolError::InvalidOwner.into());
+    }
+    if !position_account.data.borrow().starts_with(&POSITION_DISCRIMINATOR) {
+        return Err(LiquidityPoolError::InvalidAccountType.into());
+    }
+
+    let position = Position::deserialize(&mut &position_account.data.borrow()[..])?;
+    if position.is_initialized == 0 {
+        return Err(LiquidityPoolError::PoolNotInitialized.into());
+    }
+    if position.pool != *pool_state_account.key {
+        return Err(LiquidityPoolError::InvalidPdaAccount.into());
+    }
+    if position.owner != *owner_account.key {
+        return Err(LiquidityPoolError::Unauthorized.into());
+    }
+
+    Ok(position)
+}
+
+/// Checks that the accounts are the vaults recorded in the pool state.
+fn validate_concentrated_vaults(
+    pool_state_data: &ConcentratedPoolState,
+    pool: &Pubkey,
+    pool_token_a_account: &AccountInfo,
+    pool_token_b_account: &AccountInfo,
+) -> ProgramResult {
+    if *pool_token_a_account.key != pool_state_data.token_a_vault || *pool_token_b_account.key != pool_state_data.token_b_vault {
+        return Err(LiquidityPoolError::InvalidTokenAccount.into());
+    }
+    validate_pool_vault(pool_token_a_account, pool, &pool_state_data.token_a_mint)?;
+    validate_pool_vault(pool_token_b_account, pool, &pool_state_data.token_b_mint)?;
+    Ok(())
+}
+
+// ------------------ Token Movements ------------------
+fn transfer_out<'a>(
+    pool_state_data: &ConcentratedPoolState,
+    token_program_account: &AccountInfo<'a>,
+    vault_account: &AccountInfo<'a>,
+    destination_account: &AccountInfo<'a>,
+    pool_state_account: &AccountInfo<'a>,
+    amount: u64,
+) -> ProgramResult {
+    TokenAccounts::new(token_program_account, &[])?.transfer(
+        vault_account,
+        destination_account,
+        pool_state_account, // PDA is the authority
+        amount,
+        &[&[
+            b"concentrated_pool",
+            pool_state_data.token_a_mint.as_ref(),
+            pool_state_data.token_b_mint.as_ref(),
+            &[pool_state_data.bump_seed],
+        ]],
+    )?;
+    Ok(())
+}
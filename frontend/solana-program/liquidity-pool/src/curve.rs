@@ -0,0 +1,164 @@
+//! Constant-product AMM math.
+//!
+//! Everything here is a pure function over plain integers so it can be used
+//! both on-chain and off-chain for quoting. Every rounding decision favours the
+//! pool: LP tokens and swap outputs round down, required inputs round up.
+
+use thiserror::Error;
+
+/// Denominator for fees expressed in basis points.
+pub const BPS_DENOMINATOR: u64 = 10_000;
+
+#[derive(Error, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CurveError {
+    #[error("Math overflow")]
+    MathOverflow,
+    #[error("Pool has zero reserves")]
+    ZeroReserves,
+    #[error("Insufficient liquidity for the requested amount")]
+    InsufficientLiquidity,
+    #[error("Invalid fee")]
+    InvalidFee,
+    #[error("Invalid amplification coefficient")]
+    InvalidAmplification,
+    #[error("Invariant solver did not converge")]
+    NotConverged,
+    #[error("Tick out of range")]
+    InvalidTick,
+}
+
+/// Integer square root (floor) using Newton's method.
+pub fn integer_sqrt(value: u128) -> u128 {
+    if value < 2 {
+        return value;
+    }
+    let mut x = value;
+    let mut y = x / 2 + x % 2;
+    while y < x {
+        x = y;
+        y = (x + value / x) / 2;
+    }
+    x
+}
+
+fn to_u64(value: u128) -> Result<u64, CurveError> {
+    u64::try_from(value).map_err(|_| CurveError::MathOverflow)
+}
+
+/// LP supply for a fresh pool: the geometric mean of the deposits, which does
+/// not depend on either mint's decimals.
+pub fn initial_lp_supply(amount_a: u64, amount_b: u64) -> Result<u64, CurveError> {
+    to_u64(integer_sqrt(amount_a as u128 * amount_b as u128))
+}
+
+/// Amounts actually deposited for a deposit of at most `max_amount_a` and
+/// `max_amount_b`, keeping the current pool ratio. The side in excess is reduced
+/// and the other is rounded up.
+pub fn deposit_amounts(
+    max_amount_a: u64,
+    max_amount_b: u64,
+    reserve_a: u64,
+    reserve_b: u64,
+) -> Result<(u64, u64), CurveError> {
+    if reserve_a == 0 || reserve_b == 0 {
+        return Err(CurveError::ZeroReserves);
+    }
+
+    let optimal_b = (max_amount_a as u128 * reserve_b as u128).div_ceil(reserve_a as u128);
+    if optimal_b <= max_amount_b as u128 {
+        Ok((max_amount_a, to_u64(optimal_b)?))
+    } else {
+        let optimal_a = (max_amount_b as u128 * reserve_a as u128).div_ceil(reserve_b as u128);
+        Ok((to_u64(optimal_a)?, max_amount_b))
+    }
+}
+
+/// LP tokens minted for depositing `amount_a` and `amount_b`: the smaller of the
+/// two proportional shares, rounded down.
+pub fn lp_tokens_for_deposit(
+    amount_a: u64,
+    amount_b: u64,
+    reserve_a: u64,
+    reserve_b: u64,
+    lp_supply: u64,
+) -> Result<u64, CurveError> {
+    if reserve_a == 0 || reserve_b == 0 {
+        return Err(CurveError::ZeroReserves);
+    }
+
+    let share_a = amount_a as u128 * lp_supply as u128 / reserve_a as u128;
+    let share_b = amount_b as u128 * lp_supply as u128 / reserve_b as u128;
+    to_u64(share_a.min(share_b))
+}
+
+/// Tokens returned for burning `lp_amount`, each rounded down.
+pub fn withdraw_amounts(
+    lp_amount: u64,
+    reserve_a: u64,
+    reserve_b: u64,
+    lp_supply: u64,
+) -> Result<(u64, u64), CurveError> {
+    if lp_supply == 0 || lp_amount > lp_supply {
+        return Err(CurveError::InsufficientLiquidity);
+    }
+
+    let amount_a = lp_amount as u128 * reserve_a as u128 / lp_supply as u128;
+    let amount_b = lp_amount as u128 * reserve_b as u128 / lp_supply as u128;
+    Ok((to_u64(amount_a)?, to_u64(amount_b)?))
+}
+
+/// Output for selling exactly `amount_in`, after a `fee_bps` fee that stays in
+/// the pool.
+pub fn swap_exact_in(
+    amount_in: u64,
+    reserve_in: u64,
+    reserve_out: u64,
+    fee_bps: u64,
+) -> Result<u64, CurveError> {
+    if fee_bps >= BPS_DENOMINATOR {
+        return Err(CurveError::InvalidFee);
+    }
+    if reserve_in == 0 || reserve_out == 0 {
+        return Err(CurveError::ZeroReserves);
+    }
+
+    let amount_in_after_fee = amount_in as u128 * (BPS_DENOMINATOR - fee_bps) as u128;
+    let numerator = (reserve_out as u128)
+        .checked_mul(amount_in_after_fee)
+        .ok_or(CurveError::MathOverflow)?;
+    let denominator = reserve_in as u128 * BPS_DENOMINATOR as u128 + amount_in_after_fee;
+    to_u64(numerator / denominator)
+}
+
+/// Input required to buy exactly `amount_out`, including a `fee_bps` fee. The
+/// inverse of [`swap_exact_in`], rounded up.
+pub fn swap_exact_out(
+    amount_out: u64,
+    reserve_in: u64,
+    reserve_out: u64,
+    fee_bps: u64,
+) -> Result<u64, CurveError> {
+    if fee_bps >= BPS_DENOMINATOR {
+        return Err(CurveError::InvalidFee);
+    }
+    if reserve_in == 0 || reserve_out == 0 {
+        return Err(CurveError::ZeroReserves);
+    }
+    if amount_out >= reserve_out {
+        return Err(CurveError::InsufficientLiquidity);
+    }
+
+    let numerator = (reserve_in as u128 * amount_out as u128)
+        .checked_mul(BPS_DENOMINATOR as u128)
+        .ok_or(CurveError::MathOverflow)?;
+    let denominator = (reserve_out - amount_out) as u128 * (BPS_DENOMINATOR - fee_bps) as u128;
+    to_u64(numerator.div_ceil(denominator))
+}
+
+/// Fee owed on a flash loan of `amount`, rounded up.
+pub fn flash_loan_fee(amount: u64, fee_bps: u64) -> Result<u64, CurveError> {
+    if fee_bps >= BPS_DENOMINATOR {
+        return Err(CurveError::InvalidFee);
+    }
+    to_u64((amount as u128 * fee_bps as u128).div_ceil(BPS_DENOMINATOR as u128))
+}
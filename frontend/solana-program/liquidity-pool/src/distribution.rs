@@ -0,0 +1,783 @@
+//! Pro-rata distribution of a property's income to holders of its token.
+//!
+//! A property manager creates a distribution at
+//! `[b"distribution", property_mint, manager]` paying out in a quote token, and
+//! deposits the property's income into it. Holders lock their property tokens
+//! into the distribution to earn a share of every later deposit, tracked by a
+//! cumulative income-per-token index: a holding checkpoints the index whenever
+//! it changes, and earns its amount times the growth since. Because locked
+//! tokens cannot move, and a new holding starts from the current index, the
+//! same tokens cannot claim the same deposit twice by changing hands.
+//!
+//! Property tokens held in a registered liquidity pool earn on behalf of its
+//! LPs. The manager passes every such pool with every deposit, which first sets
+//! the pool's holding to its average reserve of the property token since the
+//! previous deposit, read from the pool's reserve accumulators, so a swap just
+//! before a deposit moves the pool's share only for the seconds it lasted. A
+//! pool has no average at its first sync and earns from the deposit after.
+//! Anyone can then pay the pool's income to its LPs with `ClaimPoolIncome`:
+//!
+//! - a constant-product pool of the property and quote tokens adds it to its
+//!   reserve of the quote token, where every LP, staked or not, owns a share of
+//!   it in proportion to their LP tokens and collects it on removing liquidity;
+//! - a constant-product pool against any other token adds it to the rewards of
+//!   its farm, which must reward in the quote token, for its stakers; until the
+//!   pool has such a farm, the income waits in its holding;
+//! - a concentrated pool of the property and quote tokens credits it to the
+//!   positions in range as swap fees in the quote token.
+//!
+//! Concentrated pools against another token are left out: their positions can
+//! only be paid in the pool's own tokens.
+//!
+//! A closed pool drops out of the registry and so out of the deposits. Its
+//! holding stays behind, but no longer counts towards the eligible total, and
+//! if the pool is reopened at the same address, which its registry entry tells
+//! apart by its creation slot, the holding starts over: the income it still
+//! owed the old LPs goes back to the distribution.
+//!
+//! Holdings live at `[b"holding", distribution, owner]`, where the owner is the
+//! holder or the pool. The program creates the distribution in
+//! `InitializeDistribution`, the manager paying its rent, and each holding on
+//! first use, the holder or, for a pool, the manager paying.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_program,
+    sysvar::{clock::Clock, Sysvar},
+};
+
+use crate::{
+    checked_add, checked_sub,
+    concentrated_math,
+    concentrated_pool::{self, CONCENTRATED_POOL_STATE_DISCRIMINATOR},
+    create_account,
+    curve::CurveError,
+    farm, load_pool_state,
+    registry::{self, RegistryEntry},
+    token::TokenAccounts,
+    validate_mint, validate_pool_vault, validate_token_program, LiquidityPoolError, PoolState,
+};
+
+// ------------------ Distribution Structs ------------------
+/// First bytes of every [`Distribution`] account: the first 8 bytes of
+/// `sha256("account:Distribution")`.
+pub const DISTRIBUTION_DISCRIMINATOR: [u8; 8] = [176, 85, 17, 11, 13, 194, 18, 1];
+
+/// First bytes of every [`Holding`] account: the first 8 bytes of
+/// `sha256("account:Holding")`.
+pub const HOLDING_DISCRIMINATOR: [u8; 8] = [23, 96, 64, 250, 235, 191, 0, 144];
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Default, PartialEq)]
+pub struct Distribution {
+    /// Always [`DISTRIBUTION_DISCRIMINATOR`].
+    pub discriminator: [u8; 8],
+    pub is_initialized: u8,
+    pub bump_seed: u8,
+    pub manager: Pubkey,
+    pub property_mint: Pubkey,
+    pub quote_mint: Pubkey,
+    /// Holds the locked property tokens; owned by the distribution PDA.
+    pub property_vault: Pubkey,
+    /// Holds deposited income until claimed; owned by the distribution PDA.
+    pub quote_vault: Pubkey,
+    /// Income deposited per eligible property token so far, in Q64.64. Allowed
+    /// to wrap.
+    pub income_per_token_x64: u128,
+    /// Property tokens earning income: locked by holders, and held by pools on
+    /// average over the time before the last deposit.
+    pub total_eligible: u64,
+    /// Property tokens locked by holders.
+    pub total_locked: u64,
+    /// Income deposited while nothing was eligible, paid out with the next deposit.
+    pub unallocated_income: u64,
+}
+
+impl Distribution {
+    pub const LEN: usize = 8 + 1 + 1 + 32 * 5 + 16 + 8 + 8 + 8;
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Default, PartialEq)]
+pub struct Holding {
+    /// Always [`HOLDING_DISCRIMINATOR`].
+    pub discriminator: [u8; 8],
+    pub is_initialized: u8,
+    pub distribution: Pubkey,
+    /// The holder, or the pool for a pool's holding.
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub income_per_token_last_x64: u128,
+    /// Income earned but not yet claimed.
+    pub income_owed: u64,
+    /// For a pool's holding: the pool's reserve accumulator of the property
+    /// token and the time when it was last synced, and the slot the pool was
+    /// created in.
+    pub reserve_cumulative_last: u128,
+    pub last_sync_ts: i64,
+    pub pool_created_slot: u64,
+}
+
+impl Holding {
+    pub const LEN: usize = 8 + 1 + 32 + 32 + 8 + 16 + 8 + 16 + 8 + 8;
+
+    /// Credits the income earned since the holding was last touched.
+    pub fn settle(&mut self, distribution: &Distribution) -> Result<(), CurveError> {
+        let delta = distribution.income_per_token_x64.wrapping_sub(self.income_per_token_last_x64);
+        let earned = farm::rewards_earned(delta, self.amount)?;
+        self.income_owed = self.income_owed.checked_add(earned).ok_or(CurveError::MathOverflow)?;
+        self.income_per_token_last_x64 = distribution.income_per_token_x64;
+        Ok(())
+    }
+}
+
+pub fn distribution_address(program_id: &Pubkey, property_mint: &Pubkey, manager: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"distribution", property_mint.as_ref(), manager.as_ref()], program_id)
+}
+
+pub fn holding_address(program_id: &Pubkey, distribution: &Pubkey, owner: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"holding", distribution.as_ref(), owner.as_ref()], program_id)
+}
+
+// ------------------ Initialize Distribution ------------------
+/// Accounts: manager, distribution, property mint, quote mint, property vault,
+/// quote vault, system program.
+pub(crate) fn process_initialize_distribution(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let manager_account = next_account_info(account_info_iter)?; // 0
+    let distribution_account = next_account_info(account_info_iter)?; // 1
+    let property_mint_account = next_account_info(account_info_iter)?; // 2
+    let quote_mint_account = next_account_info(account_info_iter)?; // 3
+    let property_vault_account = next_account_info(account_info_iter)?; // 4
+    let quote_vault_account = next_account_info(account_info_iter)?; // 5
+    let system_program_account = next_account_info(account_info_iter)?; // 6
+
+    if !manager_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if *system_program_account.key != system_program::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if property_mint_account.key == quote_mint_account.key {
+        return Err(LiquidityPoolError::SameTokenMints.into());
+    }
+
+    let (pda, bump_seed) = distribution_address(program_id, property_mint_account.key, manager_account.key);
+    if pda != *distribution_account.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if distribution_account.owner == program_id {
+        if distribution_account.data.borrow().starts_with(&DISTRIBUTION_DISCRIMINATOR) {
+            return Err(LiquidityPoolError::PoolAlreadyInitialized.into());
+        }
+        return Err(LiquidityPoolError::InvalidAccountType.into());
+    }
+    validate_mint(property_mint_account)?;
+    validate_mint(quote_mint_account)?;
+    validate_pool_vault(property_vault_account, &pda, property_mint_account.key)?;
+    validate_pool_vault(quote_vault_account, &pda, quote_mint_account.key)?;
+
+    create_account(
+        manager_account,
+        distribution_account,
+        system_program_account,
+        program_id,
+        Distribution::LEN,
+        &[&[b"distribution", property_mint_account.key.as_ref(), manager_account.key.as_ref(), &[bump_seed]]],
+    )?;
+
+    let distribution = Distribution {
+        discriminator: DISTRIBUTION_DISCRIMINATOR,
+        is_initialized: 1,
+        bump_seed,
+        manager: *manager_account.key,
+        property_mint: *property_mint_account.key,
+        quote_mint: *quote_mint_account.key,
+        property_vault: *property_vault_account.key,
+        quote_vault: *quote_vault_account.key,
+        income_per_token_x64: 0,
+        total_eligible: 0,
+        total_locked: 0,
+        unallocated_income: 0,
+    };
+    distribution.serialize(&mut &mut distribution_account.data.borrow_mut()[..])?;
+
+    msg!("Distribution initialized for property: {}", property_mint_account.key);
+    Ok(())
+}
+
+// ------------------ Deposit Income ------------------
+/// Accounts: manager, distribution, manager quote token, quote vault, token
+/// program, system program, registry header, every registry page in order,
+/// then a pool state and its pool holding for each registered pool holding the
+/// property token, but concentrated pools only against the quote token, in
+/// registry order. Pool holdings are created on first use.
+pub(crate) fn process_deposit_income(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let manager_account = next_account_info(account_info_iter)?; // 0
+    let distribution_account = next_account_info(account_info_iter)?; // 1
+    let manager_quote_account = next_account_info(account_info_iter)?; // 2
+    let quote_vault_account = next_account_info(account_info_iter)?; // 3
+    let token_program_account = next_account_info(account_info_iter)?; // 4
+    let system_program_account = next_account_info(account_info_iter)?; // 5
+    let registry_header_account = next_account_info(account_info_iter)?; // 6
+
+    if !manager_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    let mut distribution = load_distribution(program_id, distribution_account)?;
+    if distribution.manager != *manager_account.key {
+        return Err(LiquidityPoolError::Unauthorized.into());
+    }
+    validate_token_program(token_program_account)?;
+    validate_distribution_vault(distribution_account.key, quote_vault_account, &distribution.quote_vault, &distribution.quote_mint)?;
+
+    if amount == 0 {
+        return Err(LiquidityPoolError::ZeroAmountNotAllowed.into());
+    }
+
+    // Every pool of the property token must be synced, or the manager could
+    // leave out pools to shift their share of the income to other holders
+    let pools: Vec<RegistryEntry> = registry::load_entries(program_id, registry_header_account, account_info_iter)?
+        .into_iter()
+        .filter(|entry| earns_for_lps(&distribution, entry))
+        .collect();
+    let pool_accounts = account_info_iter.as_slice();
+    if pool_accounts.len() != 2 * pools.len() || pool_accounts.chunks(2).zip(&pools).any(|(pair, entry)| *pair[0].key != entry.pool) {
+        return Err(LiquidityPoolError::PoolSetMismatch.into());
+    }
+    // Holdings of pools no longer registered keep their last amount but are
+    // left out, so the eligible total is rebuilt from the synced pools
+    let now = Clock::get()?.unix_timestamp;
+    let mut pool_holdings = Vec::with_capacity(pools.len());
+    let mut total_eligible = distribution.total_locked;
+    for (pair, entry) in pool_accounts.chunks(2).zip(&pools) {
+        let holding = sync_pool_holding(
+            program_id,
+            &mut distribution,
+            entry,
+            now,
+            manager_account,
+            distribution_account,
+            &pair[0],
+            &pair[1],
+            system_program_account,
+        )?;
+        total_eligible = checked_add(total_eligible, holding.amount)?;
+        pool_holdings.push((holding, &pair[1]));
+    }
+    distribution.total_eligible = total_eligible;
+
+    let income = checked_add(amount, distribution.unallocated_income)?;
+    if distribution.total_eligible == 0 {
+        distribution.unallocated_income = income;
+    } else {
+        distribution.income_per_token_x64 = distribution
+            .income_per_token_x64
+            .wrapping_add(farm::reward_per_share_x64(income, distribution.total_eligible));
+        distribution.unallocated_income = 0;
+    }
+    for (mut holding, holding_account) in pool_holdings {
+        holding.settle(&distribution)?;
+        holding.serialize(&mut &mut holding_account.data.borrow_mut()[..])?;
+    }
+
+    TokenAccounts::new(token_program_account, &[])?.transfer(manager_quote_account, quote_vault_account, manager_account, amount, &[])?;
+
+    distribution.serialize(&mut &mut distribution_account.data.borrow_mut()[..])?;
+
+    msg!("Deposited {} income for {} eligible tokens", amount, distribution.total_eligible);
+    Ok(())
+}
+
+// ------------------ Lock Property Tokens ------------------
+/// Accounts: holder, distribution, holding, holder property token, property
+/// vault, token program, system program. The holding is created on first use.
+pub(crate) fn process_lock_property_tokens(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let holder_account = next_account_info(account_info_iter)?; // 0
+    let distribution_account = next_account_info(account_info_iter)?; // 1
+    let holding_account = next_account_info(account_info_iter)?; // 2
+    let holder_property_account = next_account_info(account_info_iter)?; // 3
+    let property_vault_account = next_account_info(account_info_iter)?; // 4
+    let token_program_account = next_account_info(account_info_iter)?; // 5
+    let system_program_account = next_account_info(account_info_iter)?; // 6
+
+    if !holder_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    let mut distribution = load_distribution(program_id, distribution_account)?;
+    let mut holding =
+        load_or_create_holding(program_id, holder_account, distribution_account, holder_account.key, holding_account, system_program_account)?;
+    validate_token_program(token_program_account)?;
+    validate_distribution_vault(distribution_account.key, property_vault_account, &distribution.property_vault, &distribution.property_mint)?;
+
+    if amount == 0 {
+        return Err(LiquidityPoolError::ZeroAmountNotAllowed.into());
+    }
+
+    holding.settle(&distribution)?;
+    holding.amount = checked_add(holding.amount, amount)?;
+    distribution.total_locked = checked_add(distribution.total_locked, amount)?;
+    distribution.total_eligible = checked_add(distribution.total_eligible, amount)?;
+
+    TokenAccounts::new(token_program_account, &[])?.transfer(holder_property_account, property_vault_account, holder_account, amount, &[])?;
+
+    distribution.serialize(&mut &mut distribution_account.data.borrow_mut()[..])?;
+    holding.serialize(&mut &mut holding_account.data.borrow_mut()[..])?;
+
+    msg!("Locked {} property tokens", amount);
+    Ok(())
+}
+
+// ------------------ Unlock Property Tokens ------------------
+/// Accounts: holder, distribution, holding, holder property token, property
+/// vault, token program. Income earned so far stays owed to the holding.
+pub(crate) fn process_unlock_property_tokens(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let holder_account = next_account_info(account_info_iter)?; // 0
+    let distribution_account = next_account_info(account_info_iter)?; // 1
+    let holding_account = next_account_info(account_info_iter)?; // 2
+    let holder_property_account = next_account_info(account_info_iter)?; // 3
+    let property_vault_account = next_account_info(account_info_iter)?; // 4
+    let token_program_account = next_account_info(account_info_iter)?; // 5
+
+    if !holder_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    let mut distribution = load_distribution(program_id, distribution_account)?;
+    let mut holding = load_holding(program_id, distribution_account.key, holder_account.key, holding_account)?;
+    validate_token_program(token_program_account)?;
+    validate_distribution_vault(distribution_account.key, property_vault_account, &distribution.property_vault, &distribution.property_mint)?;
+
+    if amount == 0 {
+        return Err(LiquidityPoolError::ZeroAmountNotAllowed.into());
+    }
+    if amount > holding.amount {
+        return Err(LiquidityPoolError::InsufficientFunds.into());
+    }
+
+    holding.settle(&distribution)?;
+    holding.amount = checked_sub(holding.amount, amount)?;
+    distribution.total_locked = checked_sub(distribution.total_locked, amount)?;
+    distribution.total_eligible = checked_sub(distribution.total_eligible, amount)?;
+
+    transfer_from_distribution(
+        &distribution,
+        token_program_account,
+        property_vault_account,
+        holder_property_account,
+        distribution_account,
+        amount,
+    )?;
+
+    distribution.serialize(&mut &mut distribution_account.data.borrow_mut()[..])?;
+    holding.serialize(&mut &mut holding_account.data.borrow_mut()[..])?;
+
+    msg!("Unlocked {} property tokens", amount);
+    Ok(())
+}
+
+// ------------------ Claim Income ------------------
+/// Accounts: holder, distribution, holding, quote vault, holder quote token,
+/// token program.
+pub(crate) fn process_claim_income(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let holder_account = next_account_info(account_info_iter)?; // 0
+    let distribution_account = next_account_info(account_info_iter)?; // 1
+    let holding_account = next_account_info(account_info_iter)?; // 2
+    let quote_vault_account = next_account_info(account_info_iter)?; // 3
+    let holder_quote_account = next_account_info(account_info_iter)?; // 4
+    let token_program_account = next_account_info(account_info_iter)?; // 5
+
+    if !holder_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    let distribution = load_distribution(program_id, distribution_account)?;
+    let mut holding = load_holding(program_id, distribution_account.key, holder_account.key, holding_account)?;
+    validate_token_program(token_program_account)?;
+    validate_distribution_vault(distribution_account.key, quote_vault_account, &distribution.quote_vault, &distribution.quote_mint)?;
+
+    holding.settle(&distribution)?;
+    let amount = holding.income_owed;
+    holding.income_owed = 0;
+
+    if amount > 0 {
+        transfer_from_distribution(
+            &distribution,
+            token_program_account,
+            quote_vault_account,
+            holder_quote_account,
+            distribution_account,
+            amount,
+        )?;
+    }
+
+    holding.serialize(&mut &mut holding_account.data.borrow_mut()[..])?;
+
+    msg!("Claimed {} income", amount);
+    Ok(())
+}
+
+// ------------------ Claim Pool Income ------------------
+/// Accounts: distribution, pool state, pool holding, quote vault, destination,
+/// token program, then for a constant-product pool not trading the quote token
+/// its farm. The destination is the pool's vault of the quote token, or the
+/// farm's reward vault. Permissionless: pays the pool's income to its LPs.
+pub(crate) fn process_claim_pool_income(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let distribution_account = next_account_info(account_info_iter)?; // 0
+    let pool_state_account = next_account_info(account_info_iter)?; // 1
+    let holding_account = next_account_info(account_info_iter)?; // 2
+    let quote_vault_account = next_account_info(account_info_iter)?; // 3
+    let destination_account = next_account_info(account_info_iter)?; // 4
+    let token_program_account = next_account_info(account_info_iter)?; // 5
+
+    let distribution = load_distribution(program_id, distribution_account)?;
+    let mut holding = load_holding(program_id, distribution_account.key, pool_state_account.key, holding_account)?;
+    validate_token_program(token_program_account)?;
+    validate_distribution_vault(distribution_account.key, quote_vault_account, &distribution.quote_vault, &distribution.quote_mint)?;
+
+    // Pool holdings are settled by every deposit that syncs them. One behind
+    // the index missed a deposit while its pool was closed, and what it still
+    // owes is returned to the distribution when the reopened pool is synced
+    if holding.income_per_token_last_x64 != distribution.income_per_token_x64 {
+        msg!("Pool holding has missed a deposit; nothing to pay until it is synced");
+        return Ok(());
+    }
+    let amount = holding.income_owed;
+    if amount == 0 {
+        msg!("Paid 0 pool income to its LPs");
+        return Ok(());
+    }
+
+    let now = Clock::get()?.unix_timestamp;
+    let pay = || {
+        transfer_from_distribution(&distribution, token_program_account, quote_vault_account, destination_account, distribution_account, amount)
+    };
+    let paid = if pool_state_account.data.borrow().starts_with(&CONCENTRATED_POOL_STATE_DISCRIMINATOR) {
+        pay_concentrated_pool(program_id, &distribution, pool_state_account, destination_account, now, pay)?
+    } else {
+        let pool_state_data = load_pool_state(program_id, pool_state_account)?;
+        if pairs_property_with_quote(&distribution, &pool_state_data.token_a_mint, &pool_state_data.token_b_mint) {
+            pay_pool_reserve(&distribution, pool_state_data, pool_state_account, destination_account, now, pay)?
+        } else {
+            let farm_account = next_account_info(account_info_iter)?; // 6
+            pay_pool_farm(program_id, &distribution, pool_state_account, farm_account, destination_account, now, pay)?
+        }
+    };
+    if !paid {
+        return Ok(());
+    }
+    holding.income_owed = 0;
+    holding.serialize(&mut &mut holding_account.data.borrow_mut()[..])?;
+
+    msg!("Paid {} pool income to its LPs", amount);
+    Ok(())
+}
+
+/// Adds a constant-product pool's income, received through `pay`, to its
+/// reserve of the quote token.
+fn pay_pool_reserve<'a>(
+    distribution: &Distribution,
+    mut pool_state_data: PoolState,
+    pool_state_account: &AccountInfo<'a>,
+    pool_quote_vault_account: &AccountInfo<'a>,
+    now: i64,
+    pay: impl FnOnce() -> Result<u64, ProgramError>,
+) -> Result<bool, ProgramError> {
+    let quote_is_a = pool_state_data.token_a_mint == distribution.quote_mint;
+    let pool_quote_vault = if quote_is_a { pool_state_data.vault_a } else { pool_state_data.vault_b };
+    if *pool_quote_vault_account.key != pool_quote_vault {
+        return Err(LiquidityPoolError::InvalidTokenAccount.into());
+    }
+    validate_pool_vault(pool_quote_vault_account, pool_state_account.key, &distribution.quote_mint)?;
+
+    let received = pay()?;
+    pool_state_data.update_price_accumulators(now);
+    if quote_is_a {
+        pool_state_data.token_a_reserve = checked_add(pool_state_data.token_a_reserve, received)?;
+    } else {
+        pool_state_data.token_b_reserve = checked_add(pool_state_data.token_b_reserve, received)?;
+    }
+    pool_state_data.serialize(&mut &mut pool_state_account.data.borrow_mut()[..])?;
+    Ok(true)
+}
+
+/// Adds a constant-product pool's income, received through `pay`, to the
+/// rewards of its farm, which must reward in the quote token.
+fn pay_pool_farm<'a>(
+    program_id: &Pubkey,
+    distribution: &Distribution,
+    pool_state_account: &AccountInfo<'a>,
+    farm_account: &AccountInfo<'a>,
+    reward_vault_account: &AccountInfo<'a>,
+    now: i64,
+    pay: impl FnOnce() -> Result<u64, ProgramError>,
+) -> Result<bool, ProgramError> {
+    let mut farm = farm::load_farm(program_id, farm_account)?;
+    if farm.pool != *pool_state_account.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if farm.reward_mint != distribution.quote_mint {
+        return Err(LiquidityPoolError::InvalidTokenMint.into());
+    }
+    if *reward_vault_account.key != farm.reward_vault {
+        return Err(LiquidityPoolError::InvalidTokenAccount.into());
+    }
+    validate_pool_vault(reward_vault_account, farm_account.key, &distribution.quote_mint)?;
+
+    let received = pay()?;
+    farm.fund(now, received)?;
+    farm.serialize(&mut &mut farm_account.data.borrow_mut()[..])?;
+    Ok(true)
+}
+
+/// Credits a concentrated pool's income, received through `pay`, to the
+/// positions in range as fees in the quote token. With no liquidity in range
+/// nothing is paid, and the income waits in the holding.
+fn pay_concentrated_pool<'a>(
+    program_id: &Pubkey,
+    distribution: &Distribution,
+    pool_state_account: &AccountInfo<'a>,
+    pool_quote_vault_account: &AccountInfo<'a>,
+    now: i64,
+    pay: impl FnOnce() -> Result<u64, ProgramError>,
+) -> Result<bool, ProgramError> {
+    let mut pool_state_data = concentrated_pool::load_concentrated_pool(program_id, pool_state_account)?;
+    if !pairs_property_with_quote(distribution, &pool_state_data.token_a_mint, &pool_state_data.token_b_mint) {
+        return Err(LiquidityPoolError::InvalidTokenMint.into());
+    }
+    let quote_is_a = pool_state_data.token_a_mint == distribution.quote_mint;
+    let pool_quote_vault = if quote_is_a { pool_state_data.token_a_vault } else { pool_state_data.token_b_vault };
+    if *pool_quote_vault_account.key != pool_quote_vault {
+        return Err(LiquidityPoolError::InvalidTokenAccount.into());
+    }
+    validate_pool_vault(pool_quote_vault_account, pool_state_account.key, &distribution.quote_mint)?;
+    if pool_state_data.liquidity == 0 {
+        msg!("No liquidity in range; nothing to pay until there is");
+        return Ok(false);
+    }
+
+    let received = pay()?;
+    let fee_growth = concentrated_math::fee_growth(received, pool_state_data.liquidity);
+    pool_state_data.update_reserve_accumulators(now);
+    if quote_is_a {
+        pool_state_data.reserve_a = checked_add(pool_state_data.reserve_a, received)?;
+        pool_state_data.fee_growth_global_a_x64 = pool_state_data.fee_growth_global_a_x64.wrapping_add(fee_growth);
+    } else {
+        pool_state_data.reserve_b = checked_add(pool_state_data.reserve_b, received)?;
+        pool_state_data.fee_growth_global_b_x64 = pool_state_data.fee_growth_global_b_x64.wrapping_add(fee_growth);
+    }
+    pool_state_data.serialize(&mut &mut pool_state_account.data.borrow_mut()[..])?;
+    Ok(true)
+}
+
+// ------------------ Pool Holdings ------------------
+/// Whether a pool of `mint_a` and `mint_b` trades the property token against
+/// the quote token.
+fn pairs_property_with_quote(distribution: &Distribution, mint_a: &Pubkey, mint_b: &Pubkey) -> bool {
+    (*mint_a == distribution.property_mint && *mint_b == distribution.quote_mint)
+        || (*mint_a == distribution.quote_mint && *mint_b == distribution.property_mint)
+}
+
+/// Whether a registered pool holds the property token in a way its LPs can be
+/// paid income for: any constant-product pool of it, and a concentrated pool
+/// of it against the quote token.
+fn earns_for_lps(distribution: &Distribution, entry: &RegistryEntry) -> bool {
+    if registry::is_concentrated(entry) {
+        return pairs_property_with_quote(distribution, &entry.token_a_mint, &entry.token_b_mint);
+    }
+    entry.token_a_mint == distribution.property_mint || entry.token_b_mint == distribution.property_mint
+}
+
+/// Sets a pool's eligible amount to its average reserve of the property token
+/// since it was last synced, returning the holding for the deposit to settle
+/// once it has been added. A holding not synced before, or left behind by an
+/// earlier pool at the same address, has no average and is eligible for
+/// nothing this time. A holding behind the index missed deposits while its
+/// pool was closed. In either of the last two cases it earned nothing from
+/// them, and income it still owed from before goes back to the distribution
+/// rather than to the pool's new LPs.
+#[allow(clippy::too_many_arguments)]
+fn sync_pool_holding<'a>(
+    program_id: &Pubkey,
+    distribution: &mut Distribution,
+    entry: &RegistryEntry,
+    now: i64,
+    manager_account: &AccountInfo<'a>,
+    distribution_account: &AccountInfo<'a>,
+    pool_state_account: &AccountInfo<'a>,
+    holding_account: &AccountInfo<'a>,
+    system_program_account: &AccountInfo<'a>,
+) -> Result<Holding, ProgramError> {
+    let (cumulative_a, cumulative_b) = if registry::is_concentrated(entry) {
+        concentrated_pool::load_concentrated_pool(program_id, pool_state_account)?.reserve_cumulatives(now)
+    } else {
+        load_pool_state(program_id, pool_state_account)?.reserve_cumulatives(now)
+    };
+    let reserve_cumulative = if entry.token_a_mint == distribution.property_mint { cumulative_a } else { cumulative_b };
+    let mut holding = load_or_create_holding(
+        program_id,
+        manager_account,
+        distribution_account,
+        pool_state_account.key,
+        holding_account,
+        system_program_account,
+    )?;
+
+    if holding.last_sync_ts == 0
+        || holding.pool_created_slot != entry.created_slot
+        || holding.income_per_token_last_x64 != distribution.income_per_token_x64
+    {
+        distribution.unallocated_income = checked_add(distribution.unallocated_income, holding.income_owed)?;
+        holding.income_owed = 0;
+        holding.income_per_token_last_x64 = distribution.income_per_token_x64;
+        holding.pool_created_slot = entry.created_slot;
+        holding.amount = 0;
+    } else if now > holding.last_sync_ts {
+        let elapsed = (now - holding.last_sync_ts) as u128;
+        let average = reserve_cumulative.wrapping_sub(holding.reserve_cumulative_last) / elapsed;
+        holding.amount = u64::try_from(average).map_err(|_| LiquidityPoolError::MathOverflow)?;
+    }
+    holding.reserve_cumulative_last = reserve_cumulative;
+    holding.last_sync_ts = now;
+    Ok(holding)
+}
+
+// ------------------ Account Validation ------------------
+/// Loads a distribution, checking that the account is owned by this program,
+/// holds a distribution, is initialized, and is the canonical PDA for its
+/// property and manager.
+pub fn load_distribution(program_id: &Pubkey, distribution_account: &AccountInfo) -> Result<Distribution, ProgramError> {
+    if distribution_account.owner != program_id {
+        return Err(LiquidityPoolError::InvalidOwner.into());
+    }
+    if !distribution_account.data.borrow().starts_with(&DISTRIBUTION_DISCRIMINATOR) {
+        return Err(LiquidityPoolError::InvalidAccountType.into());
+    }
+
+    let distribution = Distribution::deserialize(&mut &distribution_account.data.borrow()[..])?;
+    if distribution.is_initialized == 0 {
+        return Err(LiquidityPoolError::PoolNotInitialized.into());
+    }
+
+    let pda = Pubkey::create_program_address(
+        &[
+            b"distribution",
+            distribution.property_mint.as_ref(),
+            distribution.manager.as_ref(),
+            &[distribution.bump_seed],
+        ],
+        program_id,
+    )
+    .map_err(|_| ProgramError::InvalidSeeds)?;
+    if pda != *distribution_account.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    Ok(distribution)
+}
+
+/// Loads `owner`'s holding in the distribution.
+pub fn load_holding(
+    program_id: &Pubkey,
+    distribution: &Pubkey,
+    owner: &Pubkey,
+    holding_account: &AccountInfo,
+) -> Result<Holding, ProgramError> {
+    if holding_account.owner != program_id {
+        return Err(LiquidityPoolError::InvalidOwner.into());
+    }
+    if *holding_account.key != holding_address(program_id, distribution, owner).0 {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if !holding_account.data.borrow().starts_with(&HOLDING_DISCRIMINATOR) {
+        return Err(LiquidityPoolError::InvalidAccountType.into());
+    }
+
+    let holding = Holding::deserialize(&mut &holding_account.data.borrow()[..])?;
+    if holding.is_initialized == 0 {
+        return Err(LiquidityPoolError::PoolNotInitialized.into());
+    }
+    Ok(holding)
+}
+
+/// Like [`load_holding`], but first creates the holding at its PDA, `payer`
+/// paying its rent, and returns a new, empty one if it does not exist yet.
+fn load_or_create_holding<'a>(
+    program_id: &Pubkey,
+    payer: &AccountInfo<'a>,
+    distribution_account: &AccountInfo<'a>,
+    owner: &Pubkey,
+    holding_account: &AccountInfo<'a>,
+    system_program_account: &AccountInfo<'a>,
+) -> Result<Holding, ProgramError> {
+    if *holding_account.owner != system_program::id() {
+        return load_holding(program_id, distribution_account.key, owner, holding_account);
+    }
+    if *system_program_account.key != system_program::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let (pda, bump_seed) = holding_address(program_id, distribution_account.key, owner);
+    if *holding_account.key != pda {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    create_account(
+        payer,
+        holding_account,
+        system_program_account,
+        program_id,
+        Holding::LEN,
+        &[&[b"holding", distribution_account.key.as_ref(), owner.as_ref(), &[bump_seed]]],
+    )?;
+    Ok(Holding {
+        discriminator: HOLDING_DISCRIMINATOR,
+        is_initialized: 1,
+        distribution: *distribution_account.key,
+        owner: *owner,
+        ..Holding::default()
+    })
+}
+
+/// Checks that the account is the given vault recorded in the distribution.
+fn validate_distribution_vault(distribution_key: &Pubkey, vault_account: &AccountInfo, vault: &Pubkey, mint: &Pubkey) -> ProgramResult {
+    if vault_account.key != vault {
+        return Err(LiquidityPoolError::InvalidTokenAccount.into());
+    }
+    validate_pool_vault(vault_account, distribution_key, mint)?;
+    Ok(())
+}
+
+// ------------------ Token Movements ------------------
+fn transfer_from_distribution<'a>(
+    distribution: &Distribution,
+    token_program_account: &AccountInfo<'a>,
+    vault_account: &AccountInfo<'a>,
+    destination_account: &AccountInfo<'a>,
+    distribution_account: &AccountInfo<'a>,
+    amount: u64,
+) -> Result<u64, ProgramError> {
+    TokenAccounts::new(token_program_account, &[])?.transfer(
+        vault_account,
+        destination_account,
+        distribution_account, // PDA is the authority
+        amount,
+        &[&[
+            b"distribution",
+            distribution.property_mint.as_ref(),
+            distribution.manager.as_ref(),
+            &[distribution.bump_seed],
+        ]],
+    )
+}
@@ -0,0 +1,563 @@
+//! LP staking farms that emit a reward token to stakers of a pool's LP tokens.
+//!
+//! Each pool may have one farm, at `[b"farm", pool]`, created and managed by the
+//! pool's creator. The farm emits `emission_rate` reward tokens per second out of
+//! what it has been funded with, split between stakers in proportion to their
+//! stake through a reward-per-share accumulator: each second's emission adds
+//! `emitted / total_staked` to it, and a stake earns its amount times the growth
+//! since it was last touched. Nothing is emitted while nobody is staked, and
+//! emission stops once the funded rewards run out. Stakes live at
+//! `[b"stake", farm, owner]`. The program creates the farm in `InitializeFarm`,
+//! the pool creator paying its rent, and each stake on its owner's first
+//! `Stake`, the owner paying.
+//!
+//! Once nothing is staked and every stake has claimed its rewards, the admin
+//! can close the farm with `CloseFarm`, taking back what is left in its vaults
+//! and its rent. Only then can the pool itself be closed.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_program,
+    sysvar::{clock::Clock, Sysvar},
+};
+
+use crate::{
+    checked_add, checked_sub, create_account, curve::CurveError, load_pool_state, stable_swap::U256,
+    token::{unpack_account, TokenAccounts}, validate_mint, validate_pool_vault, validate_token_program, LiquidityPoolError,
+};
+
+// ------------------ Farm Structs ------------------
+/// First bytes of every [`Farm`] account: the first 8 bytes of
+/// `sha256("account:Farm")`.
+pub const FARM_DISCRIMINATOR: [u8; 8] = [161, 156, 211, 253, 250, 64, 53, 250];
+
+/// First bytes of every [`Stake`] account: the first 8 bytes of
+/// `sha256("account:Stake")`.
+pub const STAKE_DISCRIMINATOR: [u8; 8] = [150, 197, 176, 29, 55, 132, 112, 149];
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Default, PartialEq)]
+pub struct Farm {
+    /// Always [`FARM_DISCRIMINATOR`].
+    pub discriminator: [u8; 8],
+    pub is_initialized: u8,
+    pub bump_seed: u8,
+    pub admin: Pubkey,
+    pub pool: Pubkey,
+    pub lp_mint: Pubkey,
+    pub reward_mint: Pubkey,
+    /// Holds the staked LP tokens; owned by the farm PDA.
+    pub staked_vault: Pubkey,
+    /// Holds the funded rewards; owned by the farm PDA.
+    pub reward_vault: Pubkey,
+    /// Reward tokens emitted per second.
+    pub emission_rate: u64,
+    /// Rewards emitted per staked LP token so far, in Q64.64. Allowed to wrap.
+    pub reward_per_share_x64: u128,
+    pub total_staked: u64,
+    /// Funded rewards not yet emitted.
+    pub undistributed_rewards: u64,
+    /// Rewards credited to stakes but not yet claimed.
+    pub unclaimed_rewards: u64,
+    pub last_update_ts: i64,
+}
+
+impl Farm {
+    pub const LEN: usize = 8 + 1 + 1 + 32 * 6 + 8 + 16 + 8 + 8 + 8 + 8;
+
+    /// Emits the rewards due since the last update, up to what is left.
+    pub fn accrue(&mut self, now: i64) -> Result<(), CurveError> {
+        let elapsed = now.saturating_sub(self.last_update_ts);
+        if elapsed <= 0 {
+            return Ok(());
+        }
+        self.last_update_ts = now;
+        if self.total_staked == 0 {
+            return Ok(());
+        }
+
+        let due = (self.emission_rate as u128).saturating_mul(elapsed as u128);
+        let emitted = due.min(self.undistributed_rewards as u128) as u64;
+        self.reward_per_share_x64 = self.reward_per_share_x64.wrapping_add(reward_per_share_x64(emitted, self.total_staked));
+        self.undistributed_rewards -= emitted;
+        Ok(())
+    }
+
+    /// Adds `amount` to the rewards to emit, first emitting those due at `now`.
+    pub fn fund(&mut self, now: i64, amount: u64) -> ProgramResult {
+        self.accrue(now)?;
+        self.undistributed_rewards = checked_add(self.undistributed_rewards, amount)?;
+        Ok(())
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Default, PartialEq)]
+pub struct Stake {
+    /// Always [`STAKE_DISCRIMINATOR`].
+    pub discriminator: [u8; 8],
+    pub is_initialized: u8,
+    pub farm: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub reward_per_share_last_x64: u128,
+    /// Rewards earned but not yet claimed.
+    pub rewards_owed: u64,
+}
+
+impl Stake {
+    pub const LEN: usize = 8 + 1 + 32 + 32 + 8 + 16 + 8;
+
+    /// Credits the rewards earned since the stake was last touched.
+    pub fn settle(&mut self, farm: &mut Farm) -> Result<(), CurveError> {
+        let earned = rewards_earned(farm.reward_per_share_x64.wrapping_sub(self.reward_per_share_last_x64), self.amount)?;
+        self.rewards_owed = self.rewards_owed.checked_add(earned).ok_or(CurveError::MathOverflow)?;
+        farm.unclaimed_rewards = farm.unclaimed_rewards.checked_add(earned).ok_or(CurveError::MathOverflow)?;
+        self.reward_per_share_last_x64 = farm.reward_per_share_x64;
+        Ok(())
+    }
+}
+
+pub fn farm_address(program_id: &Pubkey, pool: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"farm", pool.as_ref()], program_id)
+}
+
+pub fn stake_address(program_id: &Pubkey, farm: &Pubkey, owner: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"stake", farm.as_ref(), owner.as_ref()], program_id)
+}
+
+// ------------------ Reward Math ------------------
+/// Reward-per-share growth for emitting `rewards` over `total_staked`, in Q64.64.
+pub fn reward_per_share_x64(rewards: u64, total_staked: u64) -> u128 {
+    if total_staked == 0 {
+        return 0;
+    }
+    ((rewards as u128) << 64) / total_staked as u128
+}
+
+/// Rewards earned by `amount` staked over a reward-per-share delta, rounded down.
+pub fn rewards_earned(reward_per_share_delta_x64: u128, amount: u64) -> Result<u64, CurveError> {
+    let earned = (U256::from(reward_per_share_delta_x64) * U256::from(amount)) >> 64;
+    if earned > U256::from(u64::MAX) {
+        return Err(CurveError::MathOverflow);
+    }
+    Ok(earned.as_u64())
+}
+
+// ------------------ Initialize Farm ------------------
+/// Accounts: pool creator, pool state, farm, reward mint, staked vault, reward
+/// vault, system program.
+pub(crate) fn process_initialize_farm(program_id: &Pubkey, accounts: &[AccountInfo], emission_rate: u64) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let admin_account = next_account_info(account_info_iter)?; // 0
+    let pool_state_account = next_account_info(account_info_iter)?; // 1
+    let farm_account = next_account_info(account_info_iter)?; // 2
+    let reward_mint_account = next_account_info(account_info_iter)?; // 3
+    let staked_vault_account = next_account_info(account_info_iter)?; // 4
+    let reward_vault_account = next_account_info(account_info_iter)?; // 5
+    let system_program_account = next_account_info(account_info_iter)?; // 6
+
+    if !admin_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if *system_program_account.key != system_program::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let pool_state_data = load_pool_state(program_id, pool_state_account)?;
+    if pool_state_data.creator != *admin_account.key {
+        return Err(LiquidityPoolError::Unauthorized.into());
+    }
+
+    let (pda, bump_seed) = farm_address(program_id, pool_state_account.key);
+    if pda != *farm_account.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if farm_account.owner == program_id {
+        if farm_account.data.borrow().starts_with(&FARM_DISCRIMINATOR) {
+            return Err(LiquidityPoolError::PoolAlreadyInitialized.into());
+        }
+        return Err(LiquidityPoolError::InvalidAccountType.into());
+    }
+    validate_mint(reward_mint_account)?;
+    validate_pool_vault(staked_vault_account, &pda, &pool_state_data.lp_mint)?;
+    validate_pool_vault(reward_vault_account, &pda, reward_mint_account.key)?;
+
+    create_account(
+        admin_account,
+        farm_account,
+        system_program_account,
+        program_id,
+        Farm::LEN,
+        &[&[b"farm", pool_state_account.key.as_ref(), &[bump_seed]]],
+    )?;
+
+    let farm = Farm {
+        discriminator: FARM_DISCRIMINATOR,
+        is_initialized: 1,
+        bump_seed,
+        admin: *admin_account.key,
+        pool: *pool_state_account.key,
+        lp_mint: pool_state_data.lp_mint,
+        reward_mint: *reward_mint_account.key,
+        staked_vault: *staked_vault_account.key,
+        reward_vault: *reward_vault_account.key,
+        emission_rate,
+        reward_per_share_x64: 0,
+        total_staked: 0,
+        undistributed_rewards: 0,
+        unclaimed_rewards: 0,
+        last_update_ts: Clock::get()?.unix_timestamp,
+    };
+    farm.serialize(&mut &mut farm_account.data.borrow_mut()[..])?;
+
+    msg!("Farm initialized emitting {} reward tokens per second", emission_rate);
+    Ok(())
+}
+
+// ------------------ Fund Farm ------------------
+/// Accounts: farm admin, farm, admin reward token, reward vault, token program.
+pub(crate) fn process_fund_farm(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let admin_account = next_account_info(account_info_iter)?; // 0
+    let farm_account = next_account_info(account_info_iter)?; // 1
+    let admin_reward_account = next_account_info(account_info_iter)?; // 2
+    let reward_vault_account = next_account_info(account_info_iter)?; // 3
+    let token_program_account = next_account_info(account_info_iter)?; // 4
+
+    let mut farm = load_farm_for_admin(program_id, admin_account, farm_account)?;
+    validate_token_program(token_program_account)?;
+    validate_farm_vault(farm_account.key, reward_vault_account, &farm.reward_vault, &farm.reward_mint)?;
+
+    if amount == 0 {
+        return Err(LiquidityPoolError::ZeroAmountNotAllowed.into());
+    }
+
+    farm.fund(Clock::get()?.unix_timestamp, amount)?;
+
+    TokenAccounts::new(token_program_account, &[])?.transfer(admin_reward_account, reward_vault_account, admin_account, amount, &[])?;
+
+    farm.serialize(&mut &mut farm_account.data.borrow_mut()[..])?;
+
+    msg!("Funded farm with {} reward tokens", amount);
+    Ok(())
+}
+
+// ------------------ Set Emission Rate ------------------
+/// Accounts: farm admin, farm. Rewards due at the old rate are emitted first.
+pub(crate) fn process_set_emission_rate(program_id: &Pubkey, accounts: &[AccountInfo], emission_rate: u64) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let admin_account = next_account_info(account_info_iter)?; // 0
+    let farm_account = next_account_info(account_info_iter)?; // 1
+
+    let mut farm = load_farm_for_admin(program_id, admin_account, farm_account)?;
+    farm.accrue(Clock::get()?.unix_timestamp)?;
+    farm.emission_rate = emission_rate;
+    farm.serialize(&mut &mut farm_account.data.borrow_mut()[..])?;
+
+    msg!("Farm emission rate set to {} per second", emission_rate);
+    Ok(())
+}
+
+// ------------------ Stake ------------------
+/// Accounts: owner, farm, stake, owner LP token, staked vault, token program,
+/// system program. The stake is created on first use.
+pub(crate) fn process_stake(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let owner_account = next_account_info(account_info_iter)?; // 0
+    let farm_account = next_account_info(account_info_iter)?; // 1
+    let stake_account = next_account_info(account_info_iter)?; // 2
+    let owner_lp_account = next_account_info(account_info_iter)?; // 3
+    let staked_vault_account = next_account_info(account_info_iter)?; // 4
+    let token_program_account = next_account_info(account_info_iter)?; // 5
+    let system_program_account = next_account_info(account_info_iter)?; // 6
+
+    let mut farm = load_farm(program_id, farm_account)?;
+    let mut stake = load_or_create_stake(program_id, owner_account, farm_account, stake_account, system_program_account)?;
+    validate_token_program(token_program_account)?;
+    validate_farm_vault(farm_account.key, staked_vault_account, &farm.staked_vault, &farm.lp_mint)?;
+
+    if amount == 0 {
+        return Err(LiquidityPoolError::ZeroAmountNotAllowed.into());
+    }
+
+    farm.accrue(Clock::get()?.unix_timestamp)?;
+    stake.settle(&mut farm)?;
+    stake.amount = checked_add(stake.amount, amount)?;
+    farm.total_staked = checked_add(farm.total_staked, amount)?;
+
+    TokenAccounts::new(token_program_account, &[])?.transfer(owner_lp_account, staked_vault_account, owner_account, amount, &[])?;
+
+    farm.serialize(&mut &mut farm_account.data.borrow_mut()[..])?;
+    stake.serialize(&mut &mut stake_account.data.borrow_mut()[..])?;
+
+    msg!("Staked {} LP tokens", amount);
+    Ok(())
+}
+
+// ------------------ Unstake ------------------
+/// Accounts: owner, farm, stake, owner LP token, staked vault, token program.
+/// Rewards earned so far stay owed to the stake.
+pub(crate) fn process_unstake(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let owner_account = next_account_info(account_info_iter)?; // 0
+    let farm_account = next_account_info(account_info_iter)?; // 1
+    let stake_account = next_account_info(account_info_iter)?; // 2
+    let owner_lp_account = next_account_info(account_info_iter)?; // 3
+    let staked_vault_account = next_account_info(account_info_iter)?; // 4
+    let token_program_account = next_account_info(account_info_iter)?; // 5
+
+    let mut farm = load_farm(program_id, farm_account)?;
+    let mut stake = load_stake(program_id, owner_account, farm_account, stake_account)?;
+    validate_token_program(token_program_account)?;
+    validate_farm_vault(farm_account.key, staked_vault_account, &farm.staked_vault, &farm.lp_mint)?;
+
+    if amount == 0 {
+        return Err(LiquidityPoolError::ZeroAmountNotAllowed.into());
+    }
+    if amount > stake.amount {
+        return Err(LiquidityPoolError::InsufficientFunds.into());
+    }
+
+    farm.accrue(Clock::get()?.unix_timestamp)?;
+    stake.settle(&mut farm)?;
+    stake.amount = checked_sub(stake.amount, amount)?;
+    farm.total_staked = checked_sub(farm.total_staked, amount)?;
+
+    transfer_from_farm(&farm, token_program_account, staked_vault_account, owner_lp_account, farm_account, amount)?;
+
+    farm.serialize(&mut &mut farm_account.data.borrow_mut()[..])?;
+    stake.serialize(&mut &mut stake_account.data.borrow_mut()[..])?;
+
+    msg!("Unstaked {} LP tokens", amount);
+    Ok(())
+}
+
+// ------------------ Claim Rewards ------------------
+/// Accounts: owner, farm, stake, reward vault, owner reward token, token program.
+pub(crate) fn process_claim_rewards(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let owner_account = next_account_info(account_info_iter)?; // 0
+    let farm_account = next_account_info(account_info_iter)?; // 1
+    let stake_account = next_account_info(account_info_iter)?; // 2
+    let reward_vault_account = next_account_info(account_info_iter)?; // 3
+    let owner_reward_account = next_account_info(account_info_iter)?; // 4
+    let token_program_account = next_account_info(account_info_iter)?; // 5
+
+    let mut farm = load_farm(program_id, farm_account)?;
+    let mut stake = load_stake(program_id, owner_account, farm_account, stake_account)?;
+    validate_token_program(token_program_account)?;
+    validate_farm_vault(farm_account.key, reward_vault_account, &farm.reward_vault, &farm.reward_mint)?;
+
+    farm.accrue(Clock::get()?.unix_timestamp)?;
+    stake.settle(&mut farm)?;
+    let amount = stake.rewards_owed;
+    stake.rewards_owed = 0;
+    farm.unclaimed_rewards = checked_sub(farm.unclaimed_rewards, amount)?;
+
+    if amount > 0 {
+        transfer_from_farm(&farm, token_program_account, reward_vault_account, owner_reward_account, farm_account, amount)?;
+    }
+
+    farm.serialize(&mut &mut farm_account.data.borrow_mut()[..])?;
+    stake.serialize(&mut &mut stake_account.data.borrow_mut()[..])?;
+
+    msg!("Claimed {} reward tokens", amount);
+    Ok(())
+}
+
+// ------------------ Close Farm ------------------
+/// Accounts: farm admin, farm, staked vault, reward vault, admin LP token,
+/// admin reward token, token program.
+pub(crate) fn process_close_farm(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let admin_account = next_account_info(account_info_iter)?; // 0
+    let farm_account = next_account_info(account_info_iter)?; // 1
+    let staked_vault_account = next_account_info(account_info_iter)?; // 2
+    let reward_vault_account = next_account_info(account_info_iter)?; // 3
+    let admin_lp_account = next_account_info(account_info_iter)?; // 4
+    let admin_reward_account = next_account_info(account_info_iter)?; // 5
+    let token_program_account = next_account_info(account_info_iter)?; // 6
+
+    let mut farm = load_farm_for_admin(program_id, admin_account, farm_account)?;
+    validate_token_program(token_program_account)?;
+    validate_farm_vault(farm_account.key, staked_vault_account, &farm.staked_vault, &farm.lp_mint)?;
+    validate_farm_vault(farm_account.key, reward_vault_account, &farm.reward_vault, &farm.reward_mint)?;
+
+    // With nothing staked every stake has been settled, so what the stakes are
+    // owed is exactly what has been credited to them and not claimed
+    farm.accrue(Clock::get()?.unix_timestamp)?;
+    if farm.total_staked > 0 || farm.unclaimed_rewards > 0 {
+        return Err(LiquidityPoolError::FarmNotEmpty.into());
+    }
+
+    // The reward vault holds the rewards never emitted and the rounding dust
+    // of those that were; the staked vault holds only what was sent to it
+    // directly. Both go to the admin so the vaults can be closed.
+    let tokens = TokenAccounts::new(token_program_account, &[])?;
+    let farm_seeds: &[&[u8]] = &[b"farm", farm.pool.as_ref(), &[farm.bump_seed]];
+    let returned_rewards = unpack_account(reward_vault_account)?.amount;
+    for (vault_account, admin_token_account) in [
+        (staked_vault_account, admin_lp_account),
+        (reward_vault_account, admin_reward_account),
+    ] {
+        let balance = unpack_account(vault_account)?.amount;
+        if balance > 0 {
+            tokens.transfer(vault_account, admin_token_account, farm_account, balance, &[farm_seeds])?;
+        }
+        tokens.close_account(vault_account, admin_account, farm_account, &[farm_seeds])?;
+    }
+
+    // Close the farm the way ClosePool closes the pool state, so that the pool
+    // can be closed and a new farm created at the same address
+    let farm_lamports = farm_account.lamports();
+    **admin_account.lamports.borrow_mut() = checked_add(admin_account.lamports(), farm_lamports)?;
+    **farm_account.lamports.borrow_mut() = 0;
+    farm_account.assign(&system_program::id());
+    farm_account.realloc(0, false)?;
+
+    msg!("Closed farm, returning {} reward tokens", returned_rewards);
+    Ok(())
+}
+
+// ------------------ Account Validation ------------------
+/// Loads a farm, checking that the account is owned by this program, holds a
+/// farm, is initialized, and is the canonical PDA for its pool.
+pub fn load_farm(program_id: &Pubkey, farm_account: &AccountInfo) -> Result<Farm, ProgramError> {
+    if farm_account.owner != program_id {
+        return Err(LiquidityPoolError::InvalidOwner.into());
+    }
+    if !farm_account.data.borrow().starts_with(&FARM_DISCRIMINATOR) {
+        return Err(LiquidityPoolError::InvalidAccountType.into());
+    }
+
+    let farm = Farm::deserialize(&mut &farm_account.data.borrow()[..])?;
+    if farm.is_initialized == 0 {
+        return Err(LiquidityPoolError::PoolNotInitialized.into());
+    }
+
+    let pda = Pubkey::create_program_address(&[b"farm", farm.pool.as_ref(), &[farm.bump_seed]], program_id)
+        .map_err(|_| ProgramError::InvalidSeeds)?;
+    if pda != *farm_account.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    Ok(farm)
+}
+
+/// Loads a farm, checking that `admin_account` signed and is its admin.
+fn load_farm_for_admin(program_id: &Pubkey, admin_account: &AccountInfo, farm_account: &AccountInfo) -> Result<Farm, ProgramError> {
+    if !admin_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    let farm = load_farm(program_id, farm_account)?;
+    if farm.admin != *admin_account.key {
+        return Err(LiquidityPoolError::Unauthorized.into());
+    }
+    Ok(farm)
+}
+
+/// Loads a stake, checking that it is the owner's stake in the farm and that
+/// the owner signed.
+pub fn load_stake(
+    program_id: &Pubkey,
+    owner_account: &AccountInfo,
+    farm_account: &AccountInfo,
+    stake_account: &AccountInfo,
+) -> Result<Stake, ProgramError> {
+    if !owner_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if stake_account.owner != program_id {
+        return Err(LiquidityPoolError::InvalidOwner.into());
+    }
+    if *stake_account.key != stake_address(program_id, farm_account.key, owner_account.key).0 {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if !stake_account.data.borrow().starts_with(&STAKE_DISCRIMINATOR) {
+        return Err(LiquidityPoolError::InvalidAccountType.into());
+    }
+
+    let stake = Stake::deserialize(&mut &stake_account.data.borrow()[..])?;
+    if stake.is_initialized == 0 {
+        return Err(LiquidityPoolError::PoolNotInitialized.into());
+    }
+    Ok(stake)
+}
+
+/// Like [`load_stake`], but first creates the stake at its PDA, the owner
+/// paying its rent, and returns a new, empty one if it does not exist yet.
+fn load_or_create_stake<'a>(
+    program_id: &Pubkey,
+    owner_account: &AccountInfo<'a>,
+    farm_account: &AccountInfo<'a>,
+    stake_account: &AccountInfo<'a>,
+    system_program_account: &AccountInfo<'a>,
+) -> Result<Stake, ProgramError> {
+    if *stake_account.owner != system_program::id() {
+        return load_stake(program_id, owner_account, farm_account, stake_account);
+    }
+    if !owner_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if *system_program_account.key != system_program::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let (pda, bump_seed) = stake_address(program_id, farm_account.key, owner_account.key);
+    if *stake_account.key != pda {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    create_account(
+        owner_account,
+        stake_account,
+        system_program_account,
+        program_id,
+        Stake::LEN,
+        &[&[b"stake", farm_account.key.as_ref(), owner_account.key.as_ref(), &[bump_seed]]],
+    )?;
+    Ok(Stake {
+        discriminator: STAKE_DISCRIMINATOR,
+        is_initialized: 1,
+        farm: *farm_account.key,
+        owner: *owner_account.key,
+        ..Stake::default()
+    })
+}
+
+/// Checks that the account is the given vault recorded in the farm.
+fn validate_farm_vault(farm_key: &Pubkey, vault_account: &AccountInfo, vault: &Pubkey, mint: &Pubkey) -> ProgramResult {
+    if vault_account.key != vault {
+        return Err(LiquidityPoolError::InvalidTokenAccount.into());
+    }
+    validate_pool_vault(vault_account, farm_key, mint)?;
+    Ok(())
+}
+
+// ------------------ Token Movements ------------------
+fn transfer_from_farm<'a>(
+    farm: &Farm,
+    token_program_account: &AccountInfo<'a>,
+    vault_account: &AccountInfo<'a>,
+    destination_account: &AccountInfo<'a>,
+    farm_account: &AccountInfo<'a>,
+    amount: u64,
+) -> ProgramResult {
+    TokenAccounts::new(token_program_account, &[])?.transfer(
+        vault_account,
+        destination_account,
+        farm_account, // PDA is the authority
+        amount,
+        &[&[b"farm", farm.pool.as_ref(), &[farm.bump_seed]]],
+    )?;
+    Ok(())
+}
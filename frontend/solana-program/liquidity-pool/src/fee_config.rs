@@ -0,0 +1,188 @@
+//! Admin-managed list of the fee tiers pools may be created with.
+//!
+//! The config lives at `[b"fee_config"]` and is created by `InitializeFeeConfig`,
+//! the admin paying its rent. Only the program's upgrade authority may
+//! initialize it, so no one can take over the fee tiers by initializing it
+//! first after deployment. Changing the list only affects new pools: existing
+//! pools keep the fee they were created with.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    bpf_loader_upgradeable::{self, UpgradeableLoaderState},
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_program,
+};
+
+use crate::{create_account, LiquidityPoolError};
+
+/// Most fee tiers the config can hold.
+pub const MAX_FEE_TIERS: usize = 8;
+
+/// Highest fee a tier may charge, in basis points (10%).
+pub const MAX_FEE_BPS: u64 = 1_000;
+
+// ------------------ FeeConfig Struct ------------------
+/// First bytes of the [`FeeConfig`] account: the first 8 bytes of
+/// `sha256("account:FeeConfig")`.
+pub const FEE_CONFIG_DISCRIMINATOR: [u8; 8] = [143, 52, 146, 187, 219, 123, 76, 155];
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Default, PartialEq)]
+pub struct FeeConfig {
+    /// Always [`FEE_CONFIG_DISCRIMINATOR`].
+    pub discriminator: [u8; 8],
+    pub is_initialized: u8,
+    pub admin: Pubkey,
+    /// Allowed swap fees, in basis points.
+    pub fee_tiers: Vec<u64>,
+}
+
+impl FeeConfig {
+    pub const LEN: usize = 8 + 1 + 32 + 4 + MAX_FEE_TIERS * 8;
+
+    pub fn allows(&self, fee_bps: u64) -> bool {
+        self.fee_tiers.contains(&fee_bps)
+    }
+}
+
+pub fn fee_config_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"fee_config"], program_id)
+}
+
+// ------------------ Initialize Fee Config ------------------
+/// Accounts: admin, fee config, system program, program data.
+pub(crate) fn process_initialize_fee_config(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    fee_tiers: Vec<u64>,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let admin_account = next_account_info(account_info_iter)?; // 0
+    let fee_config_account = next_account_info(account_info_iter)?; // 1
+    let system_program_account = next_account_info(account_info_iter)?; // 2
+    let program_data_account = next_account_info(account_info_iter)?; // 3
+
+    if !admin_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if upgrade_authority(program_id, program_data_account)? != Some(*admin_account.key) {
+        return Err(LiquidityPoolError::Unauthorized.into());
+    }
+    if *system_program_account.key != system_program::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let (fee_config_key, bump_seed) = fee_config_address(program_id);
+    if *fee_config_account.key != fee_config_key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if fee_config_account.owner == program_id {
+        if fee_config_account.data.borrow().starts_with(&FEE_CONFIG_DISCRIMINATOR) {
+            return Err(LiquidityPoolError::PoolAlreadyInitialized.into());
+        }
+        return Err(LiquidityPoolError::InvalidAccountType.into());
+    }
+    validate_fee_tiers(&fee_tiers)?;
+
+    create_account(
+        admin_account,
+        fee_config_account,
+        system_program_account,
+        program_id,
+        FeeConfig::LEN,
+        &[&[b"fee_config", &[bump_seed]]],
+    )?;
+
+    let fee_config = FeeConfig {
+        discriminator: FEE_CONFIG_DISCRIMINATOR,
+        is_initialized: 1,
+        admin: *admin_account.key,
+        fee_tiers,
+    };
+    fee_config.serialize(&mut &mut fee_config_account.data.borrow_mut()[..])?;
+
+    msg!("Fee tiers set to {:?}", fee_config.fee_tiers);
+    Ok(())
+}
+
+// ------------------ Set Fee Tiers ------------------
+/// Accounts: admin, fee config.
+pub(crate) fn process_set_fee_tiers(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    fee_tiers: Vec<u64>,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let admin_account = next_account_info(account_info_iter)?; // 0
+    let fee_config_account = next_account_info(account_info_iter)?; // 1
+
+    if !admin_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    let mut fee_config = load_fee_config(program_id, fee_config_account)?;
+    if fee_config.admin != *admin_account.key {
+        return Err(LiquidityPoolError::Unauthorized.into());
+    }
+    validate_fee_tiers(&fee_tiers)?;
+
+    fee_config.fee_tiers = fee_tiers;
+    fee_config.serialize(&mut &mut fee_config_account.data.borrow_mut()[..])?;
+
+    msg!("Fee tiers set to {:?}", fee_config.fee_tiers);
+    Ok(())
+}
+
+// ------------------ Account Validation ------------------
+/// Loads the fee config, checking that the account is owned by this program,
+/// at its canonical address, holds a fee config, and is initialized.
+pub fn load_fee_config(program_id: &Pubkey, fee_config_account: &AccountInfo) -> Result<FeeConfig, ProgramError> {
+    if fee_config_account.owner != program_id {
+        return Err(LiquidityPoolError::InvalidOwner.into());
+    }
+    if *fee_config_account.key != fee_config_address(program_id).0 {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if !fee_config_account.data.borrow().starts_with(&FEE_CONFIG_DISCRIMINATOR) {
+        return Err(LiquidityPoolError::InvalidAccountType.into());
+    }
+
+    let fee_config = FeeConfig::deserialize(&mut &fee_config_account.data.borrow()[..])?;
+    if fee_config.is_initialized == 0 {
+        return Err(LiquidityPoolError::PoolNotInitialized.into());
+    }
+    Ok(fee_config)
+}
+
+/// Upgrade authority of the program, read from its program data account.
+fn upgrade_authority(program_id: &Pubkey, program_data_account: &AccountInfo) -> Result<Option<Pubkey>, ProgramError> {
+    if *program_data_account.key != Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::id()).0 {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if *program_data_account.owner != bpf_loader_upgradeable::id() {
+        return Err(LiquidityPoolError::InvalidOwner.into());
+    }
+
+    // `UpgradeableLoaderState::ProgramData`, bincode-encoded: a u32 variant
+    // tag of 3, the slot, then the optional authority
+    let data = program_data_account.data.borrow();
+    let metadata_len = UpgradeableLoaderState::size_of_programdata_metadata();
+    if data.len() < metadata_len || data[..4] != 3u32.to_le_bytes() {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    Ok(match data[12] {
+        0 => None,
+        _ => Some(Pubkey::new_from_array(data[13..metadata_len].try_into().unwrap())),
+    })
+}
+
+fn validate_fee_tiers(fee_tiers: &[u64]) -> ProgramResult {
+    let distinct = fee_tiers.iter().enumerate().all(|(i, fee)| !fee_tiers[..i].contains(fee));
+    if fee_tiers.is_empty() || fee_tiers.len() > MAX_FEE_TIERS || !distinct || fee_tiers.iter().any(|&fee| fee > MAX_FEE_BPS) {
+        return Err(LiquidityPoolError::InvalidFeeTier.into());
+    }
+    Ok(())
+}
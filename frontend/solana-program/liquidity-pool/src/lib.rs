@@ -5,28 +5,66 @@ use solana_program::{
     account_info::{next_account_info, AccountInfo},
     entrypoint,
     entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
     msg,
     pubkey::Pubkey,
     program_error::ProgramError,
-    program::{invoke, invoke_signed},
-    system_instruction,
-    sysvar::{rent::Rent, Sysvar},
-    borsh::try_from_slice_unchecked,
+    program::{invoke, invoke_signed, set_return_data},
+    program_option::COption,
+    program_pack::Pack,
+    system_instruction, system_program,
+    sysvar::{clock::Clock, rent::Rent, Sysvar},
 };
-use spl_token::{
+use spl_token::native_mint;
+use spl_token_2022::{
     instruction as spl_token_instruction,
-    state::{Mint, Account},
+    state::{Account, Mint},
 };
 use borsh::{BorshDeserialize, BorshSerialize};
 use thiserror::Error;
 
+pub mod concentrated_math;
+pub mod concentrated_pool;
+pub mod curve;
+pub mod distribution;
+pub mod farm;
+pub mod fee_config;
+pub mod migration;
+pub mod oracle;
+pub mod registry;
+pub mod stable_swap;
+pub mod token;
+pub mod weighted_math;
+pub mod weighted_pool;
+
+use curve::CurveError;
+use oracle::PriceObservation;
+use registry::RegistryEntry;
+use token::TokenAccounts;
+
 // ------------------ Program ID ------------------
 solana_program::declare_id!("64Kd3NVVfKLcfxXsNLEvriNSiuzGpeTaBqSLwk4vXx2Y"); // This should be the deployed program ID
 
 // ------------------ PoolState Struct ------------------
+/// Layout version of [`PoolState`]. Pools written with an older layout must
+/// be upgraded with `MigratePool` before use; see [`migration`].
+pub const POOL_STATE_VERSION: u8 = 2;
+
+/// First bytes of every [`PoolState`] account, telling it apart from any other
+/// data: the first 8 bytes of `sha256("account:PoolState")`.
+pub const POOL_STATE_DISCRIMINATOR: [u8; 8] = [247, 237, 227, 245, 215, 195, 222, 70];
+
+/// Bytes at the end of [`PoolState`] kept zeroed for fields added by later
+/// versions, so those can be migrated in place.
+pub const POOL_STATE_RESERVED: usize = 32;
+
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
 pub struct PoolState {
+    /// Always [`POOL_STATE_DISCRIMINATOR`].
+    pub discriminator: [u8; 8],
     pub is_initialized: u8,
+    /// Layout version, [`POOL_STATE_VERSION`] for pools in the current layout.
+    pub version: u8,
     pub token_a_mint: Pubkey,
     pub token_b_mint: Pubkey,
     pub token_a_reserve: u64,
@@ -34,18 +72,272 @@ pub struct PoolState {
     pub lp_mint: Pubkey,
     pub lp_supply: u64,
     pub bump_seed: u8,
+    pub curve_type: CurveType,
+    pub creator: Pubkey,
+    pub initial_amp: u64,
+    pub target_amp: u64,
+    pub ramp_start_ts: i64,
+    pub ramp_stop_ts: i64,
+    /// TWAP accumulators; see [`oracle`].
+    pub price_a_cumulative_x64: u128,
+    pub price_b_cumulative_x64: u128,
+    pub last_update_ts: i64,
+    /// Swap fee in basis points, one of the tiers allowed when the pool was created.
+    pub fee_bps: u64,
+    /// Token vaults, at [`vault_address`] for pools created since vaults are
+    /// PDAs; see [`migration`] for older pools.
+    pub vault_a: Pubkey,
+    pub vault_b: Pubkey,
+    /// Sums of each reserve times the seconds it was held, for time-weighted
+    /// balances; see [`distribution`]. Allowed to wrap. Taken from the reserved
+    /// bytes, so they start at zero in pools written before them.
+    pub reserve_a_cumulative: u128,
+    pub reserve_b_cumulative: u128,
+    pub reserved: [u8; POOL_STATE_RESERVED],
+    /// Whether the pool sits at the address derived without its fee tier, as
+    /// pools created before fee tiers do. Set by [`load_pool_state`], not stored.
+    #[borsh_skip]
+    pub legacy_seeds: bool,
 }
 
 impl PoolState {
-    pub const LEN: usize = 1 + 32 + 32 + 8 + 8 + 32 + 8 + 1;
+    pub const LEN: usize =
+        8 + 1 + 1 + 32 + 32 + 8 + 8 + 32 + 8 + 1 + 1 + 32 + 8 + 8 + 8 + 8 + 16 + 16 + 8 + 8 + 32 + 32 + 16 + 16 + POOL_STATE_RESERVED;
+
+    /// Seeds of the pool PDA, which signs for its vaults and LP mint.
+    pub fn signer_seeds(&self) -> PoolSeeds {
+        PoolSeeds {
+            token_a_mint: self.token_a_mint,
+            token_b_mint: self.token_b_mint,
+            fee_bps: (!self.legacy_seeds).then(|| self.fee_bps.to_le_bytes()),
+            bump_seed: [self.bump_seed],
+        }
+    }
+
+    /// Amplification coefficient in effect at `now`. Zero for constant-product pools.
+    pub fn amp(&self, now: i64) -> u64 {
+        stable_swap::current_amp(self.initial_amp, self.target_amp, self.ramp_start_ts, self.ramp_stop_ts, now)
+    }
+
+    /// Price accumulators as they stand at `now`, with the current reserves
+    /// held since the last update. Uses the reserve ratio, which for stable
+    /// pools is only an approximation of the marginal price.
+    pub fn observe(&self, now: i64) -> PriceObservation {
+        let elapsed = u64::try_from(now.saturating_sub(self.last_update_ts)).unwrap_or(0);
+        let (price_a_cumulative_x64, price_b_cumulative_x64) = oracle::accumulate(
+            self.price_a_cumulative_x64,
+            self.price_b_cumulative_x64,
+            self.token_a_reserve,
+            self.token_b_reserve,
+            elapsed,
+        );
+        PriceObservation {
+            timestamp: now.max(self.last_update_ts),
+            price_a_cumulative_x64,
+            price_b_cumulative_x64,
+        }
+    }
+
+    /// Output for selling exactly `amount_in` at `now`, on the pool's curve.
+    pub fn quote_exact_in(&self, a_to_b: bool, amount_in: u64, now: i64) -> Result<u64, ProgramError> {
+        let (reserve_in, reserve_out) = self.reserves(a_to_b);
+        let amount_out = match self.curve_type {
+            CurveType::ConstantProduct => curve::swap_exact_in(amount_in, reserve_in, reserve_out, self.fee_bps)?,
+            CurveType::Stable => stable_swap::swap_exact_in(amount_in, reserve_in, reserve_out, self.amp(now), self.fee_bps)?,
+        };
+        Ok(amount_out)
+    }
+
+    /// Input required to buy exactly `amount_out` at `now`, on the pool's curve.
+    pub fn quote_exact_out(&self, a_to_b: bool, amount_out: u64, now: i64) -> Result<u64, ProgramError> {
+        let (reserve_in, reserve_out) = self.reserves(a_to_b);
+        let amount_in = match self.curve_type {
+            CurveType::ConstantProduct => curve::swap_exact_out(amount_out, reserve_in, reserve_out, self.fee_bps)?,
+            CurveType::Stable => stable_swap::swap_exact_out(amount_out, reserve_in, reserve_out, self.amp(now), self.fee_bps)?,
+        };
+        Ok(amount_in)
+    }
+
+    /// Splits a single-sided deposit of `amount_in` into a swap and a
+    /// balanced deposit, at `now`. Returns `(amount_swapped, amount_out,
+    /// amount_deposited)`: selling `amount_swapped` yields `amount_out`, which is
+    /// deposited in full alongside `amount_deposited` at the post-swap ratio.
+    /// The largest such swap within `amount_in` is found by bisection, so
+    /// `amount_swapped + amount_deposited` may fall a few units short of it.
+    pub fn zap_in(&self, a_to_b: bool, amount_in: u64, now: i64) -> Result<(u64, u64, u64), ProgramError> {
+        let (reserve_in, reserve_out) = self.reserves(a_to_b);
+        // Input needed alongside the output of selling `amount_swapped`.
+        let split = |amount_swapped: u64| -> Result<(u64, u64), ProgramError> {
+            let amount_out = self.quote_exact_in(a_to_b, amount_swapped, now)?;
+            let reserve_in_after = reserve_in as u128 + amount_swapped as u128;
+            let reserve_out_after = checked_sub(reserve_out, amount_out)? as u128;
+            if reserve_out_after == 0 {
+                return Err(LiquidityPoolError::InsufficientFunds.into());
+            }
+            let amount_deposited = (amount_out as u128 * reserve_in_after).div_ceil(reserve_out_after);
+            Ok((amount_out, u64::try_from(amount_deposited).map_err(|_| LiquidityPoolError::MathOverflow)?))
+        };
+
+        // Taking `amount_swapped + amount_deposited` grows with `amount_swapped`.
+        let (mut low, mut high) = (0u64, amount_in);
+        while low < high {
+            let mid = low + (high - low).div_ceil(2);
+            let (_, amount_deposited) = split(mid)?;
+            if mid as u128 + amount_deposited as u128 <= amount_in as u128 {
+                low = mid;
+            } else {
+                high = mid - 1;
+            }
+        }
+
+        let (amount_out, amount_deposited) = split(low)?;
+        Ok((low, amount_out, amount_deposited))
+    }
+
+    /// Returns `(reserve_in, reserve_out)` for the given direction.
+    fn reserves(&self, a_to_b: bool) -> (u64, u64) {
+        if a_to_b {
+            (self.token_a_reserve, self.token_b_reserve)
+        } else {
+            (self.token_b_reserve, self.token_a_reserve)
+        }
+    }
+
+    /// Reserve accumulators as they stand at `now`, with the current reserves
+    /// held since the last update.
+    pub fn reserve_cumulatives(&self, now: i64) -> (u128, u128) {
+        let elapsed = u64::try_from(now.saturating_sub(self.last_update_ts)).unwrap_or(0) as u128;
+        (
+            self.reserve_a_cumulative.wrapping_add(self.token_a_reserve as u128 * elapsed),
+            self.reserve_b_cumulative.wrapping_add(self.token_b_reserve as u128 * elapsed),
+        )
+    }
+
+    /// Brings the price and reserve accumulators up to `now`. Called before
+    /// every change to the reserves, so each interval is priced and weighted
+    /// at the reserves in effect during it.
+    pub fn update_price_accumulators(&mut self, now: i64) {
+        let observation = self.observe(now);
+        (self.reserve_a_cumulative, self.reserve_b_cumulative) = self.reserve_cumulatives(now);
+        self.price_a_cumulative_x64 = observation.price_a_cumulative_x64;
+        self.price_b_cumulative_x64 = observation.price_b_cumulative_x64;
+        self.last_update_ts = observation.timestamp;
+    }
+}
+
+/// Owned seeds of a pool PDA: `[b"liquidity_pool", mint_a, mint_b, fee_bps, bump]`,
+/// or without `fee_bps` for pools created before fee tiers.
+pub struct PoolSeeds {
+    token_a_mint: Pubkey,
+    token_b_mint: Pubkey,
+    fee_bps: Option<[u8; 8]>,
+    bump_seed: [u8; 1],
+}
+
+impl PoolSeeds {
+    pub fn as_slices(&self) -> Vec<&[u8]> {
+        let mut seeds: Vec<&[u8]> = vec![b"liquidity_pool", self.token_a_mint.as_ref(), self.token_b_mint.as_ref()];
+        if let Some(fee_bps) = &self.fee_bps {
+            seeds.push(fee_bps);
+        }
+        seeds.push(&self.bump_seed);
+        seeds
+    }
+}
+
+/// Of several pools for the same pair, typically one per fee tier, the one
+/// paying the most for selling exactly `amount_in`, with its output. Pools that
+/// cannot fill the trade are skipped.
+pub fn best_pool_for_exact_in(
+    pools: &[PoolState],
+    a_to_b: bool,
+    amount_in: u64,
+    now: i64,
+) -> Option<(&PoolState, u64)> {
+    pools
+        .iter()
+        .filter_map(|pool| Some((pool, pool.quote_exact_in(a_to_b, amount_in, now).ok()?)))
+        .max_by_key(|(_, amount_out)| *amount_out)
+}
+
+/// Of several pools for the same pair, the one charging the least to buy
+/// exactly `amount_out`, with its input.
+pub fn best_pool_for_exact_out(
+    pools: &[PoolState],
+    a_to_b: bool,
+    amount_out: u64,
+    now: i64,
+) -> Option<(&PoolState, u64)> {
+    pools
+        .iter()
+        .filter_map(|pool| Some((pool, pool.quote_exact_out(a_to_b, amount_out, now).ok()?)))
+        .min_by_key(|(_, amount_in)| *amount_in)
+}
+
+/// Invariant a pool trades along, chosen at initialization.
+#[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq, Clone, Copy)]
+pub enum CurveType {
+    /// `x * y = k`, for uncorrelated pairs.
+    ConstantProduct,
+    /// StableSwap with an amplification coefficient, for pairs that should trade near 1:1.
+    Stable,
+}
+
+/// Which side of the pool a swap sells into the pool.
+#[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq, Clone, Copy)]
+pub enum SwapDirection {
+    AToB,
+    BToA,
+}
+
+/// One pool of a `RouteSwap`, and which side of it the route sells into.
+#[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq, Clone, Copy)]
+pub struct RouteHop {
+    pub a_to_b: bool,
+}
+
+/// Most pools a single `RouteSwap` may pass through.
+pub const MAX_ROUTE_HOPS: usize = 4;
+
+/// LP tokens permanently locked on pool creation. They are counted in
+/// `lp_supply` but never minted, so they can never be burned: the pool can
+/// never be fully drained and the first depositor cannot inflate the LP price.
+pub const MINIMUM_LIQUIDITY: u64 = 1_000;
+
+/// Swap fee in basis points, retained in the pool for LPs. Weighted and
+/// concentrated pools always charge it; other pools choose a fee tier.
+pub const SWAP_FEE_BPS: u64 = 30;
+
+/// Flash loan fee in basis points, retained in the pool for LPs.
+pub const FLASH_LOAN_FEE_BPS: u64 = 9;
+
+/// Instruction data passed to the borrower program by `FlashLoan`. The
+/// borrower must return `amount + fee` of each token to the pool's vaults
+/// before its instruction ends.
+#[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq)]
+pub struct FlashLoanCallback {
+    pub amount_a: u64,
+    pub amount_b: u64,
+    pub fee_a: u64,
+    pub fee_b: u64,
 }
 
 // ------------------ LiquidityPool Instructions ------------------
 #[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq)]
 pub enum LiquidityPoolInstruction {
+    /// Creates the pool state, the LP mint and both vaults at their PDAs, and
+    /// the registry header and page on first use, the initializer paying their
+    /// rent, and seeds the pool. The initializer's
+    /// LP token account may be passed unallocated, signing as a new keypair,
+    /// to be created too.
     InitializePool {
         initial_amount_a: u64,
         initial_amount_b: u64,
+        curve_type: CurveType,
+        /// Amplification coefficient for `CurveType::Stable`; must be zero otherwise.
+        amp: u64,
+        /// Swap fee in basis points; must be one of the configured fee tiers.
+        fee_bps: u64,
     },
     AddLiquidity {
         amount_a: u64,
@@ -54,12 +346,251 @@ pub enum LiquidityPoolInstruction {
     RemoveLiquidity {
         lp_token_amount: u64,
     },
+    /// Deprecated: use `Swap { a_to_b: true, .. }`.
     SwapAforB {
         amount_a_in: u64,
     },
+    /// Deprecated: use `Swap { a_to_b: false, .. }`. The swapper's token
+    /// accounts are passed in B, A order.
     SwapBforA {
         amount_b_in: u64,
     },
+    /// Sets the recorded reserves to the actual vault balances.
+    Sync,
+    /// Transfers any vault balance in excess of the recorded reserves to the recipients.
+    Skim,
+    /// Buys exactly `amount_out`, paying at most `max_amount_in`.
+    SwapExactOut {
+        amount_out: u64,
+        max_amount_in: u64,
+        direction: SwapDirection,
+    },
+    /// Sells exactly `amount_in`, receiving at least `min_out`.
+    Swap {
+        amount_in: u64,
+        min_out: u64,
+        a_to_b: bool,
+    },
+    /// Ramps a stable pool's amplification linearly from its current value to
+    /// `target_amp` at `stop_ramp_ts`. Signed by the pool creator.
+    RampAmp {
+        target_amp: u64,
+        stop_ramp_ts: i64,
+    },
+    /// Freezes a stable pool's amplification at its current value. Signed by the
+    /// pool creator.
+    StopRampAmp,
+    /// Creates a weighted pool holding one token per entry of `weights`, which
+    /// must sum to `weighted_math::ONE`, seeded with `amounts`. The pool state
+    /// is created at its PDA, and the LP mint from a new keypair if passed
+    /// unallocated, the creator paying their rent.
+    InitializeWeightedPool {
+        weights: Vec<u64>,
+        amounts: Vec<u64>,
+    },
+    /// Sells exactly `amount_in` of the weighted pool's `token_in` for at least
+    /// `min_out` of its `token_out`.
+    WeightedSwap {
+        token_in: u8,
+        token_out: u8,
+        amount_in: u64,
+        min_out: u64,
+    },
+    /// Mints `lp_amount` weighted-pool LP tokens for a proportional deposit of
+    /// every token, paying at most `max_amounts`.
+    JoinWeightedPool {
+        lp_amount: u64,
+        max_amounts: Vec<u64>,
+    },
+    /// Burns `lp_amount` weighted-pool LP tokens for a proportional share of
+    /// every token, receiving at least `min_amounts`.
+    ExitWeightedPool {
+        lp_amount: u64,
+        min_amounts: Vec<u64>,
+    },
+    /// Creates a concentrated-liquidity pool whose positions use ticks that are
+    /// multiples of `tick_spacing`, starting at `initial_sqrt_price_x64` (Q64.64).
+    /// The pool state and its vaults are created at their PDAs, the creator
+    /// paying their rent, and the pool is recorded in the registry.
+    InitializeConcentratedPool {
+        tick_spacing: u16,
+        initial_sqrt_price_x64: u128,
+    },
+    /// Creates an empty position over `[tick_lower, tick_upper)` from a new
+    /// keypair, the owner paying its rent.
+    OpenPosition {
+        tick_lower: i32,
+        tick_upper: i32,
+    },
+    /// Adds `liquidity` to a position, paying at most `max_amount_a` and
+    /// `max_amount_b`. The position must end up with at least
+    /// `MIN_TICK_LIQUIDITY`.
+    IncreaseLiquidity {
+        liquidity: u128,
+        max_amount_a: u64,
+        max_amount_b: u64,
+    },
+    /// Removes `liquidity` from a position, releasing at least `min_amount_a`
+    /// and `min_amount_b` to be collected. The position must be left empty or
+    /// with at least `MIN_TICK_LIQUIDITY`.
+    DecreaseLiquidity {
+        liquidity: u128,
+        min_amount_a: u64,
+        min_amount_b: u64,
+    },
+    /// Pays out a position's released liquidity and earned fees.
+    CollectPosition,
+    /// Sells up to `amount_in` to a concentrated pool, stopping if the price
+    /// reaches `sqrt_price_limit_x64`, and receiving at least `min_out`.
+    ConcentratedSwap {
+        amount_in: u64,
+        min_out: u64,
+        a_to_b: bool,
+        sqrt_price_limit_x64: u128,
+    },
+    /// Returns the pool's current `oracle::PriceObservation` as return data,
+    /// without modifying the pool.
+    ObservePrice,
+    /// Lends `amount_a` and `amount_b` of the pool's reserves to the borrower,
+    /// invokes the borrower program with a `FlashLoanCallback`, and fails unless
+    /// both vaults have been repaid with a `FLASH_LOAN_FEE_BPS` fee.
+    FlashLoan {
+        amount_a: u64,
+        amount_b: u64,
+    },
+    /// Creates the fee config at its PDA with the allowed `fee_tiers`, making
+    /// the signer its admin and paying its rent. The signer must be the
+    /// program's upgrade authority, shown by the program data account.
+    InitializeFeeConfig {
+        fee_tiers: Vec<u64>,
+    },
+    /// Replaces the allowed fee tiers. Signed by the fee config admin.
+    SetFeeTiers {
+        fee_tiers: Vec<u64>,
+    },
+    /// Sells exactly `amount_in` through each pool of `hops` in turn, feeding
+    /// each hop's output into the next, and receiving at least `min_out` from
+    /// the last. Intermediate hops have no slippage bound of their own.
+    RouteSwap {
+        amount_in: u64,
+        min_out: u64,
+        hops: Vec<RouteHop>,
+    },
+    /// Creates the farm for a pool's LP tokens, emitting `emission_rate` of the
+    /// reward mint per second once funded. Signed by the pool creator, who pays
+    /// its rent.
+    InitializeFarm {
+        emission_rate: u64,
+    },
+    /// Adds `amount` reward tokens to the farm. Signed by the farm admin.
+    FundFarm {
+        amount: u64,
+    },
+    /// Changes the farm's emission rate from now on. Signed by the farm admin.
+    SetEmissionRate {
+        emission_rate: u64,
+    },
+    /// Stakes `amount` LP tokens into the farm, creating the owner's stake on
+    /// first use.
+    Stake {
+        amount: u64,
+    },
+    /// Withdraws `amount` staked LP tokens from the farm.
+    Unstake {
+        amount: u64,
+    },
+    /// Pays out the stake's earned rewards.
+    ClaimRewards,
+    /// Creates a distribution of income in the quote mint to holders of the
+    /// property mint, managed by the signer, who pays its rent.
+    InitializeDistribution,
+    /// Deposits `amount` of income, split pro rata between the property tokens
+    /// locked by holders and those held on average since the previous deposit
+    /// by the registered pools of the property token, all of which must be
+    /// listed. Signed by the manager.
+    DepositIncome {
+        amount: u64,
+    },
+    /// Locks `amount` property tokens into the distribution to earn income,
+    /// creating the holder's holding on first use.
+    LockPropertyTokens {
+        amount: u64,
+    },
+    /// Withdraws `amount` locked property tokens.
+    UnlockPropertyTokens {
+        amount: u64,
+    },
+    /// Pays out the holder's earned income.
+    ClaimIncome,
+    /// Pays a pool's earned income to its LPs: into its reserve of the quote
+    /// token, its farm's rewards if it does not trade the quote token, or the
+    /// fees of a concentrated pool's positions in range.
+    ClaimPoolIncome,
+    /// Deposits up to `amount_in` of only token A (`token_in == 0`) or B
+    /// (`token_in == 1`), swapping part of it for the other side, and mints at
+    /// least `min_lp_out` LP tokens.
+    AddLiquiditySingle {
+        token_in: u8,
+        amount_in: u64,
+        min_lp_out: u64,
+    },
+    /// Burns `lp_amount` LP tokens and swaps the share of the other side for
+    /// token A (`token_out == 0`) or B (`token_out == 1`), receiving at least
+    /// `min_amount_out` of it.
+    RemoveLiquiditySingle {
+        token_out: u8,
+        lp_amount: u64,
+        min_amount_out: u64,
+    },
+    /// `Swap` paying or receiving the pool's wSOL side in lamports. Takes the
+    /// `Swap` accounts, then the system program, then the `Swap` trailing
+    /// accounts, which must include the SPL Token program if the pool's token
+    /// program is Token-2022. The swapper's account for the wSOL side must be a
+    /// wSOL account they own, which is closed afterwards.
+    SwapNative {
+        amount_in: u64,
+        min_out: u64,
+        a_to_b: bool,
+    },
+    /// `AddLiquidity` paying the pool's wSOL side in lamports, with unused
+    /// lamports refunded. Accounts as for `SwapNative`.
+    AddLiquidityNative {
+        amount_a: u64,
+        amount_b: u64,
+    },
+    /// `RemoveLiquidity` receiving the pool's wSOL side in lamports. Accounts
+    /// as for `SwapNative`.
+    RemoveLiquidityNative {
+        lp_token_amount: u64,
+    },
+    /// Upgrades a pool written with an older `PoolState` layout to the current
+    /// one, growing the account if needed with the payer covering the rent.
+    /// Moves the pool's tokens from its old vaults, which must hold at least
+    /// its reserves, into new ones at their PDAs, the payer paying their rent.
+    ///
+    /// Accounts: payer, pool state, system program, old pool token A, old pool
+    /// token B, token A mint, token B mint, pool token A, pool token B, token
+    /// program, followed by the other token program if the mints differ.
+    MigratePool,
+    /// Closes a pool whose only liquidity left is the locked minimum, sending
+    /// what remains in the vaults to the creator's token accounts and the rent
+    /// of the vaults and the pool state to the creator. The LP mint cannot be
+    /// closed; it stays with the pool's PDA and is taken up again if the pool
+    /// is reopened. The pool's registry entry is marked closed. A pool with a
+    /// farm cannot be closed until the farm is closed with `CloseFarm`.
+    ///
+    /// Accounts: creator, pool state, pool token A, pool token B, LP mint,
+    /// creator token A, creator token B, token program, farm, registry page
+    /// holding the pool, then any trailing token accounts.
+    ClosePool,
+    /// Closes a farm once nothing is staked in it and every stake has claimed
+    /// its rewards, sending what is left in its vaults to the admin's token
+    /// accounts and the rent of the vaults and the farm to the admin. Signed
+    /// by the farm admin.
+    ///
+    /// Accounts: farm admin, farm, staked vault, reward vault, admin LP token,
+    /// admin reward token, token program.
+    CloseFarm,
 }
 
 // ------------------ LiquidityPool Errors ------------------
@@ -89,6 +620,52 @@ pub enum LiquidityPoolError {
     InvalidPdaAccount,
     #[error("Token A and B mints cannot be the same")]
     SameTokenMints,
+    #[error("Insufficient initial liquidity")]
+    InsufficientInitialLiquidity,
+    #[error("Vault balance is below the recorded reserve")]
+    ReserveMismatch,
+    #[error("Slippage tolerance exceeded")]
+    SlippageExceeded,
+    #[error("Math overflow")]
+    MathOverflow,
+    #[error("Invalid amplification coefficient")]
+    InvalidAmplification,
+    #[error("Signer is not the pool creator")]
+    Unauthorized,
+    #[error("Invalid pool weights")]
+    InvalidWeights,
+    #[error("Invalid tick range")]
+    InvalidTickRange,
+    #[error("Pool has no room for another initialized tick")]
+    TickCapacityExceeded,
+    #[error("Flash loan was not repaid with its fee")]
+    FlashLoanNotRepaid,
+    #[error("Token A mint must sort before token B mint")]
+    NonCanonicalMintOrder,
+    #[error("Fee tier is not allowed")]
+    InvalidFeeTier,
+    #[error("Invalid swap route")]
+    InvalidRoute,
+    #[error("Mint has an unsupported Token-2022 extension")]
+    UnsupportedMintExtension,
+    #[error("Pool uses an outdated layout and must be migrated")]
+    PoolNeedsMigration,
+    #[error("Pool already uses the current layout")]
+    PoolAlreadyMigrated,
+    #[error("Account holds data of another type")]
+    InvalidAccountType,
+    #[error("Pool still holds liquidity")]
+    PoolNotEmpty,
+    #[error("Pools do not match the registered pools of the pair")]
+    PoolSetMismatch,
+    #[error("Pool is not recorded in the registry page")]
+    PoolNotRegistered,
+    #[error("Pool still has a farm")]
+    PoolHasFarm,
+    #[error("Position liquidity is below the minimum")]
+    LiquidityBelowMinimum,
+    #[error("Farm still has stakes or unclaimed rewards")]
+    FarmNotEmpty,
 }
 
 impl From<LiquidityPoolError> for ProgramError {
@@ -97,6 +674,18 @@ impl From<LiquidityPoolError> for ProgramError {
     }
 }
 
+impl From<CurveError> for ProgramError {
+    fn from(e: CurveError) -> Self {
+        match e {
+            CurveError::MathOverflow => LiquidityPoolError::MathOverflow.into(),
+            CurveError::ZeroReserves | CurveError::InsufficientLiquidity => LiquidityPoolError::InsufficientFunds.into(),
+            CurveError::InvalidFee | CurveError::NotConverged => LiquidityPoolError::InvalidAmount.into(),
+            CurveError::InvalidAmplification => LiquidityPoolError::InvalidAmplification.into(),
+            CurveError::InvalidTick => LiquidityPoolError::InvalidTickRange.into(),
+        }
+    }
+}
+
 // ------------------ Entrypoint ------------------
 entrypoint!(process_instruction);
 
@@ -111,9 +700,9 @@ pub fn process_instruction(
         .map_err(|_| ProgramError::InvalidInstructionData)?;
 
     match instruction {
-        LiquidityPoolInstruction::InitializePool { initial_amount_a, initial_amount_b } => {
+        LiquidityPoolInstruction::InitializePool { initial_amount_a, initial_amount_b, curve_type, amp, fee_bps } => {
             msg!("Instruction: InitializePool");
-            process_initialize_pool(program_id, accounts, initial_amount_a, initial_amount_b)
+            process_initialize_pool(program_id, accounts, initial_amount_a, initial_amount_b, curve_type, amp, fee_bps)
         }
         LiquidityPoolInstruction::AddLiquidity { amount_a, amount_b } => {
             msg!("Instruction: AddLiquidity");
@@ -125,12 +714,176 @@ pub fn process_instruction(
         }
         LiquidityPoolInstruction::SwapAforB { amount_a_in } => {
             msg!("Instruction: SwapAforB");
-            process_swap_a_for_b(program_id, accounts, amount_a_in)
+            process_swap(program_id, accounts, amount_a_in, 0, true)
         }
         LiquidityPoolInstruction::SwapBforA { amount_b_in } => {
             msg!("Instruction: SwapBforA");
             process_swap_b_for_a(program_id, accounts, amount_b_in)
         }
+        LiquidityPoolInstruction::Sync => {
+            msg!("Instruction: Sync");
+            process_sync(program_id, accounts)
+        }
+        LiquidityPoolInstruction::Skim => {
+            msg!("Instruction: Skim");
+            process_skim(program_id, accounts)
+        }
+        LiquidityPoolInstruction::SwapExactOut { amount_out, max_amount_in, direction } => {
+            msg!("Instruction: SwapExactOut");
+            process_swap_exact_out(program_id, accounts, amount_out, max_amount_in, direction)
+        }
+        LiquidityPoolInstruction::Swap { amount_in, min_out, a_to_b } => {
+            msg!("Instruction: Swap");
+            process_swap(program_id, accounts, amount_in, min_out, a_to_b)
+        }
+        LiquidityPoolInstruction::RampAmp { target_amp, stop_ramp_ts } => {
+            msg!("Instruction: RampAmp");
+            process_ramp_amp(program_id, accounts, target_amp, stop_ramp_ts)
+        }
+        LiquidityPoolInstruction::StopRampAmp => {
+            msg!("Instruction: StopRampAmp");
+            process_stop_ramp_amp(program_id, accounts)
+        }
+        LiquidityPoolInstruction::InitializeWeightedPool { weights, amounts } => {
+            msg!("Instruction: InitializeWeightedPool");
+            weighted_pool::process_initialize_weighted_pool(program_id, accounts, weights, amounts)
+        }
+        LiquidityPoolInstruction::WeightedSwap { token_in, token_out, amount_in, min_out } => {
+            msg!("Instruction: WeightedSwap");
+            weighted_pool::process_weighted_swap(program_id, accounts, token_in, token_out, amount_in, min_out)
+        }
+        LiquidityPoolInstruction::JoinWeightedPool { lp_amount, max_amounts } => {
+            msg!("Instruction: JoinWeightedPool");
+            weighted_pool::process_join_weighted_pool(program_id, accounts, lp_amount, max_amounts)
+        }
+        LiquidityPoolInstruction::ExitWeightedPool { lp_amount, min_amounts } => {
+            msg!("Instruction: ExitWeightedPool");
+            weighted_pool::process_exit_weighted_pool(program_id, accounts, lp_amount, min_amounts)
+        }
+        LiquidityPoolInstruction::InitializeConcentratedPool { tick_spacing, initial_sqrt_price_x64 } => {
+            msg!("Instruction: InitializeConcentratedPool");
+            concentrated_pool::process_initialize_concentrated_pool(program_id, accounts, tick_spacing, initial_sqrt_price_x64)
+        }
+        LiquidityPoolInstruction::OpenPosition { tick_lower, tick_upper } => {
+            msg!("Instruction: OpenPosition");
+            concentrated_pool::process_open_position(program_id, accounts, tick_lower, tick_upper)
+        }
+        LiquidityPoolInstruction::IncreaseLiquidity { liquidity, max_amount_a, max_amount_b } => {
+            msg!("Instruction: IncreaseLiquidity");
+            concentrated_pool::process_increase_liquidity(program_id, accounts, liquidity, max_amount_a, max_amount_b)
+        }
+        LiquidityPoolInstruction::DecreaseLiquidity { liquidity, min_amount_a, min_amount_b } => {
+            msg!("Instruction: DecreaseLiquidity");
+            concentrated_pool::process_decrease_liquidity(program_id, accounts, liquidity, min_amount_a, min_amount_b)
+        }
+        LiquidityPoolInstruction::CollectPosition => {
+            msg!("Instruction: CollectPosition");
+            concentrated_pool::process_collect_position(program_id, accounts)
+        }
+        LiquidityPoolInstruction::ConcentratedSwap { amount_in, min_out, a_to_b, sqrt_price_limit_x64 } => {
+            msg!("Instruction: ConcentratedSwap");
+            concentrated_pool::process_concentrated_swap(program_id, accounts, amount_in, min_out, a_to_b, sqrt_price_limit_x64)
+        }
+        LiquidityPoolInstruction::ObservePrice => {
+            msg!("Instruction: ObservePrice");
+            process_observe_price(program_id, accounts)
+        }
+        LiquidityPoolInstruction::FlashLoan { amount_a, amount_b } => {
+            msg!("Instruction: FlashLoan");
+            process_flash_loan(program_id, accounts, amount_a, amount_b)
+        }
+        LiquidityPoolInstruction::InitializeFeeConfig { fee_tiers } => {
+            msg!("Instruction: InitializeFeeConfig");
+            fee_config::process_initialize_fee_config(program_id, accounts, fee_tiers)
+        }
+        LiquidityPoolInstruction::SetFeeTiers { fee_tiers } => {
+            msg!("Instruction: SetFeeTiers");
+            fee_config::process_set_fee_tiers(program_id, accounts, fee_tiers)
+        }
+        LiquidityPoolInstruction::RouteSwap { amount_in, min_out, hops } => {
+            msg!("Instruction: RouteSwap");
+            process_route_swap(program_id, accounts, amount_in, min_out, hops)
+        }
+        LiquidityPoolInstruction::InitializeFarm { emission_rate } => {
+            msg!("Instruction: InitializeFarm");
+            farm::process_initialize_farm(program_id, accounts, emission_rate)
+        }
+        LiquidityPoolInstruction::FundFarm { amount } => {
+            msg!("Instruction: FundFarm");
+            farm::process_fund_farm(program_id, accounts, amount)
+        }
+        LiquidityPoolInstruction::SetEmissionRate { emission_rate } => {
+            msg!("Instruction: SetEmissionRate");
+            farm::process_set_emission_rate(program_id, accounts, emission_rate)
+        }
+        LiquidityPoolInstruction::Stake { amount } => {
+            msg!("Instruction: Stake");
+            farm::process_stake(program_id, accounts, amount)
+        }
+        LiquidityPoolInstruction::Unstake { amount } => {
+            msg!("Instruction: Unstake");
+            farm::process_unstake(program_id, accounts, amount)
+        }
+        LiquidityPoolInstruction::ClaimRewards => {
+            msg!("Instruction: ClaimRewards");
+            farm::process_claim_rewards(program_id, accounts)
+        }
+        LiquidityPoolInstruction::InitializeDistribution => {
+            msg!("Instruction: InitializeDistribution");
+            distribution::process_initialize_distribution(program_id, accounts)
+        }
+        LiquidityPoolInstruction::DepositIncome { amount } => {
+            msg!("Instruction: DepositIncome");
+            distribution::process_deposit_income(program_id, accounts, amount)
+        }
+        LiquidityPoolInstruction::LockPropertyTokens { amount } => {
+            msg!("Instruction: LockPropertyTokens");
+            distribution::process_lock_property_tokens(program_id, accounts, amount)
+        }
+        LiquidityPoolInstruction::UnlockPropertyTokens { amount } => {
+            msg!("Instruction: UnlockPropertyTokens");
+            distribution::process_unlock_property_tokens(program_id, accounts, amount)
+        }
+        LiquidityPoolInstruction::ClaimIncome => {
+            msg!("Instruction: ClaimIncome");
+            distribution::process_claim_income(program_id, accounts)
+        }
+        LiquidityPoolInstruction::ClaimPoolIncome => {
+            msg!("Instruction: ClaimPoolIncome");
+            distribution::process_claim_pool_income(program_id, accounts)
+        }
+        LiquidityPoolInstruction::AddLiquiditySingle { token_in, amount_in, min_lp_out } => {
+            msg!("Instruction: AddLiquiditySingle");
+            process_add_liquidity_single(program_id, accounts, token_in, amount_in, min_lp_out)
+        }
+        LiquidityPoolInstruction::RemoveLiquiditySingle { token_out, lp_amount, min_amount_out } => {
+            msg!("Instruction: RemoveLiquiditySingle");
+            process_remove_liquidity_single(program_id, accounts, token_out, lp_amount, min_amount_out)
+        }
+        LiquidityPoolInstruction::SwapNative { amount_in, min_out, a_to_b } => {
+            msg!("Instruction: SwapNative");
+            process_swap_native(program_id, accounts, amount_in, min_out, a_to_b)
+        }
+        LiquidityPoolInstruction::AddLiquidityNative { amount_a, amount_b } => {
+            msg!("Instruction: AddLiquidityNative");
+            process_add_liquidity_native(program_id, accounts, amount_a, amount_b)
+        }
+        LiquidityPoolInstruction::RemoveLiquidityNative { lp_token_amount } => {
+            msg!("Instruction: RemoveLiquidityNative");
+            process_remove_liquidity_native(program_id, accounts, lp_token_amount)
+        }
+        LiquidityPoolInstruction::MigratePool => {
+            msg!("Instruction: MigratePool");
+            migration::process_migrate_pool(program_id, accounts)
+        }
+        LiquidityPoolInstruction::ClosePool => {
+            msg!("Instruction: ClosePool");
+            process_close_pool(program_id, accounts)
+        }
+        LiquidityPoolInstruction::CloseFarm => {
+            msg!("Instruction: CloseFarm");
+            farm::process_close_farm(program_id, accounts)
+        }
     }
 }
 
@@ -140,6 +893,9 @@ fn process_initialize_pool(
     accounts: &[AccountInfo],
     initial_amount_a: u64,
     initial_amount_b: u64,
+    curve_type: CurveType,
+    amp: u64,
+    fee_bps: u64,
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
 
@@ -156,6 +912,9 @@ fn process_initialize_pool(
     let token_program_account = next_account_info(account_info_iter)?; // 10
     let rent_sysvar_account = next_account_info(account_info_iter)?; // 11
     let system_program_account = next_account_info(account_info_iter)?; // 12
+    let registry_header_account = next_account_info(account_info_iter)?; // 13
+    let registry_page_account = next_account_info(account_info_iter)?; // 14
+    let fee_config_account = next_account_info(account_info_iter)?; // 15
 
     // Validate accounts
     if !initializer_account.is_signer {
@@ -165,6 +924,25 @@ fn process_initialize_pool(
     if token_a_mint_account.key == token_b_mint_account.key {
         return Err(LiquidityPoolError::SameTokenMints.into());
     }
+    // One pool per pair: A/B and B/A would otherwise derive different PDAs.
+    if token_a_mint_account.key > token_b_mint_account.key {
+        return Err(LiquidityPoolError::NonCanonicalMintOrder.into());
+    }
+
+    if initial_amount_a == 0 || initial_amount_b == 0 {
+        return Err(LiquidityPoolError::ZeroAmountNotAllowed.into());
+    }
+
+    let amp_in_range = (stable_swap::MIN_AMP..=stable_swap::MAX_AMP).contains(&amp);
+    match curve_type {
+        CurveType::ConstantProduct if amp != 0 => return Err(LiquidityPoolError::InvalidAmplification.into()),
+        CurveType::Stable if !amp_in_range => return Err(LiquidityPoolError::InvalidAmplification.into()),
+        _ => {}
+    }
+
+    if !fee_config::load_fee_config(program_id, fee_config_account)?.allows(fee_bps) {
+        return Err(LiquidityPoolError::InvalidFeeTier.into());
+    }
 
     // Derive PDA and check against provided pool_state_account
     let (pda, bump_seed) = Pubkey::find_program_address(
@@ -172,6 +950,7 @@ fn process_initialize_pool(
             b"liquidity_pool",
             token_a_mint_account.key.as_ref(),
             token_b_mint_account.key.as_ref(),
+            &fee_bps.to_le_bytes(),
         ],
         program_id,
     );
@@ -179,119 +958,171 @@ fn process_initialize_pool(
     if pda != *pool_state_account.key {
         return Err(ProgramError::InvalidSeeds);
     }
-
-    // Check if pool_state_account is already initialized
-    let mut pool_state_data = try_from_slice_unchecked::<PoolState>(&pool_state_account.data.borrow()).unwrap_or_else(|_| PoolState {
-        is_initialized: 0,
-        token_a_mint: Pubkey::default(),
-        token_b_mint: Pubkey::default(),
-        token_a_reserve: 0,
-        token_b_reserve: 0,
-        lp_mint: Pubkey::default(),
-        lp_supply: 0,
-        bump_seed: 0,
-    });
-
-    if pool_state_data.is_initialized != 0 {
-        return Err(LiquidityPoolError::PoolAlreadyInitialized.into());
+    let (lp_mint_pda, lp_mint_bump) = lp_mint_address(program_id, &pda);
+    let (vault_a_pda, vault_a_bump) = vault_address(program_id, &pda, token_a_mint_account.key);
+    let (vault_b_pda, vault_b_bump) = vault_address(program_id, &pda, token_b_mint_account.key);
+    if *lp_mint_account.key != lp_mint_pda
+        || *pool_token_a_account.key != vault_a_pda
+        || *pool_token_b_account.key != vault_b_pda
+    {
+        return Err(ProgramError::InvalidSeeds);
     }
-
-    // Check rent exemption for pool_state_account
-    if !Rent::get()?.is_exempt(pool_state_account.lamports(), PoolState::LEN) {
-        return Err(LiquidityPoolError::NotRentExempt.into());
+    if *system_program_account.key != system_program::id() {
+        return Err(ProgramError::IncorrectProgramId);
     }
 
-    // Initialize LP Mint
-    invoke(
-        &spl_token_instruction::initialize_mint(
-            token_program_account.key,
-            lp_mint_account.key,
-            pool_state_account.key, // LP mint authority is the PDA
-            Some(pool_state_account.key), // Freeze authority is also the PDA
-            6, // Decimals (assuming 6 for now, client doesn't specify)
-        )?,
-        &[
-            lp_mint_account.clone(),
-            rent_sysvar_account.clone(),
-            token_program_account.clone(),
-        ],
-    )?;
+    // The program creates the pool state, so one it already owns is either
+    // this pool or some other data that must not be overwritten
+    if pool_state_account.owner == program_id {
+        if pool_state_account.data.borrow().starts_with(&POOL_STATE_DISCRIMINATOR) || migration::is_legacy_pool(pool_state_account) {
+            return Err(LiquidityPoolError::PoolAlreadyInitialized.into());
+        }
+        return Err(LiquidityPoolError::InvalidAccountType.into());
+    }
 
-    // Transfer initial liquidity from initializer to pool
-    // Token A
-    invoke(
-        &spl_token_instruction::transfer(
+    let tokens = TokenAccounts::new(token_program_account, account_info_iter.as_slice())?;
+    validate_mint(token_a_mint_account)?;
+    validate_mint(token_b_mint_account)?;
+
+    let pool_seeds: &[&[u8]] = &[
+        b"liquidity_pool",
+        token_a_mint_account.key.as_ref(),
+        token_b_mint_account.key.as_ref(),
+        &fee_bps.to_le_bytes(),
+        &[bump_seed],
+    ];
+    create_account(initializer_account, pool_state_account, system_program_account, program_id, PoolState::LEN, &[pool_seeds])?;
+    for (vault_account, mint_account, vault_bump) in [
+        (pool_token_a_account, token_a_mint_account, vault_a_bump),
+        (pool_token_b_account, token_b_mint_account, vault_b_bump),
+    ] {
+        tokens.create_account(
+            initializer_account,
+            vault_account,
+            mint_account,
+            &pda,
+            system_program_account,
+            &[&[b"vault", pda.as_ref(), mint_account.key.as_ref(), &[vault_bump]]],
+        )?;
+    }
+    if *lp_mint_account.owner == system_program::id() {
+        create_account(
+            initializer_account,
+            lp_mint_account,
+            system_program_account,
             token_program_account.key,
-            initializer_token_a_account.key,
-            pool_token_a_account.key,
-            initializer_account.key,
-            &[],
-            initial_amount_a,
-        )?,
-        &[
-            initializer_token_a_account.clone(),
-            pool_token_a_account.clone(),
-            initializer_account.clone(),
-            token_program_account.clone(),
-        ],
-    )?;
+            Mint::LEN,
+            &[&[b"lp_mint", pda.as_ref(), &[lp_mint_bump]]],
+        )?;
+
+        // Initialize LP Mint
+        invoke(
+            &spl_token_instruction::initialize_mint(
+                token_program_account.key,
+                lp_mint_account.key,
+                pool_state_account.key, // LP mint authority is the PDA
+                Some(pool_state_account.key), // Freeze authority is also the PDA
+                6, // Decimals (assuming 6 for now, client doesn't specify)
+            )?,
+            &[
+                lp_mint_account.clone(),
+                rent_sysvar_account.clone(),
+                token_program_account.clone(),
+            ],
+        )?;
+    } else {
+        // A pool closed with `ClosePool` left its LP mint behind, with no
+        // tokens outstanding; reopening the pool takes it up again
+        let lp_mint = token::unpack_mint(lp_mint_account)?;
+        if lp_mint.mint_authority != COption::Some(pda) || lp_mint.supply != 0 {
+            return Err(LiquidityPoolError::InvalidTokenMint.into());
+        }
+    }
 
-    // Token B
-    invoke(
-        &spl_token_instruction::transfer(
-            token_program_account.key,
-            initializer_token_b_account.key,
-            pool_token_b_account.key,
+    // The initializer's LP token account can be created along with the pool,
+    // from a new keypair, since it cannot exist before the LP mint
+    if *initializer_lp_token_account.owner == system_program::id() && initializer_lp_token_account.data_is_empty() {
+        tokens.create_account(
+            initializer_account,
+            initializer_lp_token_account,
+            lp_mint_account,
             initializer_account.key,
+            system_program_account,
             &[],
-            initial_amount_b,
-        )?,
-        &[
-            initializer_token_b_account.clone(),
-            pool_token_b_account.clone(),
-            initializer_account.clone(),
-            token_program_account.clone(),
-        ],
-    )?;
+        )?;
+    }
 
-    // Mint initial LP tokens to initializer
-    let mint_lp_ix = spl_token_instruction::mint_to(
-        token_program_account.key,
-        lp_mint_account.key,
-        initializer_lp_token_account.key,
-        pool_state_account.key, // PDA is the mint authority
-        &[&pda], // Signer for PDA
-        initial_amount_a + initial_amount_b, // Simple initial LP calculation
-    )?;
+    // Transfer initial liquidity from initializer to pool. Transfer fees may
+    // withhold part of it, so the pool starts from what actually arrived.
+    let initial_amount_a = tokens.transfer(initializer_token_a_account, pool_token_a_account, initializer_account, initial_amount_a, &[])?;
+    let initial_amount_b = tokens.transfer(initializer_token_b_account, pool_token_b_account, initializer_account, initial_amount_b, &[])?;
 
-    invoke_signed(
-        &mint_lp_ix,
-        &[
-            lp_mint_account.clone(),
-            initializer_lp_token_account.clone(),
-            pool_state_account.clone(),
-            token_program_account.clone(),
-        ],
-        &[&[
-            b"liquidity_pool",
-            token_a_mint_account.key.as_ref(),
-            token_b_mint_account.key.as_ref(),
-            &[bump_seed],
-        ]],
+    // Initial LP supply is the geometric mean of the deposits, which is independent
+    // of the mints' decimals. MINIMUM_LIQUIDITY of it is locked forever.
+    let initial_lp_supply = curve::initial_lp_supply(initial_amount_a, initial_amount_b)?;
+    if initial_lp_supply <= MINIMUM_LIQUIDITY {
+        return Err(LiquidityPoolError::InsufficientInitialLiquidity.into());
+    }
+    let initializer_lp_amount = checked_sub(initial_lp_supply, MINIMUM_LIQUIDITY)?;
+
+    // Mint initial LP tokens to initializer; the locked minimum liquidity is never minted
+    tokens.mint_to(
+        lp_mint_account,
+        initializer_lp_token_account,
+        pool_state_account, // PDA is the mint authority
+        initializer_lp_amount,
+        &[pool_seeds],
     )?;
 
-    // Update PoolState
-    pool_state_data.is_initialized = 1;
-    pool_state_data.token_a_mint = *token_a_mint_account.key;
-    pool_state_data.token_b_mint = *token_b_mint_account.key;
-    pool_state_data.token_a_reserve = initial_amount_a;
-    pool_state_data.token_b_reserve = initial_amount_b;
-    pool_state_data.lp_mint = *lp_mint_account.key;
-    pool_state_data.lp_supply = initial_amount_a + initial_amount_b; // Initial LP supply
-    pool_state_data.bump_seed = bump_seed;
+    // Write PoolState
+    let clock = Clock::get()?;
+    let pool_state_data = PoolState {
+        discriminator: POOL_STATE_DISCRIMINATOR,
+        is_initialized: 1,
+        version: POOL_STATE_VERSION,
+        token_a_mint: *token_a_mint_account.key,
+        token_b_mint: *token_b_mint_account.key,
+        token_a_reserve: initial_amount_a,
+        token_b_reserve: initial_amount_b,
+        lp_mint: *lp_mint_account.key,
+        lp_supply: initial_lp_supply, // Includes the locked MINIMUM_LIQUIDITY
+        bump_seed,
+        curve_type,
+        creator: *initializer_account.key,
+        initial_amp: amp,
+        target_amp: amp,
+        ramp_start_ts: 0,
+        ramp_stop_ts: 0,
+        price_a_cumulative_x64: 0,
+        price_b_cumulative_x64: 0,
+        last_update_ts: clock.unix_timestamp,
+        fee_bps,
+        vault_a: vault_a_pda,
+        vault_b: vault_b_pda,
+        reserve_a_cumulative: 0,
+        reserve_b_cumulative: 0,
+        reserved: [0; POOL_STATE_RESERVED],
+        legacy_seeds: false,
+    };
 
     pool_state_data.serialize(&mut &mut pool_state_account.data.borrow_mut()[..])?;
 
+    registry::append_pool(
+        program_id,
+        initializer_account,
+        registry_header_account,
+        registry_page_account,
+        system_program_account,
+        RegistryEntry {
+            token_a_mint: *token_a_mint_account.key,
+            token_b_mint: *token_b_mint_account.key,
+            pool: pda,
+            lp_mint: *lp_mint_account.key,
+            fee_bps,
+            created_slot: clock.slot,
+        },
+    )?;
+
     msg!("Liquidity Pool initialized for Token A: {} and Token B: {}", token_a_mint_account.key, token_b_mint_account.key);
     Ok(())
 }
@@ -319,96 +1150,56 @@ fn process_add_liquidity(
         return Err(ProgramError::MissingRequiredSignature);
     }
 
-    let mut pool_state_data = PoolState::try_from_slice(&pool_state_account.data.borrow())?;
-    if pool_state_data.is_initialized == 0 {
-        return Err(LiquidityPoolError::PoolNotInitialized.into());
-    }
+    let mut pool_state_data = load_pool_state(program_id, pool_state_account)?;
+    let pda = *pool_state_account.key;
+    let tokens = TokenAccounts::new(token_program_account, account_info_iter.as_slice())?;
+    validate_pool_vaults(&pool_state_data, &pda, pool_token_a_account, pool_token_b_account)?;
+    validate_lp_mint(lp_mint_account, &pool_state_data)?;
 
     if amount_a == 0 || amount_b == 0 {
         return Err(LiquidityPoolError::ZeroAmountNotAllowed.into());
     }
 
-    // Check PDA
-    let (pda, _bump_seed) = Pubkey::find_program_address(
-        &[
-            b"liquidity_pool",
-            pool_state_data.token_a_mint.as_ref(),
-            pool_state_data.token_b_mint.as_ref(),
-        ],
-        program_id,
-    );
-    if pda != *pool_state_account.key {
-        return Err(ProgramError::InvalidSeeds);
-    }
+    // Deposit at the current pool ratio
+    let (amount_a, amount_b) = curve::deposit_amounts(
+        amount_a,
+        amount_b,
+        pool_state_data.token_a_reserve,
+        pool_state_data.token_b_reserve,
+    )?;
 
     // Transfer tokens from provider to pool
-    invoke(
-        &spl_token_instruction::transfer(
-            token_program_account.key,
-            provider_token_a_account.key,
-            pool_token_a_account.key,
-            provider_account.key,
-            &[],
-            amount_a,
-        )?,
-        &[
-            provider_token_a_account.clone(),
-            pool_token_a_account.clone(),
-            provider_account.clone(),
-            token_program_account.clone(),
-        ],
+    let amount_a = tokens.transfer(provider_token_a_account, pool_token_a_account, provider_account, amount_a, &[])?;
+    let amount_b = tokens.transfer(provider_token_b_account, pool_token_b_account, provider_account, amount_b, &[])?;
+
+    // Mint LP tokens for the share of reserves that actually arrived
+    let lp_tokens_to_mint = curve::lp_tokens_for_deposit(
+        amount_a,
+        amount_b,
+        pool_state_data.token_a_reserve,
+        pool_state_data.token_b_reserve,
+        pool_state_data.lp_supply,
     )?;
 
-    invoke(
-        &spl_token_instruction::transfer(
-            token_program_account.key,
-            provider_token_b_account.key,
-            pool_token_b_account.key,
-            provider_account.key,
-            &[],
-            amount_b,
-        )?,
-        &[
-            provider_token_b_account.clone(),
-            pool_token_b_account.clone(),
-            provider_account.clone(),
-            token_program_account.clone(),
-        ],
-    )?;
+    if lp_tokens_to_mint == 0 {
+        return Err(LiquidityPoolError::InvalidAmount.into());
+    }
 
-    // Calculate LP tokens to mint (simple 1:1 for now, can be improved)
-    let lp_tokens_to_mint = amount_a + amount_b;
+    pool_state_data.update_price_accumulators(Clock::get()?.unix_timestamp);
+    pool_state_data.token_a_reserve = checked_add(pool_state_data.token_a_reserve, amount_a)?;
+    pool_state_data.token_b_reserve = checked_add(pool_state_data.token_b_reserve, amount_b)?;
+    pool_state_data.lp_supply = checked_add(pool_state_data.lp_supply, lp_tokens_to_mint)?;
 
     // Mint LP tokens to provider
-    let mint_lp_ix = spl_token_instruction::mint_to(
-        token_program_account.key,
-        lp_mint_account.key,
-        provider_lp_token_account.key,
-        pool_state_account.key, // PDA is the mint authority
-        &[&pda], // Signer for PDA
+    tokens.mint_to(
+        lp_mint_account,
+        provider_lp_token_account,
+        pool_state_account, // PDA is the mint authority
         lp_tokens_to_mint,
-    )?;
-
-    invoke_signed(
-        &mint_lp_ix,
-        &[
-            lp_mint_account.clone(),
-            provider_lp_token_account.clone(),
-            pool_state_account.clone(),
-            token_program_account.clone(),
-        ],
-        &[&[
-            b"liquidity_pool",
-            pool_state_data.token_a_mint.as_ref(),
-            pool_state_data.token_b_mint.as_ref(),
-            &[pool_state_data.bump_seed],
-        ]],
+        &[&pool_state_data.signer_seeds().as_slices()],
     )?;
 
     // Update PoolState
-    pool_state_data.token_a_reserve += amount_a;
-    pool_state_data.token_b_reserve += amount_b;
-    pool_state_data.lp_supply += lp_tokens_to_mint;
     pool_state_data.serialize(&mut &mut pool_state_account.data.borrow_mut()[..])?;
 
     msg!("Added liquidity: {} Token A, {} Token B. Minted {} LP tokens.", amount_a, amount_b, lp_tokens_to_mint);
@@ -437,315 +1228,1167 @@ fn process_remove_liquidity(
         return Err(ProgramError::MissingRequiredSignature);
     }
 
-    let mut pool_state_data = PoolState::try_from_slice(&pool_state_account.data.borrow())?;
-    if pool_state_data.is_initialized == 0 {
-        return Err(LiquidityPoolError::PoolNotInitialized.into());
-    }
+    let mut pool_state_data = load_pool_state(program_id, pool_state_account)?;
+    let pda = *pool_state_account.key;
+    let tokens = TokenAccounts::new(token_program_account, account_info_iter.as_slice())?;
+    validate_pool_vaults(&pool_state_data, &pda, pool_token_a_account, pool_token_b_account)?;
+    validate_lp_mint(lp_mint_account, &pool_state_data)?;
 
     if lp_token_amount == 0 {
         return Err(LiquidityPoolError::ZeroAmountNotAllowed.into());
     }
 
-    // Check PDA
-    let (pda, _bump_seed) = Pubkey::find_program_address(
-        &[
-            b"liquidity_pool",
-            pool_state_data.token_a_mint.as_ref(),
-            pool_state_data.token_b_mint.as_ref(),
-        ],
-        program_id,
-    );
-    if pda != *pool_state_account.key {
-        return Err(ProgramError::InvalidSeeds);
-    }
-
     // Burn LP tokens from provider
-    let burn_lp_ix = spl_token_instruction::burn(
-        token_program_account.key,
-        provider_lp_token_account.key,
-        lp_mint_account.key,
-        provider_account.key,
-        &[],
-        lp_token_amount,
-    )?;
-
-    invoke(
-        &burn_lp_ix,
-        &[
-            provider_lp_token_account.clone(),
-            lp_mint_account.clone(),
-            provider_account.clone(),
-            token_program_account.clone(),
-        ],
-    )?;
-
-    // Calculate tokens to return (simple proportional for now)
-    let amount_a_to_return = (lp_token_amount as u128 * pool_state_data.token_a_reserve as u128 / pool_state_data.lp_supply as u128) as u64;
-    let amount_b_to_return = (lp_token_amount as u128 * pool_state_data.token_b_reserve as u128 / pool_state_data.lp_supply as u128) as u64;
-
-    // Transfer tokens from pool to provider
-    // Token A
-    let transfer_a_ix = spl_token_instruction::transfer(
-        token_program_account.key,
-        pool_token_a_account.key,
-        provider_token_a_account.key,
-        pool_state_account.key, // PDA is the authority
-        &[&pda], // Signer for PDA
-        amount_a_to_return,
-    )?;
+    tokens.burn(provider_lp_token_account, lp_mint_account, provider_account, lp_token_amount)?;
 
-    invoke_signed(
-        &transfer_a_ix,
-        &[
-            pool_token_a_account.clone(),
-            provider_token_a_account.clone(),
-            pool_state_account.clone(),
-            token_program_account.clone(),
-        ],
-        &[&[
-            b"liquidity_pool",
-            pool_state_data.token_a_mint.as_ref(),
-            pool_state_data.token_b_mint.as_ref(),
-            &[pool_state_data.bump_seed],
-        ]],
+    // Calculate tokens to return (proportional share, rounded down)
+    let (amount_a_to_return, amount_b_to_return) = curve::withdraw_amounts(
+        lp_token_amount,
+        pool_state_data.token_a_reserve,
+        pool_state_data.token_b_reserve,
+        pool_state_data.lp_supply,
     )?;
 
-    // Token B
-    let transfer_b_ix = spl_token_instruction::transfer(
-        token_program_account.key,
-        pool_token_b_account.key,
-        provider_token_b_account.key,
-        pool_state_account.key, // PDA is the authority
-        &[&pda], // Signer for PDA
-        amount_b_to_return,
-    )?;
+    // Apply the new reserves up front so an underflow fails before any tokens move
+    pool_state_data.update_price_accumulators(Clock::get()?.unix_timestamp);
+    pool_state_data.token_a_reserve = checked_sub(pool_state_data.token_a_reserve, amount_a_to_return)?;
+    pool_state_data.token_b_reserve = checked_sub(pool_state_data.token_b_reserve, amount_b_to_return)?;
+    pool_state_data.lp_supply = checked_sub(pool_state_data.lp_supply, lp_token_amount)?;
 
-    invoke_signed(
-        &transfer_b_ix,
-        &[
-            pool_token_b_account.clone(),
-            provider_token_b_account.clone(),
-            pool_state_account.clone(),
-            token_program_account.clone(),
-        ],
-        &[&[
-            b"liquidity_pool",
-            pool_state_data.token_a_mint.as_ref(),
-            pool_state_data.token_b_mint.as_ref(),
-            &[pool_state_data.bump_seed],
-        ]],
-    )?;
+    // Transfer tokens from pool to provider; the PDA is the authority
+    let pool_seeds = pool_state_data.signer_seeds();
+    let pool_seeds: &[&[u8]] = &pool_seeds.as_slices();
+    tokens.transfer(pool_token_a_account, provider_token_a_account, pool_state_account, amount_a_to_return, &[pool_seeds])?;
+    tokens.transfer(pool_token_b_account, provider_token_b_account, pool_state_account, amount_b_to_return, &[pool_seeds])?;
 
     // Update PoolState
-    pool_state_data.token_a_reserve -= amount_a_to_return;
-    pool_state_data.token_b_reserve -= amount_b_to_return;
-    pool_state_data.lp_supply -= lp_token_amount;
     pool_state_data.serialize(&mut &mut pool_state_account.data.borrow_mut()[..])?;
 
     msg!("Removed liquidity: Burned {} LP tokens. Returned {} Token A, {} Token B.", lp_token_amount, amount_a_to_return, amount_b_to_return);
     Ok(())
 }
 
-// ------------------ Swap A for B ------------------
-fn process_swap_a_for_b(
+// ------------------ Add Liquidity Single ------------------
+/// Accounts: provider, pool state, pool token A, pool token B, LP mint,
+/// provider input token, provider LP token, token program, then any trailing
+/// token accounts. The swapped output never leaves the pool: it is deposited
+/// on the provider's behalf.
+fn process_add_liquidity_single(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
-    amount_a_in: u64,
+    token_in: u8,
+    amount_in: u64,
+    min_lp_out: u64,
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
 
-    let swapper_account = next_account_info(account_info_iter)?;
-    let pool_state_account = next_account_info(account_info_iter)?;
-    let pool_token_a_account = next_account_info(account_info_iter)?;
-    let pool_token_b_account = next_account_info(account_info_iter)?;
-    let swapper_token_a_account = next_account_info(account_info_iter)?;
-    let swapper_token_b_account = next_account_info(account_info_iter)?;
-    let token_program_account = next_account_info(account_info_iter)?;
+    let provider_account = next_account_info(account_info_iter)?; // 0
+    let pool_state_account = next_account_info(account_info_iter)?; // 1
+    let pool_token_a_account = next_account_info(account_info_iter)?; // 2
+    let pool_token_b_account = next_account_info(account_info_iter)?; // 3
+    let lp_mint_account = next_account_info(account_info_iter)?; // 4
+    let provider_token_in_account = next_account_info(account_info_iter)?; // 5
+    let provider_lp_token_account = next_account_info(account_info_iter)?; // 6
+    let token_program_account = next_account_info(account_info_iter)?; // 7
 
-    if !swapper_account.is_signer {
+    if !provider_account.is_signer {
         return Err(ProgramError::MissingRequiredSignature);
     }
-
-    let mut pool_state_data = PoolState::try_from_slice(&pool_state_account.data.borrow())?;
-    if pool_state_data.is_initialized == 0 {
-        return Err(LiquidityPoolError::PoolNotInitialized.into());
+    if token_in > 1 {
+        return Err(LiquidityPoolError::InvalidInstruction.into());
     }
 
-    if amount_a_in == 0 {
+    let mut pool_state_data = load_pool_state(program_id, pool_state_account)?;
+    let pda = *pool_state_account.key;
+    let tokens = TokenAccounts::new(token_program_account, account_info_iter.as_slice())?;
+    let (pool_token_a, pool_token_b) =
+        validate_pool_vaults(&pool_state_data, &pda, pool_token_a_account, pool_token_b_account)?;
+    check_reserves(&pool_state_data, &pool_token_a, &pool_token_b)?;
+    validate_lp_mint(lp_mint_account, &pool_state_data)?;
+
+    if amount_in == 0 {
         return Err(LiquidityPoolError::ZeroAmountNotAllowed.into());
     }
 
-    // Check PDA
-    let (pda, _bump_seed) = Pubkey::find_program_address(
-        &[
-            b"liquidity_pool",
-            pool_state_data.token_a_mint.as_ref(),
-            pool_state_data.token_b_mint.as_ref(),
-        ],
-        program_id,
-    );
-    if pda != *pool_state_account.key {
-        return Err(ProgramError::InvalidSeeds);
+    // Size the zap on what will arrive after any transfer fee
+    let a_in = token_in == 0;
+    let mint_in = if a_in { pool_state_data.token_a_mint } else { pool_state_data.token_b_mint };
+    let amount_in = checked_sub(amount_in, tokens.transfer_fee(&mint_in, amount_in)?)?;
+    let now = Clock::get()?.unix_timestamp;
+    let (amount_swapped, amount_out, amount_deposited) = pool_state_data.zap_in(a_in, amount_in, now)?;
+    let (reserve_in, reserve_out) = pool_state_data.reserves(a_in);
+    let reserve_in_after_swap = checked_add(reserve_in, amount_swapped)?;
+    let reserve_out_after_swap = checked_sub(reserve_out, amount_out)?;
+    let (deposit_a, deposit_b, reserve_a, reserve_b) = if a_in {
+        (amount_deposited, amount_out, reserve_in_after_swap, reserve_out_after_swap)
+    } else {
+        (amount_out, amount_deposited, reserve_out_after_swap, reserve_in_after_swap)
+    };
+    let lp_tokens_to_mint = curve::lp_tokens_for_deposit(deposit_a, deposit_b, reserve_a, reserve_b, pool_state_data.lp_supply)?;
+
+    if lp_tokens_to_mint == 0 {
+        return Err(LiquidityPoolError::InvalidAmount.into());
+    }
+    if lp_tokens_to_mint < min_lp_out {
+        return Err(LiquidityPoolError::SlippageExceeded.into());
     }
 
-    // Transfer Token A from swapper to pool
-    invoke(
-        &spl_token_instruction::transfer(
-            token_program_account.key,
-            swapper_token_a_account.key,
-            pool_token_a_account.key,
-            swapper_account.key,
-            &[],
-            amount_a_in,
-        )?,
-        &[
-            swapper_token_a_account.clone(),
-            pool_token_a_account.clone(),
-            swapper_account.clone(),
-            token_program_account.clone(),
-        ],
+    // Apply the new reserves up front so an overflow fails before any tokens move
+    let amount_taken = checked_add(amount_swapped, amount_deposited)?;
+    pool_state_data.update_price_accumulators(now);
+    if a_in {
+        pool_state_data.token_a_reserve = checked_add(pool_state_data.token_a_reserve, amount_taken)?;
+    } else {
+        pool_state_data.token_b_reserve = checked_add(pool_state_data.token_b_reserve, amount_taken)?;
+    }
+    pool_state_data.lp_supply = checked_add(pool_state_data.lp_supply, lp_tokens_to_mint)?;
+
+    // Transfer the input from provider to pool
+    let pool_token_in_account = if a_in { pool_token_a_account } else { pool_token_b_account };
+    let amount_sent = tokens.amount_before_fee(&mint_in, amount_taken)?;
+    if tokens.transfer(provider_token_in_account, pool_token_in_account, provider_account, amount_sent, &[])? < amount_taken {
+        return Err(LiquidityPoolError::InvalidAmount.into());
+    }
+
+    // Mint LP tokens to provider
+    tokens.mint_to(
+        lp_mint_account,
+        provider_lp_token_account,
+        pool_state_account, // PDA is the mint authority
+        lp_tokens_to_mint,
+        &[&pool_state_data.signer_seeds().as_slices()],
     )?;
 
-    // Calculate amount of Token B to send to swapper (simple constant product formula)
-    let amount_b_out = (pool_state_data.token_b_reserve as u128 * amount_a_in as u128 / (pool_state_data.token_a_reserve as u128 + amount_a_in as u128)) as u64;
+    // Update PoolState
+    pool_state_data.serialize(&mut &mut pool_state_account.data.borrow_mut()[..])?;
+
+    msg!("Added liquidity from {} of one token, swapping {}. Minted {} LP tokens.", amount_taken, amount_swapped, lp_tokens_to_mint);
+    Ok(())
+}
 
-    if amount_b_out == 0 {
-        return Err(LiquidityPoolError::InvalidAmount.into());
+// ------------------ Remove Liquidity Single ------------------
+/// Accounts: provider, pool state, pool token A, pool token B, LP mint,
+/// provider output token, provider LP token, token program, then any trailing
+/// token accounts. The share of the other side never leaves the pool: it is
+/// sold back to it for the output.
+fn process_remove_liquidity_single(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    token_out: u8,
+    lp_amount: u64,
+    min_amount_out: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let provider_account = next_account_info(account_info_iter)?; // 0
+    let pool_state_account = next_account_info(account_info_iter)?; // 1
+    let pool_token_a_account = next_account_info(account_info_iter)?; // 2
+    let pool_token_b_account = next_account_info(account_info_iter)?; // 3
+    let lp_mint_account = next_account_info(account_info_iter)?; // 4
+    let provider_token_out_account = next_account_info(account_info_iter)?; // 5
+    let provider_lp_token_account = next_account_info(account_info_iter)?; // 6
+    let token_program_account = next_account_info(account_info_iter)?; // 7
+
+    if !provider_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if token_out > 1 {
+        return Err(LiquidityPoolError::InvalidInstruction.into());
+    }
+
+    let mut pool_state_data = load_pool_state(program_id, pool_state_account)?;
+    let pda = *pool_state_account.key;
+    let tokens = TokenAccounts::new(token_program_account, account_info_iter.as_slice())?;
+    let (pool_token_a, pool_token_b) =
+        validate_pool_vaults(&pool_state_data, &pda, pool_token_a_account, pool_token_b_account)?;
+    check_reserves(&pool_state_data, &pool_token_a, &pool_token_b)?;
+    validate_lp_mint(lp_mint_account, &pool_state_data)?;
+
+    if lp_amount == 0 {
+        return Err(LiquidityPoolError::ZeroAmountNotAllowed.into());
     }
 
-    // Transfer Token B from pool to swapper
-    let transfer_b_ix = spl_token_instruction::transfer(
-        token_program_account.key,
-        pool_token_b_account.key,
-        swapper_token_b_account.key,
-        pool_state_account.key, // PDA is the authority
-        &[&pda], // Signer for PDA
-        amount_b_out,
+    // Withdraw both sides, then sell the other side to the remaining reserves
+    let (amount_a, amount_b) = curve::withdraw_amounts(
+        lp_amount,
+        pool_state_data.token_a_reserve,
+        pool_state_data.token_b_reserve,
+        pool_state_data.lp_supply,
     )?;
+    let now = Clock::get()?.unix_timestamp;
+    pool_state_data.update_price_accumulators(now);
+    pool_state_data.token_a_reserve = checked_sub(pool_state_data.token_a_reserve, amount_a)?;
+    pool_state_data.token_b_reserve = checked_sub(pool_state_data.token_b_reserve, amount_b)?;
+    pool_state_data.lp_supply = checked_sub(pool_state_data.lp_supply, lp_amount)?;
+
+    let a_out = token_out == 0;
+    let (amount_withdrawn, amount_sold) = if a_out { (amount_a, amount_b) } else { (amount_b, amount_a) };
+    let amount_bought = pool_state_data.quote_exact_in(!a_out, amount_sold, now)?;
+    let amount_out = checked_add(amount_withdrawn, amount_bought)?;
+    if a_out {
+        pool_state_data.token_a_reserve = checked_sub(pool_state_data.token_a_reserve, amount_bought)?;
+        pool_state_data.token_b_reserve = checked_add(pool_state_data.token_b_reserve, amount_sold)?;
+    } else {
+        pool_state_data.token_b_reserve = checked_sub(pool_state_data.token_b_reserve, amount_bought)?;
+        pool_state_data.token_a_reserve = checked_add(pool_state_data.token_a_reserve, amount_sold)?;
+    }
 
-    invoke_signed(
-        &transfer_b_ix,
-        &[
-            pool_token_b_account.clone(),
-            swapper_token_b_account.clone(),
-            pool_state_account.clone(),
-            token_program_account.clone(),
-        ],
-        &[&[
-            b"liquidity_pool",
-            pool_state_data.token_a_mint.as_ref(),
-            pool_state_data.token_b_mint.as_ref(),
-            &[pool_state_data.bump_seed],
-        ]],
+    // Burn LP tokens from provider
+    tokens.burn(provider_lp_token_account, lp_mint_account, provider_account, lp_amount)?;
+
+    // Transfer the output from pool to provider; the PDA is the authority.
+    // Slippage is measured on what reaches the provider, after any transfer fee.
+    let pool_token_out_account = if a_out { pool_token_a_account } else { pool_token_b_account };
+    let amount_received = tokens.transfer(
+        pool_token_out_account,
+        provider_token_out_account,
+        pool_state_account,
+        amount_out,
+        &[&pool_state_data.signer_seeds().as_slices()],
     )?;
+    if amount_received < min_amount_out {
+        return Err(LiquidityPoolError::SlippageExceeded.into());
+    }
 
     // Update PoolState
-    pool_state_data.token_a_reserve += amount_a_in;
-    pool_state_data.token_b_reserve -= amount_b_out;
     pool_state_data.serialize(&mut &mut pool_state_account.data.borrow_mut()[..])?;
 
-    msg!("Swapped {} Token A for {} Token B.", amount_a_in, amount_b_out);
+    msg!("Removed liquidity: Burned {} LP tokens for {} of one token.", lp_amount, amount_out);
+    Ok(())
+}
+
+// ------------------ Swap ------------------
+/// Accounts shared by every swap instruction, in canonical A/B order:
+/// swapper, pool state, pool token A, pool token B, swapper token A,
+/// swapper token B, token program, then any trailing token accounts.
+struct SwapAccounts<'a, 'b> {
+    swapper_account: &'a AccountInfo<'b>,
+    pool_state_account: &'a AccountInfo<'b>,
+    pool_token_a_account: &'a AccountInfo<'b>,
+    pool_token_b_account: &'a AccountInfo<'b>,
+    swapper_token_a_account: &'a AccountInfo<'b>,
+    swapper_token_b_account: &'a AccountInfo<'b>,
+    tokens: TokenAccounts<'a, 'b>,
+    pool_state_data: PoolState,
+}
+
+impl<'a, 'b> SwapAccounts<'a, 'b> {
+    /// Parses and validates the swap accounts.
+    fn load(program_id: &Pubkey, accounts: &'a [AccountInfo<'b>]) -> Result<Self, ProgramError> {
+        let account_info_iter = &mut accounts.iter();
+
+        let swapper_account = next_account_info(account_info_iter)?;
+        let pool_state_account = next_account_info(account_info_iter)?;
+        let pool_token_a_account = next_account_info(account_info_iter)?;
+        let pool_token_b_account = next_account_info(account_info_iter)?;
+        let swapper_token_a_account = next_account_info(account_info_iter)?;
+        let swapper_token_b_account = next_account_info(account_info_iter)?;
+        let token_program_account = next_account_info(account_info_iter)?;
+
+        if !swapper_account.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let pool_state_data = load_pool_state(program_id, pool_state_account)?;
+        let pda = *pool_state_account.key;
+        let tokens = TokenAccounts::new(token_program_account, account_info_iter.as_slice())?;
+        let (pool_token_a, pool_token_b) =
+            validate_pool_vaults(&pool_state_data, &pda, pool_token_a_account, pool_token_b_account)?;
+        check_reserves(&pool_state_data, &pool_token_a, &pool_token_b)?;
+
+        Ok(Self {
+            swapper_account,
+            pool_state_account,
+            pool_token_a_account,
+            pool_token_b_account,
+            swapper_token_a_account,
+            swapper_token_b_account,
+            tokens,
+            pool_state_data,
+        })
+    }
+
+    /// Mints of the input and output tokens.
+    fn mints(&self, a_to_b: bool) -> (Pubkey, Pubkey) {
+        let (mint_a, mint_b) = (self.pool_state_data.token_a_mint, self.pool_state_data.token_b_mint);
+        if a_to_b { (mint_a, mint_b) } else { (mint_b, mint_a) }
+    }
+
+    /// Output for selling exactly `amount_in`, on the pool's curve.
+    fn quote_exact_in(&self, a_to_b: bool, amount_in: u64) -> Result<u64, ProgramError> {
+        self.pool_state_data.quote_exact_in(a_to_b, amount_in, Clock::get()?.unix_timestamp)
+    }
+
+    /// Input required to buy exactly `amount_out`, on the pool's curve.
+    fn quote_exact_out(&self, a_to_b: bool, amount_out: u64) -> Result<u64, ProgramError> {
+        self.pool_state_data.quote_exact_out(a_to_b, amount_out, Clock::get()?.unix_timestamp)
+    }
+
+    /// Moves `amount_in` from the swapper into the pool and returns what the
+    /// pool received, less than `amount_in` under a transfer fee.
+    fn pay_in(&self, a_to_b: bool, amount_in: u64) -> Result<u64, ProgramError> {
+        let (pool_in_account, swapper_in_account, reserve_in) = if a_to_b {
+            (self.pool_token_a_account, self.swapper_token_a_account, self.pool_state_data.token_a_reserve)
+        } else {
+            (self.pool_token_b_account, self.swapper_token_b_account, self.pool_state_data.token_b_reserve)
+        };
+        // An overflowing reserve fails before any tokens move
+        checked_add(reserve_in, amount_in)?;
+        self.tokens.transfer(swapper_in_account, pool_in_account, self.swapper_account, amount_in, &[])
+    }
+
+    /// Records `amount_in` received by the pool, moves `amount_out` from the
+    /// pool to the swapper and returns what the swapper received.
+    fn settle(mut self, a_to_b: bool, amount_in: u64, amount_out: u64) -> Result<u64, ProgramError> {
+        let (pool_out_account, swapper_out_account) = if a_to_b {
+            (self.pool_token_b_account, self.swapper_token_b_account)
+        } else {
+            (self.pool_token_a_account, self.swapper_token_a_account)
+        };
+
+        // Apply the new reserves before the output moves so an overflow fails first
+        let pool_state_data = &mut self.pool_state_data;
+        pool_state_data.update_price_accumulators(Clock::get()?.unix_timestamp);
+        if a_to_b {
+            pool_state_data.token_a_reserve = checked_add(pool_state_data.token_a_reserve, amount_in)?;
+            pool_state_data.token_b_reserve = checked_sub(pool_state_data.token_b_reserve, amount_out)?;
+        } else {
+            pool_state_data.token_b_reserve = checked_add(pool_state_data.token_b_reserve, amount_in)?;
+            pool_state_data.token_a_reserve = checked_sub(pool_state_data.token_a_reserve, amount_out)?;
+        }
+
+        // Transfer output from pool to swapper; the PDA is the authority
+        let amount_received = self.tokens.transfer(
+            pool_out_account,
+            swapper_out_account,
+            self.pool_state_account,
+            amount_out,
+            &[&self.pool_state_data.signer_seeds().as_slices()],
+        )?;
+
+        // Update PoolState
+        self.pool_state_data.serialize(&mut &mut self.pool_state_account.data.borrow_mut()[..])?;
+
+        if a_to_b {
+            msg!("Swapped {} Token A for {} Token B.", amount_in, amount_out);
+        } else {
+            msg!("Swapped {} Token B for {} Token A.", amount_in, amount_out);
+        }
+        Ok(amount_received)
+    }
+
+    /// Sells exactly `amount_in`, pricing what the pool actually received, and
+    /// returns what the swapper received.
+    fn swap_exact_in(self, a_to_b: bool, amount_in: u64) -> Result<u64, ProgramError> {
+        let amount_in = self.pay_in(a_to_b, amount_in)?;
+        let amount_out = self.quote_exact_in(a_to_b, amount_in)?;
+        if amount_out == 0 {
+            return Err(LiquidityPoolError::InvalidAmount.into());
+        }
+        self.settle(a_to_b, amount_in, amount_out)
+    }
+}
+
+fn process_swap(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount_in: u64,
+    min_out: u64,
+    a_to_b: bool,
+) -> ProgramResult {
+    let swap_accounts = SwapAccounts::load(program_id, accounts)?;
+
+    if amount_in == 0 {
+        return Err(LiquidityPoolError::ZeroAmountNotAllowed.into());
+    }
+
+    // Slippage is measured on what reaches the swapper, after any transfer fees
+    let amount_out = swap_accounts.swap_exact_in(a_to_b, amount_in)?;
+    if amount_out < min_out {
+        return Err(LiquidityPoolError::SlippageExceeded.into());
+    }
     Ok(())
 }
 
 // ------------------ Swap B for A ------------------
+/// Compatibility shim: `SwapBforA` passes the swapper's B account before its A
+/// account, so reorder them into the canonical layout.
 fn process_swap_b_for_a(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
     amount_b_in: u64,
+) -> ProgramResult {
+    let mut accounts = accounts.to_vec();
+    if accounts.len() < 6 {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+    accounts.swap(4, 5);
+    process_swap(program_id, &accounts, amount_b_in, 0, false)
+}
+
+// ------------------ Swap Exact Out ------------------
+fn process_swap_exact_out(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount_out: u64,
+    max_amount_in: u64,
+    direction: SwapDirection,
+) -> ProgramResult {
+    let swap_accounts = SwapAccounts::load(program_id, accounts)?;
+
+    if amount_out == 0 {
+        return Err(LiquidityPoolError::ZeroAmountNotAllowed.into());
+    }
+
+    // The swapper receives exactly `amount_out` and pays at most `max_amount_in`,
+    // transfer fees on either side included.
+    let a_to_b = direction == SwapDirection::AToB;
+    let (mint_in, mint_out) = swap_accounts.mints(a_to_b);
+    let pool_amount_out = swap_accounts.tokens.amount_before_fee(&mint_out, amount_out)?;
+    let pool_amount_in = swap_accounts.quote_exact_out(a_to_b, pool_amount_out)?;
+    let amount_in = swap_accounts.tokens.amount_before_fee(&mint_in, pool_amount_in)?;
+    if amount_in > max_amount_in {
+        return Err(LiquidityPoolError::SlippageExceeded.into());
+    }
+
+    let amount_received = swap_accounts.pay_in(a_to_b, amount_in)?;
+    if amount_received < pool_amount_in {
+        return Err(LiquidityPoolError::InvalidAmount.into());
+    }
+    swap_accounts.settle(a_to_b, amount_received, pool_amount_out)?;
+    Ok(())
+}
+
+// ------------------ Route Swap ------------------
+/// Accounts: swapper, token program, then for each hop the pool state, pool
+/// token A, pool token B, swapper token A and swapper token B, then any
+/// trailing token accounts for all hops. The swapper account receiving a hop's
+/// output must be the one paying the next hop's input.
+fn process_route_swap(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount_in: u64,
+    min_out: u64,
+    hops: Vec<RouteHop>,
+) -> ProgramResult {
+    if hops.is_empty() || hops.len() > MAX_ROUTE_HOPS {
+        return Err(LiquidityPoolError::InvalidRoute.into());
+    }
+    if amount_in == 0 {
+        return Err(LiquidityPoolError::ZeroAmountNotAllowed.into());
+    }
+
+    let account_info_iter = &mut accounts.iter();
+
+    let swapper_account = next_account_info(account_info_iter)?; // 0
+    let token_program_account = next_account_info(account_info_iter)?; // 1
+    let token_extra_accounts = accounts.get(2 + 5 * hops.len()..).unwrap_or_default();
+
+    let mut amount = amount_in;
+    let mut previous_out_key = None;
+    for hop in &hops {
+        let pool_state_account = next_account_info(account_info_iter)?;
+        let pool_token_a_account = next_account_info(account_info_iter)?;
+        let pool_token_b_account = next_account_info(account_info_iter)?;
+        let swapper_token_a_account = next_account_info(account_info_iter)?;
+        let swapper_token_b_account = next_account_info(account_info_iter)?;
+
+        let (swapper_in_account, swapper_out_account) = if hop.a_to_b {
+            (swapper_token_a_account, swapper_token_b_account)
+        } else {
+            (swapper_token_b_account, swapper_token_a_account)
+        };
+        if previous_out_key.is_some_and(|key| key != *swapper_in_account.key) {
+            return Err(LiquidityPoolError::InvalidRoute.into());
+        }
+
+        let mut hop_accounts = vec![
+            swapper_account.clone(),
+            pool_state_account.clone(),
+            pool_token_a_account.clone(),
+            pool_token_b_account.clone(),
+            swapper_token_a_account.clone(),
+            swapper_token_b_account.clone(),
+            token_program_account.clone(),
+        ];
+        hop_accounts.extend_from_slice(token_extra_accounts);
+        let swap_accounts = SwapAccounts::load(program_id, &hop_accounts)?;
+        amount = swap_accounts.swap_exact_in(hop.a_to_b, amount)?;
+        previous_out_key = Some(*swapper_out_account.key);
+    }
+
+    // Every hop has already moved tokens, but failing here reverts them all
+    if amount < min_out {
+        return Err(LiquidityPoolError::SlippageExceeded.into());
+    }
+
+    msg!("Routed {} through {} pools for {}.", amount_in, hops.len(), amount);
+    Ok(())
+}
+
+// ------------------ Native SOL ------------------
+// SOL pairs hold wSOL in the pool's vault like any other token. The native
+// variants wrap the user's lamports into a wSOL account the user owns, run the
+// regular instruction against it, and close it, so the user pays and receives
+// plain SOL. The client creates the wSOL account empty in the same transaction.
+
+/// Accounts of a native variant: the regular instruction's accounts, with its
+/// trailing token accounts, its user, the user's wSOL account and the SPL Token
+/// program, given the number of regular accounts and the index of the user's
+/// token A account among them.
+struct NativeAccounts<'a, 'b> {
+    accounts: Vec<AccountInfo<'b>>,
+    user_account: &'a AccountInfo<'b>,
+    user_wsol_account: &'a AccountInfo<'b>,
+    token_program_account: &'a AccountInfo<'b>,
+    system_program_account: &'a AccountInfo<'b>,
+    /// Whether the pool's wSOL side is token A.
+    wsol_is_a: bool,
+}
+
+impl<'a, 'b> NativeAccounts<'a, 'b> {
+    fn load(
+        program_id: &Pubkey,
+        accounts: &'a [AccountInfo<'b>],
+        len: usize,
+        user_token_a_index: usize,
+    ) -> Result<Self, ProgramError> {
+        if accounts.len() <= len {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        }
+        let system_program_account = &accounts[len];
+        if *system_program_account.key != system_program::id() {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let user_account = &accounts[0];
+        // wSOL is an SPL Token mint; with a Token-2022 pool the SPL Token
+        // program comes among the trailing accounts
+        let token_program_account = std::iter::once(&accounts[len - 1])
+            .chain(&accounts[len + 1..])
+            .find(|account| *account.key == spl_token::id())
+            .ok_or(ProgramError::IncorrectProgramId)?;
+
+        let pool_state_data = load_pool_state(program_id, &accounts[1])?;
+        let wsol_is_a = if pool_state_data.token_a_mint == native_mint::id() {
+            true
+        } else if pool_state_data.token_b_mint == native_mint::id() {
+            false
+        } else {
+            return Err(LiquidityPoolError::InvalidTokenMint.into());
+        };
+
+        let user_wsol_account = &accounts[if wsol_is_a { user_token_a_index } else { user_token_a_index + 1 }];
+        if user_wsol_account.owner != &spl_token::id() {
+            return Err(LiquidityPoolError::InvalidTokenAccount.into());
+        }
+        let user_wsol = token::unpack_account(user_wsol_account)?;
+        if !user_wsol.is_native() || user_wsol.owner != *user_account.key {
+            return Err(LiquidityPoolError::InvalidTokenAccount.into());
+        }
+
+        Ok(Self {
+            // The regular instruction's accounts without the system program
+            accounts: accounts[..len].iter().chain(&accounts[len + 1..]).cloned().collect(),
+            user_account,
+            user_wsol_account,
+            token_program_account,
+            system_program_account,
+            wsol_is_a,
+        })
+    }
+
+    /// Moves `lamports` from the user into their wSOL account and syncs its
+    /// token balance.
+    fn wrap(&self, lamports: u64) -> ProgramResult {
+        if lamports == 0 {
+            return Ok(());
+        }
+        invoke(
+            &system_instruction::transfer(self.user_account.key, self.user_wsol_account.key, lamports),
+            &[
+                self.user_account.clone(),
+                self.user_wsol_account.clone(),
+                self.system_program_account.clone(),
+            ],
+        )?;
+        invoke(
+            &spl_token_instruction::sync_native(self.token_program_account.key, self.user_wsol_account.key)?,
+            &[self.user_wsol_account.clone(), self.token_program_account.clone()],
+        )
+    }
+
+    /// Closes the user's wSOL account, returning all of its lamports, wrapped
+    /// balance included, to the user.
+    fn unwrap(&self) -> ProgramResult {
+        invoke(
+            &spl_token_instruction::close_account(
+                self.token_program_account.key,
+                self.user_wsol_account.key,
+                self.user_account.key,
+                self.user_account.key,
+                &[],
+            )?,
+            &[
+                self.user_wsol_account.clone(),
+                self.user_account.clone(),
+                self.token_program_account.clone(),
+            ],
+        )
+    }
+}
+
+fn process_swap_native(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount_in: u64,
+    min_out: u64,
+    a_to_b: bool,
+) -> ProgramResult {
+    let native = NativeAccounts::load(program_id, accounts, 7, 4)?;
+    if native.wsol_is_a == a_to_b {
+        native.wrap(amount_in)?;
+    }
+    process_swap(program_id, &native.accounts, amount_in, min_out, a_to_b)?;
+    native.unwrap()
+}
+
+fn process_add_liquidity_native(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount_a: u64,
+    amount_b: u64,
+) -> ProgramResult {
+    let native = NativeAccounts::load(program_id, accounts, 9, 5)?;
+    native.wrap(if native.wsol_is_a { amount_a } else { amount_b })?;
+    process_add_liquidity(program_id, &native.accounts, amount_a, amount_b)?;
+    native.unwrap()
+}
+
+fn process_remove_liquidity_native(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    lp_token_amount: u64,
+) -> ProgramResult {
+    let native = NativeAccounts::load(program_id, accounts, 9, 5)?;
+    process_remove_liquidity(program_id, &native.accounts, lp_token_amount)?;
+    native.unwrap()
+}
+
+// ------------------ Sync ------------------
+fn process_sync(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_state_account = next_account_info(account_info_iter)?;
+    let pool_token_a_account = next_account_info(account_info_iter)?;
+    let pool_token_b_account = next_account_info(account_info_iter)?;
+
+    let mut pool_state_data = load_pool_state(program_id, pool_state_account)?;
+    let pda = *pool_state_account.key;
+    let (pool_token_a, pool_token_b) =
+        validate_pool_vaults(&pool_state_data, &pda, pool_token_a_account, pool_token_b_account)?;
+
+    if pool_token_a.amount == 0 || pool_token_b.amount == 0 {
+        return Err(LiquidityPoolError::InsufficientFunds.into());
+    }
+
+    // Update PoolState
+    pool_state_data.update_price_accumulators(Clock::get()?.unix_timestamp);
+    pool_state_data.token_a_reserve = pool_token_a.amount;
+    pool_state_data.token_b_reserve = pool_token_b.amount;
+    pool_state_data.serialize(&mut &mut pool_state_account.data.borrow_mut()[..])?;
+
+    msg!("Synced reserves: {} Token A, {} Token B.", pool_token_a.amount, pool_token_b.amount);
+    Ok(())
+}
+
+// ------------------ Skim ------------------
+fn process_skim(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
 ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
 
-    let swapper_account = next_account_info(account_info_iter)?;
     let pool_state_account = next_account_info(account_info_iter)?;
     let pool_token_a_account = next_account_info(account_info_iter)?;
     let pool_token_b_account = next_account_info(account_info_iter)?;
-    let swapper_token_b_account = next_account_info(account_info_iter)?;
-    let swapper_token_a_account = next_account_info(account_info_iter)?;
+    let recipient_token_a_account = next_account_info(account_info_iter)?;
+    let recipient_token_b_account = next_account_info(account_info_iter)?;
     let token_program_account = next_account_info(account_info_iter)?;
 
-    if !swapper_account.is_signer {
+    let pool_state_data = load_pool_state(program_id, pool_state_account)?;
+    let pda = *pool_state_account.key;
+    let tokens = TokenAccounts::new(token_program_account, account_info_iter.as_slice())?;
+    let (pool_token_a, pool_token_b) =
+        validate_pool_vaults(&pool_state_data, &pda, pool_token_a_account, pool_token_b_account)?;
+    check_reserves(&pool_state_data, &pool_token_a, &pool_token_b)?;
+
+    let excess_a = checked_sub(pool_token_a.amount, pool_state_data.token_a_reserve)?;
+    let excess_b = checked_sub(pool_token_b.amount, pool_state_data.token_b_reserve)?;
+
+    for (vault_account, recipient_account, excess) in [
+        (pool_token_a_account, recipient_token_a_account, excess_a),
+        (pool_token_b_account, recipient_token_b_account, excess_b),
+    ] {
+        if excess == 0 {
+            continue;
+        }
+
+        tokens.transfer(
+            vault_account,
+            recipient_account,
+            pool_state_account, // PDA is the authority
+            excess,
+            &[&pool_state_data.signer_seeds().as_slices()],
+        )?;
+    }
+
+    msg!("Skimmed {} Token A, {} Token B.", excess_a, excess_b);
+    Ok(())
+}
+
+// ------------------ Ramp Amp ------------------
+fn process_ramp_amp(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    target_amp: u64,
+    stop_ramp_ts: i64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let creator_account = next_account_info(account_info_iter)?; // 0
+    let pool_state_account = next_account_info(account_info_iter)?; // 1
+
+    let mut pool_state_data = load_stable_pool_for_creator(program_id, creator_account, pool_state_account)?;
+
+    let now = Clock::get()?.unix_timestamp;
+    if stop_ramp_ts < now.saturating_add(stable_swap::MIN_RAMP_DURATION) {
+        return Err(LiquidityPoolError::InvalidAmplification.into());
+    }
+
+    // Bound both the absolute value and the size of a single ramp, so LPs always
+    // have time to react to a large change in the curve.
+    let current_amp = pool_state_data.amp(now);
+    if !(stable_swap::MIN_AMP..=stable_swap::MAX_AMP).contains(&target_amp)
+        || target_amp > current_amp.saturating_mul(stable_swap::MAX_AMP_CHANGE)
+        || target_amp.saturating_mul(stable_swap::MAX_AMP_CHANGE) < current_amp
+    {
+        return Err(LiquidityPoolError::InvalidAmplification.into());
+    }
+
+    pool_state_data.initial_amp = current_amp;
+    pool_state_data.target_amp = target_amp;
+    pool_state_data.ramp_start_ts = now;
+    pool_state_data.ramp_stop_ts = stop_ramp_ts;
+
+    pool_state_data.serialize(&mut &mut pool_state_account.data.borrow_mut()[..])?;
+
+    msg!("Ramping amplification from {} to {} by {}", current_amp, target_amp, stop_ramp_ts);
+    Ok(())
+}
+
+// ------------------ Stop Ramp Amp ------------------
+fn process_stop_ramp_amp(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let creator_account = next_account_info(account_info_iter)?; // 0
+    let pool_state_account = next_account_info(account_info_iter)?; // 1
+
+    let mut pool_state_data = load_stable_pool_for_creator(program_id, creator_account, pool_state_account)?;
+
+    let now = Clock::get()?.unix_timestamp;
+    let current_amp = pool_state_data.amp(now);
+    pool_state_data.initial_amp = current_amp;
+    pool_state_data.target_amp = current_amp;
+    pool_state_data.ramp_start_ts = now;
+    pool_state_data.ramp_stop_ts = now;
+
+    pool_state_data.serialize(&mut &mut pool_state_account.data.borrow_mut()[..])?;
+
+    msg!("Amplification fixed at {}", current_amp);
+    Ok(())
+}
+
+// ------------------ Observe Price ------------------
+fn process_observe_price(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let pool_state_account = next_account_info(account_info_iter)?; // 0
+
+    let pool_state_data = load_pool_state(program_id, pool_state_account)?;
+    let observation = pool_state_data.observe(Clock::get()?.unix_timestamp);
+    set_return_data(&observation.try_to_vec()?);
+
+    msg!("Observed prices at {}", observation.timestamp);
+    Ok(())
+}
+
+// ------------------ Flash Loan ------------------
+/// Accounts: borrower, pool state, pool token A, pool token B, borrower token
+/// A, borrower token B, token program, borrower program, then any accounts the
+/// borrower program needs, which are passed to it as given.
+fn process_flash_loan(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount_a: u64,
+    amount_b: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let borrower_account = next_account_info(account_info_iter)?; // 0
+    let pool_state_account = next_account_info(account_info_iter)?; // 1
+    let pool_token_a_account = next_account_info(account_info_iter)?; // 2
+    let pool_token_b_account = next_account_info(account_info_iter)?; // 3
+    let borrower_token_a_account = next_account_info(account_info_iter)?; // 4
+    let borrower_token_b_account = next_account_info(account_info_iter)?; // 5
+    let token_program_account = next_account_info(account_info_iter)?; // 6
+    let borrower_program_account = next_account_info(account_info_iter)?; // 7
+    let callback_accounts = account_info_iter.as_slice();
+
+    if !borrower_account.is_signer {
         return Err(ProgramError::MissingRequiredSignature);
     }
 
+    let mut pool_state_data = load_pool_state(program_id, pool_state_account)?;
+    let pda = *pool_state_account.key;
+    // Mints and hook accounts the loan's transfers need are found among the
+    // borrower's accounts.
+    let tokens = TokenAccounts::new(token_program_account, callback_accounts)?;
+    let (pool_token_a, pool_token_b) =
+        validate_pool_vaults(&pool_state_data, &pda, pool_token_a_account, pool_token_b_account)?;
+    check_reserves(&pool_state_data, &pool_token_a, &pool_token_b)?;
+
+    // The runtime rejects indirect reentrancy, so while the loan is out the
+    // borrower cannot call back into this program except by direct recursion,
+    // which is ruled out here.
+    if borrower_program_account.key == program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if amount_a == 0 && amount_b == 0 {
+        return Err(LiquidityPoolError::ZeroAmountNotAllowed.into());
+    }
+    if amount_a > pool_state_data.token_a_reserve || amount_b > pool_state_data.token_b_reserve {
+        return Err(LiquidityPoolError::InsufficientFunds.into());
+    }
+
+    let fee_a = curve::flash_loan_fee(amount_a, FLASH_LOAN_FEE_BPS)?;
+    let fee_b = curve::flash_loan_fee(amount_b, FLASH_LOAN_FEE_BPS)?;
+    let required_a = checked_add(pool_token_a.amount, fee_a)?;
+    let required_b = checked_add(pool_token_b.amount, fee_b)?;
+
+    for (vault_account, borrower_token_account, amount) in [
+        (pool_token_a_account, borrower_token_a_account, amount_a),
+        (pool_token_b_account, borrower_token_b_account, amount_b),
+    ] {
+        if amount == 0 {
+            continue;
+        }
+
+        tokens.transfer(
+            vault_account,
+            borrower_token_account,
+            pool_state_account, // PDA is the authority
+            amount,
+            &[&pool_state_data.signer_seeds().as_slices()],
+        )?;
+    }
+
+    let callback = FlashLoanCallback { amount_a, amount_b, fee_a, fee_b };
+    let callback_ix = Instruction {
+        program_id: *borrower_program_account.key,
+        accounts: callback_accounts
+            .iter()
+            .map(|account| AccountMeta {
+                pubkey: *account.key,
+                is_signer: account.is_signer,
+                is_writable: account.is_writable,
+            })
+            .collect(),
+        data: callback.try_to_vec()?,
+    };
+    invoke(&callback_ix, callback_accounts)?;
+
+    let repaid_a = token::unpack_account(pool_token_a_account)?.amount;
+    let repaid_b = token::unpack_account(pool_token_b_account)?.amount;
+    if repaid_a < required_a || repaid_b < required_b {
+        return Err(LiquidityPoolError::FlashLoanNotRepaid.into());
+    }
+
+    // The fees become part of the reserves; anything repaid beyond them is left
+    // for Skim.
+    pool_state_data.update_price_accumulators(Clock::get()?.unix_timestamp);
+    pool_state_data.token_a_reserve = checked_add(pool_state_data.token_a_reserve, fee_a)?;
+    pool_state_data.token_b_reserve = checked_add(pool_state_data.token_b_reserve, fee_b)?;
+    pool_state_data.serialize(&mut &mut pool_state_account.data.borrow_mut()[..])?;
+
+    msg!("Flash loan of {} Token A, {} Token B repaid with fees {}, {}.", amount_a, amount_b, fee_a, fee_b);
+    Ok(())
+}
+
+// ------------------ Close Pool ------------------
+/// Accounts: creator, pool state, pool token A, pool token B, LP mint,
+/// creator token A, creator token B, token program, farm, registry page, then
+/// any trailing token accounts.
+fn process_close_pool(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let creator_account = next_account_info(account_info_iter)?; // 0
+    let pool_state_account = next_account_info(account_info_iter)?; // 1
+    let pool_token_a_account = next_account_info(account_info_iter)?; // 2
+    let pool_token_b_account = next_account_info(account_info_iter)?; // 3
+    let lp_mint_account = next_account_info(account_info_iter)?; // 4
+    let creator_token_a_account = next_account_info(account_info_iter)?; // 5
+    let creator_token_b_account = next_account_info(account_info_iter)?; // 6
+    let token_program_account = next_account_info(account_info_iter)?; // 7
+    let farm_account = next_account_info(account_info_iter)?; // 8
+    let registry_page_account = next_account_info(account_info_iter)?; // 9
+
+    if !creator_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let pool_state_data = load_pool_state(program_id, pool_state_account)?;
+    if pool_state_data.creator != *creator_account.key {
+        return Err(LiquidityPoolError::Unauthorized.into());
+    }
+    let pda = *pool_state_account.key;
+    // The farm holds stakes of the pool's LP tokens, so it must be closed first
+    if *farm_account.key != farm::farm_address(program_id, &pda).0 {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if farm_account.owner == program_id {
+        return Err(LiquidityPoolError::PoolHasFarm.into());
+    }
+    let tokens = TokenAccounts::new(token_program_account, account_info_iter.as_slice())?;
+    let (pool_token_a, pool_token_b) =
+        validate_pool_vaults(&pool_state_data, &pda, pool_token_a_account, pool_token_b_account)?;
+    validate_lp_mint(lp_mint_account, &pool_state_data)?;
+
+    // The locked minimum liquidity is never minted, so once it is all that is
+    // left no LP tokens are outstanding
+    if pool_state_data.lp_supply > MINIMUM_LIQUIDITY {
+        return Err(LiquidityPoolError::PoolNotEmpty.into());
+    }
+
+    // The vaults still hold the locked liquidity's share of the reserves, and
+    // any surplus; both go to the creator so the vaults can be closed. Fees a
+    // Token-2022 vault withholds must be harvested first.
+    let pool_seeds = pool_state_data.signer_seeds();
+    let pool_seeds: &[&[u8]] = &pool_seeds.as_slices();
+    for (vault_account, creator_token_account, vault) in [
+        (pool_token_a_account, creator_token_a_account, &pool_token_a),
+        (pool_token_b_account, creator_token_b_account, &pool_token_b),
+    ] {
+        if vault.amount > 0 {
+            tokens.transfer(vault_account, creator_token_account, pool_state_account, vault.amount, &[pool_seeds])?;
+        }
+        tokens.close_account(vault_account, creator_account, pool_state_account, &[pool_seeds])?;
+    }
+    registry::remove_pool(program_id, registry_page_account, &pda)?;
+
+    // Close the pool state the way the token program closes accounts: its
+    // lamports go to the creator and it is handed back to the system program
+    let pool_state_lamports = pool_state_account.lamports();
+    **creator_account.lamports.borrow_mut() = checked_add(creator_account.lamports(), pool_state_lamports)?;
+    **pool_state_account.lamports.borrow_mut() = 0;
+    pool_state_account.assign(&system_program::id());
+    pool_state_account.realloc(0, false)?;
+
+    msg!("Closed pool {}, returning {} Token A, {} Token B.", pda, pool_token_a.amount, pool_token_b.amount);
+    Ok(())
+}
+
+// ------------------ Account Validation ------------------
+/// Loads the pool state, checking that the account is owned by this program,
+/// holds a pool in the current layout, is initialized, and is the canonical
+/// PDA for its mint pair and fee tier, or for its mint pair alone when it
+/// predates fee tiers.
+pub fn load_pool_state(program_id: &Pubkey, pool_state_account: &AccountInfo) -> Result<PoolState, ProgramError> {
+    if pool_state_account.owner != program_id {
+        return Err(LiquidityPoolError::InvalidOwner.into());
+    }
+
+    // Pools written before the layout carried a discriminator are recognised by size
+    if migration::is_legacy_pool(pool_state_account) {
+        return Err(LiquidityPoolError::PoolNeedsMigration.into());
+    }
+    if !pool_state_account.data.borrow().starts_with(&POOL_STATE_DISCRIMINATOR) {
+        return Err(LiquidityPoolError::InvalidAccountType.into());
+    }
     let mut pool_state_data = PoolState::try_from_slice(&pool_state_account.data.borrow())?;
     if pool_state_data.is_initialized == 0 {
         return Err(LiquidityPoolError::PoolNotInitialized.into());
     }
+    if pool_state_data.version != POOL_STATE_VERSION {
+        return Err(LiquidityPoolError::PoolNeedsMigration.into());
+    }
 
-    if amount_b_in == 0 {
-        return Err(LiquidityPoolError::ZeroAmountNotAllowed.into());
+    // Pools created before fee tiers sit at the address derived without one
+    let derive = |pool_state_data: &PoolState| {
+        Pubkey::create_program_address(&pool_state_data.signer_seeds().as_slices(), program_id).ok()
+    };
+    if derive(&pool_state_data) != Some(*pool_state_account.key) {
+        pool_state_data.legacy_seeds = true;
+        if derive(&pool_state_data) != Some(*pool_state_account.key) {
+            return Err(ProgramError::InvalidSeeds);
+        }
     }
 
-    // Check PDA
-    let (pda, _bump_seed) = Pubkey::find_program_address(
-        &[
-            b"liquidity_pool",
-            pool_state_data.token_a_mint.as_ref(),
-            pool_state_data.token_b_mint.as_ref(),
-        ],
-        program_id,
-    );
-    if pda != *pool_state_account.key {
-        return Err(ProgramError::InvalidSeeds);
+    Ok(pool_state_data)
+}
+
+/// Loads a stable pool's state, checking that `creator_account` signed and is
+/// the creator recorded in it.
+pub fn load_stable_pool_for_creator(
+    program_id: &Pubkey,
+    creator_account: &AccountInfo,
+    pool_state_account: &AccountInfo,
+) -> Result<PoolState, ProgramError> {
+    if !creator_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    let pool_state_data = load_pool_state(program_id, pool_state_account)?;
+    if pool_state_data.creator != *creator_account.key {
+        return Err(LiquidityPoolError::Unauthorized.into());
+    }
+    if pool_state_data.curve_type != CurveType::Stable {
+        return Err(LiquidityPoolError::InvalidAmplification.into());
     }
+    Ok(pool_state_data)
+}
 
-    // Transfer Token B from swapper to pool
-    invoke(
-        &spl_token_instruction::transfer(
-            token_program_account.key,
-            swapper_token_b_account.key,
-            pool_token_b_account.key,
-            swapper_account.key,
-            &[],
-            amount_b_in,
-        )?,
-        &[
-            swapper_token_b_account.clone(),
-            pool_token_b_account.clone(),
-            swapper_account.clone(),
-            token_program_account.clone(),
-        ],
-    )?;
+/// Checks that the account is the SPL Token or the Token-2022 program.
+pub fn validate_token_program(token_program_account: &AccountInfo) -> ProgramResult {
+    if !token::is_token_program(token_program_account.key) {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    Ok(())
+}
 
-    // Calculate amount of Token A to send to swapper (simple constant product formula)
-    let amount_a_out = (pool_state_data.token_a_reserve as u128 * amount_b_in as u128 / (pool_state_data.token_b_reserve as u128 + amount_b_in as u128)) as u64;
+/// Checks that the account is an initialized mint of either token program,
+/// with no unsupported extensions.
+pub fn validate_mint(mint_account: &AccountInfo) -> ProgramResult {
+    token::unpack_mint(mint_account)?;
+    Ok(())
+}
 
-    if amount_a_out == 0 {
-        return Err(LiquidityPoolError::InvalidAmount.into());
+/// Checks that a vault is a token account of `mint` owned by the pool PDA, with
+/// no delegate or close authority that could move funds behind the pool's back.
+pub fn validate_pool_vault(vault_account: &AccountInfo, pool: &Pubkey, mint: &Pubkey) -> Result<Account, ProgramError> {
+    let vault = token::unpack_account(vault_account)?;
+    if vault.owner != *pool {
+        return Err(LiquidityPoolError::InvalidOwner.into());
+    }
+    if vault.mint != *mint {
+        return Err(LiquidityPoolError::InvalidTokenMint.into());
+    }
+    if vault.delegate.is_some() || vault.close_authority != COption::None {
+        return Err(LiquidityPoolError::InvalidTokenAccount.into());
     }
 
-    // Transfer Token A from pool to swapper
-    let transfer_a_ix = spl_token_instruction::transfer(
-        token_program_account.key,
-        pool_token_a_account.key,
-        swapper_token_a_account.key,
-        pool_state_account.key, // PDA is the authority
-        &[&pda], // Signer for PDA
-        amount_a_out,
-    )?;
+    Ok(vault)
+}
+
+/// Checks that the accounts are the vaults recorded in the pool state.
+pub fn validate_pool_vaults(
+    pool_state_data: &PoolState,
+    pool: &Pubkey,
+    pool_token_a_account: &AccountInfo,
+    pool_token_b_account: &AccountInfo,
+) -> Result<(Account, Account), ProgramError> {
+    if *pool_token_a_account.key != pool_state_data.vault_a || *pool_token_b_account.key != pool_state_data.vault_b {
+        return Err(LiquidityPoolError::InvalidTokenAccount.into());
+    }
+    Ok((
+        validate_pool_vault(pool_token_a_account, pool, &pool_state_data.token_a_mint)?,
+        validate_pool_vault(pool_token_b_account, pool, &pool_state_data.token_b_mint)?,
+    ))
+}
+
+/// Checks that the account is the LP mint recorded in the pool state.
+pub fn validate_lp_mint(lp_mint_account: &AccountInfo, pool_state_data: &PoolState) -> ProgramResult {
+    if *lp_mint_account.key != pool_state_data.lp_mint || !token::is_token_program(lp_mint_account.owner) {
+        return Err(LiquidityPoolError::InvalidTokenMint.into());
+    }
+    Ok(())
+}
+
+/// Checks that the vaults hold at least the recorded reserves. A surplus (e.g. a
+/// direct transfer into a vault) is tolerated and can be skimmed or synced; a
+/// shortfall means the accounting is broken and the pool must not trade.
+pub fn check_reserves(pool_state_data: &PoolState, pool_token_a: &Account, pool_token_b: &Account) -> ProgramResult {
+    if pool_token_a.amount < pool_state_data.token_a_reserve || pool_token_b.amount < pool_state_data.token_b_reserve {
+        return Err(LiquidityPoolError::ReserveMismatch.into());
+    }
+    Ok(())
+}
+
+// ------------------ Account Creation ------------------
+/// Address of a pool's LP mint.
+pub fn lp_mint_address(program_id: &Pubkey, pool: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"lp_mint", pool.as_ref()], program_id)
+}
 
+/// Address of a pool's vault for `mint`.
+pub fn vault_address(program_id: &Pubkey, pool: &Pubkey, mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"vault", pool.as_ref(), mint.as_ref()], program_id)
+}
+
+/// Creates `new_account` with `space` bytes owned by `owner`, its rent paid by
+/// `payer`. The new account signs itself, or is a PDA signed for with
+/// `signers_seeds`. An address that was already sent lamports cannot be passed
+/// to `create_account`, so it is topped up, allocated and assigned instead.
+pub(crate) fn create_account<'a>(
+    payer: &AccountInfo<'a>,
+    new_account: &AccountInfo<'a>,
+    system_program_account: &AccountInfo<'a>,
+    owner: &Pubkey,
+    space: usize,
+    signers_seeds: &[&[&[u8]]],
+) -> ProgramResult {
+    let rent = Rent::get()?.minimum_balance(space);
+    if new_account.lamports() == 0 {
+        return invoke_signed(
+            &system_instruction::create_account(payer.key, new_account.key, rent, space as u64, owner),
+            &[payer.clone(), new_account.clone(), system_program_account.clone()],
+            signers_seeds,
+        );
+    }
+
+    let top_up = rent.saturating_sub(new_account.lamports());
+    if top_up > 0 {
+        invoke(
+            &system_instruction::transfer(payer.key, new_account.key, top_up),
+            &[payer.clone(), new_account.clone(), system_program_account.clone()],
+        )?;
+    }
     invoke_signed(
-        &transfer_a_ix,
-        &[
-            pool_token_a_account.clone(),
-            swapper_token_a_account.clone(),
-            pool_state_account.clone(),
-            token_program_account.clone(),
-        ],
-        &[&[
-            b"liquidity_pool",
-            pool_state_data.token_a_mint.as_ref(),
-            pool_state_data.token_b_mint.as_ref(),
-            &[pool_state_data.bump_seed],
-        ]],
+        &system_instruction::allocate(new_account.key, space as u64),
+        &[new_account.clone(), system_program_account.clone()],
+        signers_seeds,
     )?;
+    invoke_signed(
+        &system_instruction::assign(new_account.key, owner),
+        &[new_account.clone(), system_program_account.clone()],
+        signers_seeds,
+    )
+}
 
-    // Update PoolState
-    pool_state_data.token_b_reserve += amount_b_in;
-    pool_state_data.token_a_reserve -= amount_a_out;
-    pool_state_data.serialize(&mut &mut pool_state_account.data.borrow_mut()[..])?;
+// ------------------ Checked Math ------------------
+/// `a + b`, failing with `MathOverflow` instead of wrapping.
+pub fn checked_add(a: u64, b: u64) -> Result<u64, ProgramError> {
+    a.checked_add(b).ok_or_else(|| LiquidityPoolError::MathOverflow.into())
+}
 
-    msg!("Swapped {} Token B for {} Token A.", amount_b_in, amount_a_out);
-    Ok(())
+/// `a - b`, failing with `MathOverflow` instead of wrapping.
+pub fn checked_sub(a: u64, b: u64) -> Result<u64, ProgramError> {
+    a.checked_sub(b).ok_or_else(|| LiquidityPoolError::MathOverflow.into())
 }
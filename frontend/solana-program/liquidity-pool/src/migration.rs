@@ -0,0 +1,185 @@
+//! Upgrades of pool accounts written with an older `PoolState` layout.
+//!
+//! Version 1 pools predate the version byte, the discriminator and every
+//! field after `bump_seed`, and were created at exactly [`PoolStateV1::LEN`]
+//! bytes, which is how they are recognised. `MigratePool` grows them to
+//! [`PoolState::LEN`], the payer topping up the rent, and rewrites them in the
+//! current layout; see [`PoolStateV1::upgrade`] for the values the new fields
+//! take.
+//!
+//! Version 1 pools kept their vaults at whatever accounts their creator
+//! chose, rather than at [`crate::vault_address`]. Migration creates the vaults
+//! at their PDAs and moves everything the old vaults hold into them, once those
+//! are checked to be the pool's token accounts holding at least its reserves.
+//! The emptied old vaults are left to the pool.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::invoke,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_instruction, system_program,
+    sysvar::{clock::Clock, rent::Rent, Sysvar},
+};
+
+use crate::{
+    check_reserves, load_pool_state, token::TokenAccounts, validate_pool_vault, vault_address, CurveType, LiquidityPoolError,
+    PoolState,
+    POOL_STATE_DISCRIMINATOR, POOL_STATE_RESERVED, POOL_STATE_VERSION, SWAP_FEE_BPS,
+};
+
+/// Whether `pool_state_account` holds a pool in a layout `MigratePool` upgrades.
+pub fn is_legacy_pool(pool_state_account: &AccountInfo) -> bool {
+    pool_state_account.data_len() == PoolStateV1::LEN
+}
+
+// ------------------ PoolStateV1 Struct ------------------
+/// `PoolState` as written before it carried a version.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone)]
+pub struct PoolStateV1 {
+    pub is_initialized: u8,
+    pub token_a_mint: Pubkey,
+    pub token_b_mint: Pubkey,
+    pub token_a_reserve: u64,
+    pub token_b_reserve: u64,
+    pub lp_mint: Pubkey,
+    pub lp_supply: u64,
+    pub bump_seed: u8,
+}
+
+impl PoolStateV1 {
+    pub const LEN: usize = 1 + 32 + 32 + 8 + 8 + 32 + 8 + 1;
+
+    /// The same pool in the current layout, as of `now`. Version 1 pools were
+    /// constant-product pools at the address derived without a fee tier; they
+    /// charge [`SWAP_FEE_BPS`] from now on and record no creator, so no one
+    /// can close them. Price accumulation starts at `now`.
+    pub fn upgrade(self, now: i64) -> PoolState {
+        PoolState {
+            discriminator: POOL_STATE_DISCRIMINATOR,
+            is_initialized: self.is_initialized,
+            version: POOL_STATE_VERSION,
+            token_a_mint: self.token_a_mint,
+            token_b_mint: self.token_b_mint,
+            token_a_reserve: self.token_a_reserve,
+            token_b_reserve: self.token_b_reserve,
+            lp_mint: self.lp_mint,
+            lp_supply: self.lp_supply,
+            bump_seed: self.bump_seed,
+            curve_type: CurveType::ConstantProduct,
+            creator: Pubkey::default(),
+            initial_amp: 0,
+            target_amp: 0,
+            ramp_start_ts: 0,
+            ramp_stop_ts: 0,
+            price_a_cumulative_x64: 0,
+            price_b_cumulative_x64: 0,
+            last_update_ts: now,
+            fee_bps: SWAP_FEE_BPS,
+            vault_a: Pubkey::default(),
+            vault_b: Pubkey::default(),
+            reserve_a_cumulative: 0,
+            reserve_b_cumulative: 0,
+            reserved: [0; POOL_STATE_RESERVED],
+            legacy_seeds: true,
+        }
+    }
+}
+
+// ------------------ Migrate Pool ------------------
+/// Accounts: payer, pool state, system program, old pool token A, old pool
+/// token B, token A mint, token B mint, pool token A at its PDA, pool token B
+/// at its PDA, token program, followed by the other token program if the
+/// mints differ.
+pub(crate) fn process_migrate_pool(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let payer_account = next_account_info(account_info_iter)?; // 0
+    let pool_state_account = next_account_info(account_info_iter)?; // 1
+    let system_program_account = next_account_info(account_info_iter)?; // 2
+    let old_token_a_account = next_account_info(account_info_iter)?; // 3
+    let old_token_b_account = next_account_info(account_info_iter)?; // 4
+    let token_a_mint_account = next_account_info(account_info_iter)?; // 5
+    let token_b_mint_account = next_account_info(account_info_iter)?; // 6
+    let pool_token_a_account = next_account_info(account_info_iter)?; // 7
+    let pool_token_b_account = next_account_info(account_info_iter)?; // 8
+    let token_program_account = next_account_info(account_info_iter)?; // 9
+
+    if !payer_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if pool_state_account.owner != program_id {
+        return Err(LiquidityPoolError::InvalidOwner.into());
+    }
+    if *system_program_account.key != system_program::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    if !is_legacy_pool(pool_state_account) {
+        return Err(LiquidityPoolError::PoolAlreadyMigrated.into());
+    }
+    let legacy = PoolStateV1::try_from_slice(&pool_state_account.data.borrow())?;
+    if legacy.is_initialized == 0 {
+        return Err(LiquidityPoolError::PoolNotInitialized.into());
+    }
+    let mut pool_state_data = legacy.upgrade(Clock::get()?.unix_timestamp);
+
+    let old_token_a = validate_pool_vault(old_token_a_account, pool_state_account.key, &pool_state_data.token_a_mint)?;
+    let old_token_b = validate_pool_vault(old_token_b_account, pool_state_account.key, &pool_state_data.token_b_mint)?;
+    check_reserves(&pool_state_data, &old_token_a, &old_token_b)?;
+
+    if *token_a_mint_account.key != pool_state_data.token_a_mint || *token_b_mint_account.key != pool_state_data.token_b_mint {
+        return Err(LiquidityPoolError::InvalidTokenMint.into());
+    }
+    let (vault_a_pda, vault_a_bump) = vault_address(program_id, pool_state_account.key, token_a_mint_account.key);
+    let (vault_b_pda, vault_b_bump) = vault_address(program_id, pool_state_account.key, token_b_mint_account.key);
+    if *pool_token_a_account.key != vault_a_pda || *pool_token_b_account.key != vault_b_pda {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    // Move everything the old vaults hold into new ones at the vault PDAs;
+    // the mints are passed along so Token-2022 transfers are checked
+    let mut extra_accounts = vec![token_a_mint_account.clone(), token_b_mint_account.clone()];
+    extra_accounts.extend(account_info_iter.cloned());
+    let tokens = TokenAccounts::new(token_program_account, &extra_accounts)?;
+    let pool_seeds = pool_state_data.signer_seeds();
+    let pool_seeds: &[&[u8]] = &pool_seeds.as_slices();
+    for (old_account, old_vault, vault_account, mint_account, vault_bump) in [
+        (old_token_a_account, &old_token_a, pool_token_a_account, token_a_mint_account, vault_a_bump),
+        (old_token_b_account, &old_token_b, pool_token_b_account, token_b_mint_account, vault_b_bump),
+    ] {
+        tokens.create_account(
+            payer_account,
+            vault_account,
+            mint_account,
+            pool_state_account.key,
+            system_program_account,
+            &[&[b"vault", pool_state_account.key.as_ref(), mint_account.key.as_ref(), &[vault_bump]]],
+        )?;
+        tokens.transfer(old_account, vault_account, pool_state_account, old_vault.amount, &[pool_seeds])?;
+    }
+    pool_state_data.vault_a = vault_a_pda;
+    pool_state_data.vault_b = vault_b_pda;
+    let pool_token_a = validate_pool_vault(pool_token_a_account, pool_state_account.key, &pool_state_data.token_a_mint)?;
+    let pool_token_b = validate_pool_vault(pool_token_b_account, pool_state_account.key, &pool_state_data.token_b_mint)?;
+    check_reserves(&pool_state_data, &pool_token_a, &pool_token_b)?;
+
+    let top_up = Rent::get()?.minimum_balance(PoolState::LEN).saturating_sub(pool_state_account.lamports());
+    if top_up > 0 {
+        invoke(
+            &system_instruction::transfer(payer_account.key, pool_state_account.key, top_up),
+            &[payer_account.clone(), pool_state_account.clone(), system_program_account.clone()],
+        )?;
+    }
+    pool_state_account.realloc(PoolState::LEN, true)?;
+    pool_state_data.serialize(&mut &mut pool_state_account.data.borrow_mut()[..])?;
+
+    // The migrated account must load like any other pool, at its canonical PDA
+    load_pool_state(program_id, pool_state_account)?;
+
+    msg!("Pool {} migrated to version {}", pool_state_account.key, POOL_STATE_VERSION);
+    Ok(())
+}
@@ -0,0 +1,55 @@
+//! Time-weighted average price accumulators, in the style of Uniswap v2.
+//!
+//! A pool adds `spot price × seconds elapsed` to a running sum before each
+//! change to its reserves. A consumer reads the sum at two times, through
+//! `ObservePrice`, and divides the difference by the time between them to get
+//! the average price over that window. Moving that average requires holding the
+//! price away from the market for the whole window rather than for a single
+//! transaction. Accumulators are allowed to wrap, and only differences between
+//! them are meaningful.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+
+/// Snapshot of a pool's price accumulators, as returned by `ObservePrice`.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PriceObservation {
+    pub timestamp: i64,
+    /// Sum of the price of token A in token B over time, in Q64.64 × seconds.
+    pub price_a_cumulative_x64: u128,
+    /// Sum of the price of token B in token A over time, in Q64.64 × seconds.
+    pub price_b_cumulative_x64: u128,
+}
+
+/// Price of one unit of the base token in the quote token, in Q64.64, from the
+/// reserve ratio. Zero if either reserve is empty.
+pub fn spot_price_x64(base_reserve: u64, quote_reserve: u64) -> u128 {
+    if base_reserve == 0 {
+        return 0;
+    }
+    ((quote_reserve as u128) << 64) / base_reserve as u128
+}
+
+/// Adds the spot prices at the given reserves, held for `elapsed` seconds, to
+/// the accumulators.
+pub fn accumulate(
+    price_a_cumulative_x64: u128,
+    price_b_cumulative_x64: u128,
+    reserve_a: u64,
+    reserve_b: u64,
+    elapsed: u64,
+) -> (u128, u128) {
+    (
+        price_a_cumulative_x64.wrapping_add(spot_price_x64(reserve_a, reserve_b).wrapping_mul(elapsed as u128)),
+        price_b_cumulative_x64.wrapping_add(spot_price_x64(reserve_b, reserve_a).wrapping_mul(elapsed as u128)),
+    )
+}
+
+/// Average prices of token A and token B, in Q64.64, between two observations
+/// of the same pool. `None` unless `newer` is strictly later than `older`.
+pub fn twap_x64(older: &PriceObservation, newer: &PriceObservation) -> Option<(u128, u128)> {
+    let elapsed = newer.timestamp.checked_sub(older.timestamp).filter(|elapsed| *elapsed > 0)? as u128;
+    Some((
+        newer.price_a_cumulative_x64.wrapping_sub(older.price_a_cumulative_x64) / elapsed,
+        newer.price_b_cumulative_x64.wrapping_sub(older.price_b_cumulative_x64) / elapsed,
+    ))
+}
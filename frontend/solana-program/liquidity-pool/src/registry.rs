@@ -0,0 +1,224 @@
+//! On-chain registry of every pool created by `InitializePool` or
+//! `InitializeConcentratedPool`.
+//!
+//! Entries are appended to fixed-size pages so clients can enumerate pools page
+//! by page instead of scanning program accounts. A header at `[b"registry"]`
+//! counts the pools, and pool `n` is recorded in page `n / REGISTRY_PAGE_CAPACITY`
+//! at `[b"registry_page", page_index.to_le_bytes()]`. The header and each page
+//! are created by the program on first use, with the pool creator paying rent.
+//! Closing a pool clears the `pool` of its entry, leaving the slot in place so
+//! later pools keep their pages; a reopened pool is appended as a new entry.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_program,
+};
+
+use crate::{create_account, LiquidityPoolError};
+
+/// Entries per registry page.
+pub const REGISTRY_PAGE_CAPACITY: usize = 32;
+
+/// First bytes of the [`RegistryHeader`] account: the first 8 bytes of
+/// `sha256("account:RegistryHeader")`.
+pub const REGISTRY_HEADER_DISCRIMINATOR: [u8; 8] = [74, 214, 198, 41, 138, 211, 36, 245];
+
+/// First bytes of every [`RegistryPage`] account: the first 8 bytes of
+/// `sha256("account:RegistryPage")`.
+pub const REGISTRY_PAGE_DISCRIMINATOR: [u8; 8] = [190, 151, 207, 163, 226, 253, 16, 250];
+
+// ------------------ Registry Structs ------------------
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Default, PartialEq)]
+pub struct RegistryHeader {
+    /// Always [`REGISTRY_HEADER_DISCRIMINATOR`].
+    pub discriminator: [u8; 8],
+    pub is_initialized: u8,
+    pub pool_count: u64,
+}
+
+impl RegistryHeader {
+    pub const LEN: usize = 8 + 1 + 8;
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct RegistryEntry {
+    pub token_a_mint: Pubkey,
+    pub token_b_mint: Pubkey,
+    pub pool: Pubkey,
+    /// The default pubkey for a concentrated pool, which has no LP mint.
+    pub lp_mint: Pubkey,
+    pub fee_bps: u64,
+    pub created_slot: u64,
+}
+
+impl RegistryEntry {
+    pub const LEN: usize = 32 + 32 + 32 + 32 + 8 + 8;
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Default, PartialEq)]
+pub struct RegistryPage {
+    /// Always [`REGISTRY_PAGE_DISCRIMINATOR`].
+    pub discriminator: [u8; 8],
+    pub is_initialized: u8,
+    pub page_index: u32,
+    pub entries: Vec<RegistryEntry>,
+}
+
+impl RegistryPage {
+    pub const LEN: usize = 8 + 1 + 4 + 4 + REGISTRY_PAGE_CAPACITY * RegistryEntry::LEN;
+}
+
+pub fn registry_header_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"registry"], program_id)
+}
+
+pub fn registry_page_address(program_id: &Pubkey, page_index: u32) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"registry_page", &page_index.to_le_bytes()], program_id)
+}
+
+/// Whether the entry records a concentrated pool rather than a
+/// constant-product one.
+pub fn is_concentrated(entry: &RegistryEntry) -> bool {
+    entry.lp_mint == Pubkey::default()
+}
+
+/// Records a new pool in the registry. `page_account` must be the page the
+/// next entry belongs in. The header and page are created, funded by `payer`,
+/// if they do not exist yet.
+pub(crate) fn append_pool<'a>(
+    program_id: &Pubkey,
+    payer: &AccountInfo<'a>,
+    header_account: &AccountInfo<'a>,
+    page_account: &AccountInfo<'a>,
+    system_program_account: &AccountInfo<'a>,
+    entry: RegistryEntry,
+) -> ProgramResult {
+    let (header_key, header_bump) = registry_header_address(program_id);
+    if *header_account.key != header_key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let mut header = load_or_create_registry_account::<RegistryHeader>(
+        program_id,
+        payer,
+        header_account,
+        system_program_account,
+        RegistryHeader::LEN,
+        &REGISTRY_HEADER_DISCRIMINATOR,
+        &[b"registry", &[header_bump]],
+    )?;
+
+    let page_index = u32::try_from(header.pool_count / REGISTRY_PAGE_CAPACITY as u64)
+        .map_err(|_| LiquidityPoolError::MathOverflow)?;
+    let (page_key, page_bump) = registry_page_address(program_id, page_index);
+    if *page_account.key != page_key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let mut page = load_or_create_registry_account::<RegistryPage>(
+        program_id,
+        payer,
+        page_account,
+        system_program_account,
+        RegistryPage::LEN,
+        &REGISTRY_PAGE_DISCRIMINATOR,
+        &[b"registry_page", &page_index.to_le_bytes(), &[page_bump]],
+    )?;
+
+    header.discriminator = REGISTRY_HEADER_DISCRIMINATOR;
+    header.is_initialized = 1;
+    header.pool_count = header.pool_count.checked_add(1).ok_or(LiquidityPoolError::MathOverflow)?;
+    page.discriminator = REGISTRY_PAGE_DISCRIMINATOR;
+    page.is_initialized = 1;
+    page.page_index = page_index;
+    page.entries.push(entry);
+
+    header.serialize(&mut &mut header_account.data.borrow_mut()[..])?;
+    page.serialize(&mut &mut page_account.data.borrow_mut()[..])?;
+    Ok(())
+}
+
+/// Marks `pool` as closed in `page_account`, the page holding its entry.
+pub(crate) fn remove_pool(program_id: &Pubkey, page_account: &AccountInfo, pool: &Pubkey) -> ProgramResult {
+    let mut page = load_registry_account::<RegistryPage>(program_id, page_account, RegistryPage::LEN, &REGISTRY_PAGE_DISCRIMINATOR)?;
+    if *page_account.key != registry_page_address(program_id, page.page_index).0 {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let entry = page
+        .entries
+        .iter_mut()
+        .find(|entry| entry.pool == *pool)
+        .ok_or(LiquidityPoolError::PoolNotRegistered)?;
+    entry.pool = Pubkey::default();
+    page.serialize(&mut &mut page_account.data.borrow_mut()[..])?;
+    Ok(())
+}
+
+/// Reads every entry of an open pool in the registry, taking its pages, in
+/// order, from `account_info_iter`. A registry not created yet has no entries.
+pub(crate) fn load_entries<'a, 'b: 'a>(
+    program_id: &Pubkey,
+    header_account: &AccountInfo,
+    account_info_iter: &mut impl Iterator<Item = &'a AccountInfo<'b>>,
+) -> Result<Vec<RegistryEntry>, ProgramError> {
+    if *header_account.key != registry_header_address(program_id).0 {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if *header_account.owner == system_program::id() {
+        return Ok(Vec::new());
+    }
+    let header = load_registry_account::<RegistryHeader>(program_id, header_account, RegistryHeader::LEN, &REGISTRY_HEADER_DISCRIMINATOR)?;
+
+    let page_count = u32::try_from(header.pool_count.div_ceil(REGISTRY_PAGE_CAPACITY as u64))
+        .map_err(|_| LiquidityPoolError::MathOverflow)?;
+    let mut entries = Vec::new();
+    for page_index in 0..page_count {
+        let page_account = next_account_info(account_info_iter)?;
+        if *page_account.key != registry_page_address(program_id, page_index).0 {
+            return Err(ProgramError::InvalidSeeds);
+        }
+        let page = load_registry_account::<RegistryPage>(program_id, page_account, RegistryPage::LEN, &REGISTRY_PAGE_DISCRIMINATOR)?;
+        entries.extend(page.entries.into_iter().filter(|entry| entry.pool != Pubkey::default()));
+    }
+    Ok(entries)
+}
+
+/// Reads a registry account, first creating it at its PDA as an empty one if
+/// it is still owned by the system program.
+fn load_or_create_registry_account<'a, T: BorshDeserialize + Default>(
+    program_id: &Pubkey,
+    payer: &AccountInfo<'a>,
+    account: &AccountInfo<'a>,
+    system_program_account: &AccountInfo<'a>,
+    len: usize,
+    discriminator: &[u8; 8],
+    seeds: &[&[u8]],
+) -> Result<T, ProgramError> {
+    if *account.owner == system_program::id() {
+        create_account(payer, account, system_program_account, program_id, len, &[seeds])?;
+        return Ok(T::default());
+    }
+    load_registry_account(program_id, account, len, discriminator)
+}
+
+/// Reads a registry account, checking that it is owned by this program, large
+/// enough and starts with `discriminator`.
+fn load_registry_account<T: BorshDeserialize>(
+    program_id: &Pubkey,
+    account: &AccountInfo,
+    len: usize,
+    discriminator: &[u8; 8],
+) -> Result<T, ProgramError> {
+    if account.owner != program_id {
+        return Err(LiquidityPoolError::InvalidOwner.into());
+    }
+    if account.data_len() < len {
+        return Err(ProgramError::AccountDataTooSmall);
+    }
+    if !account.data.borrow().starts_with(discriminator) {
+        return Err(LiquidityPoolError::InvalidAccountType.into());
+    }
+    Ok(T::deserialize(&mut &account.data.borrow()[..])?)
+}
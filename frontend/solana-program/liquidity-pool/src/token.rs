@@ -0,0 +1,267 @@
+//! Token movements for both the SPL Token and the Token-2022 programs.
+//!
+//! Token-2022 mints may carry extensions. Those that only describe the token
+//! (metadata, groups) are accepted as they are, transfer fees and transfer
+//! hooks are honoured when moving tokens, and any other extension is rejected:
+//! a permanent delegate, a frozen default state or confidential balances could
+//! move or lock a pool's tokens without it noticing.
+//!
+//! A transfer of a mint with a fee or a hook must be checked, which needs the
+//! mint and the hook program's accounts. Instructions take these as trailing
+//! accounts, looked up by key, together with the other token program when the
+//! two sides of a pool belong to different ones. Without its mint, a token is
+//! moved with a plain transfer, which Token-2022 refuses for such mints.
+//!
+//! Constant-product and weighted pools take trailing accounts and account for
+//! transfer fees. Concentrated pools, farms and distributions move exact
+//! amounts, so they accept Token-2022 mints without fees or hooks.
+
+use solana_program::{
+    account_info::AccountInfo,
+    clock::Clock,
+    entrypoint::ProgramResult,
+    program::invoke_signed,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvar::Sysvar,
+};
+use spl_token_2022::{
+    extension::{transfer_fee::TransferFeeConfig, BaseStateWithExtensions, ExtensionType, StateWithExtensions},
+    instruction, onchain,
+    state::{Account, Mint},
+};
+
+use crate::{checked_add, checked_sub, create_account, LiquidityPoolError};
+
+/// Mint extensions a pooled token may carry.
+const SUPPORTED_MINT_EXTENSIONS: &[ExtensionType] = &[
+    ExtensionType::TransferFeeConfig,
+    ExtensionType::TransferHook,
+    ExtensionType::MetadataPointer,
+    ExtensionType::TokenMetadata,
+    ExtensionType::GroupPointer,
+    ExtensionType::TokenGroup,
+    ExtensionType::GroupMemberPointer,
+    ExtensionType::TokenGroupMember,
+];
+
+/// Whether `program_id` is the SPL Token or the Token-2022 program.
+pub fn is_token_program(program_id: &Pubkey) -> bool {
+    *program_id == spl_token::id() || *program_id == spl_token_2022::id()
+}
+
+/// Unpacks a token account of either token program.
+pub fn unpack_account(token_account: &AccountInfo) -> Result<Account, ProgramError> {
+    if !is_token_program(token_account.owner) {
+        return Err(LiquidityPoolError::InvalidTokenAccount.into());
+    }
+    let data = token_account.data.borrow();
+    let state = StateWithExtensions::<Account>::unpack(&data).map_err(|_| LiquidityPoolError::InvalidTokenAccount)?;
+    Ok(state.base)
+}
+
+/// Unpacks a mint of either token program, rejecting unsupported extensions.
+pub fn unpack_mint(mint_account: &AccountInfo) -> Result<Mint, ProgramError> {
+    if !is_token_program(mint_account.owner) {
+        return Err(LiquidityPoolError::InvalidTokenMint.into());
+    }
+    let data = mint_account.data.borrow();
+    let state = StateWithExtensions::<Mint>::unpack(&data).map_err(|_| LiquidityPoolError::InvalidTokenMint)?;
+    let extensions = state.get_extension_types()?;
+    if extensions.iter().any(|extension| !SUPPORTED_MINT_EXTENSIONS.contains(extension)) {
+        return Err(LiquidityPoolError::UnsupportedMintExtension.into());
+    }
+    Ok(state.base)
+}
+
+/// Size of a token account of `mint`, with room for the extensions the mint
+/// requires of its accounts.
+pub fn account_len(mint_account: &AccountInfo) -> Result<usize, ProgramError> {
+    let data = mint_account.data.borrow();
+    let state = StateWithExtensions::<Mint>::unpack(&data).map_err(|_| LiquidityPoolError::InvalidTokenMint)?;
+    let extensions = ExtensionType::get_required_init_account_extensions(&state.get_extension_types()?);
+    ExtensionType::try_calculate_account_len::<Account>(&extensions)
+}
+
+/// Fee withheld from a transfer of `amount` in the current epoch, zero for a
+/// mint without transfer fees.
+pub fn transfer_fee(mint_account: &AccountInfo, amount: u64) -> Result<u64, ProgramError> {
+    let data = mint_account.data.borrow();
+    let state = StateWithExtensions::<Mint>::unpack(&data).map_err(|_| LiquidityPoolError::InvalidTokenMint)?;
+    match state.get_extension::<TransferFeeConfig>() {
+        Ok(config) => config
+            .calculate_epoch_fee(Clock::get()?.epoch, amount)
+            .ok_or_else(|| LiquidityPoolError::MathOverflow.into()),
+        Err(_) => Ok(0),
+    }
+}
+
+/// Amount to send for `amount` to arrive after the transfer fee.
+pub fn amount_before_fee(mint_account: &AccountInfo, amount: u64) -> Result<u64, ProgramError> {
+    let data = mint_account.data.borrow();
+    let state = StateWithExtensions::<Mint>::unpack(&data).map_err(|_| LiquidityPoolError::InvalidTokenMint)?;
+    match state.get_extension::<TransferFeeConfig>() {
+        Ok(config) => {
+            let fee = config
+                .calculate_inverse_epoch_fee(Clock::get()?.epoch, amount)
+                .ok_or(LiquidityPoolError::MathOverflow)?;
+            checked_add(amount, fee)
+        }
+        Err(_) => Ok(amount),
+    }
+}
+
+/// The token program an instruction was given and its trailing accounts: the
+/// mints of tokens whose transfers must be checked, transfer-hook accounts,
+/// and the other token program.
+pub struct TokenAccounts<'a, 'b> {
+    token_program_account: &'a AccountInfo<'b>,
+    extra_accounts: &'a [AccountInfo<'b>],
+}
+
+impl<'a, 'b> TokenAccounts<'a, 'b> {
+    pub fn new(token_program_account: &'a AccountInfo<'b>, extra_accounts: &'a [AccountInfo<'b>]) -> Result<Self, ProgramError> {
+        if !is_token_program(token_program_account.key) {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        Ok(Self { token_program_account, extra_accounts })
+    }
+
+    /// The trailing account with key `key`, if it was passed.
+    fn find(&self, key: &Pubkey) -> Option<&'a AccountInfo<'b>> {
+        self.extra_accounts.iter().find(|account| account.key == key)
+    }
+
+    /// The token program owning `account`, which must have been passed.
+    fn program_for(&self, account: &AccountInfo) -> Result<&'a AccountInfo<'b>, ProgramError> {
+        if account.owner == self.token_program_account.key {
+            return Ok(self.token_program_account);
+        }
+        match self.find(account.owner) {
+            Some(program) if is_token_program(program.key) => Ok(program),
+            _ => Err(ProgramError::IncorrectProgramId),
+        }
+    }
+
+    /// Fee withheld from a transfer of `amount` of `mint`, zero if the mint was
+    /// not passed.
+    pub fn transfer_fee(&self, mint: &Pubkey, amount: u64) -> Result<u64, ProgramError> {
+        self.find(mint).map_or(Ok(0), |mint_account| transfer_fee(mint_account, amount))
+    }
+
+    /// Amount of `mint` to send for `amount` to arrive, `amount` itself if the
+    /// mint was not passed.
+    pub fn amount_before_fee(&self, mint: &Pubkey, amount: u64) -> Result<u64, ProgramError> {
+        self.find(mint).map_or(Ok(amount), |mint_account| amount_before_fee(mint_account, amount))
+    }
+
+    /// Creates `account` as a token account of `mint` owned by `owner`, its
+    /// rent paid by `payer`. The account signs itself, or is a PDA signed for
+    /// with `signers_seeds`.
+    pub fn create_account(
+        &self,
+        payer: &AccountInfo<'b>,
+        account: &AccountInfo<'b>,
+        mint: &AccountInfo<'b>,
+        owner: &Pubkey,
+        system_program_account: &AccountInfo<'b>,
+        signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        let program = self.program_for(mint)?;
+        create_account(payer, account, system_program_account, program.key, account_len(mint)?, signers_seeds)?;
+        invoke_signed(
+            &instruction::initialize_account3(program.key, account.key, mint.key, owner)?,
+            &[account.clone(), mint.clone(), program.clone()],
+            &[],
+        )
+    }
+
+    /// Moves `amount` from `source` to `destination` on behalf of `authority`,
+    /// signing with `signer_seeds` when the authority is a PDA, and returns
+    /// what `destination` actually received.
+    pub fn transfer(
+        &self,
+        source: &AccountInfo<'b>,
+        destination: &AccountInfo<'b>,
+        authority: &AccountInfo<'b>,
+        amount: u64,
+        signer_seeds: &[&[&[u8]]],
+    ) -> Result<u64, ProgramError> {
+        let mint = unpack_account(source)?.mint;
+        let program = self.program_for(source)?;
+        let balance_before = unpack_account(destination)?.amount;
+
+        match self.find(&mint) {
+            Some(mint_account) => onchain::invoke_transfer_checked(
+                program.key,
+                source.clone(),
+                mint_account.clone(),
+                destination.clone(),
+                authority.clone(),
+                self.extra_accounts,
+                amount,
+                unpack_mint(mint_account)?.decimals,
+                signer_seeds,
+            )?,
+            None => {
+                // Deprecated in Token-2022, but still valid for mints without
+                // fees or hooks, and the only transfer SPL Token callers that
+                // pass no mints can make.
+                #[allow(deprecated)]
+                let transfer = instruction::transfer(program.key, source.key, destination.key, authority.key, &[], amount)?;
+                invoke_signed(
+                    &transfer,
+                    &[source.clone(), destination.clone(), authority.clone(), program.clone()],
+                    signer_seeds,
+                )?
+            }
+        }
+
+        checked_sub(unpack_account(destination)?.amount, balance_before)
+    }
+
+    /// Mints `amount` of `mint` to `destination`, signed by the mint authority
+    /// PDA.
+    pub fn mint_to(
+        &self,
+        mint: &AccountInfo<'b>,
+        destination: &AccountInfo<'b>,
+        authority: &AccountInfo<'b>,
+        amount: u64,
+        signer_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        let program = self.program_for(mint)?;
+        invoke_signed(
+            &instruction::mint_to(program.key, mint.key, destination.key, authority.key, &[], amount)?,
+            &[mint.clone(), destination.clone(), authority.clone(), program.clone()],
+            signer_seeds,
+        )
+    }
+
+    /// Closes the token account `account`, sending its lamports to
+    /// `destination`, signed by its owner PDA.
+    pub fn close_account(
+        &self,
+        account: &AccountInfo<'b>,
+        destination: &AccountInfo<'b>,
+        authority: &AccountInfo<'b>,
+        signer_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        let program = self.program_for(account)?;
+        invoke_signed(
+            &instruction::close_account(program.key, account.key, destination.key, authority.key, &[])?,
+            &[account.clone(), destination.clone(), authority.clone(), program.clone()],
+            signer_seeds,
+        )
+    }
+
+    /// Burns `amount` from `source`, signed by its owner.
+    pub fn burn(&self, source: &AccountInfo<'b>, mint: &AccountInfo<'b>, authority: &AccountInfo<'b>, amount: u64) -> ProgramResult {
+        let program = self.program_for(mint)?;
+        invoke_signed(
+            &instruction::burn(program.key, source.key, mint.key, authority.key, &[], amount)?,
+            &[source.clone(), mint.clone(), authority.clone(), program.clone()],
+            &[],
+        )
+    }
+}
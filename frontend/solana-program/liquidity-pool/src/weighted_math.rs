@@ -0,0 +1,155 @@
+//! Balancer-style weighted-pool math.
+//!
+//! A weighted pool keeps `Π balance_i ^ weight_i` constant across swaps, so a
+//! token's share of the pool's value stays at its weight. Weights and
+//! intermediate ratios are fixed-point numbers scaled by [`ONE`]. Outputs round
+//! down and inputs round up, as in [`crate::curve`].
+
+use crate::curve::{CurveError, BPS_DENOMINATOR};
+
+/// Fixed-point scale for weights and ratios.
+pub const ONE: u128 = 1_000_000_000_000_000_000;
+
+/// Smallest weight a token may have (1%).
+pub const MIN_WEIGHT: u64 = (ONE / 100) as u64;
+
+/// Largest share of the input balance a single swap may add (30%), which keeps
+/// the power function in the range where it is accurate.
+pub const MAX_IN_RATIO: u128 = ONE * 3 / 10;
+
+/// Bits of the fractional exponent evaluated by [`pow_up`].
+const FRACTION_BITS: u32 = 32;
+
+fn mul_down(a: u128, b: u128) -> Result<u128, CurveError> {
+    Ok(a.checked_mul(b).ok_or(CurveError::MathOverflow)? / ONE)
+}
+
+fn mul_up(a: u128, b: u128) -> Result<u128, CurveError> {
+    Ok(a.checked_mul(b).ok_or(CurveError::MathOverflow)?.div_ceil(ONE))
+}
+
+fn div_down(a: u128, b: u128) -> Result<u128, CurveError> {
+    Ok(a.checked_mul(ONE).ok_or(CurveError::MathOverflow)? / b)
+}
+
+fn div_up(a: u128, b: u128) -> Result<u128, CurveError> {
+    Ok(a.checked_mul(ONE).ok_or(CurveError::MathOverflow)?.div_ceil(b))
+}
+
+/// Fixed-point square root of `x`, rounded up.
+fn sqrt_up(x: u128) -> u128 {
+    // x ≤ ONE here, so x · ONE fits in a u128. Newton's method from a power of
+    // two above the root converges in a handful of steps.
+    let value = x * ONE;
+    if value < 2 {
+        return value;
+    }
+    let mut root = 1u128 << (128 - value.leading_zeros()).div_ceil(2);
+    loop {
+        let next = (root + value / root) / 2;
+        if next >= root {
+            break;
+        }
+        root = next;
+    }
+    if root * root < value {
+        root + 1
+    } else {
+        root
+    }
+}
+
+/// `base ^ exponent` for `base ≤ ONE`, rounded up.
+///
+/// The integer part of the exponent is applied by repeated squaring and the
+/// fractional part bit by bit with successive square roots. Every step rounds
+/// up, and the fractional bits past [`FRACTION_BITS`] are dropped, which for a
+/// base below one can only make the result larger.
+pub fn pow_up(base: u128, exponent: u128) -> Result<u128, CurveError> {
+    if base > ONE {
+        return Err(CurveError::MathOverflow);
+    }
+
+    let mut result = ONE;
+
+    let mut integer = exponent / ONE;
+    let mut square = base;
+    while integer > 0 {
+        if integer & 1 == 1 {
+            result = mul_up(result, square)?;
+        }
+        square = mul_up(square, square)?;
+        integer >>= 1;
+    }
+
+    let mut fraction = exponent % ONE;
+    let mut root = base;
+    for _ in 0..FRACTION_BITS {
+        if fraction == 0 {
+            break;
+        }
+        root = sqrt_up(root);
+        fraction *= 2;
+        if fraction >= ONE {
+            result = mul_up(result, root)?;
+            fraction -= ONE;
+        }
+    }
+
+    Ok(result.min(ONE))
+}
+
+/// Output for selling exactly `amount_in` of a token with `weight_in` for one
+/// with `weight_out`, after a `fee_bps` fee that stays in the pool.
+pub fn swap_exact_in(
+    amount_in: u64,
+    balance_in: u64,
+    weight_in: u64,
+    balance_out: u64,
+    weight_out: u64,
+    fee_bps: u64,
+) -> Result<u64, CurveError> {
+    if fee_bps >= BPS_DENOMINATOR {
+        return Err(CurveError::InvalidFee);
+    }
+    if balance_in == 0 || balance_out == 0 || weight_in == 0 || weight_out == 0 {
+        return Err(CurveError::ZeroReserves);
+    }
+
+    let amount_in_after_fee = amount_in as u128 * (BPS_DENOMINATOR - fee_bps) as u128 / BPS_DENOMINATOR as u128;
+    if amount_in_after_fee > mul_down(balance_in as u128, MAX_IN_RATIO)? {
+        return Err(CurveError::InsufficientLiquidity);
+    }
+
+    // out = balance_out · (1 − (balance_in / (balance_in + in)) ^ (weight_in / weight_out))
+    let base = div_up(balance_in as u128, balance_in as u128 + amount_in_after_fee)?;
+    let exponent = div_down(weight_in as u128, weight_out as u128)?;
+    let power = pow_up(base, exponent)?;
+    let amount_out = mul_down(balance_out as u128, ONE - power)?;
+    u64::try_from(amount_out).map_err(|_| CurveError::MathOverflow)
+}
+
+/// Amounts of each token to deposit for `lp_amount` new LP tokens, rounded up.
+pub fn join_amounts(lp_amount: u64, balances: &[u64], lp_supply: u64) -> Result<Vec<u64>, CurveError> {
+    if lp_supply == 0 {
+        return Err(CurveError::ZeroReserves);
+    }
+    balances
+        .iter()
+        .map(|&balance| {
+            let amount = (lp_amount as u128 * balance as u128).div_ceil(lp_supply as u128);
+            u64::try_from(amount).map_err(|_| CurveError::MathOverflow)
+        })
+        .collect()
+}
+
+/// Amounts of each token returned for burning `lp_amount`, rounded down.
+pub fn exit_amounts(lp_amount: u64, balances: &[u64], lp_supply: u64) -> Result<Vec<u64>, CurveError> {
+    if lp_supply == 0 || lp_amount > lp_supply {
+        return Err(CurveError::InsufficientLiquidity);
+    }
+    balances
+        .iter()
+        .map(|&balance| Ok((lp_amount as u128 * balance as u128 / lp_supply as u128) as u64))
+        .collect()
+}
@@ -0,0 +1,513 @@
+//! Weighted pools holding two to [`MAX_WEIGHTED_TOKENS`] tokens, each with a
+//! fixed share of the pool's value. Any pair can be swapped along the weighted
+//! invariant in [`crate::weighted_math`], and liquidity is joined and exited in
+//! proportion to the current balances.
+//!
+//! A weighted pool lives in its own PDA, `[b"weighted_pool", lp_mint]`, since
+//! its mints no longer fit in the seeds. The program creates it when the pool
+//! is initialized, the creator paying its rent, together with a vault for each
+//! token at [`crate::vault_address`], as for two-token pools. The vaults are
+//! recorded in the pool state and checked on every instruction.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::invoke,
+    program_error::ProgramError,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    system_program,
+};
+use spl_token_2022::{
+    instruction as spl_token_instruction,
+    state::{Account, Mint},
+};
+
+use crate::{
+    checked_add, checked_sub, create_account, token::TokenAccounts, validate_mint, validate_pool_vault,
+    validate_token_program, vault_address, weighted_math, LiquidityPoolError, MINIMUM_LIQUIDITY, SWAP_FEE_BPS,
+};
+
+/// Most tokens a weighted pool can hold.
+pub const MAX_WEIGHTED_TOKENS: usize = 8;
+
+/// LP supply minted when a weighted pool is created, of which
+/// `MINIMUM_LIQUIDITY` is locked. Unlike a two-token pool there is no natural
+/// mean of the deposits, so every pool starts from the same supply.
+pub const INITIAL_WEIGHTED_LP_SUPPLY: u64 = 1_000_000_000;
+
+// ------------------ WeightedPoolState Struct ------------------
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct WeightedToken {
+    pub mint: Pubkey,
+    pub vault: Pubkey,
+    pub reserve: u64,
+    /// Share of the pool's value, scaled by `weighted_math::ONE`.
+    pub weight: u64,
+}
+
+impl WeightedToken {
+    pub const LEN: usize = 32 + 32 + 8 + 8;
+}
+
+/// First bytes of every [`WeightedPoolState`] account: the first 8 bytes of
+/// `sha256("account:WeightedPoolState")`.
+pub const WEIGHTED_POOL_STATE_DISCRIMINATOR: [u8; 8] = [15, 71, 101, 65, 51, 212, 8, 89];
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Default)]
+pub struct WeightedPoolState {
+    /// Always [`WEIGHTED_POOL_STATE_DISCRIMINATOR`].
+    pub discriminator: [u8; 8],
+    pub is_initialized: u8,
+    pub bump_seed: u8,
+    pub creator: Pubkey,
+    pub lp_mint: Pubkey,
+    pub lp_supply: u64,
+    pub tokens: Vec<WeightedToken>,
+}
+
+impl WeightedPoolState {
+    /// Space for a pool with `MAX_WEIGHTED_TOKENS` tokens; smaller pools leave
+    /// the tail zeroed.
+    pub const LEN: usize = 8 + 1 + 1 + 32 + 32 + 8 + 4 + MAX_WEIGHTED_TOKENS * WeightedToken::LEN;
+
+    fn reserves(&self) -> Vec<u64> {
+        self.tokens.iter().map(|token| token.reserve).collect()
+    }
+}
+
+// ------------------ Initialize Weighted Pool ------------------
+/// Accounts: creator, pool state, LP mint, creator LP account, token program,
+/// rent sysvar, system program, `(mint, vault, creator token account)` for each
+/// token, then any trailing token accounts. The vaults are created at their
+/// PDAs. The LP mint may be passed
+/// unallocated, signing as a new keypair, for the program to create. The
+/// reserves are what arrives in the vaults, after any transfer fees.
+pub(crate) fn process_initialize_weighted_pool(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    weights: Vec<u64>,
+    amounts: Vec<u64>,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let creator_account = next_account_info(account_info_iter)?; // 0
+    let pool_state_account = next_account_info(account_info_iter)?; // 1
+    let lp_mint_account = next_account_info(account_info_iter)?; // 2
+    let creator_lp_token_account = next_account_info(account_info_iter)?; // 3
+    let token_program_account = next_account_info(account_info_iter)?; // 4
+    let rent_sysvar_account = next_account_info(account_info_iter)?; // 5
+    let system_program_account = next_account_info(account_info_iter)?; // 6
+
+    if !creator_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    if weights.len() != amounts.len() {
+        return Err(LiquidityPoolError::InvalidInstruction.into());
+    }
+    if !(2..=MAX_WEIGHTED_TOKENS).contains(&weights.len()) {
+        return Err(LiquidityPoolError::InvalidWeights.into());
+    }
+    if weights.iter().any(|&weight| weight < weighted_math::MIN_WEIGHT) {
+        return Err(LiquidityPoolError::InvalidWeights.into());
+    }
+    if weights.iter().map(|&weight| weight as u128).sum::<u128>() != weighted_math::ONE {
+        return Err(LiquidityPoolError::InvalidWeights.into());
+    }
+    if amounts.contains(&0) {
+        return Err(LiquidityPoolError::ZeroAmountNotAllowed.into());
+    }
+
+    let (pda, bump_seed) = Pubkey::find_program_address(&[b"weighted_pool", lp_mint_account.key.as_ref()], program_id);
+    if pda != *pool_state_account.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    // The program creates the pool state, so one it already owns is either
+    // this pool or some other data that must not be overwritten
+    if pool_state_account.owner == program_id {
+        if pool_state_account.data.borrow().starts_with(&WEIGHTED_POOL_STATE_DISCRIMINATOR) {
+            return Err(LiquidityPoolError::PoolAlreadyInitialized.into());
+        }
+        return Err(LiquidityPoolError::InvalidAccountType.into());
+    }
+    if *system_program_account.key != system_program::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    validate_token_program(token_program_account)?;
+    let create_lp_mint = *lp_mint_account.owner == system_program::id() && lp_mint_account.data_is_empty();
+    if !create_lp_mint && lp_mint_account.owner != token_program_account.key {
+        return Err(LiquidityPoolError::InvalidTokenMint.into());
+    }
+
+    let mut pool_tokens: Vec<WeightedToken> = Vec::with_capacity(weights.len());
+    let mut creator_token_accounts = Vec::with_capacity(weights.len());
+    let mut vaults = Vec::with_capacity(weights.len());
+    for (&weight, &amount) in weights.iter().zip(&amounts) {
+        let mint_account = next_account_info(account_info_iter)?;
+        let vault_account = next_account_info(account_info_iter)?;
+        let creator_token_account = next_account_info(account_info_iter)?;
+
+        validate_mint(mint_account)?;
+        if pool_tokens.iter().any(|token| token.mint == *mint_account.key) {
+            return Err(LiquidityPoolError::SameTokenMints.into());
+        }
+        let (vault_pda, vault_bump) = vault_address(program_id, &pda, mint_account.key);
+        if *vault_account.key != vault_pda {
+            return Err(ProgramError::InvalidSeeds);
+        }
+
+        pool_tokens.push(WeightedToken { mint: *mint_account.key, vault: vault_pda, reserve: amount, weight });
+        vaults.push((vault_account, mint_account, vault_bump));
+        creator_token_accounts.push(creator_token_account);
+    }
+    let tokens = TokenAccounts::new(token_program_account, account_info_iter.as_slice())?;
+
+    create_account(
+        creator_account,
+        pool_state_account,
+        system_program_account,
+        program_id,
+        WeightedPoolState::LEN,
+        &[&[b"weighted_pool", lp_mint_account.key.as_ref(), &[bump_seed]]],
+    )?;
+    for &(vault_account, mint_account, vault_bump) in &vaults {
+        tokens.create_account(
+            creator_account,
+            vault_account,
+            mint_account,
+            &pda,
+            system_program_account,
+            &[&[b"vault", pda.as_ref(), mint_account.key.as_ref(), &[vault_bump]]],
+        )?;
+    }
+
+    if create_lp_mint {
+        create_account(creator_account, lp_mint_account, system_program_account, token_program_account.key, Mint::LEN, &[])?;
+    }
+
+    // Initialize LP Mint
+    invoke(
+        &spl_token_instruction::initialize_mint(
+            token_program_account.key,
+            lp_mint_account.key,
+            pool_state_account.key, // LP mint authority is the PDA
+            Some(pool_state_account.key), // Freeze authority is also the PDA
+            6,
+        )?,
+        &[lp_mint_account.clone(), rent_sysvar_account.clone(), token_program_account.clone()],
+    )?;
+
+    for ((token, creator_token_account), (vault_account, _, _)) in pool_tokens.iter_mut().zip(&creator_token_accounts).zip(&vaults) {
+        token.reserve = tokens.transfer(creator_token_account, vault_account, creator_account, token.reserve, &[])?;
+        if token.reserve == 0 {
+            return Err(LiquidityPoolError::ZeroAmountNotAllowed.into());
+        }
+    }
+
+    let pool_state_data = WeightedPoolState {
+        discriminator: WEIGHTED_POOL_STATE_DISCRIMINATOR,
+        is_initialized: 1,
+        bump_seed,
+        creator: *creator_account.key,
+        lp_mint: *lp_mint_account.key,
+        lp_supply: INITIAL_WEIGHTED_LP_SUPPLY, // Includes the locked MINIMUM_LIQUIDITY
+        tokens: pool_tokens,
+    };
+    mint_lp(
+        &pool_state_data,
+        &tokens,
+        lp_mint_account,
+        creator_lp_token_account,
+        pool_state_account,
+        checked_sub(INITIAL_WEIGHTED_LP_SUPPLY, MINIMUM_LIQUIDITY)?,
+    )?;
+
+    pool_state_data.serialize(&mut &mut pool_state_account.data.borrow_mut()[..])?;
+
+    msg!("Weighted pool initialized with {} tokens", pool_state_data.tokens.len());
+    Ok(())
+}
+
+// ------------------ Weighted Swap ------------------
+/// Accounts: swapper, pool state, pool vault in, pool vault out, swapper token
+/// in, swapper token out, token program, then any trailing token accounts.
+pub(crate) fn process_weighted_swap(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    token_in: u8,
+    token_out: u8,
+    amount_in: u64,
+    min_out: u64,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let swapper_account = next_account_info(account_info_iter)?; // 0
+    let pool_state_account = next_account_info(account_info_iter)?; // 1
+    let pool_in_account = next_account_info(account_info_iter)?; // 2
+    let pool_out_account = next_account_info(account_info_iter)?; // 3
+    let swapper_in_account = next_account_info(account_info_iter)?; // 4
+    let swapper_out_account = next_account_info(account_info_iter)?; // 5
+    let token_program_account = next_account_info(account_info_iter)?; // 6
+
+    if !swapper_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut pool_state_data = load_weighted_pool(program_id, pool_state_account)?;
+    let tokens = TokenAccounts::new(token_program_account, account_info_iter.as_slice())?;
+
+    let (token_in, token_out) = (token_in as usize, token_out as usize);
+    if token_in == token_out || token_in >= pool_state_data.tokens.len() || token_out >= pool_state_data.tokens.len() {
+        return Err(LiquidityPoolError::InvalidInstruction.into());
+    }
+    let pool_in = validate_weighted_vault(pool_in_account, pool_state_account.key, &pool_state_data.tokens[token_in])?;
+    let pool_out = validate_weighted_vault(pool_out_account, pool_state_account.key, &pool_state_data.tokens[token_out])?;
+    if pool_in.amount < pool_state_data.tokens[token_in].reserve || pool_out.amount < pool_state_data.tokens[token_out].reserve {
+        return Err(LiquidityPoolError::ReserveMismatch.into());
+    }
+
+    if amount_in == 0 {
+        return Err(LiquidityPoolError::ZeroAmountNotAllowed.into());
+    }
+
+    // The swap is priced on what the vault receives, after any transfer fee
+    let amount_received = tokens.transfer(swapper_in_account, pool_in_account, swapper_account, amount_in, &[])?;
+    let (input, output) = (&pool_state_data.tokens[token_in], &pool_state_data.tokens[token_out]);
+    let amount_out =
+        weighted_math::swap_exact_in(amount_received, input.reserve, input.weight, output.reserve, output.weight, SWAP_FEE_BPS)?;
+    if amount_out == 0 {
+        return Err(LiquidityPoolError::InvalidAmount.into());
+    }
+
+    pool_state_data.tokens[token_in].reserve = checked_add(pool_state_data.tokens[token_in].reserve, amount_received)?;
+    pool_state_data.tokens[token_out].reserve = checked_sub(pool_state_data.tokens[token_out].reserve, amount_out)?;
+
+    // Slippage is measured on what reaches the swapper, after any transfer fees
+    let amount_delivered = transfer_out(&pool_state_data, &tokens, pool_out_account, swapper_out_account, pool_state_account, amount_out)?;
+    if amount_delivered < min_out {
+        return Err(LiquidityPoolError::SlippageExceeded.into());
+    }
+
+    pool_state_data.serialize(&mut &mut pool_state_account.data.borrow_mut()[..])?;
+
+    msg!("Swapped {} of token {} for {} of token {}.", amount_in, token_in, amount_out, token_out);
+    Ok(())
+}
+
+// ------------------ Join Weighted Pool ------------------
+/// Accounts: provider, pool state, LP mint, provider LP account, token program,
+/// `(pool vault, provider token account)` for each token, then any trailing
+/// token accounts. The provider pays any transfer fees on top of `max_amounts`'
+/// share of the reserves; `max_amounts` bounds what they send.
+pub(crate) fn process_join_weighted_pool(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    lp_amount: u64,
+    max_amounts: Vec<u64>,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let provider_account = next_account_info(account_info_iter)?; // 0
+    let pool_state_account = next_account_info(account_info_iter)?; // 1
+    let lp_mint_account = next_account_info(account_info_iter)?; // 2
+    let provider_lp_token_account = next_account_info(account_info_iter)?; // 3
+    let token_program_account = next_account_info(account_info_iter)?; // 4
+
+    if !provider_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut pool_state_data = load_weighted_pool(program_id, pool_state_account)?;
+    if *lp_mint_account.key != pool_state_data.lp_mint {
+        return Err(LiquidityPoolError::InvalidTokenMint.into());
+    }
+    if max_amounts.len() != pool_state_data.tokens.len() {
+        return Err(LiquidityPoolError::InvalidInstruction.into());
+    }
+    let token_accounts = next_weighted_token_accounts(account_info_iter, pool_state_account.key, &pool_state_data)?;
+    let tokens = TokenAccounts::new(token_program_account, account_info_iter.as_slice())?;
+
+    if lp_amount == 0 {
+        return Err(LiquidityPoolError::ZeroAmountNotAllowed.into());
+    }
+
+    let amounts = weighted_math::join_amounts(lp_amount, &pool_state_data.reserves(), pool_state_data.lp_supply)?;
+    let amounts_in = pool_state_data
+        .tokens
+        .iter()
+        .zip(&amounts)
+        .map(|(token, &amount)| tokens.amount_before_fee(&token.mint, amount))
+        .collect::<Result<Vec<u64>, ProgramError>>()?;
+    if amounts_in.iter().zip(&max_amounts).any(|(amount_in, max_amount)| amount_in > max_amount) {
+        return Err(LiquidityPoolError::SlippageExceeded.into());
+    }
+
+    for (i, (vault_account, provider_token_account)) in token_accounts.iter().enumerate() {
+        let received = tokens.transfer(provider_token_account, vault_account, provider_account, amounts_in[i], &[])?;
+        if received < amounts[i] {
+            return Err(LiquidityPoolError::InvalidAmount.into());
+        }
+        pool_state_data.tokens[i].reserve = checked_add(pool_state_data.tokens[i].reserve, received)?;
+    }
+    pool_state_data.lp_supply = checked_add(pool_state_data.lp_supply, lp_amount)?;
+    mint_lp(&pool_state_data, &tokens, lp_mint_account, provider_lp_token_account, pool_state_account, lp_amount)?;
+
+    pool_state_data.serialize(&mut &mut pool_state_account.data.borrow_mut()[..])?;
+
+    msg!("Joined weighted pool for {} LP tokens.", lp_amount);
+    Ok(())
+}
+
+// ------------------ Exit Weighted Pool ------------------
+/// Accounts: as for `JoinWeightedPool`.
+pub(crate) fn process_exit_weighted_pool(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    lp_amount: u64,
+    min_amounts: Vec<u64>,
+) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    let provider_account = next_account_info(account_info_iter)?; // 0
+    let pool_state_account = next_account_info(account_info_iter)?; // 1
+    let lp_mint_account = next_account_info(account_info_iter)?; // 2
+    let provider_lp_token_account = next_account_info(account_info_iter)?; // 3
+    let token_program_account = next_account_info(account_info_iter)?; // 4
+
+    if !provider_account.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    let mut pool_state_data = load_weighted_pool(program_id, pool_state_account)?;
+    if *lp_mint_account.key != pool_state_data.lp_mint {
+        return Err(LiquidityPoolError::InvalidTokenMint.into());
+    }
+    if min_amounts.len() != pool_state_data.tokens.len() {
+        return Err(LiquidityPoolError::InvalidInstruction.into());
+    }
+    let token_accounts = next_weighted_token_accounts(account_info_iter, pool_state_account.key, &pool_state_data)?;
+    let tokens = TokenAccounts::new(token_program_account, account_info_iter.as_slice())?;
+
+    if lp_amount == 0 {
+        return Err(LiquidityPoolError::ZeroAmountNotAllowed.into());
+    }
+
+    let amounts = weighted_math::exit_amounts(lp_amount, &pool_state_data.reserves(), pool_state_data.lp_supply)?;
+
+    // Apply the new reserves up front so an underflow fails before any tokens move
+    for (token, &amount) in pool_state_data.tokens.iter_mut().zip(&amounts) {
+        token.reserve = checked_sub(token.reserve, amount)?;
+    }
+    pool_state_data.lp_supply = checked_sub(pool_state_data.lp_supply, lp_amount)?;
+
+    // Burn LP tokens from provider
+    tokens.burn(provider_lp_token_account, lp_mint_account, provider_account, lp_amount)?;
+
+    // Slippage is measured on what reaches the provider, after any transfer fees
+    for (((vault_account, provider_token_account), &amount), &min_amount) in token_accounts.iter().zip(&amounts).zip(&min_amounts) {
+        let delivered = transfer_out(&pool_state_data, &tokens, vault_account, provider_token_account, pool_state_account, amount)?;
+        if delivered < min_amount {
+            return Err(LiquidityPoolError::SlippageExceeded.into());
+        }
+    }
+
+    pool_state_data.serialize(&mut &mut pool_state_account.data.borrow_mut()[..])?;
+
+    msg!("Exited weighted pool for {} LP tokens.", lp_amount);
+    Ok(())
+}
+
+// ------------------ Account Validation ------------------
+/// Loads a weighted pool's state, checking that the account is owned by this
+/// program, holds a weighted pool, is initialized, and is the canonical PDA for
+/// its LP mint.
+pub fn load_weighted_pool(program_id: &Pubkey, pool_state_account: &AccountInfo) -> Result<WeightedPoolState, ProgramError> {
+    if pool_state_account.owner != program_id {
+        return Err(LiquidityPoolError::InvalidOwner.into());
+    }
+    if !pool_state_account.data.borrow().starts_with(&WEIGHTED_POOL_STATE_DISCRIMINATOR) {
+        return Err(LiquidityPoolError::InvalidAccountType.into());
+    }
+
+    let pool_state_data = WeightedPoolState::deserialize(&mut &pool_state_account.data.borrow()[..])?;
+    if pool_state_data.is_initialized == 0 {
+        return Err(LiquidityPoolError::PoolNotInitialized.into());
+    }
+
+    let pda = Pubkey::create_program_address(
+        &[b"weighted_pool", pool_state_data.lp_mint.as_ref(), &[pool_state_data.bump_seed]],
+        program_id,
+    )
+    .map_err(|_| ProgramError::InvalidSeeds)?;
+    if pda != *pool_state_account.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+
+    Ok(pool_state_data)
+}
+
+/// Checks that the account is the vault recorded for `token`.
+fn validate_weighted_vault(vault_account: &AccountInfo, pool: &Pubkey, token: &WeightedToken) -> Result<Account, ProgramError> {
+    if *vault_account.key != token.vault {
+        return Err(LiquidityPoolError::InvalidTokenAccount.into());
+    }
+    validate_pool_vault(vault_account, pool, &token.mint)
+}
+
+/// Takes a `(pool vault, user token account)` pair per pool token from the
+/// account iterator, validating each vault.
+fn next_weighted_token_accounts<'a, 'b>(
+    account_info_iter: &mut std::slice::Iter<'a, AccountInfo<'b>>,
+    pool: &Pubkey,
+    pool_state_data: &WeightedPoolState,
+) -> Result<Vec<(&'a AccountInfo<'b>, &'a AccountInfo<'b>)>, ProgramError> {
+    pool_state_data
+        .tokens
+        .iter()
+        .map(|token| {
+            let vault_account = next_account_info(account_info_iter)?;
+            let user_token_account = next_account_info(account_info_iter)?;
+            validate_weighted_vault(vault_account, pool, token)?;
+            Ok((vault_account, user_token_account))
+        })
+        .collect()
+}
+
+// ------------------ Token Movements ------------------
+/// Moves `amount` out of a vault, returning what `destination_account` received.
+fn transfer_out<'a>(
+    pool_state_data: &WeightedPoolState,
+    tokens: &TokenAccounts<'_, 'a>,
+    vault_account: &AccountInfo<'a>,
+    destination_account: &AccountInfo<'a>,
+    pool_state_account: &AccountInfo<'a>,
+    amount: u64,
+) -> Result<u64, ProgramError> {
+    tokens.transfer(
+        vault_account,
+        destination_account,
+        pool_state_account, // PDA is the authority
+        amount,
+        &[&[b"weighted_pool", pool_state_data.lp_mint.as_ref(), &[pool_state_data.bump_seed]]],
+    )
+}
+
+fn mint_lp<'a>(
+    pool_state_data: &WeightedPoolState,
+    tokens: &TokenAccounts<'_, 'a>,
+    lp_mint_account: &AccountInfo<'a>,
+    destination_account: &AccountInfo<'a>,
+    pool_state_account: &AccountInfo<'a>,
+    amount: u64,
+) -> ProgramResult {
+    tokens.mint_to(
+        lp_mint_account,
+        destination_account,
+        pool_state_account, // PDA is the mint authority
+        amount,
+        &[&[b"weighted_pool", pool_state_data.lp_mint.as_ref(), &[pool_state_data.bump_seed]]],
+    )
+}
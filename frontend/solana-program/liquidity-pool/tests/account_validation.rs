@@ -0,0 +1,209 @@
+mod common;
+
+use common::{
+    fee_config_account, initialize_pool_accounts, registry_accounts, run, PoolFixture, TestAccount, WeightedPoolFixture,
+};
+use liquidity_pool::{
+    concentrated_pool, distribution, farm, fee_config, registry, weighted_math::ONE, weighted_pool, CurveType, LiquidityPoolError, LiquidityPoolInstruction, POOL_STATE_DISCRIMINATOR, SWAP_FEE_BPS,
+};
+use solana_program::{hash::hash, program_error::ProgramError, program_pack::Pack, pubkey::Pubkey};
+
+// Account indices shared by AddLiquidity / RemoveLiquidity.
+const POOL_STATE: usize = 1;
+const VAULT_A: usize = 2;
+const VAULT_B: usize = 3;
+const LP_MINT: usize = 4;
+const TOKEN_PROGRAM: usize = 8;
+
+// Account indices for SwapAforB.
+const SWAP_VAULT_A: usize = 2;
+const SWAP_TOKEN_PROGRAM: usize = 6;
+
+fn fixture() -> PoolFixture {
+    PoolFixture::new(1_000_000, 1_000_000, 1_000_000, 100_000, 100_000)
+}
+
+fn add_liquidity() -> LiquidityPoolInstruction {
+    LiquidityPoolInstruction::AddLiquidity { amount_a: 10_000, amount_b: 10_000 }
+}
+
+fn remove_liquidity() -> LiquidityPoolInstruction {
+    LiquidityPoolInstruction::RemoveLiquidity { lp_token_amount: 10_000 }
+}
+
+fn swap() -> LiquidityPoolInstruction {
+    LiquidityPoolInstruction::SwapAforB { amount_a_in: 10_000 }
+}
+
+#[test]
+fn valid_accounts_are_accepted() {
+    let pool = fixture();
+    assert_eq!(run(&mut pool.liquidity_accounts(), add_liquidity()), Ok(()));
+    assert_eq!(run(&mut pool.liquidity_accounts(), remove_liquidity()), Ok(()));
+    assert_eq!(run(&mut pool.swap_accounts(), swap()), Ok(()));
+}
+
+#[test]
+fn rejects_pool_state_not_owned_by_program() {
+    let pool = fixture();
+    let mut accounts = pool.liquidity_accounts();
+    accounts[POOL_STATE].owner = Pubkey::new_unique();
+    assert_eq!(
+        run(&mut accounts, add_liquidity()),
+        Err(LiquidityPoolError::InvalidOwner.into())
+    );
+}
+
+#[test]
+fn rejects_pool_state_for_another_pair() {
+    let pool = fixture();
+    let other = fixture();
+    let mut accounts = pool.liquidity_accounts();
+    accounts[POOL_STATE].data = other.pool_state.data.clone();
+    assert_eq!(run(&mut accounts, add_liquidity()), Err(ProgramError::InvalidSeeds));
+}
+
+#[test]
+fn rejects_vault_owned_by_attacker() {
+    let pool = fixture();
+    let attacker = Pubkey::new_unique();
+    let mut accounts = pool.liquidity_accounts();
+    accounts[VAULT_B] = TestAccount::token_account(pool.mint_b.key, attacker, 0);
+    assert_eq!(
+        run(&mut accounts, remove_liquidity()),
+        Err(LiquidityPoolError::InvalidTokenAccount.into())
+    );
+}
+
+#[test]
+fn rejects_vault_with_wrong_mint() {
+    let pool = fixture();
+    let mut accounts = pool.liquidity_accounts();
+    accounts[VAULT_A] = TestAccount::token_account(pool.mint_b.key, pool.pool_state.key, 0);
+    assert_eq!(
+        run(&mut accounts, add_liquidity()),
+        Err(LiquidityPoolError::InvalidTokenAccount.into())
+    );
+}
+
+#[test]
+fn rejects_pool_token_account_other_than_its_vault() {
+    let pool = fixture();
+    let mut accounts = pool.swap_accounts();
+    accounts[SWAP_VAULT_A] = TestAccount::token_account(pool.mint_a.key, pool.pool_state.key, 1_000_000_000);
+    assert_eq!(
+        run(&mut accounts, swap()),
+        Err(LiquidityPoolError::InvalidTokenAccount.into())
+    );
+}
+
+#[test]
+fn rejects_swapped_vaults() {
+    let pool = fixture();
+    let mut accounts = pool.swap_accounts();
+    accounts.swap(SWAP_VAULT_A, SWAP_VAULT_A + 1);
+    assert_eq!(
+        run(&mut accounts, swap()),
+        Err(LiquidityPoolError::InvalidTokenAccount.into())
+    );
+}
+
+#[test]
+fn rejects_vault_not_owned_by_token_program() {
+    let pool = fixture();
+    let mut accounts = pool.swap_accounts();
+    accounts[SWAP_VAULT_A].owner = Pubkey::new_unique();
+    assert_eq!(
+        run(&mut accounts, swap()),
+        Err(LiquidityPoolError::InvalidTokenAccount.into())
+    );
+}
+
+#[test]
+fn rejects_vault_with_delegate() {
+    let pool = fixture();
+    let mut accounts = pool.liquidity_accounts();
+    let mut vault = spl_token::state::Account::unpack(&accounts[VAULT_A].data).unwrap();
+    vault.delegate = Some(Pubkey::new_unique()).into();
+    vault.delegated_amount = u64::MAX;
+    spl_token::state::Account::pack(vault, &mut accounts[VAULT_A].data).unwrap();
+    assert_eq!(
+        run(&mut accounts, remove_liquidity()),
+        Err(LiquidityPoolError::InvalidTokenAccount.into())
+    );
+}
+
+#[test]
+fn rejects_substituted_lp_mint() {
+    let pool = fixture();
+    let mut accounts = pool.liquidity_accounts();
+    accounts[LP_MINT] = TestAccount::mint(pool.pool_state.key, 0);
+    assert_eq!(
+        run(&mut accounts, add_liquidity()),
+        Err(LiquidityPoolError::InvalidTokenMint.into())
+    );
+}
+
+#[test]
+fn rejects_substituted_token_program() {
+    let pool = fixture();
+    let mut accounts = pool.liquidity_accounts();
+    accounts[TOKEN_PROGRAM] = TestAccount::program(Pubkey::new_unique());
+    assert_eq!(run(&mut accounts, add_liquidity()), Err(ProgramError::IncorrectProgramId));
+
+    let mut accounts = pool.swap_accounts();
+    accounts[SWAP_TOKEN_PROGRAM] = TestAccount::program(Pubkey::new_unique());
+    assert_eq!(run(&mut accounts, swap()), Err(ProgramError::IncorrectProgramId));
+}
+
+#[test]
+fn pool_state_discriminator_is_derived_from_its_name() {
+    assert_eq!(POOL_STATE_DISCRIMINATOR, hash(b"account:PoolState").to_bytes()[..8]);
+}
+
+#[test]
+fn account_discriminators_are_derived_from_their_names() {
+    for (name, discriminator) in [
+        ("Farm", farm::FARM_DISCRIMINATOR),
+        ("Stake", farm::STAKE_DISCRIMINATOR),
+        ("Distribution", distribution::DISTRIBUTION_DISCRIMINATOR),
+        ("Holding", distribution::HOLDING_DISCRIMINATOR),
+        ("FeeConfig", fee_config::FEE_CONFIG_DISCRIMINATOR),
+        ("RegistryHeader", registry::REGISTRY_HEADER_DISCRIMINATOR),
+        ("RegistryPage", registry::REGISTRY_PAGE_DISCRIMINATOR),
+        ("WeightedPoolState", weighted_pool::WEIGHTED_POOL_STATE_DISCRIMINATOR),
+        ("ConcentratedPoolState", concentrated_pool::CONCENTRATED_POOL_STATE_DISCRIMINATOR),
+        ("Position", concentrated_pool::POSITION_DISCRIMINATOR),
+    ] {
+        assert_eq!(discriminator, hash(format!("account:{name}").as_bytes()).to_bytes()[..8], "{name}");
+    }
+}
+
+#[test]
+fn rejects_pool_state_of_another_type() {
+    let pool = fixture();
+    let mut accounts = pool.liquidity_accounts();
+    accounts[POOL_STATE].data[..8].copy_from_slice(&[0; 8]);
+    assert_eq!(run(&mut accounts, add_liquidity()), Err(LiquidityPoolError::InvalidAccountType.into()));
+
+    let weighted = WeightedPoolFixture::new(&[ONE as u64 / 2; 2], &[1_000_000, 1_000_000], 0);
+    let mut accounts = pool.swap_accounts();
+    accounts[POOL_STATE].data = weighted.pool_state.data.clone();
+    assert_eq!(run(&mut accounts, swap()), Err(LiquidityPoolError::InvalidAccountType.into()));
+}
+
+#[test]
+fn initialize_pool_rejects_an_account_holding_data() {
+    let (header, page) = registry_accounts(0);
+    let config = fee_config_account(Pubkey::new_unique(), &[SWAP_FEE_BPS]);
+    let mut accounts = initialize_pool_accounts(&header, &page, &config, SWAP_FEE_BPS, 2_000_000);
+    accounts[POOL_STATE] = TestAccount { key: accounts[POOL_STATE].key, ..TestAccount::new(liquidity_pool::id(), vec![1; 20]) };
+    let initialize = LiquidityPoolInstruction::InitializePool {
+        initial_amount_a: 1_000_000,
+        initial_amount_b: 1_000_000,
+        curve_type: CurveType::ConstantProduct,
+        amp: 0,
+        fee_bps: SWAP_FEE_BPS,
+    };
+    assert_eq!(run(&mut accounts, initialize), Err(LiquidityPoolError::InvalidAccountType.into()));
+}
@@ -0,0 +1,43 @@
+mod common;
+
+use common::{run, PoolFixture};
+use liquidity_pool::{checked_add, checked_sub, LiquidityPoolError, LiquidityPoolInstruction};
+
+#[test]
+fn helpers_fail_instead_of_wrapping() {
+    assert_eq!(checked_add(u64::MAX - 1, 1), Ok(u64::MAX));
+    assert_eq!(checked_add(u64::MAX, 1), Err(LiquidityPoolError::MathOverflow.into()));
+    assert_eq!(checked_sub(0, 0), Ok(0));
+    assert_eq!(checked_sub(0, 1), Err(LiquidityPoolError::MathOverflow.into()));
+}
+
+#[test]
+fn swap_overflowing_reserve_fails_before_moving_tokens() {
+    let pool = PoolFixture::new(u64::MAX - 10, u64::MAX / 2, 1_000_000, 100_000, 0);
+    let mut accounts = pool.swap_accounts();
+    assert_eq!(
+        run(&mut accounts, LiquidityPoolInstruction::Swap { amount_in: 100_000, min_out: 0, a_to_b: true }),
+        Err(LiquidityPoolError::MathOverflow.into())
+    );
+    assert_eq!(accounts[4].token_amount(), 100_000);
+}
+
+#[test]
+fn add_liquidity_overflowing_lp_supply_fails() {
+    let pool = PoolFixture::new(1_000, 1_000, u64::MAX - 10, 100_000, 0);
+    let mut accounts = pool.liquidity_accounts();
+    assert_eq!(
+        run(&mut accounts, LiquidityPoolInstruction::AddLiquidity { amount_a: 1_000, amount_b: 1_000 }),
+        Err(LiquidityPoolError::MathOverflow.into())
+    );
+}
+
+#[test]
+fn add_liquidity_overflowing_lp_amount_fails() {
+    let pool = PoolFixture::new(1, 1, u64::MAX / 2, 100_000, 0);
+    let mut accounts = pool.liquidity_accounts();
+    assert_eq!(
+        run(&mut accounts, LiquidityPoolInstruction::AddLiquidity { amount_a: 3, amount_b: 3 }),
+        Err(LiquidityPoolError::MathOverflow.into())
+    );
+}
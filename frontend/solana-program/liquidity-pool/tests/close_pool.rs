@@ -0,0 +1,184 @@
+mod common;
+
+use borsh::BorshDeserialize;
+use common::{fee_config_account, registry_accounts, registry_accounts_with, run, FarmFixture, PoolFixture, TestAccount};
+use liquidity_pool::registry::{RegistryHeader, RegistryPage};
+use liquidity_pool::{farm, CurveType, LiquidityPoolError, LiquidityPoolInstruction, MINIMUM_LIQUIDITY, SWAP_FEE_BPS};
+use solana_program::{program_error::ProgramError, pubkey::Pubkey, system_program};
+
+// Account indices for ClosePool.
+const CREATOR: usize = 0;
+const POOL_STATE: usize = 1;
+const VAULT_A: usize = 2;
+const VAULT_B: usize = 3;
+const CREATOR_A: usize = 5;
+const CREATOR_B: usize = 6;
+const FARM: usize = 8;
+const REGISTRY_PAGE: usize = 9;
+
+/// A pool whose LP tokens have all been redeemed, leaving the locked minimum
+/// liquidity's share of the reserves in its vaults.
+fn drained_pool() -> PoolFixture {
+    PoolFixture::new(1_000, 2_000, MINIMUM_LIQUIDITY, 0, 0)
+}
+
+#[test]
+fn closes_a_drained_pool_and_refunds_the_creator() {
+    let pool = drained_pool();
+    let mut accounts = pool.close_accounts();
+    let refund = accounts[POOL_STATE].lamports + accounts[VAULT_A].lamports + accounts[VAULT_B].lamports;
+    let lamports_before = accounts[CREATOR].lamports;
+    assert_eq!(run(&mut accounts, LiquidityPoolInstruction::ClosePool), Ok(()));
+
+    assert_eq!(accounts[CREATOR].lamports, lamports_before + refund);
+    for index in [POOL_STATE, VAULT_A, VAULT_B] {
+        assert_eq!(accounts[index].lamports, 0);
+        assert_eq!(accounts[index].owner, system_program::id());
+    }
+    assert!(accounts[POOL_STATE].data.is_empty());
+    assert_eq!((accounts[CREATOR_A].token_amount(), accounts[CREATOR_B].token_amount()), (1_000, 2_000));
+
+    // The registry entry stays in its slot, marked closed
+    let page = RegistryPage::deserialize(&mut &accounts[REGISTRY_PAGE].data[..]).unwrap();
+    assert_eq!(page.entries.len(), 1);
+    assert_eq!(page.entries[0].pool, Pubkey::default());
+    assert_eq!(page.entries[0].token_a_mint, pool.mint_a.key);
+}
+
+#[test]
+fn rejects_a_pool_with_a_farm() {
+    let fixture = FarmFixture::new(10, 0);
+    let mut accounts = fixture.pool.close_accounts();
+    accounts[FARM] = fixture.farm.clone();
+    assert_eq!(
+        run(&mut accounts, LiquidityPoolInstruction::ClosePool),
+        Err(LiquidityPoolError::PoolHasFarm.into())
+    );
+
+    let mut elsewhere = fixture.pool.close_accounts();
+    elsewhere[FARM] = TestAccount::unallocated(Pubkey::new_unique());
+    assert_eq!(run(&mut elsewhere, LiquidityPoolInstruction::ClosePool), Err(ProgramError::InvalidSeeds));
+}
+
+#[test]
+fn closes_a_pool_once_its_farm_is_closed() {
+    let mut fixture = FarmFixture::uninitialized_over(drained_pool());
+    let mut accounts = fixture.initialize_accounts();
+    assert_eq!(run(&mut accounts, LiquidityPoolInstruction::InitializeFarm { emission_rate: 10 }), Ok(()));
+    fixture.farm = accounts[2].clone();
+    assert_eq!(fixture.close(), Ok(()));
+
+    let mut accounts = fixture.pool.close_accounts();
+    accounts[FARM] = fixture.farm.clone();
+    assert_eq!(run(&mut accounts, LiquidityPoolInstruction::ClosePool), Ok(()));
+    assert_eq!(accounts[POOL_STATE].owner, system_program::id());
+}
+
+#[test]
+fn rejects_a_page_without_the_pool() {
+    let pool = drained_pool();
+    let mut accounts = pool.close_accounts();
+    accounts[REGISTRY_PAGE] = registry_accounts_with(&[&drained_pool()]).1;
+    assert_eq!(
+        run(&mut accounts, LiquidityPoolInstruction::ClosePool),
+        Err(LiquidityPoolError::PoolNotRegistered.into())
+    );
+}
+
+#[test]
+fn rejects_a_pool_with_outstanding_lp_tokens() {
+    let mut pool = drained_pool();
+    let mut state = pool.state();
+    state.lp_supply = MINIMUM_LIQUIDITY + 1;
+    pool.set_state(&state);
+    let mut accounts = pool.close_accounts();
+    assert_eq!(
+        run(&mut accounts, LiquidityPoolInstruction::ClosePool),
+        Err(LiquidityPoolError::PoolNotEmpty.into())
+    );
+}
+
+#[test]
+fn only_the_creator_closes_a_pool() {
+    let pool = drained_pool();
+
+    let mut unsigned = pool.close_accounts();
+    unsigned[CREATOR].is_signer = false;
+    assert_eq!(run(&mut unsigned, LiquidityPoolInstruction::ClosePool), Err(ProgramError::MissingRequiredSignature));
+
+    let mut stranger = pool.close_accounts();
+    stranger[CREATOR] = TestAccount::signer();
+    assert_eq!(
+        run(&mut stranger, LiquidityPoolInstruction::ClosePool),
+        Err(LiquidityPoolError::Unauthorized.into())
+    );
+}
+
+#[test]
+fn rejects_accounts_other_than_the_pools_vaults() {
+    let pool = drained_pool();
+    let mut accounts = pool.close_accounts();
+    accounts[VAULT_A] = TestAccount::token_account(pool.mint_a.key, pool.pool_state.key, 0);
+    assert_eq!(
+        run(&mut accounts, LiquidityPoolInstruction::ClosePool),
+        Err(LiquidityPoolError::InvalidTokenAccount.into())
+    );
+}
+
+#[test]
+fn a_closed_pool_can_be_reopened() {
+    // Account indices for InitializePool
+    const INIT_POOL_STATE: usize = 1;
+    const INIT_VAULT_A: usize = 4;
+    const INIT_LP_MINT: usize = 6;
+    const INIT_USER_LP: usize = 9;
+    const INIT_REGISTRY_HEADER: usize = 13;
+    const INIT_REGISTRY_PAGE: usize = 14;
+    let initialize = || LiquidityPoolInstruction::InitializePool {
+        initial_amount_a: 1_000_000,
+        initial_amount_b: 2_000_000,
+        curve_type: CurveType::ConstantProduct,
+        amp: 0,
+        fee_bps: SWAP_FEE_BPS,
+    };
+    let (header, page) = registry_accounts(0);
+    let fee_config = fee_config_account(Pubkey::new_unique(), &[SWAP_FEE_BPS]);
+    let mut accounts = common::initialize_pool_accounts(&header, &page, &fee_config, SWAP_FEE_BPS, 2_000_000);
+    assert_eq!(run(&mut accounts, initialize()), Ok(()));
+
+    // Redeem every LP token, then close the pool
+    let pick = |accounts: &[TestAccount], indices: &[usize]| indices.iter().map(|&i| accounts[i].clone()).collect::<Vec<_>>();
+    let liquidity = [0, 1, 4, 5, 6, 7, 8, 9, 10];
+    let mut remove = pick(&accounts, &liquidity);
+    let lp_token_amount = accounts[INIT_USER_LP].token_amount();
+    assert_eq!(run(&mut remove, LiquidityPoolInstruction::RemoveLiquidity { lp_token_amount }), Ok(()));
+    for (position, &index) in liquidity.iter().enumerate() {
+        accounts[index] = remove[position].clone();
+    }
+    let close = [0, 1, 4, 5, 6, 7, 8, 10, INIT_REGISTRY_PAGE];
+    let mut close_accounts = pick(&accounts, &close);
+    let farm_key = farm::farm_address(&liquidity_pool::id(), &accounts[INIT_POOL_STATE].key).0;
+    close_accounts.insert(FARM, TestAccount::unallocated(farm_key));
+    assert_eq!(run(&mut close_accounts, LiquidityPoolInstruction::ClosePool), Ok(()));
+    close_accounts.remove(FARM);
+    for (position, &index) in close.iter().enumerate() {
+        accounts[index] = close_accounts[position].clone();
+    }
+
+    // The runtime drops accounts left without lamports at the end of the
+    // transaction
+    for account in accounts.iter_mut().filter(|account| account.lamports == 0) {
+        *account = TestAccount::unallocated(account.key);
+    }
+    assert_eq!(accounts[INIT_LP_MINT].mint_supply(), 0);
+    assert_eq!(run(&mut accounts, initialize()), Ok(()));
+    assert_eq!(accounts[INIT_VAULT_A].token_amount(), 1_000_000);
+    assert!(accounts[INIT_LP_MINT].mint_supply() > 0);
+
+    // The reopened pool is registered once, after its closed entry
+    let header = RegistryHeader::deserialize(&mut &accounts[INIT_REGISTRY_HEADER].data[..]).unwrap();
+    assert_eq!(header.pool_count, 2);
+    let page = RegistryPage::deserialize(&mut &accounts[INIT_REGISTRY_PAGE].data[..]).unwrap();
+    let pools: Vec<Pubkey> = page.entries.iter().map(|entry| entry.pool).collect();
+    assert_eq!(pools, [Pubkey::default(), accounts[INIT_POOL_STATE].key]);
+}
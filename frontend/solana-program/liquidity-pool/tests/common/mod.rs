@@ -0,0 +1,1530 @@
+//! Host-side harness for driving the program without a validator.
+//!
+//! CPIs into the SPL Token and Token-2022 programs and the system program are
+//! executed in-process against the same `AccountInfo`s, so balances, mint
+//! supplies and created accounts behave as they would on-chain. A CPI may only
+//! sign for the caller's signers and the program's PDAs it passes seeds for.
+
+#![allow(dead_code)]
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use liquidity_pool::{
+    concentrated_math,
+    concentrated_pool::{ConcentratedPoolState, CONCENTRATED_POOL_STATE_DISCRIMINATOR},
+    distribution::{self, Distribution, Holding},
+    farm::{self, Farm},
+    fee_config::{self, FeeConfig},
+    process_instruction,
+    registry::{self, RegistryEntry, RegistryHeader, RegistryPage},
+    weighted_pool::{WeightedPoolState, WeightedToken},
+    CurveType, FlashLoanCallback, LiquidityPoolInstruction, PoolState, POOL_STATE_DISCRIMINATOR, POOL_STATE_RESERVED,
+    POOL_STATE_VERSION, SWAP_FEE_BPS,
+};
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::{ProgramResult, MAX_PERMITTED_DATA_INCREASE},
+    instruction::Instruction,
+    program_error::ProgramError,
+    program_option::COption,
+    program_pack::Pack,
+    program_stubs::{set_syscall_stubs, SyscallStubs},
+    program_utils::limited_deserialize,
+    pubkey::Pubkey,
+    rent::Rent,
+    system_instruction::SystemInstruction,
+    system_program,
+    sysvar::clock::Clock,
+};
+use spl_token::state::{Account, AccountState, Mint};
+use spl_token_2022::extension::{
+    permanent_delegate::PermanentDelegate,
+    transfer_fee::{TransferFee, TransferFeeAmount, TransferFeeConfig},
+    transfer_hook::{TransferHook, TransferHookAccount},
+    BaseStateWithExtensions, BaseStateWithExtensionsMut, ExtensionType, StateWithExtensions, StateWithExtensionsMut,
+};
+use spl_tlv_account_resolution::state::ExtraAccountMetaList;
+use spl_transfer_hook_interface::{get_extra_account_metas_address, instruction::ExecuteInstruction};
+use std::cell::{Cell, RefCell};
+use std::sync::Once;
+
+/// Unix timestamp reported by the clock sysvar.
+pub const NOW: i64 = 1_700_000_000;
+
+/// Program id of the flash-loan borrower simulated by the harness. Given the
+/// borrower, its token A and B accounts, the pool's token A and B accounts and
+/// the token program, it repays each loan plus fee, less the configured
+/// shortfall.
+pub const FLASH_BORROWER_ID: Pubkey = Pubkey::new_from_array([7; 32]);
+
+/// Program id of the transfer hook simulated by the harness. It counts the
+/// transfers it is executed for and, while blocked, rejects them with
+/// [`TRANSFER_HOOK_REJECTED`].
+pub const TRANSFER_HOOK_ID: Pubkey = Pubkey::new_from_array([9; 32]);
+
+/// Error code of a transfer rejected by the simulated transfer hook.
+pub const TRANSFER_HOOK_REJECTED: u32 = 0x400;
+
+struct TestSyscallStubs;
+
+thread_local! {
+    static RETURN_DATA: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+    static FLASH_LOAN_SHORTFALL: Cell<u64> = const { Cell::new(0) };
+    static TRANSFER_HOOK_BLOCKED: Cell<bool> = const { Cell::new(false) };
+    static TRANSFER_HOOK_CALLS: Cell<u32> = const { Cell::new(0) };
+}
+
+/// Makes the simulated borrower repay `shortfall` less than it owes, on this thread.
+pub fn set_flash_loan_shortfall(shortfall: u64) {
+    FLASH_LOAN_SHORTFALL.with(|cell| cell.set(shortfall));
+}
+
+/// Makes the simulated transfer hook reject transfers, on this thread.
+pub fn set_transfer_hook_blocked(blocked: bool) {
+    TRANSFER_HOOK_BLOCKED.with(|cell| cell.set(blocked));
+}
+
+/// Transfers the simulated transfer hook has let through on this thread.
+pub fn transfer_hook_calls() -> u32 {
+    TRANSFER_HOOK_CALLS.with(Cell::get)
+}
+
+impl TestSyscallStubs {
+    fn repay_flash_loan(&self, infos: &[AccountInfo], data: &[u8]) -> ProgramResult {
+        let callback = FlashLoanCallback::try_from_slice(data)?;
+        let shortfall = FLASH_LOAN_SHORTFALL.with(Cell::get);
+        for (source, vault, owed) in [
+            (&infos[1], &infos[3], callback.amount_a + callback.fee_a),
+            (&infos[2], &infos[4], callback.amount_b + callback.fee_b),
+        ] {
+            let amount = owed.saturating_sub(shortfall);
+            if amount == 0 {
+                continue;
+            }
+            let transfer = spl_token::instruction::transfer(&spl_token::id(), source.key, vault.key, infos[0].key, &[], amount)?;
+            self.sol_invoke_signed(&transfer, infos, &[])?;
+        }
+        Ok(())
+    }
+}
+
+impl SyscallStubs for TestSyscallStubs {
+    fn sol_log(&self, _message: &str) {}
+
+    fn sol_invoke_signed(
+        &self,
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+        signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        let pda_signers: Vec<Pubkey> = signers_seeds
+            .iter()
+            .map(|seeds| Pubkey::create_program_address(seeds, &liquidity_pool::id()))
+            .collect::<Result<_, _>>()?;
+        let mut infos = Vec::with_capacity(instruction.accounts.len());
+        for meta in &instruction.accounts {
+            let mut info = account_infos
+                .iter()
+                .find(|info| *info.key == meta.pubkey)
+                .ok_or(ProgramError::NotEnoughAccountKeys)?
+                .clone();
+            // Signer privileges apply per key, as in the runtime.
+            info.is_signer |= instruction
+                .accounts
+                .iter()
+                .any(|other| other.pubkey == meta.pubkey && other.is_signer);
+            let caller_signed = account_infos.iter().any(|caller| caller.key == info.key && caller.is_signer);
+            if info.is_signer && !caller_signed && !pda_signers.contains(info.key) {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+            infos.push(info);
+        }
+
+        if instruction.program_id == spl_token::id() {
+            spl_token::processor::Processor::process(&instruction.program_id, &infos, &instruction.data)
+        } else if instruction.program_id == spl_token_2022::id() {
+            spl_token_2022::processor::Processor::process(&instruction.program_id, &infos, &instruction.data)
+        } else if instruction.program_id == FLASH_BORROWER_ID {
+            self.repay_flash_loan(&infos, &instruction.data)
+        } else if instruction.program_id == TRANSFER_HOOK_ID {
+            execute_transfer_hook(&infos)
+        } else if instruction.program_id == system_program::id() {
+            execute_system_instruction(&infos, &instruction.data)
+        } else {
+            Err(ProgramError::IncorrectProgramId)
+        }
+    }
+
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        let clock = Clock { unix_timestamp: NOW, ..Clock::default() };
+        unsafe { *(var_addr as *mut Clock) = clock };
+        0
+    }
+
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+        unsafe { *(var_addr as *mut Rent) = Rent::default() };
+        0
+    }
+
+    fn sol_set_return_data(&self, data: &[u8]) {
+        RETURN_DATA.with(|return_data| *return_data.borrow_mut() = data.to_vec());
+    }
+}
+
+/// Executes the system program instructions the program issues.
+fn execute_system_instruction(infos: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    // `SystemError::AccountAlreadyInUse`
+    const ACCOUNT_ALREADY_IN_USE: ProgramError = ProgramError::Custom(0);
+    let unused = |info: &AccountInfo| info.data_is_empty() && *info.owner == system_program::id();
+
+    match limited_deserialize(data, data.len() as u64).map_err(|_| ProgramError::InvalidInstructionData)? {
+        SystemInstruction::Transfer { lamports } => transfer_lamports(&infos[0], &infos[1], lamports),
+        SystemInstruction::CreateAccount { lamports, space, owner } => {
+            let (from, to) = (&infos[0], &infos[1]);
+            if !to.is_signer {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+            if to.lamports() > 0 || !unused(to) {
+                return Err(ACCOUNT_ALREADY_IN_USE);
+            }
+            transfer_lamports(from, to, lamports)?;
+            to.realloc(space as usize, true)?;
+            to.assign(&owner);
+            Ok(())
+        }
+        SystemInstruction::Allocate { space } => {
+            if !infos[0].is_signer {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+            if !unused(&infos[0]) {
+                return Err(ACCOUNT_ALREADY_IN_USE);
+            }
+            infos[0].realloc(space as usize, true)
+        }
+        SystemInstruction::Assign { owner } => {
+            if !infos[0].is_signer {
+                return Err(ProgramError::MissingRequiredSignature);
+            }
+            if *infos[0].owner != system_program::id() {
+                return Err(ProgramError::IllegalOwner);
+            }
+            infos[0].assign(&owner);
+            Ok(())
+        }
+        _ => Err(ProgramError::InvalidInstructionData),
+    }
+}
+
+fn transfer_lamports(from: &AccountInfo, to: &AccountInfo, lamports: u64) -> ProgramResult {
+    if !from.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    let remaining = from.lamports().checked_sub(lamports).ok_or(ProgramError::InsufficientFunds)?;
+    **from.lamports.borrow_mut() = remaining;
+    **to.lamports.borrow_mut() += lamports;
+    Ok(())
+}
+
+/// Executes the simulated transfer hook, which only accepts being called by
+/// Token-2022 in the middle of a transfer.
+fn execute_transfer_hook(infos: &[AccountInfo]) -> ProgramResult {
+    let source_data = infos[0].data.borrow();
+    let source = StateWithExtensions::<spl_token_2022::state::Account>::unpack(&source_data)?;
+    if !bool::from(source.get_extension::<TransferHookAccount>()?.transferring) {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    if TRANSFER_HOOK_BLOCKED.with(Cell::get) {
+        return Err(ProgramError::Custom(TRANSFER_HOOK_REJECTED));
+    }
+    TRANSFER_HOOK_CALLS.with(|calls| calls.set(calls.get() + 1));
+    Ok(())
+}
+
+/// Installs the test syscall stubs once per test binary.
+pub fn setup() {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| {
+        set_syscall_stubs(Box::new(TestSyscallStubs));
+    });
+}
+
+/// An owned account that can be lent out as an `AccountInfo`.
+#[derive(Clone, Debug)]
+pub struct TestAccount {
+    pub key: Pubkey,
+    pub owner: Pubkey,
+    pub lamports: u64,
+    pub data: Vec<u8>,
+    pub is_signer: bool,
+}
+
+impl TestAccount {
+    pub fn new(owner: Pubkey, data: Vec<u8>) -> Self {
+        Self {
+            key: Pubkey::new_unique(),
+            owner,
+            lamports: Rent::default().minimum_balance(data.len()),
+            data,
+            is_signer: false,
+        }
+    }
+
+    pub fn signer() -> Self {
+        Self {
+            is_signer: true,
+            ..Self::new(system_program::id(), vec![])
+        }
+    }
+
+    pub fn mint(authority: Pubkey, supply: u64) -> Self {
+        let mut data = vec![0; Mint::LEN];
+        Mint::pack(
+            Mint {
+                mint_authority: COption::Some(authority),
+                supply,
+                decimals: 6,
+                is_initialized: true,
+                freeze_authority: COption::None,
+            },
+            &mut data,
+        )
+        .unwrap();
+        Self::new(spl_token::id(), data)
+    }
+
+    pub fn token_account(mint: Pubkey, owner: Pubkey, amount: u64) -> Self {
+        let mut data = vec![0; Account::LEN];
+        Account::pack(
+            Account {
+                mint,
+                owner,
+                amount,
+                delegate: COption::None,
+                state: AccountState::Initialized,
+                is_native: COption::None,
+                delegated_amount: 0,
+                close_authority: COption::None,
+            },
+            &mut data,
+        )
+        .unwrap();
+        Self::new(spl_token::id(), data)
+    }
+
+    /// A wSOL token account holding `amount` wrapped lamports on top of its rent.
+    pub fn native_token_account(owner: Pubkey, amount: u64) -> Self {
+        let rent_reserve = Rent::default().minimum_balance(Account::LEN);
+        let mut data = vec![0; Account::LEN];
+        Account::pack(
+            Account {
+                mint: spl_token::native_mint::id(),
+                owner,
+                amount,
+                delegate: COption::None,
+                state: AccountState::Initialized,
+                is_native: COption::Some(rent_reserve),
+                delegated_amount: 0,
+                close_authority: COption::None,
+            },
+            &mut data,
+        )
+        .unwrap();
+        Self {
+            lamports: rent_reserve + amount,
+            ..Self::new(spl_token::id(), data)
+        }
+    }
+
+    /// The wSOL mint.
+    pub fn native_mint() -> Self {
+        let mut data = vec![0; Mint::LEN];
+        Mint::pack(
+            Mint {
+                mint_authority: COption::None,
+                supply: 0,
+                decimals: spl_token::native_mint::DECIMALS,
+                is_initialized: true,
+                freeze_authority: COption::None,
+            },
+            &mut data,
+        )
+        .unwrap();
+        Self {
+            key: spl_token::native_mint::id(),
+            ..Self::new(spl_token::id(), data)
+        }
+    }
+
+    /// A Token-2022 mint carrying `extensions`, which `init` sets up.
+    fn token_2022_mint(
+        authority: Pubkey,
+        supply: u64,
+        extensions: &[ExtensionType],
+        init: impl FnOnce(&mut StateWithExtensionsMut<spl_token_2022::state::Mint>),
+    ) -> Self {
+        let len = ExtensionType::try_calculate_account_len::<spl_token_2022::state::Mint>(extensions).unwrap();
+        let mut data = vec![0; len];
+        let mut state = StateWithExtensionsMut::<spl_token_2022::state::Mint>::unpack_uninitialized(&mut data).unwrap();
+        init(&mut state);
+        state.base = spl_token_2022::state::Mint {
+            mint_authority: COption::Some(authority),
+            supply,
+            decimals: 6,
+            is_initialized: true,
+            freeze_authority: COption::None,
+        };
+        state.pack_base();
+        state.init_account_type().unwrap();
+        Self::new(spl_token_2022::id(), data)
+    }
+
+    /// A Token-2022 mint without extensions.
+    pub fn mint_2022(authority: Pubkey, supply: u64) -> Self {
+        Self::token_2022_mint(authority, supply, &[], |_| {})
+    }
+
+    /// A Token-2022 mint charging `fee_bps` on transfers, up to `maximum_fee`.
+    pub fn transfer_fee_mint(authority: Pubkey, supply: u64, fee_bps: u16, maximum_fee: u64) -> Self {
+        Self::token_2022_mint(authority, supply, &[ExtensionType::TransferFeeConfig], |state| {
+            let fee = TransferFee {
+                epoch: 0.into(),
+                maximum_fee: maximum_fee.into(),
+                transfer_fee_basis_points: fee_bps.into(),
+            };
+            let config = state.init_extension::<TransferFeeConfig>(true).unwrap();
+            config.older_transfer_fee = fee;
+            config.newer_transfer_fee = fee;
+        })
+    }
+
+    /// A Token-2022 mint whose transfers run the simulated transfer hook.
+    pub fn transfer_hook_mint(authority: Pubkey, supply: u64) -> Self {
+        Self::token_2022_mint(authority, supply, &[ExtensionType::TransferHook], |state| {
+            let hook = state.init_extension::<TransferHook>(true).unwrap();
+            hook.program_id = Some(TRANSFER_HOOK_ID).try_into().unwrap();
+        })
+    }
+
+    /// A Token-2022 mint with a permanent delegate, an extension pools refuse.
+    pub fn permanent_delegate_mint(authority: Pubkey, supply: u64) -> Self {
+        Self::token_2022_mint(authority, supply, &[ExtensionType::PermanentDelegate], |state| {
+            let delegate = state.init_extension::<PermanentDelegate>(true).unwrap();
+            delegate.delegate = Some(authority).try_into().unwrap();
+        })
+    }
+
+    /// A Token-2022 account of `mint` holding `amount`, with the extensions
+    /// the mint requires of its accounts.
+    pub fn token_2022_account(mint: &TestAccount, owner: Pubkey, amount: u64) -> Self {
+        let mint_state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint.data).unwrap();
+        let extensions = ExtensionType::get_required_init_account_extensions(&mint_state.get_extension_types().unwrap());
+        let len = ExtensionType::try_calculate_account_len::<spl_token_2022::state::Account>(&extensions).unwrap();
+        let mut data = vec![0; len];
+        let mut state = StateWithExtensionsMut::<spl_token_2022::state::Account>::unpack_uninitialized(&mut data).unwrap();
+        for extension in extensions {
+            match extension {
+                ExtensionType::TransferFeeAmount => {
+                    state.init_extension::<TransferFeeAmount>(true).unwrap();
+                }
+                ExtensionType::TransferHookAccount => {
+                    state.init_extension::<TransferHookAccount>(true).unwrap();
+                }
+                other => panic!("unexpected account extension {other:?}"),
+            }
+        }
+        state.base = spl_token_2022::state::Account {
+            mint: mint.key,
+            owner,
+            amount,
+            delegate: COption::None,
+            state: spl_token_2022::state::AccountState::Initialized,
+            is_native: COption::None,
+            delegated_amount: 0,
+            close_authority: COption::None,
+        };
+        state.pack_base();
+        state.init_account_type().unwrap();
+        Self::new(spl_token_2022::id(), data)
+    }
+
+    /// The simulated transfer hook's validation account for `mint`, listing
+    /// no extra accounts.
+    pub fn transfer_hook_validation(mint: &Pubkey) -> Self {
+        let mut data = vec![0; ExtraAccountMetaList::size_of(0).unwrap()];
+        ExtraAccountMetaList::init::<ExecuteInstruction>(&mut data, &[]).unwrap();
+        Self {
+            key: get_extra_account_metas_address(mint, &TRANSFER_HOOK_ID),
+            ..Self::new(TRANSFER_HOOK_ID, data)
+        }
+    }
+
+    /// A token account of `mint` holding `amount`, under the mint's token
+    /// program, a wSOL account if `mint` is the wSOL mint.
+    pub fn token_account_for(mint: &TestAccount, owner: Pubkey, amount: u64) -> Self {
+        if mint.key == spl_token::native_mint::id() {
+            Self::native_token_account(owner, amount)
+        } else if mint.owner == spl_token_2022::id() {
+            Self::token_2022_account(mint, owner, amount)
+        } else {
+            Self::token_account(mint.key, owner, amount)
+        }
+    }
+
+    /// An address the program has yet to create an account at.
+    pub fn unallocated(key: Pubkey) -> Self {
+        Self { key, lamports: 0, ..Self::new(system_program::id(), vec![]) }
+    }
+
+    /// The rent sysvar account, holding `Rent::default()`.
+    pub fn rent_sysvar() -> Self {
+        let rent = Rent::default();
+        let mut data = Vec::with_capacity(17);
+        data.extend_from_slice(&rent.lamports_per_byte_year.to_le_bytes());
+        data.extend_from_slice(&rent.exemption_threshold.to_le_bytes());
+        data.push(rent.burn_percent);
+        Self {
+            key: solana_program::sysvar::rent::id(),
+            ..Self::new(solana_program::sysvar::id(), data)
+        }
+    }
+
+    pub fn program(program_id: Pubkey) -> Self {
+        Self {
+            key: program_id,
+            ..Self::new(solana_program::bpf_loader::id(), vec![])
+        }
+    }
+
+    pub fn token_amount(&self) -> u64 {
+        Account::unpack(&self.data[..Account::LEN]).unwrap().amount
+    }
+
+    pub fn set_token_amount(&mut self, amount: u64) {
+        let mut account = Account::unpack(&self.data[..Account::LEN]).unwrap();
+        account.amount = amount;
+        Account::pack(account, &mut self.data[..Account::LEN]).unwrap();
+    }
+
+    pub fn mint_supply(&self) -> u64 {
+        Mint::unpack(&self.data[..Mint::LEN]).unwrap().supply
+    }
+}
+
+/// Data most recently set with `set_return_data` on this thread.
+pub fn return_data() -> Vec<u8> {
+    RETURN_DATA.with(|return_data| return_data.borrow().clone())
+}
+
+/// Runs one instruction against the given accounts, in order. An account
+/// repeated by key shares the state of its first occurrence, and a failed
+/// instruction leaves the accounts untouched, as in the runtime.
+pub fn run(accounts: &mut [TestAccount], instruction: LiquidityPoolInstruction) -> ProgramResult {
+    setup();
+    let snapshot = accounts.to_vec();
+    let result = execute(accounts, instruction);
+    if result.is_err() {
+        accounts.clone_from_slice(&snapshot);
+    }
+    result
+}
+
+/// Offsets of the key, the owner and the data in a [`serialize_account`] buffer.
+const KEY_OFFSET: usize = 8;
+const OWNER_OFFSET: usize = KEY_OFFSET + 32;
+const DATA_OFFSET: usize = OWNER_OFFSET + 32 + 8;
+
+/// Lays out an account's key, owner and data as the runtime serializes them
+/// for a program, which `AccountInfo::realloc` and `AccountInfo::assign` rely
+/// on: the original data length right before the key, the current length
+/// right before the data, and room for the data to grow. Words keep the
+/// lengths aligned.
+fn serialize_account(account: &TestAccount) -> Vec<u64> {
+    let len = account.data.len();
+    let mut buffer = vec![0u64; (DATA_OFFSET + len + MAX_PERMITTED_DATA_INCREASE).div_ceil(8)];
+    let bytes = unsafe { std::slice::from_raw_parts_mut(buffer.as_mut_ptr() as *mut u8, buffer.len() * 8) };
+    bytes[KEY_OFFSET - 4..KEY_OFFSET].copy_from_slice(&(len as u32).to_le_bytes());
+    bytes[KEY_OFFSET..KEY_OFFSET + 32].copy_from_slice(account.key.as_ref());
+    bytes[OWNER_OFFSET..OWNER_OFFSET + 32].copy_from_slice(account.owner.as_ref());
+    bytes[DATA_OFFSET - 8..DATA_OFFSET].copy_from_slice(&(len as u64).to_le_bytes());
+    bytes[DATA_OFFSET..DATA_OFFSET + len].copy_from_slice(&account.data);
+    buffer
+}
+
+fn execute(accounts: &mut [TestAccount], instruction: LiquidityPoolInstruction) -> ProgramResult {
+    let mut buffers: Vec<Vec<u64>> = accounts.iter().map(serialize_account).collect();
+    let mut infos: Vec<AccountInfo> = Vec::with_capacity(accounts.len());
+    for (account, buffer) in accounts.iter_mut().zip(buffers.iter_mut()) {
+        let info = match infos.iter().find(|info| *info.key == account.key) {
+            Some(first) => first.clone(),
+            None => {
+                let base = buffer.as_mut_ptr() as *mut u8;
+                let (key, owner, data) = unsafe {
+                    (
+                        &*(base.add(KEY_OFFSET) as *const Pubkey),
+                        &*(base.add(OWNER_OFFSET) as *const Pubkey),
+                        std::slice::from_raw_parts_mut(base.add(DATA_OFFSET), account.data.len()),
+                    )
+                };
+                AccountInfo::new(key, account.is_signer, true, &mut account.lamports, data, owner, false, 0)
+            }
+        };
+        infos.push(info);
+    }
+    let data = instruction.try_to_vec().unwrap();
+    let result = process_instruction(&liquidity_pool::id(), &infos, &data);
+
+    let written: Vec<(Pubkey, Vec<u8>)> = infos.iter().map(|info| (*info.owner, info.data.borrow().to_vec())).collect();
+    drop(infos);
+    drop(buffers);
+    for (account, (owner, data)) in accounts.iter_mut().zip(written) {
+        account.owner = owner;
+        account.data = data;
+    }
+    result
+}
+
+/// The registry header and the given page, not yet created.
+pub fn registry_accounts(page_index: u32) -> (TestAccount, TestAccount) {
+    let program_id = liquidity_pool::id();
+    (
+        TestAccount::unallocated(registry::registry_header_address(&program_id).0),
+        TestAccount::unallocated(registry::registry_page_address(&program_id, page_index).0),
+    )
+}
+
+/// The registry header and its first page, recording `pools`.
+pub fn registry_accounts_with(pools: &[&PoolFixture]) -> (TestAccount, TestAccount) {
+    let program_id = liquidity_pool::id();
+    let entries = pools
+        .iter()
+        .map(|pool| RegistryEntry {
+            token_a_mint: pool.mint_a.key,
+            token_b_mint: pool.mint_b.key,
+            pool: pool.pool_state.key,
+            lp_mint: pool.lp_mint.key,
+            fee_bps: SWAP_FEE_BPS,
+            created_slot: 0,
+        })
+        .collect();
+    let header = RegistryHeader {
+        discriminator: registry::REGISTRY_HEADER_DISCRIMINATOR,
+        is_initialized: 1,
+        pool_count: pools.len() as u64,
+    };
+    let mut header = TestAccount::new(program_id, header.try_to_vec().unwrap());
+    header.key = registry::registry_header_address(&program_id).0;
+    let page = RegistryPage { discriminator: registry::REGISTRY_PAGE_DISCRIMINATOR, is_initialized: 1, page_index: 0, entries };
+    let mut page_data = page.try_to_vec().unwrap();
+    page_data.resize(RegistryPage::LEN, 0);
+    let mut page = TestAccount::new(program_id, page_data);
+    page.key = registry::registry_page_address(&program_id, 0).0;
+    (header, page)
+}
+
+/// An initialized fee config allowing `fee_tiers`, administered by `admin`.
+pub fn fee_config_account(admin: Pubkey, fee_tiers: &[u64]) -> TestAccount {
+    let program_id = liquidity_pool::id();
+    let fee_config = FeeConfig {
+        discriminator: fee_config::FEE_CONFIG_DISCRIMINATOR,
+        is_initialized: 1,
+        admin,
+        fee_tiers: fee_tiers.to_vec(),
+    };
+    let mut data = fee_config.try_to_vec().unwrap();
+    data.resize(FeeConfig::LEN, 0);
+    let mut account = TestAccount::new(program_id, data);
+    account.key = fee_config::fee_config_address(&program_id).0;
+    account
+}
+
+/// Accounts for `InitializePool` of two new mints in canonical order at
+/// `fee_bps`, by a user holding `user_balance` of each, recorded in the given
+/// registry accounts.
+pub fn initialize_pool_accounts(
+    registry_header: &TestAccount,
+    registry_page: &TestAccount,
+    fee_config: &TestAccount,
+    fee_bps: u64,
+    user_balance: u64,
+) -> Vec<TestAccount> {
+    let mint_authority = Pubkey::new_unique();
+    let mut mints = [TestAccount::mint(mint_authority, u64::MAX / 2), TestAccount::mint(mint_authority, u64::MAX / 2)];
+    mints.sort_by_key(|mint| mint.key);
+    let [mint_a, mint_b] = mints;
+    initialize_pool_accounts_for_mints(&mint_a, &mint_b, registry_header, registry_page, fee_config, fee_bps, user_balance)
+}
+
+/// As [`initialize_pool_accounts`], for existing mints.
+pub fn initialize_pool_accounts_for_mints(
+    mint_a: &TestAccount,
+    mint_b: &TestAccount,
+    registry_header: &TestAccount,
+    registry_page: &TestAccount,
+    fee_config: &TestAccount,
+    fee_bps: u64,
+    user_balance: u64,
+) -> Vec<TestAccount> {
+    let program_id = liquidity_pool::id();
+    let user = TestAccount { lamports: 1_000_000_000, ..TestAccount::signer() };
+    let (pool_key, _) = Pubkey::find_program_address(
+        &[b"liquidity_pool", mint_a.key.as_ref(), mint_b.key.as_ref(), &fee_bps.to_le_bytes()],
+        &program_id,
+    );
+    // The LP mint belongs to the token program of token A
+    let token_program_id = mint_a.owner;
+    let lp_mint_key = liquidity_pool::lp_mint_address(&program_id, &pool_key).0;
+    let mut user_lp = TestAccount::token_account(lp_mint_key, user.key, 0);
+    user_lp.owner = token_program_id;
+
+    vec![
+        user.clone(),
+        TestAccount::unallocated(pool_key),
+        mint_a.clone(),
+        mint_b.clone(),
+        TestAccount::unallocated(liquidity_pool::vault_address(&program_id, &pool_key, &mint_a.key).0),
+        TestAccount::unallocated(liquidity_pool::vault_address(&program_id, &pool_key, &mint_b.key).0),
+        TestAccount::unallocated(lp_mint_key),
+        TestAccount::token_account_for(mint_a, user.key, user_balance),
+        TestAccount::token_account_for(mint_b, user.key, user_balance),
+        user_lp,
+        TestAccount::program(token_program_id),
+        TestAccount::rent_sysvar(),
+        TestAccount::program(system_program::id()),
+        registry_header.clone(),
+        registry_page.clone(),
+        fee_config.clone(),
+    ]
+}
+
+/// A pool with funded vaults and a funded user holding both tokens.
+pub struct PoolFixture {
+    pub user: TestAccount,
+    pub pool_state: TestAccount,
+    pub mint_a: TestAccount,
+    pub mint_b: TestAccount,
+    pub vault_a: TestAccount,
+    pub vault_b: TestAccount,
+    pub lp_mint: TestAccount,
+    pub user_a: TestAccount,
+    pub user_b: TestAccount,
+    pub user_lp: TestAccount,
+    pub token_program: TestAccount,
+}
+
+impl PoolFixture {
+    /// Builds an initialized pool with the given reserves, and a user holding
+    /// `user_balance` of each token and `user_lp` LP tokens.
+    pub fn new(reserve_a: u64, reserve_b: u64, lp_supply: u64, user_balance: u64, user_lp: u64) -> Self {
+        let mint_authority = Pubkey::new_unique();
+        let mint_a = TestAccount::mint(mint_authority, u64::MAX / 2);
+        let mint_b = TestAccount::mint(mint_authority, u64::MAX / 2);
+        Self::for_mints(mint_a, mint_b, reserve_a, reserve_b, lp_supply, user_balance, user_lp)
+    }
+
+    /// Like [`PoolFixture::new`], but trading the given mints.
+    pub fn for_mints(
+        mint_a: TestAccount,
+        mint_b: TestAccount,
+        reserve_a: u64,
+        reserve_b: u64,
+        lp_supply: u64,
+        user_balance: u64,
+        user_lp: u64,
+    ) -> Self {
+        let program_id = liquidity_pool::id();
+        let (pool_key, bump_seed) = Pubkey::find_program_address(
+            &[b"liquidity_pool", mint_a.key.as_ref(), mint_b.key.as_ref(), &SWAP_FEE_BPS.to_le_bytes()],
+            &program_id,
+        );
+        // Both sides and the LP mint belong to the token program of token A
+        let token_program_id = mint_a.owner;
+        let mut lp_mint = TestAccount::mint(pool_key, user_lp);
+        lp_mint.owner = token_program_id;
+        let user = TestAccount::signer();
+        let mut user_lp_account = TestAccount::token_account(lp_mint.key, user.key, user_lp);
+        user_lp_account.owner = token_program_id;
+        let mut vault_a = TestAccount::token_account_for(&mint_a, pool_key, reserve_a);
+        vault_a.key = liquidity_pool::vault_address(&program_id, &pool_key, &mint_a.key).0;
+        let mut vault_b = TestAccount::token_account_for(&mint_b, pool_key, reserve_b);
+        vault_b.key = liquidity_pool::vault_address(&program_id, &pool_key, &mint_b.key).0;
+
+        let state = PoolState {
+            discriminator: POOL_STATE_DISCRIMINATOR,
+            is_initialized: 1,
+            version: POOL_STATE_VERSION,
+            token_a_mint: mint_a.key,
+            token_b_mint: mint_b.key,
+            token_a_reserve: reserve_a,
+            token_b_reserve: reserve_b,
+            lp_mint: lp_mint.key,
+            lp_supply,
+            bump_seed,
+            curve_type: CurveType::ConstantProduct,
+            creator: user.key,
+            initial_amp: 0,
+            target_amp: 0,
+            ramp_start_ts: 0,
+            ramp_stop_ts: 0,
+            price_a_cumulative_x64: 0,
+            price_b_cumulative_x64: 0,
+            last_update_ts: NOW,
+            fee_bps: SWAP_FEE_BPS,
+            vault_a: vault_a.key,
+            vault_b: vault_b.key,
+            reserve_a_cumulative: 0,
+            reserve_b_cumulative: 0,
+            reserved: [0; POOL_STATE_RESERVED],
+            legacy_seeds: false,
+        };
+        let mut pool_state = TestAccount::new(program_id, state.try_to_vec().unwrap());
+        pool_state.key = pool_key;
+
+        Self {
+            vault_a,
+            vault_b,
+            user_a: TestAccount::token_account_for(&mint_a, user.key, user_balance),
+            user_b: TestAccount::token_account_for(&mint_b, user.key, user_balance),
+            user_lp: user_lp_account,
+            token_program: TestAccount::program(token_program_id),
+            user,
+            pool_state,
+            mint_a,
+            mint_b,
+            lp_mint,
+        }
+    }
+
+    /// Like [`PoolFixture::new`], but a stable pool with a fixed `amp`.
+    pub fn stable(reserve_a: u64, reserve_b: u64, lp_supply: u64, user_balance: u64, amp: u64) -> Self {
+        let mut pool = Self::new(reserve_a, reserve_b, lp_supply, user_balance, 0);
+        let mut state = pool.state();
+        state.curve_type = CurveType::Stable;
+        state.initial_amp = amp;
+        state.target_amp = amp;
+        pool.set_state(&state);
+        pool
+    }
+
+    /// Like [`PoolFixture::new`], but at the address derived without the fee
+    /// tier, as pools created before fee tiers are.
+    pub fn legacy_seeds(reserve_a: u64, reserve_b: u64, lp_supply: u64, user_balance: u64, user_lp: u64) -> Self {
+        let mut pool = Self::new(reserve_a, reserve_b, lp_supply, user_balance, user_lp);
+        let program_id = liquidity_pool::id();
+        let (pool_key, bump_seed) =
+            Pubkey::find_program_address(&[b"liquidity_pool", pool.mint_a.key.as_ref(), pool.mint_b.key.as_ref()], &program_id);
+        let lp_mint_key = pool.lp_mint.key;
+        pool.lp_mint = TestAccount { key: lp_mint_key, ..TestAccount::mint(pool_key, user_lp) };
+        pool.vault_a = TestAccount::token_account_for(&pool.mint_a, pool_key, reserve_a);
+        pool.vault_a.key = liquidity_pool::vault_address(&program_id, &pool_key, &pool.mint_a.key).0;
+        pool.vault_b = TestAccount::token_account_for(&pool.mint_b, pool_key, reserve_b);
+        pool.vault_b.key = liquidity_pool::vault_address(&program_id, &pool_key, &pool.mint_b.key).0;
+        let state = PoolState { bump_seed, vault_a: pool.vault_a.key, vault_b: pool.vault_b.key, ..pool.state() };
+        pool.set_state(&state);
+        pool.pool_state.key = pool_key;
+        pool
+    }
+
+    pub fn state(&self) -> PoolState {
+        PoolState::try_from_slice(&self.pool_state.data).unwrap()
+    }
+
+    pub fn set_state(&mut self, state: &PoolState) {
+        self.pool_state.data = state.try_to_vec().unwrap();
+    }
+
+    /// Accounts for `RampAmp` / `StopRampAmp`, signed by the creator.
+    pub fn ramp_accounts(&self) -> Vec<TestAccount> {
+        vec![self.user.clone(), self.pool_state.clone()]
+    }
+
+    /// Accounts for `AddLiquidity` / `RemoveLiquidity`.
+    pub fn liquidity_accounts(&self) -> Vec<TestAccount> {
+        vec![
+            self.user.clone(),
+            self.pool_state.clone(),
+            self.vault_a.clone(),
+            self.vault_b.clone(),
+            self.lp_mint.clone(),
+            self.user_a.clone(),
+            self.user_b.clone(),
+            self.user_lp.clone(),
+            self.token_program.clone(),
+        ]
+    }
+
+    /// Accounts for `Sync`.
+    pub fn sync_accounts(&self) -> Vec<TestAccount> {
+        vec![self.pool_state.clone(), self.vault_a.clone(), self.vault_b.clone()]
+    }
+
+    /// Accounts for `Skim`, paying the excess to the user.
+    pub fn skim_accounts(&self) -> Vec<TestAccount> {
+        vec![
+            self.pool_state.clone(),
+            self.vault_a.clone(),
+            self.vault_b.clone(),
+            self.user_a.clone(),
+            self.user_b.clone(),
+            self.token_program.clone(),
+        ]
+    }
+
+    /// Accounts for `ClosePool`, signed by the user as the pool's creator.
+    pub fn close_accounts(&self) -> Vec<TestAccount> {
+        vec![
+            self.user.clone(),
+            self.pool_state.clone(),
+            self.vault_a.clone(),
+            self.vault_b.clone(),
+            self.lp_mint.clone(),
+            self.user_a.clone(),
+            self.user_b.clone(),
+            self.token_program.clone(),
+            TestAccount::unallocated(farm::farm_address(&liquidity_pool::id(), &self.pool_state.key).0),
+            registry_accounts_with(&[self]).1,
+        ]
+    }
+
+    /// Accounts for `FlashLoan` to the user, repaid by the simulated borrower.
+    pub fn flash_loan_accounts(&self) -> Vec<TestAccount> {
+        vec![
+            self.user.clone(),
+            self.pool_state.clone(),
+            self.vault_a.clone(),
+            self.vault_b.clone(),
+            self.user_a.clone(),
+            self.user_b.clone(),
+            self.token_program.clone(),
+            TestAccount::program(FLASH_BORROWER_ID),
+            self.user.clone(),
+            self.user_a.clone(),
+            self.user_b.clone(),
+            self.vault_a.clone(),
+            self.vault_b.clone(),
+            self.token_program.clone(),
+        ]
+    }
+
+    /// Accounts for `SwapAforB`.
+    pub fn swap_accounts(&self) -> Vec<TestAccount> {
+        vec![
+            self.user.clone(),
+            self.pool_state.clone(),
+            self.vault_a.clone(),
+            self.vault_b.clone(),
+            self.user_a.clone(),
+            self.user_b.clone(),
+            self.token_program.clone(),
+        ]
+    }
+}
+
+/// A weighted pool created through `InitializeWeightedPool`, and a user holding
+/// `user_balance` of every token.
+pub struct WeightedPoolFixture {
+    pub user: TestAccount,
+    pub pool_state: TestAccount,
+    pub lp_mint: TestAccount,
+    pub user_lp: TestAccount,
+    pub token_program: TestAccount,
+    pub mints: Vec<TestAccount>,
+    pub vaults: Vec<TestAccount>,
+    pub user_tokens: Vec<TestAccount>,
+}
+
+impl WeightedPoolFixture {
+    pub fn new(weights: &[u64], amounts: &[u64], user_balance: u64) -> Self {
+        Self::try_new(weights, amounts, user_balance).unwrap()
+    }
+
+    pub fn try_new(weights: &[u64], amounts: &[u64], user_balance: u64) -> Result<Self, ProgramError> {
+        let mint_authority = Pubkey::new_unique();
+        let mints = amounts.iter().map(|_| TestAccount::mint(mint_authority, u64::MAX / 2)).collect();
+        Self::try_for_mints(weights, mints, amounts, user_balance)
+    }
+
+    /// As [`WeightedPoolFixture::new`], for existing mints of one token
+    /// program, which are passed as trailing accounts.
+    pub fn try_for_mints(
+        weights: &[u64],
+        mints: Vec<TestAccount>,
+        amounts: &[u64],
+        user_balance: u64,
+    ) -> Result<Self, ProgramError> {
+        let program_id = liquidity_pool::id();
+        let user = TestAccount { lamports: 1_000_000_000, ..TestAccount::signer() };
+        // A new keypair the program creates the LP mint at
+        let lp_mint = TestAccount { is_signer: true, ..TestAccount::unallocated(Pubkey::new_unique()) };
+        let (pool_key, _) = Pubkey::find_program_address(&[b"weighted_pool", lp_mint.key.as_ref()], &program_id);
+        let pool_state = TestAccount::unallocated(pool_key);
+
+        let token_program_id = mints[0].owner;
+        let mut user_lp = TestAccount::token_account(lp_mint.key, user.key, 0);
+        user_lp.owner = token_program_id;
+        let mut fixture = Self {
+            user_lp,
+            vaults: mints
+                .iter()
+                .map(|mint| TestAccount::unallocated(liquidity_pool::vault_address(&program_id, &pool_key, &mint.key).0))
+                .collect(),
+            user_tokens: mints
+                .iter()
+                .zip(amounts)
+                .map(|(mint, amount)| TestAccount::token_account_for(mint, user.key, amount + user_balance))
+                .collect(),
+            token_program: TestAccount::program(token_program_id),
+            user,
+            pool_state,
+            lp_mint,
+            mints,
+        };
+
+        let mut accounts = vec![
+            fixture.user.clone(),
+            fixture.pool_state.clone(),
+            fixture.lp_mint.clone(),
+            fixture.user_lp.clone(),
+            fixture.token_program.clone(),
+            TestAccount::rent_sysvar(),
+            TestAccount::program(system_program::id()),
+        ];
+        for i in 0..amounts.len() {
+            accounts.push(fixture.mints[i].clone());
+            accounts.push(fixture.vaults[i].clone());
+            accounts.push(fixture.user_tokens[i].clone());
+        }
+        accounts.extend(fixture.mints.iter().cloned());
+        let instruction = LiquidityPoolInstruction::InitializeWeightedPool {
+            weights: weights.to_vec(),
+            amounts: amounts.to_vec(),
+        };
+        run(&mut accounts, instruction)?;
+
+        fixture.pool_state = accounts[1].clone();
+        fixture.lp_mint = accounts[2].clone();
+        fixture.user_lp = accounts[3].clone();
+        for i in 0..amounts.len() {
+            fixture.vaults[i] = accounts[7 + 3 * i + 1].clone();
+            fixture.user_tokens[i] = accounts[7 + 3 * i + 2].clone();
+        }
+        Ok(fixture)
+    }
+
+    pub fn state(&self) -> WeightedPoolState {
+        WeightedPoolState::deserialize(&mut &self.pool_state.data[..]).unwrap()
+    }
+
+    pub fn token(&self, index: usize) -> WeightedToken {
+        self.state().tokens[index].clone()
+    }
+
+    /// Accounts for `WeightedSwap` from token `token_in` to token `token_out`.
+    pub fn swap_accounts(&self, token_in: usize, token_out: usize) -> Vec<TestAccount> {
+        vec![
+            self.user.clone(),
+            self.pool_state.clone(),
+            self.vaults[token_in].clone(),
+            self.vaults[token_out].clone(),
+            self.user_tokens[token_in].clone(),
+            self.user_tokens[token_out].clone(),
+            self.token_program.clone(),
+        ]
+    }
+
+    /// Accounts for `JoinWeightedPool` / `ExitWeightedPool`.
+    pub fn liquidity_accounts(&self) -> Vec<TestAccount> {
+        let mut accounts = vec![
+            self.user.clone(),
+            self.pool_state.clone(),
+            self.lp_mint.clone(),
+            self.user_lp.clone(),
+            self.token_program.clone(),
+        ];
+        for (vault, user_token) in self.vaults.iter().zip(&self.user_tokens) {
+            accounts.push(vault.clone());
+            accounts.push(user_token.clone());
+        }
+        accounts
+    }
+}
+
+/// A concentrated pool created through `InitializeConcentratedPool` at
+/// `initial_tick`, and a user holding `user_balance` of both tokens.
+pub struct ConcentratedPoolFixture {
+    pub user: TestAccount,
+    pub pool_state: TestAccount,
+    pub mint_a: TestAccount,
+    pub mint_b: TestAccount,
+    pub vault_a: TestAccount,
+    pub vault_b: TestAccount,
+    pub user_a: TestAccount,
+    pub user_b: TestAccount,
+    pub token_program: TestAccount,
+    pub registry_header: TestAccount,
+    pub registry_page: TestAccount,
+}
+
+impl ConcentratedPoolFixture {
+    pub fn new(tick_spacing: u16, initial_tick: i32, user_balance: u64) -> Self {
+        let mut fixture = Self::uninitialized(user_balance);
+        let mut accounts = fixture.initialize_accounts();
+        let instruction = LiquidityPoolInstruction::InitializeConcentratedPool {
+            tick_spacing,
+            initial_sqrt_price_x64: concentrated_math::sqrt_price_at_tick(initial_tick).unwrap(),
+        };
+        run(&mut accounts, instruction).unwrap();
+        fixture.pool_state = accounts[1].clone();
+        fixture.vault_a = accounts[4].clone();
+        fixture.vault_b = accounts[5].clone();
+        fixture.registry_header = accounts[8].clone();
+        fixture.registry_page = accounts[9].clone();
+        fixture
+    }
+
+    /// A pool of two new mints in canonical order, not created yet, with its
+    /// vaults and the registry still unallocated.
+    pub fn uninitialized(user_balance: u64) -> Self {
+        let program_id = liquidity_pool::id();
+        let user = TestAccount { lamports: 1_000_000_000, ..TestAccount::signer() };
+        let mint_authority = Pubkey::new_unique();
+        let mut mints = [TestAccount::mint(mint_authority, u64::MAX / 2), TestAccount::mint(mint_authority, u64::MAX / 2)];
+        mints.sort_by_key(|mint| mint.key);
+        let [mint_a, mint_b] = mints;
+        let (pool_key, _) = Pubkey::find_program_address(
+            &[b"concentrated_pool", mint_a.key.as_ref(), mint_b.key.as_ref()],
+            &program_id,
+        );
+        let (registry_header, registry_page) = registry_accounts(0);
+
+        Self {
+            pool_state: TestAccount::unallocated(pool_key),
+            vault_a: TestAccount::unallocated(liquidity_pool::vault_address(&program_id, &pool_key, &mint_a.key).0),
+            vault_b: TestAccount::unallocated(liquidity_pool::vault_address(&program_id, &pool_key, &mint_b.key).0),
+            user_a: TestAccount::token_account(mint_a.key, user.key, user_balance),
+            user_b: TestAccount::token_account(mint_b.key, user.key, user_balance),
+            token_program: TestAccount::program(spl_token::id()),
+            user,
+            mint_a,
+            mint_b,
+            registry_header,
+            registry_page,
+        }
+    }
+
+    /// Accounts for `InitializeConcentratedPool`.
+    pub fn initialize_accounts(&self) -> Vec<TestAccount> {
+        vec![
+            self.user.clone(),
+            self.pool_state.clone(),
+            self.mint_a.clone(),
+            self.mint_b.clone(),
+            self.vault_a.clone(),
+            self.vault_b.clone(),
+            TestAccount::program(system_program::id()),
+            self.token_program.clone(),
+            self.registry_header.clone(),
+            self.registry_page.clone(),
+        ]
+    }
+
+    pub fn state(&self) -> ConcentratedPoolState {
+        ConcentratedPoolState::deserialize(&mut &self.pool_state.data[..]).unwrap()
+    }
+
+    /// Opens a position owned by the user.
+    pub fn open_position(&self, tick_lower: i32, tick_upper: i32) -> Result<TestAccount, ProgramError> {
+        let mut accounts = vec![
+            self.user.clone(),
+            self.pool_state.clone(),
+            TestAccount { is_signer: true, ..TestAccount::unallocated(Pubkey::new_unique()) },
+            TestAccount::program(system_program::id()),
+        ];
+        run(&mut accounts, LiquidityPoolInstruction::OpenPosition { tick_lower, tick_upper })?;
+        Ok(accounts.swap_remove(2))
+    }
+
+    /// Runs `IncreaseLiquidity`, `DecreaseLiquidity` or `CollectPosition` on
+    /// `position` and keeps the resulting account states.
+    pub fn run_position(&mut self, position: &mut TestAccount, instruction: LiquidityPoolInstruction) -> ProgramResult {
+        let mut accounts = vec![
+            self.user.clone(),
+            self.pool_state.clone(),
+            position.clone(),
+            self.vault_a.clone(),
+            self.vault_b.clone(),
+            self.user_a.clone(),
+            self.user_b.clone(),
+            self.token_program.clone(),
+        ];
+        run(&mut accounts, instruction)?;
+        self.pool_state = accounts[1].clone();
+        *position = accounts[2].clone();
+        self.vault_a = accounts[3].clone();
+        self.vault_b = accounts[4].clone();
+        self.user_a = accounts[5].clone();
+        self.user_b = accounts[6].clone();
+        Ok(())
+    }
+
+    /// Runs `ConcentratedSwap` for the user and keeps the resulting account states.
+    pub fn run_swap(&mut self, instruction: LiquidityPoolInstruction) -> ProgramResult {
+        let mut accounts = vec![
+            self.user.clone(),
+            self.pool_state.clone(),
+            self.vault_a.clone(),
+            self.vault_b.clone(),
+            self.user_a.clone(),
+            self.user_b.clone(),
+            self.token_program.clone(),
+        ];
+        run(&mut accounts, instruction)?;
+        self.pool_state = accounts[1].clone();
+        self.vault_a = accounts[2].clone();
+        self.vault_b = accounts[3].clone();
+        self.user_a = accounts[4].clone();
+        self.user_b = accounts[5].clone();
+        Ok(())
+    }
+}
+
+/// A farm over a constant-product pool's LP tokens, created by the pool
+/// creator, who also holds reward tokens to fund it with.
+pub struct FarmFixture {
+    pub pool: PoolFixture,
+    pub farm: TestAccount,
+    pub reward_mint: TestAccount,
+    pub staked_vault: TestAccount,
+    pub reward_vault: TestAccount,
+    pub admin_reward: TestAccount,
+}
+
+/// A user staking into a farm, with their LP and reward token accounts and
+/// their not yet created stake.
+pub struct Staker {
+    pub user: TestAccount,
+    pub lp: TestAccount,
+    pub reward: TestAccount,
+    pub stake: TestAccount,
+}
+
+impl FarmFixture {
+    /// A farm created through `InitializeFarm` and funded with `funding` reward
+    /// tokens.
+    pub fn new(emission_rate: u64, funding: u64) -> Self {
+        let mut fixture = Self::uninitialized();
+        let mut accounts = fixture.initialize_accounts();
+        run(&mut accounts, LiquidityPoolInstruction::InitializeFarm { emission_rate }).unwrap();
+        fixture.farm = accounts[2].clone();
+        if funding > 0 {
+            fixture.run_admin(LiquidityPoolInstruction::FundFarm { amount: funding }).unwrap();
+        }
+        fixture
+    }
+
+    /// The accounts of a farm not yet initialized.
+    pub fn uninitialized() -> Self {
+        Self::uninitialized_over(PoolFixture::new(1_000_000, 1_000_000, 1_000_000, 0, 0))
+    }
+
+    /// The accounts of a farm not yet initialized over `pool`.
+    pub fn uninitialized_over(mut pool: PoolFixture) -> Self {
+        let program_id = liquidity_pool::id();
+        pool.user.lamports = 1_000_000_000;
+        let (farm_key, _) = farm::farm_address(&program_id, &pool.pool_state.key);
+        let farm = TestAccount::unallocated(farm_key);
+        let reward_mint = TestAccount::mint(Pubkey::new_unique(), u64::MAX / 2);
+
+        Self {
+            staked_vault: TestAccount::token_account(pool.lp_mint.key, farm_key, 0),
+            reward_vault: TestAccount::token_account(reward_mint.key, farm_key, 0),
+            admin_reward: TestAccount::token_account(reward_mint.key, pool.user.key, u64::MAX / 4),
+            pool,
+            farm,
+            reward_mint,
+        }
+    }
+
+    /// Accounts for `InitializeFarm`, signed by the pool creator.
+    pub fn initialize_accounts(&self) -> Vec<TestAccount> {
+        vec![
+            self.pool.user.clone(),
+            self.pool.pool_state.clone(),
+            self.farm.clone(),
+            self.reward_mint.clone(),
+            self.staked_vault.clone(),
+            self.reward_vault.clone(),
+            TestAccount::program(system_program::id()),
+        ]
+    }
+
+    pub fn state(&self) -> Farm {
+        Farm::deserialize(&mut &self.farm.data[..]).unwrap()
+    }
+
+    /// Moves the farm's last update `seconds` into the past, as if that much
+    /// time had passed since.
+    pub fn advance(&mut self, seconds: i64) {
+        let mut state = self.state();
+        state.last_update_ts = NOW - seconds;
+        self.farm.data = state.try_to_vec().unwrap();
+    }
+
+    /// A new user holding `lp_balance` LP tokens.
+    pub fn staker(&self, lp_balance: u64) -> Staker {
+        let program_id = liquidity_pool::id();
+        let user = TestAccount { lamports: 1_000_000_000, ..TestAccount::signer() };
+        let stake = TestAccount::unallocated(farm::stake_address(&program_id, &self.farm.key, &user.key).0);
+        Staker {
+            lp: TestAccount::token_account(self.pool.lp_mint.key, user.key, lp_balance),
+            reward: TestAccount::token_account(self.reward_mint.key, user.key, 0),
+            user,
+            stake,
+        }
+    }
+
+    /// Runs `FundFarm` or `SetEmissionRate`, signed by the admin, and keeps the
+    /// resulting account states.
+    pub fn run_admin(&mut self, instruction: LiquidityPoolInstruction) -> ProgramResult {
+        let mut accounts = vec![
+            self.pool.user.clone(),
+            self.farm.clone(),
+            self.admin_reward.clone(),
+            self.reward_vault.clone(),
+            self.pool.token_program.clone(),
+        ];
+        run(&mut accounts, instruction)?;
+        self.farm = accounts[1].clone();
+        self.admin_reward = accounts[2].clone();
+        self.reward_vault = accounts[3].clone();
+        Ok(())
+    }
+
+    /// Runs `Stake` or `Unstake` for `staker` and keeps the resulting account
+    /// states.
+    pub fn run_stake(&mut self, staker: &mut Staker, instruction: LiquidityPoolInstruction) -> ProgramResult {
+        let mut accounts = vec![
+            staker.user.clone(),
+            self.farm.clone(),
+            staker.stake.clone(),
+            staker.lp.clone(),
+            self.staked_vault.clone(),
+            self.pool.token_program.clone(),
+            TestAccount::program(system_program::id()),
+        ];
+        run(&mut accounts, instruction)?;
+        staker.user = accounts[0].clone();
+        self.farm = accounts[1].clone();
+        staker.stake = accounts[2].clone();
+        staker.lp = accounts[3].clone();
+        self.staked_vault = accounts[4].clone();
+        Ok(())
+    }
+
+    /// Runs `CloseFarm`, signed by the admin, and keeps the resulting account
+    /// states.
+    pub fn close(&mut self) -> ProgramResult {
+        let mut accounts = vec![
+            self.pool.user.clone(),
+            self.farm.clone(),
+            self.staked_vault.clone(),
+            self.reward_vault.clone(),
+            self.pool.user_lp.clone(),
+            self.admin_reward.clone(),
+            self.pool.token_program.clone(),
+        ];
+        run(&mut accounts, LiquidityPoolInstruction::CloseFarm)?;
+        self.pool.user = accounts[0].clone();
+        self.farm = accounts[1].clone();
+        self.staked_vault = accounts[2].clone();
+        self.reward_vault = accounts[3].clone();
+        self.pool.user_lp = accounts[4].clone();
+        self.admin_reward = accounts[5].clone();
+        Ok(())
+    }
+
+    /// Runs `ClaimRewards` for `staker` and keeps the resulting account states.
+    pub fn claim(&mut self, staker: &mut Staker) -> ProgramResult {
+        let mut accounts = vec![
+            staker.user.clone(),
+            self.farm.clone(),
+            staker.stake.clone(),
+            self.reward_vault.clone(),
+            staker.reward.clone(),
+            self.pool.token_program.clone(),
+        ];
+        run(&mut accounts, LiquidityPoolInstruction::ClaimRewards)?;
+        self.farm = accounts[1].clone();
+        staker.stake = accounts[2].clone();
+        self.reward_vault = accounts[3].clone();
+        staker.reward = accounts[4].clone();
+        Ok(())
+    }
+}
+
+/// A distribution created through `InitializeDistribution`, with a manager
+/// holding quote tokens to deposit as income.
+pub struct DistributionFixture {
+    pub manager: TestAccount,
+    pub distribution: TestAccount,
+    pub property_mint: TestAccount,
+    pub quote_mint: TestAccount,
+    pub property_vault: TestAccount,
+    pub quote_vault: TestAccount,
+    pub manager_quote: TestAccount,
+    pub token_program: TestAccount,
+    pub registry_header: TestAccount,
+    pub registry_pages: Vec<TestAccount>,
+}
+
+/// A property token holder, with their property and quote token accounts and
+/// their not yet created holding.
+pub struct Holder {
+    pub user: TestAccount,
+    pub property: TestAccount,
+    pub quote: TestAccount,
+    pub holding: TestAccount,
+}
+
+impl DistributionFixture {
+    pub fn new() -> Self {
+        let mint_authority = Pubkey::new_unique();
+        Self::with_mints(TestAccount::mint(mint_authority, u64::MAX / 2), TestAccount::mint(mint_authority, u64::MAX / 2))
+    }
+
+    /// A distribution of income in `quote_mint` to holders of `property_mint`.
+    pub fn with_mints(property_mint: TestAccount, quote_mint: TestAccount) -> Self {
+        let program_id = liquidity_pool::id();
+        let manager = TestAccount { lamports: 1_000_000_000, ..TestAccount::signer() };
+        let (distribution_key, _) = distribution::distribution_address(&program_id, &property_mint.key, &manager.key);
+        let distribution = TestAccount::unallocated(distribution_key);
+
+        let mut fixture = Self {
+            property_vault: TestAccount::token_account(property_mint.key, distribution_key, 0),
+            quote_vault: TestAccount::token_account(quote_mint.key, distribution_key, 0),
+            manager_quote: TestAccount::token_account(quote_mint.key, manager.key, u64::MAX / 4),
+            token_program: TestAccount::program(spl_token::id()),
+            registry_header: registry_accounts(0).0,
+            registry_pages: Vec::new(),
+            manager,
+            distribution,
+            property_mint,
+            quote_mint,
+        };
+
+        let mut accounts = vec![
+            fixture.manager.clone(),
+            fixture.distribution.clone(),
+            fixture.property_mint.clone(),
+            fixture.quote_mint.clone(),
+            fixture.property_vault.clone(),
+            fixture.quote_vault.clone(),
+            TestAccount::program(system_program::id()),
+        ];
+        run(&mut accounts, LiquidityPoolInstruction::InitializeDistribution).unwrap();
+        fixture.manager = accounts[0].clone();
+        fixture.distribution = accounts[1].clone();
+        fixture
+    }
+
+    pub fn state(&self) -> Distribution {
+        Distribution::deserialize(&mut &self.distribution.data[..]).unwrap()
+    }
+
+    /// A new user holding `balance` property tokens.
+    pub fn holder(&self, balance: u64) -> Holder {
+        let user = TestAccount { lamports: 1_000_000_000, ..TestAccount::signer() };
+        Holder {
+            property: TestAccount::token_account(self.property_mint.key, user.key, balance),
+            quote: TestAccount::token_account(self.quote_mint.key, user.key, 0),
+            holding: self.holding_account(&user.key),
+            user,
+        }
+    }
+
+    /// The not yet created holding of `owner`, a holder or a pool.
+    pub fn holding_account(&self, owner: &Pubkey) -> TestAccount {
+        let program_id = liquidity_pool::id();
+        TestAccount::unallocated(distribution::holding_address(&program_id, &self.distribution.key, owner).0)
+    }
+
+    /// Moves the last updates of `pools`, given as pool state and pool holding
+    /// pairs, `seconds` into the past, as if that much time had passed since.
+    pub fn advance(pools: &mut [TestAccount], seconds: i64) {
+        for pair in pools.chunks_mut(2) {
+            if pair[0].data.starts_with(&CONCENTRATED_POOL_STATE_DISCRIMINATOR) {
+                let mut state = ConcentratedPoolState::deserialize(&mut &pair[0].data[..]).unwrap();
+                state.last_update_ts -= seconds;
+                state.serialize(&mut &mut pair[0].data[..]).unwrap();
+            } else {
+                let mut state = PoolState::deserialize(&mut &pair[0].data[..]).unwrap();
+                state.last_update_ts -= seconds;
+                state.serialize(&mut &mut pair[0].data[..]).unwrap();
+            }
+            if !pair[1].data.is_empty() {
+                let mut holding = Holding::deserialize(&mut &pair[1].data[..]).unwrap();
+                holding.last_sync_ts -= seconds;
+                holding.serialize(&mut &mut pair[1].data[..]).unwrap();
+            }
+        }
+    }
+
+    /// Runs `DepositIncome` signed by the manager, syncing `pools`, given as
+    /// pool state and pool holding pairs, and keeps the resulting account states.
+    pub fn deposit(&mut self, amount: u64, pools: &mut [TestAccount]) -> ProgramResult {
+        let mut accounts = vec![
+            self.manager.clone(),
+            self.distribution.clone(),
+            self.manager_quote.clone(),
+            self.quote_vault.clone(),
+            self.token_program.clone(),
+            TestAccount::program(system_program::id()),
+            self.registry_header.clone(),
+        ];
+        accounts.extend(self.registry_pages.iter().cloned());
+        accounts.extend(pools.iter().cloned());
+        run(&mut accounts, LiquidityPoolInstruction::DepositIncome { amount })?;
+        self.manager = accounts[0].clone();
+        self.distribution = accounts[1].clone();
+        self.manager_quote = accounts[2].clone();
+        self.quote_vault = accounts[3].clone();
+        pools.clone_from_slice(&accounts[7 + self.registry_pages.len()..]);
+        Ok(())
+    }
+
+    /// Runs `LockPropertyTokens` or `UnlockPropertyTokens` for `holder` and
+    /// keeps the resulting account states.
+    pub fn run_holder(&mut self, holder: &mut Holder, instruction: LiquidityPoolInstruction) -> ProgramResult {
+        let mut accounts = vec![
+            holder.user.clone(),
+            self.distribution.clone(),
+            holder.holding.clone(),
+            holder.property.clone(),
+            self.property_vault.clone(),
+            self.token_program.clone(),
+            TestAccount::program(system_program::id()),
+        ];
+        run(&mut accounts, instruction)?;
+        holder.user = accounts[0].clone();
+        self.distribution = accounts[1].clone();
+        holder.holding = accounts[2].clone();
+        holder.property = accounts[3].clone();
+        self.property_vault = accounts[4].clone();
+        Ok(())
+    }
+
+    /// Runs `ClaimIncome` for `holder` and keeps the resulting account states.
+    pub fn claim(&mut self, holder: &mut Holder) -> ProgramResult {
+        let mut accounts = vec![
+            holder.user.clone(),
+            self.distribution.clone(),
+            holder.holding.clone(),
+            self.quote_vault.clone(),
+            holder.quote.clone(),
+            self.token_program.clone(),
+        ];
+        run(&mut accounts, LiquidityPoolInstruction::ClaimIncome)?;
+        holder.holding = accounts[2].clone();
+        self.quote_vault = accounts[3].clone();
+        holder.quote = accounts[4].clone();
+        Ok(())
+    }
+}
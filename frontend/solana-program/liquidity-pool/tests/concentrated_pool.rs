@@ -0,0 +1,377 @@
+mod common;
+
+use borsh::BorshDeserialize;
+use common::{ConcentratedPoolFixture, TestAccount};
+use liquidity_pool::concentrated_math::{self, MAX_TICK, MIN_TICK, Q64};
+use liquidity_pool::concentrated_pool::{ConcentratedPoolState, Position, MIN_TICK_LIQUIDITY, POSITION_DISCRIMINATOR};
+use liquidity_pool::registry::RegistryPage;
+use liquidity_pool::{LiquidityPoolError, LiquidityPoolInstruction, SWAP_FEE_BPS};
+use proptest::prelude::*;
+use solana_program::{hash::hash, program_error::ProgramError, pubkey::Pubkey, rent::Rent, system_program};
+
+const LIQUIDITY: u128 = 1_000_000_000;
+
+fn pool() -> ConcentratedPoolFixture {
+    ConcentratedPoolFixture::new(10, 0, 1_000_000_000)
+}
+
+fn position(account: &TestAccount) -> Position {
+    Position::deserialize(&mut &account.data[..]).unwrap()
+}
+
+fn increase(liquidity: u128) -> LiquidityPoolInstruction {
+    LiquidityPoolInstruction::IncreaseLiquidity { liquidity, max_amount_a: u64::MAX, max_amount_b: u64::MAX }
+}
+
+fn decrease(liquidity: u128) -> LiquidityPoolInstruction {
+    LiquidityPoolInstruction::DecreaseLiquidity { liquidity, min_amount_a: 0, min_amount_b: 0 }
+}
+
+fn swap(amount_in: u64, a_to_b: bool) -> LiquidityPoolInstruction {
+    let limit_tick = if a_to_b { MIN_TICK } else { MAX_TICK };
+    LiquidityPoolInstruction::ConcentratedSwap {
+        amount_in,
+        min_out: 0,
+        a_to_b,
+        sqrt_price_limit_x64: concentrated_math::sqrt_price_at_tick(limit_tick).unwrap(),
+    }
+}
+
+proptest! {
+    #[test]
+    fn tick_round_trips_through_sqrt_price(tick in MIN_TICK..=MAX_TICK) {
+        let sqrt_price = concentrated_math::sqrt_price_at_tick(tick).unwrap();
+        prop_assert_eq!(concentrated_math::tick_at_sqrt_price(sqrt_price).unwrap(), tick);
+        if tick < MAX_TICK {
+            let next = concentrated_math::sqrt_price_at_tick(tick + 1).unwrap();
+            prop_assert!(next > sqrt_price);
+            prop_assert_eq!(concentrated_math::tick_at_sqrt_price(next - 1).unwrap(), tick);
+        }
+    }
+
+    #[test]
+    fn swap_round_trip_never_profits(
+        tick in -100_000i32..=100_000,
+        liquidity in 1_000u128..=1_000_000_000_000_000,
+        amount_in in 1u64..=1_000_000_000_000,
+        a_to_b: bool,
+    ) {
+        let sqrt_price = concentrated_math::sqrt_price_at_tick(tick).unwrap();
+        let (there, back) = if a_to_b { (MIN_TICK, MAX_TICK) } else { (MAX_TICK, MIN_TICK) };
+
+        let first = concentrated_math::compute_swap_step(
+            sqrt_price, concentrated_math::sqrt_price_at_tick(there).unwrap(), liquidity, amount_in, SWAP_FEE_BPS,
+        ).unwrap();
+        prop_assert!(first.amount_in + first.fee_amount <= amount_in);
+
+        let second = concentrated_math::compute_swap_step(
+            first.sqrt_price_next, concentrated_math::sqrt_price_at_tick(back).unwrap(), liquidity, first.amount_out, 0,
+        ).unwrap();
+        prop_assert!(second.amount_out <= first.amount_in);
+    }
+}
+
+#[test]
+fn sqrt_price_matches_floating_point() {
+    assert_eq!(concentrated_math::sqrt_price_at_tick(0), Ok(Q64));
+    for tick in [-443_636, -200_000, -1, 1, 60, 12_345, 200_000, 443_636] {
+        let expected = 1.0001f64.powf(tick as f64 / 2.0) * Q64 as f64;
+        let actual = concentrated_math::sqrt_price_at_tick(tick).unwrap() as f64;
+        // Near MIN_TICK the sqrt price has only about 32 significant bits.
+        assert!((actual / expected - 1.0).abs() < 1e-9, "{tick}");
+    }
+    assert!(concentrated_math::sqrt_price_at_tick(MAX_TICK + 1).is_err());
+}
+
+#[test]
+fn creates_the_pool_and_positions_in_program() {
+    let pool = pool();
+    assert_eq!(pool.pool_state.owner, liquidity_pool::id());
+    assert_eq!(pool.pool_state.lamports, Rent::default().minimum_balance(ConcentratedPoolState::LEN));
+
+    let account = pool.open_position(-600, 600).unwrap();
+    assert_eq!(account.owner, liquidity_pool::id());
+    assert_eq!(account.lamports, Rent::default().minimum_balance(Position::LEN));
+    assert_eq!(position(&account).owner, pool.user.key);
+
+    let mut accounts = vec![
+        pool.user.clone(),
+        pool.pool_state.clone(),
+        TestAccount::unallocated(Pubkey::new_unique()),
+        TestAccount::program(system_program::id()),
+    ];
+    let open = LiquidityPoolInstruction::OpenPosition { tick_lower: -600, tick_upper: 600 };
+    assert_eq!(common::run(&mut accounts, open), Err(ProgramError::MissingRequiredSignature));
+}
+
+#[test]
+fn creates_vaults_and_registers_the_pool_like_constant_product_pools() {
+    let pool = pool();
+    let program_id = liquidity_pool::id();
+    for (vault, mint) in [(&pool.vault_a, &pool.mint_a), (&pool.vault_b, &pool.mint_b)] {
+        assert_eq!(vault.key, liquidity_pool::vault_address(&program_id, &pool.pool_state.key, &mint.key).0);
+        assert_eq!(vault.owner, spl_token::id());
+    }
+    let state = pool.state();
+    assert_eq!((state.token_a_vault, state.token_b_vault), (pool.vault_a.key, pool.vault_b.key));
+
+    let page = RegistryPage::deserialize(&mut &pool.registry_page.data[..]).unwrap();
+    assert_eq!(page.entries.len(), 1);
+    assert_eq!(page.entries[0].pool, pool.pool_state.key);
+    assert_eq!((page.entries[0].token_a_mint, page.entries[0].token_b_mint), (pool.mint_a.key, pool.mint_b.key));
+    assert_eq!(page.entries[0].lp_mint, Pubkey::default());
+}
+
+#[test]
+fn rejects_mints_out_of_canonical_order_and_look_alike_vaults() {
+    let initialize = || LiquidityPoolInstruction::InitializeConcentratedPool { tick_spacing: 10, initial_sqrt_price_x64: Q64 };
+    let fixture = ConcentratedPoolFixture::uninitialized(0);
+
+    let mut accounts = fixture.initialize_accounts();
+    accounts.swap(2, 3);
+    assert_eq!(common::run(&mut accounts, initialize()), Err(LiquidityPoolError::NonCanonicalMintOrder.into()));
+
+    let mut accounts = fixture.initialize_accounts();
+    accounts[4] = TestAccount::unallocated(Pubkey::new_unique());
+    assert_eq!(common::run(&mut accounts, initialize()), Err(ProgramError::InvalidSeeds));
+}
+
+#[test]
+fn rejects_invalid_tick_ranges() {
+    let pool = pool();
+    for (lower, upper) in [(10, -10), (10, 10), (-15, 10), (MIN_TICK - 4, 0)] {
+        assert_eq!(
+            pool.open_position(lower, upper).err(),
+            Some(LiquidityPoolError::InvalidTickRange.into()),
+            "[{lower}, {upper})"
+        );
+    }
+}
+
+#[test]
+fn deposits_depend_on_the_range() {
+    let mut pool = pool();
+    let cases = [((-600, 600), true, true), ((100, 600), true, false), ((-600, -100), false, true)];
+    for ((lower, upper), needs_a, needs_b) in cases {
+        let (balance_a, balance_b) = (pool.user_a.token_amount(), pool.user_b.token_amount());
+        let mut account = pool.open_position(lower, upper).unwrap();
+        pool.run_position(&mut account, increase(LIQUIDITY)).unwrap();
+
+        let (paid_a, paid_b) = (balance_a - pool.user_a.token_amount(), balance_b - pool.user_b.token_amount());
+        assert_eq!((paid_a > 0, paid_b > 0), (needs_a, needs_b), "[{lower}, {upper})");
+        assert_eq!(position(&account).liquidity, LIQUIDITY);
+    }
+    // Only the range around the current price is active.
+    assert_eq!(pool.state().liquidity, LIQUIDITY);
+    assert_eq!(pool.state().ticks.len(), 4);
+}
+
+#[test]
+fn enforces_deposit_slippage() {
+    let mut pool = pool();
+    let mut account = pool.open_position(-600, 600).unwrap();
+    let instruction = LiquidityPoolInstruction::IncreaseLiquidity { liquidity: LIQUIDITY, max_amount_a: 1, max_amount_b: u64::MAX };
+    assert_eq!(
+        pool.run_position(&mut account, instruction),
+        Err(LiquidityPoolError::SlippageExceeded.into())
+    );
+}
+
+#[test]
+fn swap_crosses_ticks() {
+    let mut pool = pool();
+    let mut wide = pool.open_position(-600, 600).unwrap();
+    let mut narrow = pool.open_position(-60, 60).unwrap();
+    pool.run_position(&mut wide, increase(LIQUIDITY)).unwrap();
+    pool.run_position(&mut narrow, increase(LIQUIDITY)).unwrap();
+    assert_eq!(pool.state().liquidity, 2 * LIQUIDITY);
+
+    let (balance_a, balance_b) = (pool.user_a.token_amount(), pool.user_b.token_amount());
+    pool.run_swap(swap(10_000_000, true)).unwrap();
+    let state = pool.state();
+    assert!((-600..-60).contains(&state.tick_current), "{}", state.tick_current);
+    assert_eq!(state.liquidity, LIQUIDITY);
+    assert_eq!(pool.user_a.token_amount(), balance_a - 10_000_000);
+    assert!(pool.user_b.token_amount() > balance_b);
+
+    // Swapping back re-enters the narrow range.
+    pool.run_swap(swap(10_000_000, false)).unwrap();
+    let state = pool.state();
+    assert!((-60..60).contains(&state.tick_current), "{}", state.tick_current);
+    assert_eq!(state.liquidity, 2 * LIQUIDITY);
+}
+
+#[test]
+fn stops_at_the_price_limit() {
+    let mut pool = pool();
+    let mut account = pool.open_position(-600, 600).unwrap();
+    pool.run_position(&mut account, increase(LIQUIDITY)).unwrap();
+
+    let limit = concentrated_math::sqrt_price_at_tick(-100).unwrap();
+    let balance_a = pool.user_a.token_amount();
+    let instruction = LiquidityPoolInstruction::ConcentratedSwap {
+        amount_in: 100_000_000,
+        min_out: 0,
+        a_to_b: true,
+        sqrt_price_limit_x64: limit,
+    };
+    pool.run_swap(instruction).unwrap();
+    assert_eq!(pool.state().sqrt_price_x64, limit);
+    assert!(balance_a - pool.user_a.token_amount() < 100_000_000);
+
+    let instruction = LiquidityPoolInstruction::ConcentratedSwap {
+        amount_in: 1_000,
+        min_out: 0,
+        a_to_b: false,
+        sqrt_price_limit_x64: limit,
+    };
+    assert_eq!(pool.run_swap(instruction), Err(LiquidityPoolError::SlippageExceeded.into()));
+}
+
+#[test]
+fn fees_accrue_only_in_range() {
+    let mut pool = pool();
+    let mut in_range = pool.open_position(-600, 600).unwrap();
+    let mut out_of_range = pool.open_position(1_000, 2_000).unwrap();
+    pool.run_position(&mut in_range, increase(LIQUIDITY)).unwrap();
+    pool.run_position(&mut out_of_range, increase(LIQUIDITY)).unwrap();
+
+    pool.run_swap(swap(1_000_000, true)).unwrap();
+    let fee = 1_000_000 * SWAP_FEE_BPS / 10_000;
+
+    let balance_a = pool.user_a.token_amount();
+    pool.run_position(&mut out_of_range, LiquidityPoolInstruction::CollectPosition).unwrap();
+    assert_eq!(pool.user_a.token_amount(), balance_a);
+
+    pool.run_position(&mut in_range, LiquidityPoolInstruction::CollectPosition).unwrap();
+    let collected = pool.user_a.token_amount() - balance_a;
+    assert!((fee - 1..=fee + 1).contains(&collected), "{collected}");
+
+    // Fees are paid out once.
+    pool.run_position(&mut in_range, LiquidityPoolInstruction::CollectPosition).unwrap();
+    assert_eq!(pool.user_a.token_amount(), balance_a + collected);
+}
+
+#[test]
+fn reserves_follow_the_vaults() {
+    let mut pool = pool();
+    let mut account = pool.open_position(-600, 600).unwrap();
+    let matches_vaults = |pool: &ConcentratedPoolFixture| {
+        let state = pool.state();
+        (state.reserve_a, state.reserve_b) == (pool.vault_a.token_amount(), pool.vault_b.token_amount())
+    };
+    pool.run_position(&mut account, increase(LIQUIDITY)).unwrap();
+    assert!(matches_vaults(&pool));
+    pool.run_swap(swap(1_000_000, true)).unwrap();
+    assert!(matches_vaults(&pool));
+    pool.run_swap(swap(1_000_000, false)).unwrap();
+    assert!(matches_vaults(&pool));
+    pool.run_position(&mut account, decrease(LIQUIDITY)).unwrap();
+    pool.run_position(&mut account, LiquidityPoolInstruction::CollectPosition).unwrap();
+    assert!(matches_vaults(&pool));
+}
+
+#[test]
+fn withdraws_no_more_than_deposited() {
+    let mut pool = pool();
+    let (balance_a, balance_b) = (pool.user_a.token_amount(), pool.user_b.token_amount());
+    let mut account = pool.open_position(-600, 600).unwrap();
+    pool.run_position(&mut account, increase(LIQUIDITY)).unwrap();
+
+    assert_eq!(
+        pool.run_position(&mut account, decrease(LIQUIDITY + 1)),
+        Err(LiquidityPoolError::InsufficientFunds.into())
+    );
+    pool.run_position(&mut account, decrease(LIQUIDITY)).unwrap();
+    let owed = position(&account);
+    assert_eq!(owed.liquidity, 0);
+    assert!(pool.state().ticks.is_empty());
+
+    pool.run_position(&mut account, LiquidityPoolInstruction::CollectPosition).unwrap();
+    assert!(pool.user_a.token_amount() <= balance_a && pool.user_a.token_amount() + 1 >= balance_a);
+    assert!(pool.user_b.token_amount() <= balance_b && pool.user_b.token_amount() + 1 >= balance_b);
+    assert_eq!(pool.vault_a.token_amount(), balance_a - pool.user_a.token_amount());
+    assert_eq!(pool.vault_b.token_amount(), balance_b - pool.user_b.token_amount());
+}
+
+#[test]
+fn positions_hold_at_least_the_minimum_liquidity() {
+    let mut pool = pool();
+    let mut account = pool.open_position(-600, 600).unwrap();
+    assert_eq!(
+        pool.run_position(&mut account, increase(MIN_TICK_LIQUIDITY - 1)),
+        Err(LiquidityPoolError::LiquidityBelowMinimum.into())
+    );
+    assert!(pool.state().ticks.is_empty());
+
+    pool.run_position(&mut account, increase(MIN_TICK_LIQUIDITY)).unwrap();
+    assert_eq!(
+        pool.run_position(&mut account, decrease(1)),
+        Err(LiquidityPoolError::LiquidityBelowMinimum.into())
+    );
+    // Withdrawing everything is always allowed.
+    pool.run_position(&mut account, decrease(MIN_TICK_LIQUIDITY)).unwrap();
+    assert_eq!(position(&account).liquidity, 0);
+}
+
+#[test]
+fn position_discriminator_is_derived_from_its_name() {
+    assert_eq!(POSITION_DISCRIMINATOR, hash(b"account:Position").to_bytes()[..8]);
+}
+
+#[test]
+fn rejects_a_position_of_another_type() {
+    let mut pool = pool();
+    let mut account = pool.open_position(-600, 600).unwrap();
+    pool.run_position(&mut account, increase(LIQUIDITY)).unwrap();
+
+    let mut forged = account.clone();
+    forged.data[..8].copy_from_slice(&[0; 8]);
+    assert_eq!(
+        pool.run_position(&mut forged, LiquidityPoolInstruction::CollectPosition),
+        Err(LiquidityPoolError::InvalidAccountType.into())
+    );
+
+    // A program-owned account of another type cannot be opened over either.
+    let mut accounts = vec![
+        pool.user.clone(),
+        pool.pool_state.clone(),
+        TestAccount { is_signer: true, ..forged },
+        TestAccount::program(system_program::id()),
+    ];
+    let open = LiquidityPoolInstruction::OpenPosition { tick_lower: -600, tick_upper: 600 };
+    assert_eq!(common::run(&mut accounts, open), Err(LiquidityPoolError::InvalidAccountType.into()));
+}
+
+#[test]
+fn rejects_a_pool_of_another_type() {
+    let mut pool = pool();
+    let mut account = pool.open_position(-600, 600).unwrap();
+    pool.run_position(&mut account, increase(LIQUIDITY)).unwrap();
+
+    pool.pool_state.data[..8].copy_from_slice(&[0; 8]);
+    assert_eq!(pool.run_swap(swap(1_000, true)), Err(LiquidityPoolError::InvalidAccountType.into()));
+}
+
+#[test]
+fn only_the_owner_can_manage_a_position() {
+    let mut pool = pool();
+    let mut account = pool.open_position(-600, 600).unwrap();
+    pool.run_position(&mut account, increase(LIQUIDITY)).unwrap();
+
+    let owner = pool.user.clone();
+    pool.user = TestAccount::signer();
+    for instruction in [decrease(1), LiquidityPoolInstruction::CollectPosition] {
+        assert_eq!(
+            pool.run_position(&mut account, instruction),
+            Err(LiquidityPoolError::Unauthorized.into())
+        );
+    }
+
+    pool.user = owner;
+    pool.user.is_signer = false;
+    assert_eq!(
+        pool.run_position(&mut account, decrease(1)),
+        Err(ProgramError::MissingRequiredSignature)
+    );
+}
@@ -0,0 +1,137 @@
+use liquidity_pool::curve::{self, CurveError};
+use liquidity_pool::{MINIMUM_LIQUIDITY, SWAP_FEE_BPS};
+use proptest::prelude::*;
+
+/// Reserves large enough to be realistic but small enough that intermediate
+/// products stay in range for the invariants we check.
+fn reserve() -> impl Strategy<Value = u64> {
+    1_000u64..=1_000_000_000_000
+}
+
+fn fee_bps() -> impl Strategy<Value = u64> {
+    prop_oneof![Just(0u64), Just(SWAP_FEE_BPS), 0u64..1_000]
+}
+
+fn k(reserve_a: u64, reserve_b: u64) -> u128 {
+    reserve_a as u128 * reserve_b as u128
+}
+
+proptest! {
+    #[test]
+    fn swap_exact_in_never_decreases_k(
+        reserve_in in reserve(),
+        reserve_out in reserve(),
+        amount_in in 1u64..=1_000_000_000_000,
+        fee in fee_bps(),
+    ) {
+        let amount_out = curve::swap_exact_in(amount_in, reserve_in, reserve_out, fee).unwrap();
+        prop_assert!(amount_out < reserve_out);
+        prop_assert!(k(reserve_in + amount_in, reserve_out - amount_out) >= k(reserve_in, reserve_out));
+    }
+
+    #[test]
+    fn swap_exact_out_never_decreases_k(
+        reserve_in in reserve(),
+        reserve_out in reserve(),
+        amount_out_fraction in 1u64..1_000,
+        fee in fee_bps(),
+    ) {
+        let amount_out = (reserve_out as u128 * amount_out_fraction as u128 / 1_000) as u64;
+        prop_assume!(amount_out > 0);
+        let amount_in = curve::swap_exact_out(amount_out, reserve_in, reserve_out, fee).unwrap();
+        prop_assert!(k(reserve_in + amount_in, reserve_out - amount_out) >= k(reserve_in, reserve_out));
+    }
+
+    #[test]
+    fn exact_out_input_buys_at_least_the_output(
+        reserve_in in reserve(),
+        reserve_out in reserve(),
+        amount_out_fraction in 1u64..1_000,
+        fee in fee_bps(),
+    ) {
+        // Rounding favours the pool: paying the quoted input through the
+        // exact-in path never yields less than was asked for, and one unit less
+        // never yields more.
+        let amount_out = (reserve_out as u128 * amount_out_fraction as u128 / 1_000) as u64;
+        prop_assume!(amount_out > 0);
+        let amount_in = curve::swap_exact_out(amount_out, reserve_in, reserve_out, fee).unwrap();
+        prop_assert!(curve::swap_exact_in(amount_in, reserve_in, reserve_out, fee).unwrap() >= amount_out);
+        prop_assert!(curve::swap_exact_in(amount_in - 1, reserve_in, reserve_out, fee).unwrap() < amount_out);
+    }
+
+    #[test]
+    fn swap_round_trip_never_profits(
+        reserve_a in reserve(),
+        reserve_b in reserve(),
+        amount_in in 1u64..=1_000_000_000_000,
+        fee in fee_bps(),
+    ) {
+        let amount_b = curve::swap_exact_in(amount_in, reserve_a, reserve_b, fee).unwrap();
+        let amount_a = curve::swap_exact_in(amount_b, reserve_b - amount_b, reserve_a + amount_in, fee).unwrap();
+        prop_assert!(amount_a <= amount_in);
+    }
+
+    #[test]
+    fn removing_all_lp_never_returns_more_than_deposited(
+        amount_a in 1u64..=u64::MAX / 2,
+        amount_b in 1u64..=u64::MAX / 2,
+    ) {
+        let lp_supply = curve::initial_lp_supply(amount_a, amount_b).unwrap();
+        prop_assume!(lp_supply > MINIMUM_LIQUIDITY);
+
+        let (out_a, out_b) = curve::withdraw_amounts(lp_supply - MINIMUM_LIQUIDITY, amount_a, amount_b, lp_supply).unwrap();
+        prop_assert!(out_a <= amount_a && out_b <= amount_b);
+        // The locked minimum liquidity keeps both reserves non-zero.
+        prop_assert!(out_a < amount_a || out_b < amount_b);
+    }
+
+    #[test]
+    fn deposit_then_withdraw_never_profits(
+        reserve_a in reserve(),
+        reserve_b in reserve(),
+        lp_supply in reserve(),
+        max_a in 1u64..=1_000_000_000_000,
+        max_b in 1u64..=1_000_000_000_000,
+    ) {
+        let (amount_a, amount_b) = curve::deposit_amounts(max_a, max_b, reserve_a, reserve_b).unwrap();
+        prop_assert!(amount_a <= max_a && amount_b <= max_b);
+
+        let minted = curve::lp_tokens_for_deposit(amount_a, amount_b, reserve_a, reserve_b, lp_supply).unwrap();
+        let (out_a, out_b) = curve::withdraw_amounts(
+            minted,
+            reserve_a + amount_a,
+            reserve_b + amount_b,
+            lp_supply + minted,
+        ).unwrap();
+        prop_assert!(out_a <= amount_a && out_b <= amount_b);
+    }
+
+    #[test]
+    fn sqrt_is_floor(value in any::<u128>()) {
+        let root = curve::integer_sqrt(value);
+        prop_assert!(root * root <= value);
+        prop_assert!((root + 1).checked_mul(root + 1).is_none_or(|next| next > value));
+    }
+}
+
+#[test]
+fn overflow_is_an_error_not_a_wrap() {
+    assert_eq!(
+        curve::swap_exact_in(u64::MAX, u64::MAX, u64::MAX, 0),
+        Err(CurveError::MathOverflow)
+    );
+    assert_eq!(
+        curve::swap_exact_out(u64::MAX - 1, u64::MAX, u64::MAX, 0),
+        Err(CurveError::MathOverflow)
+    );
+    assert_eq!(curve::initial_lp_supply(u64::MAX, u64::MAX), Ok(u64::MAX));
+}
+
+#[test]
+fn rejects_degenerate_inputs() {
+    assert_eq!(curve::swap_exact_in(1, 0, 1, 0), Err(CurveError::ZeroReserves));
+    assert_eq!(curve::swap_exact_in(1, 1, 1, 10_000), Err(CurveError::InvalidFee));
+    assert_eq!(curve::swap_exact_out(10, 10, 10, 0), Err(CurveError::InsufficientLiquidity));
+    assert_eq!(curve::withdraw_amounts(1, 10, 10, 0), Err(CurveError::InsufficientLiquidity));
+    assert_eq!(curve::deposit_amounts(1, 1, 0, 10), Err(CurveError::ZeroReserves));
+}
@@ -0,0 +1,409 @@
+mod common;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use common::{registry_accounts_with, run, ConcentratedPoolFixture, DistributionFixture, FarmFixture, PoolFixture, TestAccount, NOW};
+use liquidity_pool::distribution::{Distribution, Holding};
+use liquidity_pool::registry::RegistryPage;
+use liquidity_pool::{LiquidityPoolError, LiquidityPoolInstruction, PoolState};
+use solana_program::{program_error::ProgramError, pubkey::Pubkey, rent::Rent};
+
+fn lock(amount: u64) -> LiquidityPoolInstruction {
+    LiquidityPoolInstruction::LockPropertyTokens { amount }
+}
+
+fn unlock(amount: u64) -> LiquidityPoolInstruction {
+    LiquidityPoolInstruction::UnlockPropertyTokens { amount }
+}
+
+#[test]
+fn holders_claim_pro_rata() {
+    let mut fixture = DistributionFixture::new();
+    let mut alice = fixture.holder(1_000);
+    let mut bob = fixture.holder(3_000);
+    assert_eq!(fixture.run_holder(&mut alice, lock(1_000)), Ok(()));
+    assert_eq!(fixture.run_holder(&mut bob, lock(3_000)), Ok(()));
+    assert_eq!(fixture.property_vault.token_amount(), 4_000);
+
+    assert_eq!(fixture.deposit(8_000, &mut []), Ok(()));
+    assert_eq!(fixture.claim(&mut alice), Ok(()));
+    assert_eq!(fixture.claim(&mut bob), Ok(()));
+    assert_eq!(alice.quote.token_amount(), 2_000);
+    assert_eq!(bob.quote.token_amount(), 6_000);
+
+    // Claiming again pays nothing more.
+    assert_eq!(fixture.claim(&mut alice), Ok(()));
+    assert_eq!(alice.quote.token_amount(), 2_000);
+}
+
+#[test]
+fn transferred_tokens_cannot_claim_twice() {
+    let mut fixture = DistributionFixture::new();
+    let mut alice = fixture.holder(1_000);
+    let mut bob = fixture.holder(0);
+    assert_eq!(fixture.run_holder(&mut alice, lock(1_000)), Ok(()));
+    assert_eq!(fixture.deposit(1_000, &mut []), Ok(()));
+
+    // Alice unlocks and hands her tokens to Bob, who locks them.
+    assert_eq!(fixture.run_holder(&mut alice, unlock(1_000)), Ok(()));
+    bob.property.set_token_amount(alice.property.token_amount());
+    alice.property.set_token_amount(0);
+    assert_eq!(fixture.run_holder(&mut bob, lock(1_000)), Ok(()));
+
+    assert_eq!(fixture.claim(&mut alice), Ok(()));
+    assert_eq!(fixture.claim(&mut bob), Ok(()));
+    assert_eq!(alice.quote.token_amount(), 1_000);
+    assert_eq!(bob.quote.token_amount(), 0);
+
+    assert_eq!(fixture.deposit(500, &mut []), Ok(()));
+    assert_eq!(fixture.claim(&mut bob), Ok(()));
+    assert_eq!(bob.quote.token_amount(), 500);
+}
+
+#[test]
+fn income_without_holders_waits_for_the_next_deposit() {
+    let mut fixture = DistributionFixture::new();
+    assert_eq!(fixture.deposit(1_000, &mut []), Ok(()));
+    assert_eq!(fixture.state().unallocated_income, 1_000);
+
+    let mut alice = fixture.holder(1_000);
+    assert_eq!(fixture.run_holder(&mut alice, lock(1_000)), Ok(()));
+    assert_eq!(fixture.deposit(1_000, &mut []), Ok(()));
+    assert_eq!(fixture.claim(&mut alice), Ok(()));
+    assert_eq!(alice.quote.token_amount(), 2_000);
+    assert_eq!(fixture.state().unallocated_income, 0);
+}
+
+#[test]
+fn only_the_manager_deposits() {
+    let mut fixture = DistributionFixture::new();
+    fixture.manager = TestAccount::signer();
+    assert_eq!(fixture.deposit(1_000, &mut []), Err(LiquidityPoolError::Unauthorized.into()));
+}
+
+#[test]
+fn unlocking_keeps_earned_income() {
+    let mut fixture = DistributionFixture::new();
+    let mut alice = fixture.holder(1_000);
+    assert_eq!(fixture.run_holder(&mut alice, lock(1_000)), Ok(()));
+    assert_eq!(fixture.deposit(1_000, &mut []), Ok(()));
+
+    assert_eq!(fixture.run_holder(&mut alice, unlock(1_001)), Err(LiquidityPoolError::InsufficientFunds.into()));
+    assert_eq!(fixture.run_holder(&mut alice, unlock(1_000)), Ok(()));
+    assert_eq!(alice.property.token_amount(), 1_000);
+    assert_eq!(Holding::try_from_slice(&alice.holding.data).unwrap().income_owed, 1_000);
+    assert_eq!(fixture.state().total_eligible, 0);
+}
+
+#[test]
+fn creates_the_distribution_and_holdings_in_program() {
+    let mut fixture = DistributionFixture::new();
+    let rent = Rent::default();
+    assert_eq!(fixture.distribution.owner, liquidity_pool::id());
+    assert!(rent.is_exempt(fixture.distribution.lamports, Distribution::LEN));
+
+    let mut alice = fixture.holder(1_000);
+    let lamports_before = alice.user.lamports;
+    assert_eq!(fixture.run_holder(&mut alice, lock(1_000)), Ok(()));
+    assert_eq!(alice.holding.owner, liquidity_pool::id());
+    assert_eq!(alice.user.lamports, lamports_before - rent.minimum_balance(Holding::LEN));
+}
+
+/// Runs `ClaimPoolIncome` for the pool and holding in `pools`, paying into
+/// `destination`, and returns the resulting accounts.
+fn claim_pool_income(
+    fixture: &DistributionFixture,
+    pools: &[TestAccount],
+    destination: &TestAccount,
+    farm: Option<&TestAccount>,
+) -> Result<Vec<TestAccount>, ProgramError> {
+    let mut accounts = vec![
+        fixture.distribution.clone(),
+        pools[0].clone(),
+        pools[1].clone(),
+        fixture.quote_vault.clone(),
+        destination.clone(),
+        fixture.token_program.clone(),
+    ];
+    accounts.extend(farm.cloned());
+    run(&mut accounts, LiquidityPoolInstruction::ClaimPoolIncome)?;
+    Ok(accounts)
+}
+
+/// `pools` registered as the only pools, in order.
+fn register(fixture: &mut DistributionFixture, pools: &[&PoolFixture]) {
+    let (header, page) = registry_accounts_with(pools);
+    fixture.registry_header = header;
+    fixture.registry_pages = vec![page];
+}
+
+#[test]
+fn pools_earn_for_all_their_lps() {
+    // The pool holds 1_000_000 of the property token as token A against the
+    // quote token as token B.
+    let pool = PoolFixture::new(1_000_000, 1_000_000, 1_000_000, 0, 0);
+    let mut fixture = DistributionFixture::with_mints(pool.mint_a.clone(), pool.mint_b.clone());
+    register(&mut fixture, &[&pool]);
+
+    let mut alice = fixture.holder(1_000_000);
+    assert_eq!(fixture.run_holder(&mut alice, lock(1_000_000)), Ok(()));
+    let mut pools = [pool.pool_state.clone(), fixture.holding_account(&pool.pool_state.key)];
+    // A pool has no average reserve at its first sync, so earns from the next
+    // deposit on
+    assert_eq!(fixture.deposit(1_000_000, &mut pools), Ok(()));
+    assert_eq!(fixture.state().total_eligible, 1_000_000);
+    DistributionFixture::advance(&mut pools, 100);
+    assert_eq!(fixture.deposit(2_000_000, &mut pools), Ok(()));
+    assert_eq!(fixture.state().total_eligible, 2_000_000);
+
+    let accounts = claim_pool_income(&fixture, &pools, &pool.vault_b, None).unwrap();
+    // The income joins the quote reserve, so every LP token is worth more.
+    assert_eq!(accounts[4].token_amount(), 2_000_000);
+    assert_eq!(PoolState::try_from_slice(&accounts[1].data).unwrap().token_b_reserve, 2_000_000);
+
+    assert_eq!(fixture.claim(&mut alice), Ok(()));
+    assert_eq!(alice.quote.token_amount(), 2_000_000);
+}
+
+#[test]
+fn pools_earn_on_their_average_reserve() {
+    let pool = PoolFixture::new(1_000_000, 1_000_000, 1_000_000, 0, 0);
+    let mut fixture = DistributionFixture::with_mints(pool.mint_a.clone(), pool.mint_b.clone());
+    register(&mut fixture, &[&pool]);
+    let mut pools = [pool.pool_state.clone(), fixture.holding_account(&pool.pool_state.key)];
+    assert_eq!(fixture.deposit(1_000, &mut pools), Ok(()));
+
+    // A swap tenfolding the pool's property reserve right before the deposit
+    // does not move its share
+    DistributionFixture::advance(&mut pools, 100);
+    let mut state = PoolState::try_from_slice(&pools[0].data).unwrap();
+    state.update_price_accumulators(NOW);
+    state.token_a_reserve = 10_000_000;
+    state.serialize(&mut &mut pools[0].data[..]).unwrap();
+    assert_eq!(fixture.deposit(1_000, &mut pools), Ok(()));
+    assert_eq!(Holding::try_from_slice(&pools[1].data).unwrap().amount, 1_000_000);
+
+    // Held for half of the next period, it counts for half
+    DistributionFixture::advance(&mut pools, 50);
+    let mut state = PoolState::try_from_slice(&pools[0].data).unwrap();
+    state.update_price_accumulators(NOW);
+    state.token_a_reserve = 0;
+    state.serialize(&mut &mut pools[0].data[..]).unwrap();
+    DistributionFixture::advance(&mut pools, 50);
+    assert_eq!(fixture.deposit(1_000, &mut pools), Ok(()));
+    assert_eq!(fixture.state().total_eligible, 5_000_000);
+}
+
+#[test]
+fn deposits_sync_every_registered_pool_of_the_property() {
+    let pool = PoolFixture::new(1_000_000, 1_000_000, 1_000_000, 0, 0);
+    let other_pair = PoolFixture::new(1_000_000, 1_000_000, 1_000_000, 0, 0);
+    // The property token as token B, against neither the quote token nor in
+    // canonical order
+    let other_mint = TestAccount::mint(Pubkey::new_unique(), u64::MAX / 2);
+    let against_other = PoolFixture::for_mints(other_mint, pool.mint_a.clone(), 1_000_000, 3_000_000, 1_000_000, 0, 0);
+    let mut fixture = DistributionFixture::with_mints(pool.mint_a.clone(), pool.mint_b.clone());
+    register(&mut fixture, &[&other_pair, &against_other, &pool]);
+
+    let mismatch = Err(LiquidityPoolError::PoolSetMismatch.into());
+    let mut pools = [pool.pool_state.clone(), fixture.holding_account(&pool.pool_state.key)];
+    assert_eq!(fixture.deposit(1_000, &mut pools), mismatch);
+    let mut pools = [
+        other_pair.pool_state.clone(),
+        fixture.holding_account(&other_pair.pool_state.key),
+        against_other.pool_state.clone(),
+        fixture.holding_account(&against_other.pool_state.key),
+        pool.pool_state.clone(),
+        fixture.holding_account(&pool.pool_state.key),
+    ];
+    assert_eq!(fixture.deposit(1_000, &mut pools), mismatch);
+
+    // Pools without the property token are not eligible, and need not be listed.
+    let mut pools = [
+        against_other.pool_state.clone(),
+        fixture.holding_account(&against_other.pool_state.key),
+        pool.pool_state.clone(),
+        fixture.holding_account(&pool.pool_state.key),
+    ];
+    assert_eq!(fixture.deposit(1_000, &mut pools), Ok(()));
+    DistributionFixture::advance(&mut pools, 100);
+    assert_eq!(fixture.deposit(1_000, &mut pools), Ok(()));
+    assert_eq!(fixture.state().total_eligible, 4_000_000);
+}
+
+#[test]
+fn pools_against_another_token_pay_through_their_farm() {
+    // The farm's pool trades the property token, as token A, against a token
+    // other than the quote token, which the farm rewards in.
+    let mut farm = FarmFixture::new(10, 0);
+    let mut fixture = DistributionFixture::with_mints(farm.pool.mint_a.clone(), farm.reward_mint.clone());
+    register(&mut fixture, &[&farm.pool]);
+    let mut alice = fixture.holder(1_000_000);
+    assert_eq!(fixture.run_holder(&mut alice, lock(1_000_000)), Ok(()));
+    let mut pools = [farm.pool.pool_state.clone(), fixture.holding_account(&farm.pool.pool_state.key)];
+    assert_eq!(fixture.deposit(1_000, &mut pools), Ok(()));
+    DistributionFixture::advance(&mut pools, 100);
+    assert_eq!(fixture.deposit(2_000_000, &mut pools), Ok(()));
+
+    assert_eq!(
+        claim_pool_income(&fixture, &pools, &farm.reward_vault, None).map(|_| ()),
+        Err(ProgramError::NotEnoughAccountKeys)
+    );
+    let mut elsewhere = farm.reward_vault.clone();
+    elsewhere.key = Pubkey::new_unique();
+    assert_eq!(
+        claim_pool_income(&fixture, &pools, &elsewhere, Some(&farm.farm)).map(|_| ()),
+        Err(LiquidityPoolError::InvalidTokenAccount.into())
+    );
+
+    let accounts = claim_pool_income(&fixture, &pools, &farm.reward_vault, Some(&farm.farm)).unwrap();
+    farm.reward_vault = accounts[4].clone();
+    farm.farm = accounts[6].clone();
+    assert_eq!(farm.reward_vault.token_amount(), 1_000_000);
+    assert_eq!(farm.state().undistributed_rewards, 1_000_000);
+}
+
+#[test]
+fn concentrated_pools_credit_their_positions_in_range() {
+    let mut pool = ConcentratedPoolFixture::new(10, 0, 1_000_000_000);
+    let mut position = pool.open_position(-600, 600).unwrap();
+    let increase = LiquidityPoolInstruction::IncreaseLiquidity { liquidity: 1_000_000_000, max_amount_a: u64::MAX, max_amount_b: u64::MAX };
+    assert_eq!(pool.run_position(&mut position, increase), Ok(()));
+    let reserve = pool.state().reserve_a;
+    assert_eq!(reserve, pool.vault_a.token_amount());
+
+    let mut fixture = DistributionFixture::with_mints(pool.mint_a.clone(), pool.mint_b.clone());
+    fixture.registry_header = pool.registry_header.clone();
+    fixture.registry_pages = vec![pool.registry_page.clone()];
+    let mut alice = fixture.holder(reserve);
+    assert_eq!(fixture.run_holder(&mut alice, lock(reserve)), Ok(()));
+    let mut pools = [pool.pool_state.clone(), fixture.holding_account(&pool.pool_state.key)];
+    assert_eq!(fixture.deposit(1_000, &mut pools), Ok(()));
+    DistributionFixture::advance(&mut pools, 100);
+    assert_eq!(fixture.deposit(2_000_000, &mut pools), Ok(()));
+    assert_eq!(fixture.state().total_eligible, 2 * reserve);
+
+    let accounts = claim_pool_income(&fixture, &pools, &pool.vault_b, None).unwrap();
+    pool.pool_state = accounts[1].clone();
+    pool.vault_b = accounts[4].clone();
+    assert_eq!(pool.state().reserve_b, pool.vault_b.token_amount());
+
+    let balance_b = pool.user_b.token_amount();
+    assert_eq!(pool.run_position(&mut position, LiquidityPoolInstruction::CollectPosition), Ok(()));
+    // Fee growth rounds down in the pool's favour
+    let collected = pool.user_b.token_amount() - balance_b;
+    assert!((1_000_000 - 2..1_000_000).contains(&collected), "collected {collected}");
+}
+
+#[test]
+fn closed_pools_are_not_synced() {
+    let pool = PoolFixture::new(1_000_000, 1_000_000, 1_000_000, 0, 0);
+    let mut fixture = DistributionFixture::with_mints(pool.mint_a.clone(), pool.mint_b.clone());
+    // An earlier pool of the pair, closed and reopened at the same address
+    let (header, mut page) = registry_accounts_with(&[&pool, &pool]);
+    let mut registry_page = RegistryPage::deserialize(&mut &page.data[..]).unwrap();
+    registry_page.entries[0].pool = Pubkey::default();
+    registry_page.serialize(&mut &mut page.data[..]).unwrap();
+    fixture.registry_header = header;
+    fixture.registry_pages = vec![page];
+
+    let mut pools = [pool.pool_state.clone(), fixture.holding_account(&pool.pool_state.key)];
+    assert_eq!(fixture.deposit(1_000, &mut pools), Ok(()));
+    DistributionFixture::advance(&mut pools, 100);
+    assert_eq!(fixture.deposit(1_000, &mut pools), Ok(()));
+    assert_eq!(fixture.state().total_eligible, 1_000_000);
+}
+
+/// The registry header and page for `pool` reopened after closing: its closed
+/// entry, then a new one created in `created_slot`.
+fn reopened(pool: &PoolFixture, created_slot: u64) -> (TestAccount, TestAccount) {
+    let (header, mut page) = registry_accounts_with(&[pool, pool]);
+    let mut registry_page = RegistryPage::deserialize(&mut &page.data[..]).unwrap();
+    registry_page.entries[0].pool = Pubkey::default();
+    registry_page.entries[1].created_slot = created_slot;
+    registry_page.serialize(&mut &mut page.data[..]).unwrap();
+    (header, page)
+}
+
+#[test]
+fn a_reopened_pool_does_not_inherit_its_old_holding() {
+    let pool = PoolFixture::new(1_000_000, 1_000_000, 1_000_000, 0, 0);
+    let mut fixture = DistributionFixture::with_mints(pool.mint_a.clone(), pool.mint_b.clone());
+    register(&mut fixture, &[&pool]);
+    let mut alice = fixture.holder(1_000_000);
+    assert_eq!(fixture.run_holder(&mut alice, lock(1_000_000)), Ok(()));
+    let mut pools = [pool.pool_state.clone(), fixture.holding_account(&pool.pool_state.key)];
+    assert_eq!(fixture.deposit(1_000_000, &mut pools), Ok(()));
+    DistributionFixture::advance(&mut pools, 100);
+    assert_eq!(fixture.deposit(2_000_000, &mut pools), Ok(()));
+
+    // The pool closes with its income unclaimed, and misses a deposit
+    let (_, closed_page) = reopened(&pool, 0);
+    let mut closed_page_data = RegistryPage::deserialize(&mut &closed_page.data[..]).unwrap();
+    closed_page_data.entries.truncate(1);
+    let mut closed_page = closed_page;
+    closed_page_data.serialize(&mut &mut closed_page.data[..]).unwrap();
+    fixture.registry_pages = vec![closed_page];
+    assert_eq!(fixture.deposit(1_000_000, &mut []), Ok(()));
+    assert_eq!(fixture.state().total_eligible, 1_000_000);
+
+    // Reopened at the same address, it has nothing to claim until synced
+    let (header, page) = reopened(&pool, 0);
+    fixture.registry_header = header;
+    fixture.registry_pages = vec![page];
+    let paid = |fixture: &DistributionFixture, pools: &[TestAccount]| {
+        claim_pool_income(fixture, pools, &pool.vault_b, None).map(|accounts| accounts[4].token_amount())
+    };
+    assert_eq!(paid(&fixture, &pools), Ok(1_000_000));
+
+    // The old LPs' income is shared out again with the next deposit, which the
+    // pool earns from as a new holder from the one after
+    DistributionFixture::advance(&mut pools, 100);
+    assert_eq!(fixture.deposit(1_000_000, &mut pools), Ok(()));
+    assert_eq!(fixture.state().total_eligible, 1_000_000);
+    assert_eq!(paid(&fixture, &pools), Ok(1_000_000));
+    DistributionFixture::advance(&mut pools, 100);
+    assert_eq!(fixture.deposit(1_000_000, &mut pools), Ok(()));
+    assert_eq!(fixture.state().total_eligible, 2_000_000);
+    assert_eq!(paid(&fixture, &pools), Ok(1_500_000));
+
+    assert_eq!(fixture.claim(&mut alice), Ok(()));
+    assert_eq!(alice.quote.token_amount(), 1_000_000 + 1_000_000 + 1_000_000 + 2_000_000 + 500_000);
+}
+
+#[test]
+fn a_pool_reopened_between_deposits_starts_over() {
+    let pool = PoolFixture::new(1_000_000, 1_000_000, 1_000_000, 0, 0);
+    let mut fixture = DistributionFixture::with_mints(pool.mint_a.clone(), pool.mint_b.clone());
+    register(&mut fixture, &[&pool]);
+    let mut alice = fixture.holder(1_000_000);
+    assert_eq!(fixture.run_holder(&mut alice, lock(1_000_000)), Ok(()));
+    let mut pools = [pool.pool_state.clone(), fixture.holding_account(&pool.pool_state.key)];
+    assert_eq!(fixture.deposit(1_000_000, &mut pools), Ok(()));
+    DistributionFixture::advance(&mut pools, 100);
+    assert_eq!(fixture.deposit(2_000_000, &mut pools), Ok(()));
+
+    // Closed and reopened without missing a deposit, the pool is told apart
+    // by the creation slot of its new registry entry
+    let (header, page) = reopened(&pool, 1);
+    fixture.registry_header = header;
+    fixture.registry_pages = vec![page];
+    DistributionFixture::advance(&mut pools, 100);
+    assert_eq!(fixture.deposit(1_000_000, &mut pools), Ok(()));
+    assert_eq!(Holding::try_from_slice(&pools[1].data).unwrap().income_owed, 0);
+
+    assert_eq!(fixture.claim(&mut alice), Ok(()));
+    assert_eq!(alice.quote.token_amount(), 1_000_000 + 1_000_000 + 2_000_000);
+}
+
+#[test]
+fn rejects_a_distribution_or_holding_of_another_type() {
+    let mut fixture = DistributionFixture::new();
+    let mut alice = fixture.holder(1_000);
+    assert_eq!(fixture.run_holder(&mut alice, lock(1_000)), Ok(()));
+
+    alice.holding.data[..8].copy_from_slice(&[0; 8]);
+    assert_eq!(fixture.claim(&mut alice), Err(LiquidityPoolError::InvalidAccountType.into()));
+
+    fixture.distribution.data[..8].copy_from_slice(&[0; 8]);
+    assert_eq!(fixture.deposit(1_000, &mut []), Err(LiquidityPoolError::InvalidAccountType.into()));
+}
@@ -0,0 +1,199 @@
+mod common;
+
+use borsh::BorshDeserialize;
+use common::{FarmFixture, TestAccount};
+use liquidity_pool::farm::{self, Farm, Stake};
+use liquidity_pool::{LiquidityPoolError, LiquidityPoolInstruction};
+use solana_program::{program_error::ProgramError, rent::Rent, system_program};
+
+fn stake(amount: u64) -> LiquidityPoolInstruction {
+    LiquidityPoolInstruction::Stake { amount }
+}
+
+#[test]
+fn only_the_pool_creator_creates_the_farm() {
+    let fixture = FarmFixture::uninitialized();
+    let mut accounts = fixture.initialize_accounts();
+    accounts[0] = TestAccount::signer();
+    assert_eq!(
+        common::run(&mut accounts, LiquidityPoolInstruction::InitializeFarm { emission_rate: 1 }),
+        Err(LiquidityPoolError::Unauthorized.into())
+    );
+
+    let fixture = FarmFixture::new(100, 0);
+    let mut accounts = fixture.initialize_accounts();
+    assert_eq!(
+        common::run(&mut accounts, LiquidityPoolInstruction::InitializeFarm { emission_rate: 1 }),
+        Err(LiquidityPoolError::PoolAlreadyInitialized.into())
+    );
+}
+
+#[test]
+fn creates_the_farm_and_stakes_in_program() {
+    let mut fixture = FarmFixture::new(100, 0);
+    let rent = Rent::default();
+    assert_eq!(fixture.farm.owner, liquidity_pool::id());
+    assert!(rent.is_exempt(fixture.farm.lamports, Farm::LEN));
+
+    let mut alice = fixture.staker(1_000);
+    let lamports_before = alice.user.lamports;
+    assert_eq!(fixture.run_stake(&mut alice, stake(1_000)), Ok(()));
+    assert_eq!(alice.stake.owner, liquidity_pool::id());
+    assert_eq!(alice.stake.data.len(), Stake::LEN);
+    assert_eq!(alice.user.lamports, lamports_before - rent.minimum_balance(Stake::LEN));
+}
+
+#[test]
+fn stakers_split_emissions_by_stake() {
+    let mut fixture = FarmFixture::new(100, 1_000_000);
+    let mut alice = fixture.staker(1_000);
+    let mut bob = fixture.staker(3_000);
+    assert_eq!(fixture.run_stake(&mut alice, stake(1_000)), Ok(()));
+    assert_eq!(fixture.run_stake(&mut bob, stake(3_000)), Ok(()));
+    assert_eq!(fixture.state().total_staked, 4_000);
+    assert_eq!(fixture.staked_vault.token_amount(), 4_000);
+
+    fixture.advance(100);
+    assert_eq!(fixture.claim(&mut alice), Ok(()));
+    assert_eq!(fixture.claim(&mut bob), Ok(()));
+    assert_eq!(alice.reward.token_amount(), 2_500);
+    assert_eq!(bob.reward.token_amount(), 7_500);
+    assert_eq!(fixture.state().undistributed_rewards, 1_000_000 - 10_000);
+}
+
+#[test]
+fn emission_stops_when_funding_runs_out() {
+    let mut fixture = FarmFixture::new(100, 1_000);
+    let mut alice = fixture.staker(1_000);
+    assert_eq!(fixture.run_stake(&mut alice, stake(1_000)), Ok(()));
+
+    fixture.advance(100);
+    assert_eq!(fixture.claim(&mut alice), Ok(()));
+    assert_eq!(alice.reward.token_amount(), 1_000);
+    assert_eq!(fixture.state().undistributed_rewards, 0);
+    assert_eq!(fixture.reward_vault.token_amount(), 0);
+}
+
+#[test]
+fn nothing_is_emitted_while_nobody_is_staked() {
+    let mut fixture = FarmFixture::new(100, 1_000_000);
+    fixture.advance(100);
+    let mut alice = fixture.staker(1_000);
+    assert_eq!(fixture.run_stake(&mut alice, stake(1_000)), Ok(()));
+    assert_eq!(fixture.claim(&mut alice), Ok(()));
+
+    assert_eq!(alice.reward.token_amount(), 0);
+    assert_eq!(fixture.state().undistributed_rewards, 1_000_000);
+}
+
+#[test]
+fn rate_changes_apply_from_now_on() {
+    let mut fixture = FarmFixture::new(100, 1_000_000);
+    let mut alice = fixture.staker(1_000);
+    assert_eq!(fixture.run_stake(&mut alice, stake(1_000)), Ok(()));
+
+    fixture.advance(10);
+    assert_eq!(fixture.run_admin(LiquidityPoolInstruction::SetEmissionRate { emission_rate: 10 }), Ok(()));
+    fixture.advance(10);
+    assert_eq!(fixture.claim(&mut alice), Ok(()));
+    // Both accruals round down in the farm's favour.
+    assert_eq!(alice.reward.token_amount(), 1_000 + 100 - 1);
+
+    fixture.pool.user = TestAccount::signer();
+    assert_eq!(
+        fixture.run_admin(LiquidityPoolInstruction::SetEmissionRate { emission_rate: 0 }),
+        Err(LiquidityPoolError::Unauthorized.into())
+    );
+}
+
+#[test]
+fn unstaking_keeps_earned_rewards() {
+    let mut fixture = FarmFixture::new(100, 1_000_000);
+    let mut alice = fixture.staker(1_000);
+    assert_eq!(fixture.run_stake(&mut alice, stake(1_000)), Ok(()));
+
+    fixture.advance(10);
+    assert_eq!(
+        fixture.run_stake(&mut alice, LiquidityPoolInstruction::Unstake { amount: 1_001 }),
+        Err(LiquidityPoolError::InsufficientFunds.into())
+    );
+    assert_eq!(fixture.run_stake(&mut alice, LiquidityPoolInstruction::Unstake { amount: 1_000 }), Ok(()));
+    assert_eq!(alice.lp.token_amount(), 1_000);
+    assert_eq!(Stake::try_from_slice(&alice.stake.data).unwrap().rewards_owed, 1_000);
+
+    // No stake left, so nothing more accrues.
+    fixture.advance(10);
+    assert_eq!(fixture.claim(&mut alice), Ok(()));
+    assert_eq!(alice.reward.token_amount(), 1_000);
+}
+
+#[test]
+fn stakes_belong_to_their_owner() {
+    let mut fixture = FarmFixture::new(100, 1_000_000);
+    let mut alice = fixture.staker(1_000);
+    assert_eq!(fixture.run_stake(&mut alice, stake(1_000)), Ok(()));
+
+    let mut mallory = fixture.staker(0);
+    mallory.stake = alice.stake.clone();
+    assert_eq!(fixture.claim(&mut mallory), Err(ProgramError::InvalidSeeds));
+    assert_eq!(
+        alice.stake.key,
+        farm::stake_address(&liquidity_pool::id(), &fixture.farm.key, &alice.user.key).0
+    );
+}
+
+#[test]
+fn rejects_a_farm_or_stake_of_another_type() {
+    let mut fixture = FarmFixture::new(100, 1_000_000);
+    let mut alice = fixture.staker(1_000);
+    assert_eq!(fixture.run_stake(&mut alice, stake(1_000)), Ok(()));
+
+    let mut forged = alice.stake.clone();
+    forged.data[..8].copy_from_slice(&[0; 8]);
+    let stake_account = std::mem::replace(&mut alice.stake, forged);
+    assert_eq!(fixture.claim(&mut alice), Err(LiquidityPoolError::InvalidAccountType.into()));
+    alice.stake = stake_account;
+
+    fixture.farm.data[..8].copy_from_slice(&[0; 8]);
+    assert_eq!(fixture.claim(&mut alice), Err(LiquidityPoolError::InvalidAccountType.into()));
+    let mut accounts = fixture.initialize_accounts();
+    assert_eq!(
+        common::run(&mut accounts, LiquidityPoolInstruction::InitializeFarm { emission_rate: 1 }),
+        Err(LiquidityPoolError::InvalidAccountType.into())
+    );
+}
+
+#[test]
+fn closes_a_farm_once_every_stake_has_claimed() {
+    let mut fixture = FarmFixture::new(100, 1_000_000);
+    let mut alice = fixture.staker(1_000);
+    assert_eq!(fixture.run_stake(&mut alice, stake(1_000)), Ok(()));
+    fixture.advance(10);
+    assert_eq!(fixture.close(), Err(LiquidityPoolError::FarmNotEmpty.into()));
+
+    // Unstaked but not yet claimed, the rewards still belong to alice
+    assert_eq!(fixture.run_stake(&mut alice, LiquidityPoolInstruction::Unstake { amount: 1_000 }), Ok(()));
+    assert_eq!(fixture.state().unclaimed_rewards, 1_000);
+    assert_eq!(fixture.close(), Err(LiquidityPoolError::FarmNotEmpty.into()));
+    assert_eq!(fixture.claim(&mut alice), Ok(()));
+    assert_eq!(fixture.state().unclaimed_rewards, 0);
+
+    let admin_reward_before = fixture.admin_reward.token_amount();
+    let refund = fixture.farm.lamports + fixture.staked_vault.lamports + fixture.reward_vault.lamports;
+    let lamports_before = fixture.pool.user.lamports;
+    assert_eq!(fixture.close(), Ok(()));
+    assert_eq!(fixture.admin_reward.token_amount(), admin_reward_before + 1_000_000 - 1_000);
+    assert_eq!(fixture.pool.user.lamports, lamports_before + refund);
+    for account in [&fixture.farm, &fixture.staked_vault, &fixture.reward_vault] {
+        assert_eq!(account.lamports, 0);
+        assert_eq!(account.owner, system_program::id());
+    }
+    assert!(fixture.farm.data.is_empty());
+}
+
+#[test]
+fn only_the_admin_closes_the_farm() {
+    let mut fixture = FarmFixture::new(100, 0);
+    fixture.pool.user = TestAccount::signer();
+    assert_eq!(fixture.close(), Err(LiquidityPoolError::Unauthorized.into()));
+}
@@ -0,0 +1,179 @@
+mod common;
+
+use borsh::BorshDeserialize;
+use common::{
+    fee_config_account, initialize_pool_accounts, initialize_pool_accounts_for_mints, registry_accounts, run, PoolFixture,
+    TestAccount,
+};
+use liquidity_pool::fee_config::{self, FeeConfig, MAX_FEE_BPS, MAX_FEE_TIERS};
+use liquidity_pool::{curve, CurveType, LiquidityPoolError, LiquidityPoolInstruction, PoolState};
+use solana_program::{bpf_loader_upgradeable, program_error::ProgramError, pubkey::Pubkey, rent::Rent, system_program};
+
+const TIERS: [u64; 3] = [5, 30, 100];
+
+fn initialize_pool(fee_bps: u64) -> LiquidityPoolInstruction {
+    LiquidityPoolInstruction::InitializePool {
+        initial_amount_a: 1_000_000,
+        initial_amount_b: 2_000_000,
+        curve_type: CurveType::ConstantProduct,
+        amp: 0,
+        fee_bps,
+    }
+}
+
+/// The program's program data account, recording `upgrade_authority`.
+fn program_data(upgrade_authority: Option<Pubkey>) -> TestAccount {
+    let mut data = 3u32.to_le_bytes().to_vec();
+    data.extend(0u64.to_le_bytes());
+    match upgrade_authority {
+        Some(authority) => data.extend([&[1][..], authority.as_ref()].concat()),
+        None => data.extend([0; 33]),
+    }
+    let mut account = TestAccount::new(bpf_loader_upgradeable::id(), data);
+    account.key = Pubkey::find_program_address(&[liquidity_pool::id().as_ref()], &bpf_loader_upgradeable::id()).0;
+    account
+}
+
+fn fee_config(account: &TestAccount) -> FeeConfig {
+    FeeConfig::deserialize(&mut &account.data[..]).unwrap()
+}
+
+#[test]
+fn initializes_the_fee_config_once() {
+    let program_id = liquidity_pool::id();
+    let admin = TestAccount { lamports: 1_000_000_000, ..TestAccount::signer() };
+    let config = TestAccount::unallocated(fee_config::fee_config_address(&program_id).0);
+    let mut accounts =
+        vec![admin.clone(), config, TestAccount::program(system_program::id()), program_data(Some(admin.key))];
+
+    let initialize = |fee_tiers: Vec<u64>| LiquidityPoolInstruction::InitializeFeeConfig { fee_tiers };
+    assert_eq!(run(&mut accounts, initialize(TIERS.to_vec())), Ok(()));
+    assert_eq!(accounts[1].owner, program_id);
+    assert!(Rent::default().is_exempt(accounts[1].lamports, FeeConfig::LEN));
+    assert_eq!(fee_config(&accounts[1]).admin, admin.key);
+    assert_eq!(fee_config(&accounts[1]).fee_tiers, TIERS);
+
+    assert_eq!(
+        run(&mut accounts, initialize(vec![1])),
+        Err(LiquidityPoolError::PoolAlreadyInitialized.into())
+    );
+}
+
+#[test]
+fn only_the_upgrade_authority_initializes_the_fee_config() {
+    let program_id = liquidity_pool::id();
+    let admin = TestAccount { lamports: 1_000_000_000, ..TestAccount::signer() };
+    let config = TestAccount::unallocated(fee_config::fee_config_address(&program_id).0);
+    let initialize = || LiquidityPoolInstruction::InitializeFeeConfig { fee_tiers: TIERS.to_vec() };
+
+    for upgrade_authority in [Some(Pubkey::new_unique()), None] {
+        let mut accounts =
+            vec![admin.clone(), config.clone(), TestAccount::program(system_program::id()), program_data(upgrade_authority)];
+        assert_eq!(run(&mut accounts, initialize()), Err(LiquidityPoolError::Unauthorized.into()));
+    }
+
+    let mut forged = program_data(Some(admin.key));
+    forged.key = Pubkey::new_unique();
+    let mut accounts = vec![admin.clone(), config, TestAccount::program(system_program::id()), forged];
+    assert_eq!(run(&mut accounts, initialize()), Err(ProgramError::InvalidSeeds));
+}
+
+#[test]
+fn only_the_admin_sets_valid_tiers() {
+    let admin = TestAccount::signer();
+    let mut accounts = vec![admin.clone(), fee_config_account(admin.key, &TIERS)];
+    let set = |fee_tiers: Vec<u64>| LiquidityPoolInstruction::SetFeeTiers { fee_tiers };
+
+    for fee_tiers in [vec![], vec![30, 30], vec![MAX_FEE_BPS + 1], vec![1; MAX_FEE_TIERS + 1]] {
+        assert_eq!(
+            run(&mut accounts, set(fee_tiers.clone())),
+            Err(LiquidityPoolError::InvalidFeeTier.into()),
+            "{fee_tiers:?}"
+        );
+    }
+    assert_eq!(run(&mut accounts, set(vec![1, 25])), Ok(()));
+    assert_eq!(fee_config(&accounts[1]).fee_tiers, [1, 25]);
+
+    accounts[0] = TestAccount::signer();
+    assert_eq!(run(&mut accounts, set(vec![30])), Err(LiquidityPoolError::Unauthorized.into()));
+}
+
+#[test]
+fn one_pair_can_have_a_pool_per_tier() {
+    let config = fee_config_account(TestAccount::signer().key, &TIERS);
+    let (header, page) = registry_accounts(0);
+    let mut first = initialize_pool_accounts(&header, &page, &config, 5, 2_000_000);
+    assert_eq!(run(&mut first, initialize_pool(5)), Ok(()));
+
+    let mut second = initialize_pool_accounts_for_mints(&first[2], &first[3], &first[13], &first[14], &config, 100, 2_000_000);
+    assert_eq!(run(&mut second, initialize_pool(100)), Ok(()));
+
+    assert_ne!(first[1].key, second[1].key);
+    for (accounts, fee_bps) in [(&first, 5), (&second, 100)] {
+        assert_eq!(PoolState::try_from_slice(&accounts[1].data).unwrap().fee_bps, fee_bps);
+    }
+}
+
+#[test]
+fn rejects_a_fee_config_of_another_type() {
+    let mut config = fee_config_account(TestAccount::signer().key, &TIERS);
+    config.data[..8].copy_from_slice(&[0; 8]);
+    let (header, page) = registry_accounts(0);
+    let mut accounts = initialize_pool_accounts(&header, &page, &config, TIERS[0], 2_000_000);
+    assert_eq!(run(&mut accounts, initialize_pool(TIERS[0])), Err(LiquidityPoolError::InvalidAccountType.into()));
+}
+
+#[test]
+fn rejects_a_tier_not_in_the_config() {
+    let config = fee_config_account(TestAccount::signer().key, &TIERS);
+    let (header, page) = registry_accounts(0);
+    let mut accounts = initialize_pool_accounts(&header, &page, &config, 50, 2_000_000);
+    assert_eq!(run(&mut accounts, initialize_pool(50)), Err(LiquidityPoolError::InvalidFeeTier.into()));
+}
+
+#[test]
+fn swaps_charge_the_pool_fee() {
+    let config = fee_config_account(TestAccount::signer().key, &TIERS);
+    let (header, page) = registry_accounts(0);
+    let mut accounts = initialize_pool_accounts(&header, &page, &config, 100, 2_000_000);
+    assert_eq!(run(&mut accounts, initialize_pool(100)), Ok(()));
+
+    let expected = curve::swap_exact_in(10_000, 1_000_000, 2_000_000, 100).unwrap();
+    let mut swap_accounts: Vec<TestAccount> = [0, 1, 4, 5, 7, 8, 10].iter().map(|&i| accounts[i].clone()).collect();
+    let swap = LiquidityPoolInstruction::Swap { amount_in: 10_000, min_out: expected, a_to_b: true };
+    assert_eq!(run(&mut swap_accounts, swap), Ok(()));
+    assert_eq!(swap_accounts[5].token_amount(), expected);
+}
+
+#[test]
+fn quotes_pick_the_best_tier() {
+    let deep = PoolFixture::new(10_000_000, 20_000_000, 14_142_135, 0, 0).state();
+    let cheap_but_shallow = PoolState { fee_bps: 5, token_a_reserve: 100_000, token_b_reserve: 200_000, ..deep.clone() };
+    let pools = [deep.clone(), cheap_but_shallow.clone()];
+
+    // Small trades go to the cheaper tier, large ones to the deeper pool.
+    let (pool, out) = liquidity_pool::best_pool_for_exact_in(&pools, true, 100, 0).unwrap();
+    assert_eq!((pool.fee_bps, out), (5, curve::swap_exact_in(100, 100_000, 200_000, 5).unwrap()));
+    let (pool, _) = liquidity_pool::best_pool_for_exact_in(&pools, true, 50_000, 0).unwrap();
+    assert_eq!(pool.fee_bps, deep.fee_bps);
+
+    let (pool, amount_in) = liquidity_pool::best_pool_for_exact_out(&pools, true, 500_000, 0).unwrap();
+    assert_eq!(pool.fee_bps, deep.fee_bps);
+    assert_eq!(amount_in, curve::swap_exact_out(500_000, 10_000_000, 20_000_000, deep.fee_bps).unwrap());
+    assert!(liquidity_pool::best_pool_for_exact_out(&[cheap_but_shallow], true, 500_000, 0).is_none());
+}
+
+#[test]
+fn pools_created_before_fee_tiers_keep_their_address() {
+    let pool = PoolFixture::legacy_seeds(1_000_000, 2_000_000, 1_414_213, 100_000, 10_000);
+    assert_ne!(pool.pool_state.key, PoolFixture::new(1, 1, 1, 0, 0).pool_state.key);
+
+    // Both swaps and withdrawals are signed for with the seeds the pool was created at.
+    let mut accounts = pool.swap_accounts();
+    let swap = LiquidityPoolInstruction::Swap { amount_in: 1_000, min_out: 1, a_to_b: true };
+    assert_eq!(run(&mut accounts, swap), Ok(()));
+    let mut accounts = pool.liquidity_accounts();
+    let remove = LiquidityPoolInstruction::RemoveLiquidity { lp_token_amount: 10_000 };
+    assert_eq!(run(&mut accounts, remove), Ok(()));
+    assert_eq!(accounts[5].token_amount(), 100_000 + 10_000 * 1_000_000 / 1_414_213);
+}
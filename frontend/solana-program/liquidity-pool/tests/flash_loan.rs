@@ -0,0 +1,73 @@
+mod common;
+
+use borsh::BorshDeserialize;
+use common::{run, set_flash_loan_shortfall, PoolFixture, TestAccount};
+use liquidity_pool::curve;
+use liquidity_pool::{LiquidityPoolError, LiquidityPoolInstruction, PoolState, FLASH_LOAN_FEE_BPS};
+use solana_program::program_error::ProgramError;
+
+fn fixture() -> PoolFixture {
+    PoolFixture::new(1_000_000, 2_000_000, 1_414_213, 100_000, 0)
+}
+
+fn flash_loan(amount_a: u64, amount_b: u64) -> LiquidityPoolInstruction {
+    LiquidityPoolInstruction::FlashLoan { amount_a, amount_b }
+}
+
+#[test]
+fn fee_rounds_up() {
+    assert_eq!(curve::flash_loan_fee(0, FLASH_LOAN_FEE_BPS), Ok(0));
+    assert_eq!(curve::flash_loan_fee(1, FLASH_LOAN_FEE_BPS), Ok(1));
+    assert_eq!(curve::flash_loan_fee(10_000, FLASH_LOAN_FEE_BPS), Ok(9));
+    assert_eq!(curve::flash_loan_fee(10_001, FLASH_LOAN_FEE_BPS), Ok(10));
+}
+
+#[test]
+fn repaid_loan_pays_fees_to_the_reserves() {
+    let pool = fixture();
+    let mut accounts = pool.flash_loan_accounts();
+    assert_eq!(run(&mut accounts, flash_loan(500_000, 1_000_000)), Ok(()));
+
+    // 0.09% of each amount, rounded up.
+    let (fee_a, fee_b) = (450, 900);
+    assert_eq!(accounts[2].token_amount(), 1_000_000 + fee_a);
+    assert_eq!(accounts[3].token_amount(), 2_000_000 + fee_b);
+    assert_eq!(accounts[4].token_amount(), 100_000 - fee_a);
+    assert_eq!(accounts[5].token_amount(), 100_000 - fee_b);
+
+    let state = PoolState::try_from_slice(&accounts[1].data).unwrap();
+    assert_eq!((state.token_a_reserve, state.token_b_reserve), (1_000_000 + fee_a, 2_000_000 + fee_b));
+}
+
+#[test]
+fn rejects_a_short_repayment() {
+    let pool = fixture();
+    let mut accounts = pool.flash_loan_accounts();
+    set_flash_loan_shortfall(1);
+    assert_eq!(
+        run(&mut accounts, flash_loan(500_000, 0)),
+        Err(LiquidityPoolError::FlashLoanNotRepaid.into())
+    );
+    set_flash_loan_shortfall(0);
+}
+
+#[test]
+fn rejects_invalid_amounts() {
+    let pool = fixture();
+    assert_eq!(
+        run(&mut pool.flash_loan_accounts(), flash_loan(0, 0)),
+        Err(LiquidityPoolError::ZeroAmountNotAllowed.into())
+    );
+    assert_eq!(
+        run(&mut pool.flash_loan_accounts(), flash_loan(1_000_001, 0)),
+        Err(LiquidityPoolError::InsufficientFunds.into())
+    );
+}
+
+#[test]
+fn rejects_this_program_as_borrower() {
+    let pool = fixture();
+    let mut accounts = pool.flash_loan_accounts();
+    accounts[7] = TestAccount::program(liquidity_pool::id());
+    assert_eq!(run(&mut accounts, flash_loan(1_000, 0)), Err(ProgramError::IncorrectProgramId));
+}
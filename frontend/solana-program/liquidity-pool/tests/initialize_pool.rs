@@ -0,0 +1,107 @@
+mod common;
+
+use borsh::BorshDeserialize;
+use common::{fee_config_account, registry_accounts, run, TestAccount};
+use liquidity_pool::registry::{RegistryHeader, RegistryPage};
+use liquidity_pool::{CurveType, LiquidityPoolError, LiquidityPoolInstruction, PoolState, SWAP_FEE_BPS};
+use solana_program::{program_error::ProgramError, program_pack::Pack, pubkey::Pubkey, rent::Rent};
+use spl_token::state::{Account, Mint};
+
+// Account indices for InitializePool.
+const INITIALIZER: usize = 0;
+const POOL_STATE: usize = 1;
+const VAULT_A: usize = 4;
+const VAULT_B: usize = 5;
+const LP_MINT: usize = 6;
+const USER_LP: usize = 9;
+
+fn initialize_pool() -> LiquidityPoolInstruction {
+    LiquidityPoolInstruction::InitializePool {
+        initial_amount_a: 1_000_000,
+        initial_amount_b: 2_000_000,
+        curve_type: CurveType::ConstantProduct,
+        amp: 0,
+        fee_bps: SWAP_FEE_BPS,
+    }
+}
+
+fn initialize_pool_accounts() -> Vec<TestAccount> {
+    let (header, page) = registry_accounts(0);
+    let fee_config = fee_config_account(Pubkey::new_unique(), &[SWAP_FEE_BPS]);
+    common::initialize_pool_accounts(&header, &page, &fee_config, SWAP_FEE_BPS, 2_000_000)
+}
+
+#[test]
+fn creates_the_pool_accounts_at_their_pdas() {
+    let mut accounts = initialize_pool_accounts();
+    let lamports_before = accounts[INITIALIZER].lamports;
+    assert_eq!(run(&mut accounts, initialize_pool()), Ok(()));
+
+    let pool = accounts[POOL_STATE].key;
+    assert_eq!(accounts[POOL_STATE].owner, liquidity_pool::id());
+    assert_eq!(accounts[POOL_STATE].data.len(), PoolState::LEN);
+    assert_eq!(PoolState::try_from_slice(&accounts[POOL_STATE].data).unwrap().lp_mint, accounts[LP_MINT].key);
+
+    for (vault, mint) in [(VAULT_A, 2), (VAULT_B, 3)] {
+        let token_account = Account::unpack(&accounts[vault].data).unwrap();
+        assert_eq!(accounts[vault].owner, spl_token::id());
+        assert_eq!((token_account.mint, token_account.owner), (accounts[mint].key, pool));
+    }
+    let lp_mint = Mint::unpack(&accounts[LP_MINT].data).unwrap();
+    assert_eq!(lp_mint.mint_authority, Some(pool).into());
+
+    let rent = Rent::default();
+    let paid = rent.minimum_balance(PoolState::LEN)
+        + 2 * rent.minimum_balance(Account::LEN)
+        + rent.minimum_balance(Mint::LEN)
+        + rent.minimum_balance(RegistryHeader::LEN)
+        + rent.minimum_balance(RegistryPage::LEN);
+    for index in [POOL_STATE, VAULT_A, VAULT_B, LP_MINT] {
+        assert!(rent.is_exempt(accounts[index].lamports, accounts[index].data.len()));
+    }
+    assert_eq!(accounts[INITIALIZER].lamports, lamports_before - paid);
+}
+
+#[test]
+fn creates_accounts_at_addresses_already_sent_lamports() {
+    let mut accounts = initialize_pool_accounts();
+    accounts[POOL_STATE].lamports = 1;
+    accounts[VAULT_A].lamports = 1_000_000_000;
+    assert_eq!(run(&mut accounts, initialize_pool()), Ok(()));
+
+    assert_eq!(accounts[POOL_STATE].lamports, Rent::default().minimum_balance(PoolState::LEN));
+    assert_eq!(accounts[VAULT_A].lamports, 1_000_000_000);
+    assert_eq!(accounts[VAULT_A].token_amount(), 1_000_000);
+}
+
+#[test]
+fn rejects_accounts_not_at_their_pdas() {
+    for index in [VAULT_A, VAULT_B, LP_MINT] {
+        let mut accounts = initialize_pool_accounts();
+        accounts[index] = TestAccount::unallocated(Pubkey::new_unique());
+        assert_eq!(run(&mut accounts, initialize_pool()), Err(ProgramError::InvalidSeeds), "{index}");
+    }
+}
+
+#[test]
+fn initializes_a_pool_once() {
+    let mut accounts = initialize_pool_accounts();
+    assert_eq!(run(&mut accounts, initialize_pool()), Ok(()));
+    assert_eq!(
+        run(&mut accounts, initialize_pool()),
+        Err(LiquidityPoolError::PoolAlreadyInitialized.into())
+    );
+}
+
+#[test]
+fn creates_the_initializers_lp_account_from_a_new_keypair() {
+    let mut accounts = initialize_pool_accounts();
+    accounts[USER_LP] = TestAccount::unallocated(Pubkey::new_unique());
+    assert_eq!(run(&mut accounts, initialize_pool()), Err(ProgramError::MissingRequiredSignature));
+
+    accounts[USER_LP].is_signer = true;
+    assert_eq!(run(&mut accounts, initialize_pool()), Ok(()));
+    let lp_account = Account::unpack(&accounts[USER_LP].data).unwrap();
+    assert_eq!((lp_account.mint, lp_account.owner), (accounts[LP_MINT].key, accounts[INITIALIZER].key));
+    assert!(lp_account.amount > 0);
+}
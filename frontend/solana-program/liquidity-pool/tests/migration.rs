@@ -0,0 +1,167 @@
+mod common;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use common::{run, PoolFixture, TestAccount, NOW};
+use liquidity_pool::{
+    migration::PoolStateV1, CurveType, LiquidityPoolError, LiquidityPoolInstruction, PoolState, POOL_STATE_RESERVED, POOL_STATE_VERSION,
+    SWAP_FEE_BPS,
+};
+use solana_program::{program_error::ProgramError, program_pack::Pack, pubkey::Pubkey, rent::Rent, system_program};
+use spl_token::state::Account;
+
+/// A pool whose state account was written by version 1, rent exempt at that
+/// size, at the address derived without a fee tier, with vaults at keypair
+/// addresses.
+fn v1_pool() -> (PoolFixture, PoolStateV1) {
+    let mut pool = PoolFixture::legacy_seeds(1_000_000, 2_000_000, 1_414_213, 100_000, 0);
+    pool.vault_a.key = Pubkey::new_unique();
+    pool.vault_b.key = Pubkey::new_unique();
+    let state = pool.state();
+    let legacy = PoolStateV1 {
+        is_initialized: 1,
+        token_a_mint: state.token_a_mint,
+        token_b_mint: state.token_b_mint,
+        token_a_reserve: state.token_a_reserve,
+        token_b_reserve: state.token_b_reserve,
+        lp_mint: state.lp_mint,
+        lp_supply: state.lp_supply,
+        bump_seed: state.bump_seed,
+    };
+    pool.pool_state.data = legacy.try_to_vec().unwrap();
+    pool.pool_state.lamports = Rent::default().minimum_balance(PoolStateV1::LEN);
+    (pool, legacy)
+}
+
+fn migrate_accounts(pool: &PoolFixture, payer: &TestAccount) -> Vec<TestAccount> {
+    vec![
+        payer.clone(),
+        pool.pool_state.clone(),
+        TestAccount::program(system_program::id()),
+        pool.vault_a.clone(),
+        pool.vault_b.clone(),
+        pool.mint_a.clone(),
+        pool.mint_b.clone(),
+        TestAccount::unallocated(liquidity_pool::vault_address(&liquidity_pool::id(), &pool.pool_state.key, &pool.mint_a.key).0),
+        TestAccount::unallocated(liquidity_pool::vault_address(&liquidity_pool::id(), &pool.pool_state.key, &pool.mint_b.key).0),
+        pool.token_program.clone(),
+    ]
+}
+
+#[test]
+fn reads_v1_data() {
+    let (pool, expected) = v1_pool();
+    assert_eq!(pool.pool_state.data.len(), PoolStateV1::LEN);
+    assert_eq!(PoolStateV1::LEN, 122);
+
+    let legacy = PoolStateV1::try_from_slice(&pool.pool_state.data).unwrap();
+    assert_eq!(legacy.token_a_mint, expected.token_a_mint);
+    assert_eq!(legacy.lp_supply, expected.lp_supply);
+    assert_eq!(legacy.bump_seed, expected.bump_seed);
+}
+
+#[test]
+fn migrates_a_v1_pool() {
+    let (mut pool, expected) = v1_pool();
+    let payer = TestAccount { lamports: 1_000_000_000, ..TestAccount::signer() };
+    let mut accounts = migrate_accounts(&pool, &payer);
+    assert_eq!(run(&mut accounts, LiquidityPoolInstruction::MigratePool), Ok(()));
+
+    assert_eq!(accounts[1].data.len(), PoolState::LEN);
+    let state = PoolState::try_from_slice(&accounts[1].data).unwrap();
+    assert_eq!(state.version, POOL_STATE_VERSION);
+    assert_eq!(state.token_b_mint, expected.token_b_mint);
+    assert_eq!(state.token_a_reserve, expected.token_a_reserve);
+    assert_eq!((state.lp_mint, state.lp_supply, state.bump_seed), (expected.lp_mint, expected.lp_supply, expected.bump_seed));
+    assert_eq!(state.curve_type, CurveType::ConstantProduct);
+    assert_eq!(state.creator, Pubkey::default());
+    assert_eq!((state.initial_amp, state.target_amp), (0, 0));
+    assert_eq!((state.price_a_cumulative_x64, state.last_update_ts), (0, NOW));
+    assert_eq!(state.fee_bps, SWAP_FEE_BPS);
+    assert_eq!((state.vault_a, state.vault_b), (accounts[7].key, accounts[8].key));
+    assert_eq!(state.reserved, [0; POOL_STATE_RESERVED]);
+
+    // Everything the old vaults held is now in the new ones.
+    assert_eq!((accounts[3].token_amount(), accounts[4].token_amount()), (0, 0));
+    assert_eq!((accounts[7].token_amount(), accounts[8].token_amount()), (1_000_000, 2_000_000));
+    assert_eq!((accounts[7].owner, accounts[8].owner), (spl_token::id(), spl_token::id()));
+
+    let rent = Rent::default();
+    let pool_rent = rent.minimum_balance(PoolState::LEN);
+    assert_eq!(accounts[1].lamports, pool_rent);
+    let paid = pool_rent - rent.minimum_balance(PoolStateV1::LEN) + 2 * rent.minimum_balance(Account::LEN);
+    assert_eq!(accounts[0].lamports, 1_000_000_000 - paid);
+
+    // The migrated pool trades as before, still at its original address.
+    pool.pool_state = accounts[1].clone();
+    pool.vault_a = accounts[7].clone();
+    pool.vault_b = accounts[8].clone();
+    let mut accounts = pool.swap_accounts();
+    let swap = LiquidityPoolInstruction::Swap { amount_in: 1_000, min_out: 1, a_to_b: true };
+    assert_eq!(run(&mut accounts, swap), Ok(()));
+}
+
+#[test]
+fn v1_pools_must_be_migrated_before_use() {
+    let (pool, _) = v1_pool();
+    let mut accounts = pool.swap_accounts();
+    let swap = LiquidityPoolInstruction::Swap { amount_in: 1_000, min_out: 1, a_to_b: true };
+    assert_eq!(run(&mut accounts, swap), Err(LiquidityPoolError::PoolNeedsMigration.into()));
+}
+
+#[test]
+fn migration_moves_only_the_pools_funded_vaults() {
+    let (pool, _) = v1_pool();
+    let payer = TestAccount { lamports: 1_000_000_000, ..TestAccount::signer() };
+
+    let mut foreign = migrate_accounts(&pool, &payer);
+    foreign[3] = TestAccount::token_account(pool.mint_a.key, Pubkey::new_unique(), 1_000_000);
+    assert_eq!(
+        run(&mut foreign, LiquidityPoolInstruction::MigratePool),
+        Err(LiquidityPoolError::InvalidOwner.into())
+    );
+
+    let mut swapped = migrate_accounts(&pool, &payer);
+    swapped.swap(3, 4);
+    assert_eq!(
+        run(&mut swapped, LiquidityPoolInstruction::MigratePool),
+        Err(LiquidityPoolError::InvalidTokenMint.into())
+    );
+
+    let mut empty = migrate_accounts(&pool, &payer);
+    empty[3] = TestAccount::token_account(pool.mint_a.key, pool.pool_state.key, 0);
+    assert_eq!(
+        run(&mut empty, LiquidityPoolInstruction::MigratePool),
+        Err(LiquidityPoolError::ReserveMismatch.into())
+    );
+    assert_eq!(empty[1].data.len(), PoolStateV1::LEN);
+
+    let mut elsewhere = migrate_accounts(&pool, &payer);
+    elsewhere[7] = TestAccount::unallocated(Pubkey::new_unique());
+    assert_eq!(run(&mut elsewhere, LiquidityPoolInstruction::MigratePool), Err(ProgramError::InvalidSeeds));
+}
+
+#[test]
+fn current_pools_are_not_migrated() {
+    let pool = PoolFixture::new(1_000_000, 2_000_000, 1_414_213, 100_000, 0);
+    let mut accounts = migrate_accounts(&pool, &TestAccount::signer());
+    assert_eq!(
+        run(&mut accounts, LiquidityPoolInstruction::MigratePool),
+        Err(LiquidityPoolError::PoolAlreadyMigrated.into())
+    );
+
+    let mut state = pool.state();
+    state.version = 1;
+    let mut outdated = pool;
+    outdated.set_state(&state);
+    let mut accounts = outdated.swap_accounts();
+    let swap = LiquidityPoolInstruction::Swap { amount_in: 1_000, min_out: 1, a_to_b: true };
+    assert_eq!(run(&mut accounts, swap), Err(LiquidityPoolError::PoolNeedsMigration.into()));
+}
+
+#[test]
+fn migration_requires_a_signing_payer() {
+    let (pool, _) = v1_pool();
+    let mut accounts = migrate_accounts(&pool, &TestAccount::new(system_program::id(), vec![]));
+    assert_eq!(run(&mut accounts, LiquidityPoolInstruction::MigratePool), Err(ProgramError::MissingRequiredSignature));
+    assert_eq!(accounts[1].data.len(), PoolStateV1::LEN);
+}
@@ -0,0 +1,140 @@
+mod common;
+
+use common::{run, PoolFixture, TestAccount};
+use liquidity_pool::{curve, LiquidityPoolError, LiquidityPoolInstruction, SWAP_FEE_BPS};
+use solana_program::{program_error::ProgramError, program_pack::Pack, pubkey::Pubkey, rent::Rent, system_program};
+use spl_token::state::Account;
+
+const USER_LAMPORTS: u64 = 10_000_000_000;
+
+/// A SOL/token pool with wSOL as token A, and a user holding lamports and an
+/// empty wSOL account, as created by the client ahead of the native variants.
+fn sol_pool(reserve_a: u64, reserve_b: u64, lp_supply: u64, user_balance: u64, user_lp: u64) -> PoolFixture {
+    let mint_b = TestAccount::mint(Pubkey::new_unique(), u64::MAX / 2);
+    let mut pool =
+        PoolFixture::for_mints(TestAccount::native_mint(), mint_b, reserve_a, reserve_b, lp_supply, user_balance, user_lp);
+    pool.user.lamports = USER_LAMPORTS;
+    pool.user_a = TestAccount::native_token_account(pool.user.key, 0);
+    pool
+}
+
+fn wsol_rent() -> u64 {
+    Rent::default().minimum_balance(Account::LEN)
+}
+
+/// The regular instruction's accounts followed by the system program.
+fn native(mut accounts: Vec<TestAccount>) -> Vec<TestAccount> {
+    accounts.push(TestAccount::program(system_program::id()));
+    accounts
+}
+
+#[test]
+fn swaps_sol_for_tokens() {
+    let pool = sol_pool(1_000_000_000, 2_000_000, 44_721_359, 0, 0);
+    let out = curve::swap_exact_in(10_000_000, 1_000_000_000, 2_000_000, SWAP_FEE_BPS).unwrap();
+    let mut accounts = native(pool.swap_accounts());
+    let swap = LiquidityPoolInstruction::SwapNative { amount_in: 10_000_000, min_out: out, a_to_b: true };
+    assert_eq!(run(&mut accounts, swap), Ok(()));
+
+    // The rent of the closed wSOL account comes back to the user.
+    assert_eq!(accounts[0].lamports, USER_LAMPORTS - 10_000_000 + wsol_rent());
+    assert_eq!((accounts[4].lamports, accounts[4].owner), (0, system_program::id()));
+    assert_eq!(accounts[5].token_amount(), out);
+    assert_eq!(accounts[2].token_amount(), 1_000_000_000 + 10_000_000);
+    assert_eq!(accounts[2].lamports, wsol_rent() + 1_000_000_000 + 10_000_000);
+}
+
+#[test]
+fn swaps_tokens_for_sol() {
+    let pool = sol_pool(1_000_000_000, 2_000_000, 44_721_359, 100_000, 0);
+    let out = curve::swap_exact_in(20_000, 2_000_000, 1_000_000_000, SWAP_FEE_BPS).unwrap();
+    let mut accounts = native(pool.swap_accounts());
+    let swap = LiquidityPoolInstruction::SwapNative { amount_in: 20_000, min_out: out, a_to_b: false };
+    assert_eq!(run(&mut accounts, swap), Ok(()));
+
+    assert_eq!(accounts[0].lamports, USER_LAMPORTS + out + wsol_rent());
+    assert_eq!(accounts[4].lamports, 0);
+    assert_eq!(accounts[5].token_amount(), 100_000 - 20_000);
+    assert_eq!(accounts[2].lamports, wsol_rent() + 1_000_000_000 - out);
+}
+
+#[test]
+fn refunds_lamports_not_deposited() {
+    let pool = sol_pool(1_000_000_000, 2_000_000, 44_721_359, 100_000, 0);
+    let (amount_a, amount_b) = curve::deposit_amounts(50_000_000, 50_000, 1_000_000_000, 2_000_000).unwrap();
+    assert!(amount_a < 50_000_000);
+    let mut accounts = native(pool.liquidity_accounts());
+    let add = LiquidityPoolInstruction::AddLiquidityNative { amount_a: 50_000_000, amount_b: 50_000 };
+    assert_eq!(run(&mut accounts, add), Ok(()));
+
+    assert_eq!(accounts[0].lamports, USER_LAMPORTS - amount_a + wsol_rent());
+    assert_eq!(accounts[5].lamports, 0);
+    assert_eq!(accounts[6].token_amount(), 100_000 - amount_b);
+    assert!(accounts[7].token_amount() > 0);
+    assert_eq!(accounts[2].token_amount(), 1_000_000_000 + amount_a);
+}
+
+#[test]
+fn withdraws_sol() {
+    let pool = sol_pool(1_000_000_000, 2_000_000, 44_721_359, 0, 4_472_135);
+    let (amount_a, amount_b) = curve::withdraw_amounts(4_472_135, 1_000_000_000, 2_000_000, 44_721_359).unwrap();
+    let mut accounts = native(pool.liquidity_accounts());
+    let remove = LiquidityPoolInstruction::RemoveLiquidityNative { lp_token_amount: 4_472_135 };
+    assert_eq!(run(&mut accounts, remove), Ok(()));
+
+    assert_eq!(accounts[0].lamports, USER_LAMPORTS + amount_a + wsol_rent());
+    assert_eq!(accounts[6].token_amount(), amount_b);
+    assert_eq!(accounts[7].token_amount(), 0);
+}
+
+#[test]
+fn requires_a_sol_pool() {
+    let pool = PoolFixture::new(1_000_000, 2_000_000, 1_414_213, 100_000, 0);
+    let mut accounts = native(pool.swap_accounts());
+    let swap = LiquidityPoolInstruction::SwapNative { amount_in: 1_000, min_out: 0, a_to_b: true };
+    assert_eq!(run(&mut accounts, swap), Err(LiquidityPoolError::InvalidTokenMint.into()));
+}
+
+#[test]
+fn requires_a_wsol_account_owned_by_the_user() {
+    let mut pool = sol_pool(1_000_000_000, 2_000_000, 44_721_359, 0, 0);
+    let swap = || LiquidityPoolInstruction::SwapNative { amount_in: 1_000, min_out: 0, a_to_b: true };
+
+    pool.user_a = TestAccount::native_token_account(Pubkey::new_unique(), 0);
+    let mut accounts = native(pool.swap_accounts());
+    assert_eq!(run(&mut accounts, swap()), Err(LiquidityPoolError::InvalidTokenAccount.into()));
+
+    pool.user_a = TestAccount::token_account(pool.mint_b.key, pool.user.key, 0);
+    let mut accounts = native(pool.swap_accounts());
+    assert_eq!(run(&mut accounts, swap()), Err(LiquidityPoolError::InvalidTokenAccount.into()));
+}
+
+#[test]
+fn requires_the_system_program() {
+    let pool = sol_pool(1_000_000_000, 2_000_000, 44_721_359, 0, 0);
+    let swap = || LiquidityPoolInstruction::SwapNative { amount_in: 1_000, min_out: 0, a_to_b: true };
+
+    let mut accounts = pool.swap_accounts();
+    assert_eq!(run(&mut accounts, swap()), Err(ProgramError::NotEnoughAccountKeys));
+    accounts.push(TestAccount::program(Pubkey::new_unique()));
+    assert_eq!(run(&mut accounts, swap()), Err(ProgramError::IncorrectProgramId));
+}
+
+#[test]
+fn swaps_sol_for_a_token_2022_fee_mint() {
+    let fee_mint = TestAccount::transfer_fee_mint(Pubkey::new_unique(), u64::MAX / 2, 100, u64::MAX);
+    let mut pool = PoolFixture::for_mints(TestAccount::native_mint(), fee_mint, 1_000_000_000, 2_000_000, 44_721_359, 0, 0);
+    pool.user.lamports = USER_LAMPORTS;
+    pool.user_a = TestAccount::native_token_account(pool.user.key, 0);
+    let out = curve::swap_exact_in(10_000_000, 1_000_000_000, 2_000_000, SWAP_FEE_BPS).unwrap();
+    let received = out - (out * 100).div_ceil(10_000);
+
+    // The fee mint's transfers are checked, so it and its token program come
+    // after the system program, as the trailing accounts of `Swap`.
+    let mut accounts = native(pool.swap_accounts());
+    accounts.extend([pool.mint_b.clone(), TestAccount::program(spl_token_2022::id())]);
+    let swap = LiquidityPoolInstruction::SwapNative { amount_in: 10_000_000, min_out: received, a_to_b: true };
+    assert_eq!(run(&mut accounts, swap), Ok(()));
+    assert_eq!(accounts[5].token_amount(), received);
+    assert_eq!(accounts[2].token_amount(), 1_000_000_000 + 10_000_000);
+}
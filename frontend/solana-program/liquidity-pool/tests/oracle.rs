@@ -0,0 +1,84 @@
+mod common;
+
+use borsh::BorshDeserialize;
+use common::{return_data, run, PoolFixture, NOW};
+use liquidity_pool::oracle::{self, PriceObservation};
+use liquidity_pool::{LiquidityPoolInstruction, PoolState};
+
+const Q64: u128 = 1 << 64;
+
+/// A pool with 1 A = 2 B, last updated `elapsed` seconds ago.
+fn fixture(elapsed: i64) -> PoolFixture {
+    let mut pool = PoolFixture::new(1_000_000, 2_000_000, 1_414_213, 100_000, 100_000);
+    let mut state = pool.state();
+    state.last_update_ts = NOW - elapsed;
+    pool.set_state(&state);
+    pool
+}
+
+#[test]
+fn swaps_accumulate_the_price_before_the_trade() {
+    let pool = fixture(100);
+    let mut accounts = pool.swap_accounts();
+    let swap = || LiquidityPoolInstruction::Swap { amount_in: 10_000, min_out: 0, a_to_b: true };
+    assert_eq!(run(&mut accounts, swap()), Ok(()));
+
+    let state = PoolState::try_from_slice(&accounts[1].data).unwrap();
+    assert_eq!(state.last_update_ts, NOW);
+    assert_eq!(state.price_a_cumulative_x64, 2 * Q64 * 100);
+    assert_eq!(state.price_b_cumulative_x64, Q64 / 2 * 100);
+
+    // No time passes within the same timestamp, whatever the trade.
+    assert_eq!(run(&mut accounts, swap()), Ok(()));
+    let after = PoolState::try_from_slice(&accounts[1].data).unwrap();
+    assert_eq!(after.price_a_cumulative_x64, state.price_a_cumulative_x64);
+}
+
+#[test]
+fn liquidity_changes_accumulate() {
+    let pool = fixture(10);
+    let mut accounts = pool.liquidity_accounts();
+    let add = LiquidityPoolInstruction::AddLiquidity { amount_a: 10_000, amount_b: 20_000 };
+    assert_eq!(run(&mut accounts, add), Ok(()));
+
+    let state = PoolState::try_from_slice(&accounts[1].data).unwrap();
+    assert_eq!(state.last_update_ts, NOW);
+    assert_eq!(state.price_a_cumulative_x64, 2 * Q64 * 10);
+}
+
+#[test]
+fn observe_price_extrapolates_without_writing() {
+    let pool = fixture(60);
+    let mut accounts = vec![pool.pool_state.clone()];
+    assert_eq!(run(&mut accounts, LiquidityPoolInstruction::ObservePrice), Ok(()));
+
+    let observation = PriceObservation::try_from_slice(&return_data()).unwrap();
+    assert_eq!(
+        observation,
+        PriceObservation {
+            timestamp: NOW,
+            price_a_cumulative_x64: 2 * Q64 * 60,
+            price_b_cumulative_x64: Q64 / 2 * 60,
+        }
+    );
+    assert_eq!(accounts[0].data, pool.pool_state.data);
+}
+
+#[test]
+fn twap_averages_over_the_window_across_wraparound() {
+    let start = PriceObservation {
+        timestamp: NOW,
+        price_a_cumulative_x64: u128::MAX - Q64,
+        price_b_cumulative_x64: 0,
+    };
+    // 2 B per A for 100 seconds, then 3 B per A for 100 seconds.
+    let (a, b) = oracle::accumulate(start.price_a_cumulative_x64, start.price_b_cumulative_x64, 1_000, 2_000, 100);
+    let (a, b) = oracle::accumulate(a, b, 1_000, 3_000, 100);
+    let end = PriceObservation { timestamp: NOW + 200, price_a_cumulative_x64: a, price_b_cumulative_x64: b };
+
+    let (price_a, price_b) = oracle::twap_x64(&start, &end).unwrap();
+    assert_eq!(price_a, Q64 * 5 / 2);
+    assert_eq!(price_b, (oracle::spot_price_x64(2_000, 1_000) + oracle::spot_price_x64(3_000, 1_000)) / 2);
+    assert_eq!(oracle::twap_x64(&end, &start), None);
+    assert_eq!(oracle::twap_x64(&start, &start), None);
+}
@@ -0,0 +1,132 @@
+mod common;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use common::{fee_config_account, registry_accounts, run, TestAccount};
+use liquidity_pool::registry::{RegistryHeader, RegistryPage, REGISTRY_HEADER_DISCRIMINATOR, REGISTRY_PAGE_CAPACITY};
+use liquidity_pool::{CurveType, LiquidityPoolError, LiquidityPoolInstruction, SWAP_FEE_BPS};
+use solana_program::{program_error::ProgramError, pubkey::Pubkey, rent::Rent};
+
+fn initialize_pool() -> LiquidityPoolInstruction {
+    LiquidityPoolInstruction::InitializePool {
+        initial_amount_a: 1_000_000,
+        initial_amount_b: 2_000_000,
+        curve_type: CurveType::ConstantProduct,
+        amp: 0,
+        fee_bps: SWAP_FEE_BPS,
+    }
+}
+
+fn initialize_pool_accounts(registry_header: &TestAccount, registry_page: &TestAccount) -> Vec<TestAccount> {
+    let fee_config = fee_config_account(Pubkey::new_unique(), &[SWAP_FEE_BPS]);
+    common::initialize_pool_accounts(registry_header, registry_page, &fee_config, SWAP_FEE_BPS, 2_000_000)
+}
+
+fn header(account: &TestAccount) -> RegistryHeader {
+    RegistryHeader::deserialize(&mut &account.data[..]).unwrap()
+}
+
+fn page(account: &TestAccount) -> RegistryPage {
+    RegistryPage::deserialize(&mut &account.data[..]).unwrap()
+}
+
+#[test]
+fn creates_the_registry_on_first_use() {
+    let (registry_header, registry_page) = registry_accounts(0);
+    let mut accounts = initialize_pool_accounts(&registry_header, &registry_page);
+    assert_eq!(run(&mut accounts, initialize_pool()), Ok(()));
+
+    let rent = Rent::default();
+    for (account, len) in [(&accounts[13], RegistryHeader::LEN), (&accounts[14], RegistryPage::LEN)] {
+        assert_eq!(account.owner, liquidity_pool::id());
+        assert_eq!(account.data.len(), len);
+        assert!(rent.is_exempt(account.lamports, len));
+    }
+    assert_eq!(header(&accounts[13]).pool_count, 1);
+}
+
+#[test]
+fn records_every_new_pool() {
+    let (mut registry_header, mut registry_page) = registry_accounts(0);
+    let mut pools = Vec::new();
+    for _ in 0..2 {
+        let mut accounts = initialize_pool_accounts(&registry_header, &registry_page);
+        assert_eq!(run(&mut accounts, initialize_pool()), Ok(()));
+        registry_header = accounts[13].clone();
+        registry_page = accounts[14].clone();
+        pools.push(accounts);
+    }
+
+    assert_eq!(header(&registry_header).pool_count, 2);
+    let page = page(&registry_page);
+    assert_eq!(page.page_index, 0);
+    assert_eq!(page.entries.len(), 2);
+    for (entry, accounts) in page.entries.iter().zip(&pools) {
+        assert_eq!(entry.token_a_mint, accounts[2].key);
+        assert_eq!(entry.token_b_mint, accounts[3].key);
+        assert_eq!(entry.pool, accounts[1].key);
+        assert_eq!(entry.lp_mint, accounts[6].key);
+        assert_eq!(entry.fee_bps, SWAP_FEE_BPS);
+    }
+}
+
+#[test]
+fn starts_a_new_page_when_full() {
+    let (unallocated_header, full_page) = registry_accounts(0);
+    let mut registry_header = TestAccount::new(
+        liquidity_pool::id(),
+        RegistryHeader {
+            discriminator: REGISTRY_HEADER_DISCRIMINATOR,
+            is_initialized: 1,
+            pool_count: REGISTRY_PAGE_CAPACITY as u64,
+        }
+        .try_to_vec()
+        .unwrap(),
+    );
+    registry_header.key = unallocated_header.key;
+
+    let mut accounts = initialize_pool_accounts(&registry_header, &full_page);
+    assert_eq!(run(&mut accounts, initialize_pool()), Err(ProgramError::InvalidSeeds));
+
+    let (_, next_page) = registry_accounts(1);
+    let mut accounts = initialize_pool_accounts(&registry_header, &next_page);
+    assert_eq!(run(&mut accounts, initialize_pool()), Ok(()));
+    assert_eq!(header(&accounts[13]).pool_count, REGISTRY_PAGE_CAPACITY as u64 + 1);
+    let page = page(&accounts[14]);
+    assert_eq!((page.page_index, page.entries.len()), (1, 1));
+}
+
+#[test]
+fn rejects_a_registry_not_owned_by_the_program() {
+    let (mut registry_header, registry_page) = registry_accounts(0);
+    registry_header.owner = Pubkey::new_unique();
+    let mut accounts = initialize_pool_accounts(&registry_header, &registry_page);
+    assert_eq!(run(&mut accounts, initialize_pool()), Err(LiquidityPoolError::InvalidOwner.into()));
+}
+
+#[test]
+fn rejects_a_registry_of_another_type() {
+    let (mut registry_header, registry_page) = common::registry_accounts_with(&[]);
+    registry_header.data[..8].copy_from_slice(&[0; 8]);
+    let mut accounts = initialize_pool_accounts(&registry_header, &registry_page);
+    assert_eq!(run(&mut accounts, initialize_pool()), Err(LiquidityPoolError::InvalidAccountType.into()));
+
+    let (registry_header, _) = common::registry_accounts_with(&[]);
+    let mut registry_page = registry_header.clone();
+    registry_page.key = registry_accounts(0).1.key;
+    registry_page.data.resize(RegistryPage::LEN, 0);
+    let mut accounts = initialize_pool_accounts(&registry_header, &registry_page);
+    assert_eq!(run(&mut accounts, initialize_pool()), Err(LiquidityPoolError::InvalidAccountType.into()));
+}
+
+#[test]
+fn rejects_mints_out_of_canonical_order() {
+    let (registry_header, registry_page) = registry_accounts(0);
+    let mut accounts = initialize_pool_accounts(&registry_header, &registry_page);
+    accounts.swap(2, 3);
+    accounts.swap(4, 5);
+    accounts.swap(7, 8);
+    assert_eq!(
+        run(&mut accounts, initialize_pool()),
+        Err(LiquidityPoolError::NonCanonicalMintOrder.into())
+    );
+}
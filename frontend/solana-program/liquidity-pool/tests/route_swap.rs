@@ -0,0 +1,90 @@
+mod common;
+
+use common::{run, PoolFixture, TestAccount};
+use liquidity_pool::{curve, LiquidityPoolError, LiquidityPoolInstruction, RouteHop, MAX_ROUTE_HOPS, SWAP_FEE_BPS};
+
+/// Two pools, A/B and B/C, routed A → B → C by the first pool's user, who
+/// holds no C yet.
+struct Route {
+    first: PoolFixture,
+    second: PoolFixture,
+    user_c: TestAccount,
+}
+
+impl Route {
+    fn new() -> Self {
+        let first = PoolFixture::new(1_000_000, 2_000_000, 1_414_213, 100_000, 0);
+        let mint_c = TestAccount::mint(first.mint_a.key, u64::MAX / 2);
+        let second = PoolFixture::for_mints(first.mint_b.clone(), mint_c, 4_000_000, 1_000_000, 2_000_000, 0, 0);
+        let user_c = TestAccount::token_account(second.mint_b.key, first.user.key, 0);
+        Self { first, second, user_c }
+    }
+
+    fn accounts(&self) -> Vec<TestAccount> {
+        vec![
+            self.first.user.clone(),
+            self.first.token_program.clone(),
+            self.first.pool_state.clone(),
+            self.first.vault_a.clone(),
+            self.first.vault_b.clone(),
+            self.first.user_a.clone(),
+            self.first.user_b.clone(),
+            self.second.pool_state.clone(),
+            self.second.vault_a.clone(),
+            self.second.vault_b.clone(),
+            self.first.user_b.clone(),
+            self.user_c.clone(),
+        ]
+    }
+}
+
+fn route_swap(amount_in: u64, min_out: u64, hops: usize) -> LiquidityPoolInstruction {
+    LiquidityPoolInstruction::RouteSwap { amount_in, min_out, hops: vec![RouteHop { a_to_b: true }; hops] }
+}
+
+#[test]
+fn routes_through_both_pools() {
+    let route = Route::new();
+    let mut accounts = route.accounts();
+
+    let amount_b = curve::swap_exact_in(10_000, 1_000_000, 2_000_000, SWAP_FEE_BPS).unwrap();
+    let amount_c = curve::swap_exact_in(amount_b, 4_000_000, 1_000_000, SWAP_FEE_BPS).unwrap();
+    assert_eq!(run(&mut accounts, route_swap(10_000, amount_c, 2)), Ok(()));
+
+    assert_eq!(accounts[5].token_amount(), 90_000);
+    assert_eq!(accounts[6].token_amount(), 100_000);
+    assert_eq!(accounts[11].token_amount(), amount_c);
+    assert_eq!(accounts[4].token_amount(), 2_000_000 - amount_b);
+    assert_eq!(accounts[8].token_amount(), 4_000_000 + amount_b);
+}
+
+#[test]
+fn checks_only_the_final_output() {
+    let route = Route::new();
+    let mut accounts = route.accounts();
+
+    let amount_b = curve::swap_exact_in(10_000, 1_000_000, 2_000_000, SWAP_FEE_BPS).unwrap();
+    let amount_c = curve::swap_exact_in(amount_b, 4_000_000, 1_000_000, SWAP_FEE_BPS).unwrap();
+    assert_eq!(
+        run(&mut accounts, route_swap(10_000, amount_c + 1, 2)),
+        Err(LiquidityPoolError::SlippageExceeded.into())
+    );
+}
+
+#[test]
+fn rejects_a_broken_chain() {
+    let route = Route::new();
+    let mut accounts = route.accounts();
+    // The second hop pays from an account the first hop did not pay into.
+    accounts[10] = TestAccount::token_account(route.first.mint_b.key, route.first.user.key, 100_000);
+    assert_eq!(run(&mut accounts, route_swap(10_000, 0, 2)), Err(LiquidityPoolError::InvalidRoute.into()));
+}
+
+#[test]
+fn rejects_empty_and_overlong_routes() {
+    let route = Route::new();
+    for hops in [0, MAX_ROUTE_HOPS + 1] {
+        let mut accounts = route.accounts();
+        assert_eq!(run(&mut accounts, route_swap(10_000, 0, hops)), Err(LiquidityPoolError::InvalidRoute.into()));
+    }
+}
@@ -0,0 +1,194 @@
+mod common;
+
+use common::{run, PoolFixture, TestAccount, NOW};
+use liquidity_pool::curve::{self, CurveError};
+use liquidity_pool::stable_swap::{self, MAX_AMP, MIN_RAMP_DURATION};
+use liquidity_pool::{CurveType, LiquidityPoolError, LiquidityPoolInstruction, SWAP_FEE_BPS};
+use proptest::prelude::*;
+
+fn reserve() -> impl Strategy<Value = u64> {
+    1_000u64..=1_000_000_000_000
+}
+
+fn amp() -> impl Strategy<Value = u64> {
+    prop_oneof![Just(1u64), Just(100u64), 1u64..=10_000]
+}
+
+fn fee_bps() -> impl Strategy<Value = u64> {
+    prop_oneof![Just(0u64), Just(SWAP_FEE_BPS), 0u64..1_000]
+}
+
+fn swap(amount_in: u64, min_out: u64) -> LiquidityPoolInstruction {
+    LiquidityPoolInstruction::Swap { amount_in, min_out, a_to_b: true }
+}
+
+fn ramp(target_amp: u64, stop_ramp_ts: i64) -> LiquidityPoolInstruction {
+    LiquidityPoolInstruction::RampAmp { target_amp, stop_ramp_ts }
+}
+
+proptest! {
+    #[test]
+    fn swap_exact_in_never_decreases_d(
+        reserve_in in reserve(),
+        reserve_out in reserve(),
+        amount_in in 1u64..=1_000_000_000_000,
+        amp in amp(),
+        fee in fee_bps(),
+    ) {
+        let amount_out = stable_swap::swap_exact_in(amount_in, reserve_in, reserve_out, amp, fee).unwrap();
+        prop_assert!(amount_out < reserve_out);
+        let d_before = stable_swap::compute_d(amp, reserve_in, reserve_out).unwrap();
+        let d_after = stable_swap::compute_d(amp, reserve_in + amount_in, reserve_out - amount_out).unwrap();
+        prop_assert!(d_after >= d_before);
+    }
+
+    #[test]
+    fn swap_exact_out_never_decreases_d(
+        reserve_in in reserve(),
+        reserve_out in reserve(),
+        amount_out_fraction in 1u64..1_000,
+        amp in amp(),
+        fee in fee_bps(),
+    ) {
+        let amount_out = (reserve_out as u128 * amount_out_fraction as u128 / 1_000) as u64;
+        prop_assume!(amount_out > 0);
+        let amount_in = stable_swap::swap_exact_out(amount_out, reserve_in, reserve_out, amp, fee).unwrap();
+        let d_before = stable_swap::compute_d(amp, reserve_in, reserve_out).unwrap();
+        let d_after = stable_swap::compute_d(amp, reserve_in + amount_in, reserve_out - amount_out).unwrap();
+        prop_assert!(d_after >= d_before);
+    }
+
+    #[test]
+    fn exact_out_input_buys_at_least_the_output(
+        reserve_in in reserve(),
+        reserve_out in reserve(),
+        amount_out_fraction in 1u64..1_000,
+        amp in amp(),
+    ) {
+        let amount_out = (reserve_out as u128 * amount_out_fraction as u128 / 1_000) as u64;
+        prop_assume!(amount_out > 0);
+        let amount_in = stable_swap::swap_exact_out(amount_out, reserve_in, reserve_out, amp, SWAP_FEE_BPS).unwrap();
+        let amount_out_for_input = stable_swap::swap_exact_in(amount_in, reserve_in, reserve_out, amp, SWAP_FEE_BPS).unwrap();
+        // Each path holds back two units for the solvers' rounding, so they
+        // agree to within four.
+        prop_assert!(amount_out_for_input + 4 >= amount_out);
+    }
+
+    #[test]
+    fn swap_round_trip_never_profits(
+        reserve_a in reserve(),
+        reserve_b in reserve(),
+        amount_in in 1u64..=1_000_000_000_000,
+        amp in amp(),
+        fee in fee_bps(),
+    ) {
+        let amount_b = stable_swap::swap_exact_in(amount_in, reserve_a, reserve_b, amp, fee).unwrap();
+        prop_assume!(amount_b > 0);
+        let amount_a = stable_swap::swap_exact_in(amount_b, reserve_b - amount_b, reserve_a + amount_in, amp, fee).unwrap();
+        prop_assert!(amount_a <= amount_in);
+    }
+
+    #[test]
+    fn amp_stays_between_ramp_endpoints(
+        initial in 1u64..=MAX_AMP,
+        target in 1u64..=MAX_AMP,
+        elapsed in 0i64..=2 * MIN_RAMP_DURATION,
+    ) {
+        let amp = stable_swap::current_amp(initial, target, NOW, NOW + MIN_RAMP_DURATION, NOW + elapsed);
+        prop_assert!(amp >= initial.min(target) && amp <= initial.max(target));
+    }
+}
+
+#[test]
+fn balanced_pool_trades_near_one_to_one() {
+    let stable = stable_swap::swap_exact_in(10_000, 1_000_000, 1_000_000, 100, SWAP_FEE_BPS).unwrap();
+    let constant_product = curve::swap_exact_in(10_000, 1_000_000, 1_000_000, SWAP_FEE_BPS).unwrap();
+    // Only the 0.3% fee and a sliver of curvature separate the output from 1:1.
+    assert!((9_960..9_970).contains(&stable), "{stable}");
+    assert!(stable > constant_product);
+}
+
+#[test]
+fn amp_ramps_linearly() {
+    let stop = NOW + MIN_RAMP_DURATION;
+    assert_eq!(stable_swap::current_amp(100, 200, NOW, stop, NOW - 1), 100);
+    assert_eq!(stable_swap::current_amp(100, 200, NOW, stop, NOW + MIN_RAMP_DURATION / 2), 150);
+    assert_eq!(stable_swap::current_amp(200, 100, NOW, stop, NOW + MIN_RAMP_DURATION / 4), 175);
+    assert_eq!(stable_swap::current_amp(100, 200, NOW, stop, stop + 1), 200);
+}
+
+#[test]
+fn rejects_invalid_amplification() {
+    assert_eq!(stable_swap::compute_d(0, 10, 10), Err(CurveError::InvalidAmplification));
+    assert_eq!(stable_swap::compute_d(MAX_AMP + 1, 10, 10), Err(CurveError::InvalidAmplification));
+    assert_eq!(stable_swap::compute_d(100, 0, 10), Err(CurveError::ZeroReserves));
+}
+
+#[test]
+fn swaps_along_the_stable_curve() {
+    let pool = PoolFixture::stable(1_000_000, 1_000_000, 1_000_000, 100_000, 100);
+    let expected = stable_swap::swap_exact_in(10_000, 1_000_000, 1_000_000, 100, SWAP_FEE_BPS).unwrap();
+
+    let mut accounts = pool.swap_accounts();
+    assert_eq!(
+        run(&mut accounts, swap(10_000, expected + 1)),
+        Err(LiquidityPoolError::SlippageExceeded.into())
+    );
+    assert_eq!(run(&mut accounts, swap(10_000, expected)), Ok(()));
+    assert_eq!(accounts[5].token_amount(), 100_000 + expected);
+}
+
+#[test]
+fn creator_ramps_and_stops_amplification() {
+    let pool = PoolFixture::stable(1_000_000, 1_000_000, 1_000_000, 100_000, 100);
+    let mut accounts = pool.ramp_accounts();
+    let stop = NOW + 2 * MIN_RAMP_DURATION;
+    assert_eq!(run(&mut accounts, ramp(1_000, stop)), Ok(()));
+
+    let mut ramping = pool;
+    ramping.pool_state = accounts[1].clone();
+    let state = ramping.state();
+    assert_eq!((state.initial_amp, state.target_amp), (100, 1_000));
+    assert_eq!((state.ramp_start_ts, state.ramp_stop_ts), (NOW, stop));
+
+    // Pretend the ramp started half a duration ago, then freeze it.
+    let mut state = ramping.state();
+    state.ramp_start_ts = NOW - MIN_RAMP_DURATION;
+    state.ramp_stop_ts = NOW + MIN_RAMP_DURATION;
+    ramping.set_state(&state);
+    let mut accounts = ramping.ramp_accounts();
+    assert_eq!(run(&mut accounts, LiquidityPoolInstruction::StopRampAmp), Ok(()));
+
+    ramping.pool_state = accounts[1].clone();
+    let state = ramping.state();
+    assert_eq!((state.initial_amp, state.target_amp), (550, 550));
+    assert_eq!(state.amp(NOW + MIN_RAMP_DURATION), 550);
+}
+
+#[test]
+fn rejects_ramps_that_are_too_fast_or_too_large() {
+    let pool = PoolFixture::stable(1_000_000, 1_000_000, 1_000_000, 100_000, 100);
+    let invalid = Err(LiquidityPoolError::InvalidAmplification.into());
+    assert_eq!(run(&mut pool.ramp_accounts(), ramp(200, NOW + MIN_RAMP_DURATION - 1)), invalid);
+    assert_eq!(run(&mut pool.ramp_accounts(), ramp(1_001, NOW + MIN_RAMP_DURATION)), invalid);
+    assert_eq!(run(&mut pool.ramp_accounts(), ramp(9, NOW + MIN_RAMP_DURATION)), invalid);
+    assert_eq!(run(&mut pool.ramp_accounts(), ramp(10, NOW + MIN_RAMP_DURATION)), Ok(()));
+}
+
+#[test]
+fn only_the_creator_can_ramp_a_stable_pool() {
+    let pool = PoolFixture::stable(1_000_000, 1_000_000, 1_000_000, 100_000, 100);
+    let mut accounts = pool.ramp_accounts();
+    accounts[0] = TestAccount::signer();
+    assert_eq!(
+        run(&mut accounts, ramp(200, NOW + MIN_RAMP_DURATION)),
+        Err(LiquidityPoolError::Unauthorized.into())
+    );
+
+    let pool = PoolFixture::new(1_000_000, 1_000_000, 1_000_000, 100_000, 0);
+    assert_eq!(pool.state().curve_type, CurveType::ConstantProduct);
+    assert_eq!(
+        run(&mut pool.ramp_accounts(), ramp(200, NOW + MIN_RAMP_DURATION)),
+        Err(LiquidityPoolError::InvalidAmplification.into())
+    );
+}
@@ -0,0 +1,64 @@
+mod common;
+
+use borsh::BorshDeserialize;
+use common::{run, PoolFixture};
+use liquidity_pool::{LiquidityPoolError, LiquidityPoolInstruction, PoolState};
+
+fn fixture() -> PoolFixture {
+    PoolFixture::new(1_000_000, 2_000_000, 1_414_213, 100_000, 0)
+}
+
+fn swap(amount_in: u64, min_out: u64, a_to_b: bool) -> LiquidityPoolInstruction {
+    LiquidityPoolInstruction::Swap { amount_in, min_out, a_to_b }
+}
+
+#[test]
+fn swaps_in_both_directions() {
+    let pool = fixture();
+    let mut accounts = pool.swap_accounts();
+    // 0.3% fee: 2_000_000 * 9_970 / 1_009_970 = 19_743
+    assert_eq!(run(&mut accounts, swap(10_000, 19_743, true)), Ok(()));
+    assert_eq!(accounts[4].token_amount(), 90_000);
+    assert_eq!(accounts[5].token_amount(), 119_743);
+
+    let state = PoolState::try_from_slice(&accounts[1].data).unwrap();
+    assert_eq!((state.token_a_reserve, state.token_b_reserve), (1_010_000, 1_980_257));
+
+    // The round trip loses the fee twice.
+    assert_eq!(run(&mut accounts, swap(19_743, 0, false)), Ok(()));
+    assert_eq!(accounts[4].token_amount(), 99_940);
+    assert_eq!(accounts[5].token_amount(), 100_000);
+}
+
+#[test]
+fn rejects_output_below_minimum() {
+    let pool = fixture();
+    let mut accounts = pool.swap_accounts();
+    assert_eq!(
+        run(&mut accounts, swap(10_000, 19_744, true)),
+        Err(LiquidityPoolError::SlippageExceeded.into())
+    );
+}
+
+#[test]
+fn legacy_variants_match_unified_swap() {
+    let pool = fixture();
+
+    let mut unified = pool.swap_accounts();
+    assert_eq!(run(&mut unified, swap(5_000, 0, false)), Ok(()));
+
+    // SwapBforA passes the swapper's B account before its A account.
+    let mut legacy = pool.swap_accounts();
+    legacy.swap(4, 5);
+    assert_eq!(run(&mut legacy, LiquidityPoolInstruction::SwapBforA { amount_b_in: 5_000 }), Ok(()));
+
+    assert_eq!(legacy[1].data, unified[1].data);
+    assert_eq!(legacy[5].token_amount(), unified[4].token_amount());
+    assert_eq!(legacy[4].token_amount(), unified[5].token_amount());
+
+    let mut unified = pool.swap_accounts();
+    assert_eq!(run(&mut unified, swap(5_000, 0, true)), Ok(()));
+    let mut legacy = pool.swap_accounts();
+    assert_eq!(run(&mut legacy, LiquidityPoolInstruction::SwapAforB { amount_a_in: 5_000 }), Ok(()));
+    assert_eq!(legacy[1].data, unified[1].data);
+}
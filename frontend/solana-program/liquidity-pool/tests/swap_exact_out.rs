@@ -0,0 +1,46 @@
+mod common;
+
+use borsh::BorshDeserialize;
+use common::{run, PoolFixture};
+use liquidity_pool::{LiquidityPoolError, LiquidityPoolInstruction, PoolState, SwapDirection};
+
+fn swap_exact_out(amount_out: u64, max_amount_in: u64, direction: SwapDirection) -> LiquidityPoolInstruction {
+    LiquidityPoolInstruction::SwapExactOut { amount_out, max_amount_in, direction }
+}
+
+#[test]
+fn buys_exact_amount_with_rounded_up_input() {
+    let pool = PoolFixture::new(1_000_000, 2_000_000, 1_414_213, 100_000, 0);
+    let mut accounts = pool.swap_accounts();
+    assert_eq!(run(&mut accounts, swap_exact_out(1_000, 10_000, SwapDirection::BToA)), Ok(()));
+
+    // ceil(2_000_000 * 1_000 * 10_000 / (999_000 * 9_970)) = 2_009
+    assert_eq!(accounts[4].token_amount(), 101_000);
+    assert_eq!(accounts[5].token_amount(), 100_000 - 2_009);
+
+    let state = PoolState::try_from_slice(&accounts[1].data).unwrap();
+    assert_eq!(state.token_a_reserve, 999_000);
+    assert_eq!(state.token_b_reserve, 2_002_009);
+    assert!(state.token_a_reserve as u128 * state.token_b_reserve as u128 >= 1_000_000u128 * 2_000_000);
+}
+
+#[test]
+fn rejects_input_above_maximum() {
+    let pool = PoolFixture::new(1_000_000, 2_000_000, 1_414_213, 100_000, 0);
+    let mut accounts = pool.swap_accounts();
+    assert_eq!(
+        run(&mut accounts, swap_exact_out(1_000, 501, SwapDirection::AToB)),
+        Err(LiquidityPoolError::SlippageExceeded.into())
+    );
+    assert_eq!(run(&mut accounts, swap_exact_out(1_000, 502, SwapDirection::AToB)), Ok(()));
+}
+
+#[test]
+fn rejects_draining_the_output_reserve() {
+    let pool = PoolFixture::new(1_000_000, 2_000_000, 1_414_213, 100_000, 0);
+    let mut accounts = pool.swap_accounts();
+    assert_eq!(
+        run(&mut accounts, swap_exact_out(2_000_000, u64::MAX, SwapDirection::AToB)),
+        Err(LiquidityPoolError::InsufficientFunds.into())
+    );
+}
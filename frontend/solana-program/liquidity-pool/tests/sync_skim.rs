@@ -0,0 +1,83 @@
+mod common;
+
+use borsh::BorshDeserialize;
+use common::{run, PoolFixture};
+use liquidity_pool::{LiquidityPoolError, LiquidityPoolInstruction, PoolState};
+
+fn fixture() -> PoolFixture {
+    PoolFixture::new(1_000_000, 2_000_000, 1_414_213, 100_000, 100_000)
+}
+
+fn state(data: &[u8]) -> PoolState {
+    PoolState::try_from_slice(data).unwrap()
+}
+
+#[test]
+fn sync_sets_reserves_to_vault_balances() {
+    let mut pool = fixture();
+    pool.vault_a.set_token_amount(1_000_500);
+    pool.vault_b.set_token_amount(2_000_700);
+
+    let mut accounts = pool.sync_accounts();
+    assert_eq!(run(&mut accounts, LiquidityPoolInstruction::Sync), Ok(()));
+
+    let synced = state(&accounts[0].data);
+    assert_eq!(synced.token_a_reserve, 1_000_500);
+    assert_eq!(synced.token_b_reserve, 2_000_700);
+}
+
+#[test]
+fn skim_sends_excess_to_recipient() {
+    let mut pool = fixture();
+    pool.vault_a.set_token_amount(1_000_500);
+
+    let mut accounts = pool.skim_accounts();
+    assert_eq!(run(&mut accounts, LiquidityPoolInstruction::Skim), Ok(()));
+
+    assert_eq!(accounts[1].token_amount(), 1_000_000);
+    assert_eq!(accounts[2].token_amount(), 2_000_000);
+    assert_eq!(accounts[3].token_amount(), 100_500);
+    assert_eq!(accounts[4].token_amount(), 100_000);
+    let skimmed = state(&accounts[0].data);
+    assert_eq!(skimmed.token_a_reserve, 1_000_000);
+    assert_eq!(skimmed.token_b_reserve, 2_000_000);
+}
+
+#[test]
+fn swap_tolerates_donations() {
+    let mut pool = fixture();
+    pool.vault_b.set_token_amount(2_500_000);
+    let mut accounts = pool.swap_accounts();
+    assert_eq!(
+        run(&mut accounts, LiquidityPoolInstruction::SwapAforB { amount_a_in: 1_000 }),
+        Ok(())
+    );
+}
+
+#[test]
+fn swap_rejects_vault_shortfall() {
+    let mut pool = fixture();
+    pool.vault_b.set_token_amount(1_999_999);
+    let mut accounts = pool.swap_accounts();
+    assert_eq!(
+        run(&mut accounts, LiquidityPoolInstruction::SwapAforB { amount_a_in: 1_000 }),
+        Err(LiquidityPoolError::ReserveMismatch.into())
+    );
+
+    let mut accounts = pool.skim_accounts();
+    assert_eq!(
+        run(&mut accounts, LiquidityPoolInstruction::Skim),
+        Err(LiquidityPoolError::ReserveMismatch.into())
+    );
+}
+
+#[test]
+fn sync_rejects_empty_vault() {
+    let mut pool = fixture();
+    pool.vault_a.set_token_amount(0);
+    let mut accounts = pool.sync_accounts();
+    assert_eq!(
+        run(&mut accounts, LiquidityPoolInstruction::Sync),
+        Err(LiquidityPoolError::InsufficientFunds.into())
+    );
+}
@@ -0,0 +1,156 @@
+mod common;
+
+use borsh::BorshDeserialize;
+use common::{
+    fee_config_account, initialize_pool_accounts_for_mints, registry_accounts, run, set_transfer_hook_blocked,
+    transfer_hook_calls, PoolFixture, TestAccount, TRANSFER_HOOK_ID, TRANSFER_HOOK_REJECTED,
+};
+use liquidity_pool::{curve, CurveType, LiquidityPoolError, LiquidityPoolInstruction, PoolState, SwapDirection, SWAP_FEE_BPS};
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+
+/// Transfer fee of the fee mints, in basis points.
+const TRANSFER_FEE_BPS: u64 = 100;
+
+fn fee_mint() -> TestAccount {
+    TestAccount::transfer_fee_mint(Pubkey::new_unique(), u64::MAX / 2, TRANSFER_FEE_BPS as u16, u64::MAX)
+}
+
+fn plain_mint() -> TestAccount {
+    TestAccount::mint_2022(Pubkey::new_unique(), u64::MAX / 2)
+}
+
+/// `amount` less the transfer fee, rounded in the mint's favour.
+fn after_fee(amount: u64) -> u64 {
+    amount - (amount * TRANSFER_FEE_BPS).div_ceil(10_000)
+}
+
+/// The given accounts followed by the pool's mints as trailing accounts.
+fn with_mints(pool: &PoolFixture, mut accounts: Vec<TestAccount>) -> Vec<TestAccount> {
+    accounts.extend([pool.mint_a.clone(), pool.mint_b.clone()]);
+    accounts
+}
+
+#[test]
+fn swap_credits_what_the_pool_receives() {
+    let pool = PoolFixture::for_mints(fee_mint(), plain_mint(), 1_000_000, 2_000_000, 1_414_213, 100_000, 0);
+    let received = after_fee(10_000);
+    let out = curve::swap_exact_in(received, 1_000_000, 2_000_000, SWAP_FEE_BPS).unwrap();
+    let mut accounts = with_mints(&pool, pool.swap_accounts());
+    let swap = LiquidityPoolInstruction::Swap { amount_in: 10_000, min_out: out, a_to_b: true };
+    assert_eq!(run(&mut accounts, swap), Ok(()));
+
+    assert_eq!(accounts[2].token_amount(), 1_000_000 + received);
+    assert_eq!(accounts[4].token_amount(), 100_000 - 10_000);
+    assert_eq!(accounts[5].token_amount(), 100_000 + out);
+}
+
+#[test]
+fn swap_slippage_is_checked_on_what_the_swapper_receives() {
+    let pool = PoolFixture::for_mints(plain_mint(), fee_mint(), 1_000_000, 2_000_000, 1_414_213, 100_000, 0);
+    let out = curve::swap_exact_in(10_000, 1_000_000, 2_000_000, SWAP_FEE_BPS).unwrap();
+    let mut accounts = with_mints(&pool, pool.swap_accounts());
+
+    let swap = |min_out| LiquidityPoolInstruction::Swap { amount_in: 10_000, min_out, a_to_b: true };
+    assert_eq!(run(&mut accounts, swap(out)), Err(LiquidityPoolError::SlippageExceeded.into()));
+    assert_eq!(accounts[4].token_amount(), 100_000);
+    assert_eq!(run(&mut accounts, swap(after_fee(out))), Ok(()));
+    assert_eq!(accounts[5].token_amount(), 100_000 + after_fee(out));
+}
+
+#[test]
+fn swap_exact_out_delivers_the_amount_after_fees() {
+    let pool = PoolFixture::for_mints(fee_mint(), fee_mint(), 1_000_000, 2_000_000, 1_414_213, 100_000, 0);
+    let mut accounts = with_mints(&pool, pool.swap_accounts());
+    let swap = LiquidityPoolInstruction::SwapExactOut {
+        amount_out: 10_000,
+        max_amount_in: 100_000,
+        direction: SwapDirection::AToB,
+    };
+    assert_eq!(run(&mut accounts, swap), Ok(()));
+
+    assert_eq!(accounts[5].token_amount(), 100_000 + 10_000);
+    let state = PoolState::try_from_slice(&accounts[1].data).unwrap();
+    assert_eq!(state.token_a_reserve, accounts[2].token_amount());
+    assert_eq!(state.token_b_reserve, accounts[3].token_amount());
+}
+
+#[test]
+fn swap_of_a_fee_mint_requires_the_mint() {
+    let pool = PoolFixture::for_mints(fee_mint(), plain_mint(), 1_000_000, 2_000_000, 1_414_213, 100_000, 0);
+    let mut accounts = pool.swap_accounts();
+    let swap = LiquidityPoolInstruction::Swap { amount_in: 10_000, min_out: 0, a_to_b: true };
+    assert!(run(&mut accounts, swap).is_err());
+    assert_eq!(accounts[4].token_amount(), 100_000);
+}
+
+#[test]
+fn add_liquidity_records_what_the_pool_receives() {
+    let pool = PoolFixture::for_mints(fee_mint(), plain_mint(), 1_000_000, 2_000_000, 1_414_213, 100_000, 0);
+    let mut accounts = with_mints(&pool, pool.liquidity_accounts());
+    let add = LiquidityPoolInstruction::AddLiquidity { amount_a: 10_000, amount_b: 20_000 };
+    assert_eq!(run(&mut accounts, add), Ok(()));
+
+    let state = PoolState::try_from_slice(&accounts[1].data).unwrap();
+    assert_eq!(state.token_a_reserve, 1_000_000 + after_fee(10_000));
+    assert_eq!(state.token_a_reserve, accounts[2].token_amount());
+    assert_eq!(state.token_b_reserve, accounts[3].token_amount());
+    assert_eq!(state.lp_supply, 1_414_213 + accounts[7].token_amount());
+    // The LP tokens are priced on token A as received, not as sent.
+    assert!(accounts[7].token_amount() < 14_142);
+}
+
+#[test]
+fn initialize_pool_seeds_reserves_with_what_arrives() {
+    let (header, page) = registry_accounts(0);
+    let config = fee_config_account(Pubkey::new_unique(), &[SWAP_FEE_BPS]);
+    let mut mints = [fee_mint(), plain_mint()];
+    mints.sort_by_key(|mint| mint.key);
+    let mut accounts = initialize_pool_accounts_for_mints(&mints[0], &mints[1], &header, &page, &config, SWAP_FEE_BPS, 5_000_000);
+    accounts.extend(mints.iter().cloned());
+    let initialize = LiquidityPoolInstruction::InitializePool {
+        initial_amount_a: 1_000_000,
+        initial_amount_b: 2_000_000,
+        curve_type: CurveType::ConstantProduct,
+        amp: 0,
+        fee_bps: SWAP_FEE_BPS,
+    };
+    assert_eq!(run(&mut accounts, initialize), Ok(()));
+
+    let state = PoolState::try_from_slice(&accounts[1].data).unwrap();
+    assert_eq!(state.token_a_reserve, accounts[4].token_amount());
+    assert_eq!(state.token_b_reserve, accounts[5].token_amount());
+    assert_eq!(state.token_a_reserve + state.token_b_reserve, after_fee(1_000_000) + 2_000_000);
+}
+
+#[test]
+fn initialize_pool_rejects_unsupported_extensions() {
+    let (header, page) = registry_accounts(0);
+    let config = fee_config_account(Pubkey::new_unique(), &[SWAP_FEE_BPS]);
+    let mut mints = [TestAccount::permanent_delegate_mint(Pubkey::new_unique(), u64::MAX / 2), plain_mint()];
+    mints.sort_by_key(|mint| mint.key);
+    let mut accounts = initialize_pool_accounts_for_mints(&mints[0], &mints[1], &header, &page, &config, SWAP_FEE_BPS, 5_000_000);
+    let initialize = LiquidityPoolInstruction::InitializePool {
+        initial_amount_a: 1_000_000,
+        initial_amount_b: 2_000_000,
+        curve_type: CurveType::ConstantProduct,
+        amp: 0,
+        fee_bps: SWAP_FEE_BPS,
+    };
+    assert_eq!(run(&mut accounts, initialize), Err(LiquidityPoolError::UnsupportedMintExtension.into()));
+}
+
+#[test]
+fn swap_runs_the_transfer_hook() {
+    let mint_a = TestAccount::transfer_hook_mint(Pubkey::new_unique(), u64::MAX / 2);
+    let pool = PoolFixture::for_mints(mint_a, plain_mint(), 1_000_000, 2_000_000, 1_414_213, 100_000, 0);
+    let mut accounts = with_mints(&pool, pool.swap_accounts());
+    accounts.extend([TestAccount::program(TRANSFER_HOOK_ID), TestAccount::transfer_hook_validation(&pool.mint_a.key)]);
+    let swap = || LiquidityPoolInstruction::Swap { amount_in: 10_000, min_out: 0, a_to_b: true };
+
+    assert_eq!(run(&mut accounts, swap()), Ok(()));
+    assert_eq!(transfer_hook_calls(), 1);
+
+    set_transfer_hook_blocked(true);
+    assert_eq!(run(&mut accounts, swap()), Err(ProgramError::Custom(TRANSFER_HOOK_REJECTED)));
+    assert_eq!(accounts[4].token_amount(), 100_000 - 10_000);
+}
@@ -0,0 +1,237 @@
+mod common;
+
+use common::{run, TestAccount, WeightedPoolFixture};
+use liquidity_pool::curve;
+use liquidity_pool::stable_swap::U256;
+use liquidity_pool::weighted_math::{self, ONE};
+use liquidity_pool::weighted_pool::{WeightedPoolState, INITIAL_WEIGHTED_LP_SUPPLY};
+use borsh::BorshDeserialize;
+use liquidity_pool::{LiquidityPoolError, LiquidityPoolInstruction, MINIMUM_LIQUIDITY, SWAP_FEE_BPS};
+use proptest::prelude::*;
+use solana_program::{pubkey::Pubkey, rent::Rent};
+
+/// Weights for a 50/30/20 city index.
+const INDEX_WEIGHTS: [u64; 3] = [ONE as u64 / 2, ONE as u64 * 3 / 10, ONE as u64 / 5];
+
+fn index_pool() -> WeightedPoolFixture {
+    WeightedPoolFixture::new(&INDEX_WEIGHTS, &[5_000_000, 3_000_000, 2_000_000], 1_000_000)
+}
+
+fn swap(token_in: u8, token_out: u8, amount_in: u64, min_out: u64) -> LiquidityPoolInstruction {
+    LiquidityPoolInstruction::WeightedSwap { token_in, token_out, amount_in, min_out }
+}
+
+fn pow(base: u64, exponent: u64) -> U256 {
+    U256::from(base).pow(U256::from(exponent))
+}
+
+proptest! {
+    #[test]
+    fn swap_never_decreases_invariant(
+        balance_in in 1_000u64..=1_000_000_000,
+        balance_out in 1_000u64..=1_000_000_000,
+        in_fraction in 1u64..=300,
+        weight_in in 1u64..=4,
+        weight_out in 1u64..=4,
+        fee in prop_oneof![Just(0u64), Just(SWAP_FEE_BPS)],
+    ) {
+        // Small integer weights let the invariant be checked exactly.
+        let amount_in = balance_in * in_fraction / 1_000;
+        let scale = ONE as u64 / 10;
+        let amount_out = weighted_math::swap_exact_in(
+            amount_in, balance_in, weight_in * scale, balance_out, weight_out * scale, fee,
+        ).unwrap();
+        prop_assert!(amount_out < balance_out);
+
+        let before = pow(balance_in, weight_in) * pow(balance_out, weight_out);
+        let after = pow(balance_in + amount_in, weight_in) * pow(balance_out - amount_out, weight_out);
+        prop_assert!(after >= before);
+    }
+
+    #[test]
+    fn pow_up_is_an_upper_bound(base in ONE / 2..=ONE, exponent in ONE / 100..=ONE * 99) {
+        let power = weighted_math::pow_up(base, exponent).unwrap();
+        let expected = (base as f64 / ONE as f64).powf(exponent as f64 / ONE as f64);
+        let actual = power as f64 / ONE as f64;
+        prop_assert!(actual >= expected * (1.0 - 1e-12));
+        prop_assert!(actual - expected <= 1e-9);
+    }
+
+    #[test]
+    fn exit_after_join_never_profits(
+        balances in prop::collection::vec(1u64..=1_000_000_000_000, 2..=8),
+        lp_supply in 1_000u64..=1_000_000_000_000,
+        lp_amount in 1u64..=1_000_000_000_000,
+    ) {
+        let joined = weighted_math::join_amounts(lp_amount, &balances, lp_supply).unwrap();
+        let new_balances: Vec<u64> = balances.iter().zip(&joined).map(|(balance, amount)| balance + amount).collect();
+        let exited = weighted_math::exit_amounts(lp_amount, &new_balances, lp_supply + lp_amount).unwrap();
+        prop_assert!(exited.iter().zip(&joined).all(|(out, paid)| out <= paid));
+    }
+}
+
+#[test]
+fn equal_weights_match_constant_product() {
+    let half = ONE as u64 / 2;
+    let weighted = weighted_math::swap_exact_in(10_000, 1_000_000, half, 2_000_000, half, SWAP_FEE_BPS).unwrap();
+    let constant_product = curve::swap_exact_in(10_000, 1_000_000, 2_000_000, SWAP_FEE_BPS).unwrap();
+    assert!(weighted <= constant_product && weighted + 1 >= constant_product);
+}
+
+#[test]
+fn rejects_swaps_above_the_input_ratio() {
+    let half = ONE as u64 / 2;
+    assert!(weighted_math::swap_exact_in(300_000, 1_000_000, half, 1_000_000, half, 0).is_ok());
+    assert_eq!(
+        weighted_math::swap_exact_in(300_001, 1_000_000, half, 1_000_000, half, 0),
+        Err(curve::CurveError::InsufficientLiquidity)
+    );
+}
+
+#[test]
+fn initializes_a_three_token_pool() {
+    let pool = index_pool();
+    let state = pool.state();
+    assert_eq!(state.tokens.len(), 3);
+    assert_eq!(state.creator, pool.user.key);
+    assert_eq!(state.lp_supply, INITIAL_WEIGHTED_LP_SUPPLY);
+    assert_eq!(pool.user_lp.token_amount(), INITIAL_WEIGHTED_LP_SUPPLY - MINIMUM_LIQUIDITY);
+    assert_eq!(pool.pool_state.owner, liquidity_pool::id());
+    assert_eq!(pool.pool_state.lamports, Rent::default().minimum_balance(WeightedPoolState::LEN));
+    // The LP mint was created from a new keypair, with the pool as its authority.
+    assert_eq!(pool.lp_mint.owner, spl_token::id());
+    assert_eq!(pool.lp_mint.mint_supply(), pool.user_lp.token_amount());
+    for (i, token) in state.tokens.iter().enumerate() {
+        assert_eq!(token.weight, INDEX_WEIGHTS[i]);
+        // Each vault was created at its PDA and recorded
+        assert_eq!(token.vault, liquidity_pool::vault_address(&liquidity_pool::id(), &pool.pool_state.key, &token.mint).0);
+        assert_eq!((pool.vaults[i].key, pool.vaults[i].owner), (token.vault, spl_token::id()));
+        assert_eq!(pool.vaults[i].token_amount(), token.reserve);
+        assert_eq!(pool.user_tokens[i].token_amount(), 1_000_000);
+    }
+}
+
+#[test]
+fn rejects_invalid_weights() {
+    let quarter = ONE as u64 / 4;
+    for weights in [vec![quarter, quarter], vec![ONE as u64 - 1, 1], vec![ONE as u64]] {
+        assert_eq!(
+            WeightedPoolFixture::try_new(&weights, &vec![1_000_000; weights.len()], 0).err(),
+            Some(LiquidityPoolError::InvalidWeights.into()),
+            "{weights:?}"
+        );
+    }
+}
+
+#[test]
+fn swaps_between_any_pair() {
+    let pool = index_pool();
+    let (token_1, token_2) = (pool.token(1), pool.token(2));
+    let expected = weighted_math::swap_exact_in(
+        30_000, token_1.reserve, token_1.weight, token_2.reserve, token_2.weight, SWAP_FEE_BPS,
+    )
+    .unwrap();
+
+    let mut accounts = pool.swap_accounts(1, 2);
+    assert_eq!(
+        run(&mut accounts, swap(1, 2, 30_000, expected + 1)),
+        Err(LiquidityPoolError::SlippageExceeded.into())
+    );
+    assert_eq!(run(&mut accounts, swap(1, 2, 30_000, expected)), Ok(()));
+    assert_eq!(accounts[4].token_amount(), 1_000_000 - 30_000);
+    assert_eq!(accounts[5].token_amount(), 1_000_000 + expected);
+    // At 30:20 weights and 3:2 balances the spot price is 1:1, less the fee.
+    assert!((19_000..30_000).contains(&expected), "{expected}");
+}
+
+#[test]
+fn rejects_vault_of_another_token() {
+    let pool = index_pool();
+    let mut accounts = pool.swap_accounts(0, 1);
+    accounts[3] = pool.vaults[2].clone();
+    assert_eq!(
+        run(&mut accounts, swap(0, 1, 10_000, 0)),
+        Err(LiquidityPoolError::InvalidTokenAccount.into())
+    );
+    assert_eq!(
+        run(&mut pool.swap_accounts(0, 0), swap(0, 0, 10_000, 0)),
+        Err(LiquidityPoolError::InvalidInstruction.into())
+    );
+}
+
+#[test]
+fn rejects_a_look_alike_vault() {
+    let pool = index_pool();
+    let mut accounts = pool.swap_accounts(0, 1);
+    accounts[2] = TestAccount::token_account(pool.mints[0].key, pool.pool_state.key, pool.token(0).reserve);
+    assert_eq!(
+        run(&mut accounts, swap(0, 1, 10_000, 0)),
+        Err(LiquidityPoolError::InvalidTokenAccount.into())
+    );
+}
+
+#[test]
+fn rejects_a_pool_of_another_type() {
+    let pool = index_pool();
+    let mut accounts = pool.swap_accounts(0, 1);
+    accounts[1].data[..8].copy_from_slice(&[0; 8]);
+    assert_eq!(
+        run(&mut accounts, swap(0, 1, 10_000, 0)),
+        Err(LiquidityPoolError::InvalidAccountType.into())
+    );
+}
+
+#[test]
+fn joins_and_exits_proportionally() {
+    let pool = index_pool();
+    let lp_amount = INITIAL_WEIGHTED_LP_SUPPLY / 10;
+
+    let mut accounts = pool.liquidity_accounts();
+    let join = |max_amounts: Vec<u64>| LiquidityPoolInstruction::JoinWeightedPool { lp_amount, max_amounts };
+    assert_eq!(
+        run(&mut accounts, join(vec![500_000, 300_000, 199_999])),
+        Err(LiquidityPoolError::SlippageExceeded.into())
+    );
+    assert_eq!(run(&mut accounts, join(vec![500_000, 300_000, 200_000])), Ok(()));
+    assert_eq!(accounts[3].token_amount(), INITIAL_WEIGHTED_LP_SUPPLY - MINIMUM_LIQUIDITY + lp_amount);
+    assert_eq!(accounts[6].token_amount(), 500_000);
+
+    let exit = |min_amounts: Vec<u64>| LiquidityPoolInstruction::ExitWeightedPool { lp_amount, min_amounts };
+    assert_eq!(run(&mut accounts, exit(vec![0, 0, 0])), Ok(()));
+    assert_eq!(accounts[3].token_amount(), INITIAL_WEIGHTED_LP_SUPPLY - MINIMUM_LIQUIDITY);
+    for i in 0..3 {
+        assert_eq!(accounts[6 + 2 * i].token_amount(), 1_000_000);
+        assert_eq!(accounts[5 + 2 * i].token_amount(), pool.vaults[i].token_amount());
+    }
+}
+
+#[test]
+fn reserves_track_what_a_fee_mint_delivers() {
+    let mints = vec![
+        TestAccount::transfer_fee_mint(Pubkey::new_unique(), u64::MAX / 2, 100, u64::MAX),
+        TestAccount::mint_2022(Pubkey::new_unique(), u64::MAX / 2),
+        TestAccount::mint_2022(Pubkey::new_unique(), u64::MAX / 2),
+    ];
+    let pool = WeightedPoolFixture::try_for_mints(&INDEX_WEIGHTS, mints, &[5_000_000, 3_000_000, 2_000_000], 1_000_000).unwrap();
+    let reserves_match = |accounts: &[TestAccount], vaults: &[usize]| {
+        let state = WeightedPoolState::deserialize(&mut &accounts[1].data[..]).unwrap();
+        vaults.iter().zip(&state.tokens).all(|(&vault, token)| accounts[vault].token_amount() == token.reserve)
+    };
+    assert_eq!(pool.token(0).reserve, 5_000_000 - 50_000);
+    assert_eq!(pool.vaults[0].token_amount(), pool.token(0).reserve);
+
+    // Swapping in the fee mint prices and credits only what arrives
+    let (token_0, token_1) = (pool.token(0), pool.token(1));
+    let expected = weighted_math::swap_exact_in(99_000, token_0.reserve, token_0.weight, token_1.reserve, token_1.weight, SWAP_FEE_BPS).unwrap();
+    let mut accounts = pool.swap_accounts(0, 1);
+    accounts.extend(pool.mints.iter().cloned());
+    assert_eq!(run(&mut accounts, swap(0, 1, 100_000, expected)), Ok(()));
+    assert!(reserves_match(&accounts, &[2, 3]));
+
+    // Joining pays the fee on top, so the pool still receives its share
+    let mut accounts = pool.liquidity_accounts();
+    accounts.extend(pool.mints.iter().cloned());
+    let join = LiquidityPoolInstruction::JoinWeightedPool { lp_amount: INITIAL_WEIGHTED_LP_SUPPLY / 10, max_amounts: vec![u64::MAX; 3] };
+    assert_eq!(run(&mut accounts, join), Ok(()));
+    assert!(reserves_match(&accounts, &[5, 7, 9]));
+}
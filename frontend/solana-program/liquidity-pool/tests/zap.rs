@@ -0,0 +1,112 @@
+mod common;
+
+use borsh::BorshDeserialize;
+use common::{run, PoolFixture, TestAccount, NOW};
+use liquidity_pool::{curve, LiquidityPoolError, LiquidityPoolInstruction, PoolState, SWAP_FEE_BPS};
+
+/// Accounts for `AddLiquiditySingle` / `RemoveLiquiditySingle` on the user's
+/// token A (`token == 0`) or B account.
+fn zap_accounts(pool: &PoolFixture, token: u8) -> Vec<TestAccount> {
+    vec![
+        pool.user.clone(),
+        pool.pool_state.clone(),
+        pool.vault_a.clone(),
+        pool.vault_b.clone(),
+        pool.lp_mint.clone(),
+        if token == 0 { pool.user_a.clone() } else { pool.user_b.clone() },
+        pool.user_lp.clone(),
+        pool.token_program.clone(),
+    ]
+}
+
+fn state(accounts: &[TestAccount]) -> PoolState {
+    PoolState::try_from_slice(&accounts[1].data).unwrap()
+}
+
+#[test]
+fn deposits_one_side_at_the_pool_ratio() {
+    let pool = PoolFixture::new(1_000_000, 2_000_000, 1_414_213, 100_000, 0);
+    let (swapped, out, deposited) = pool.state().zap_in(true, 10_000, NOW).unwrap();
+    assert_eq!(out, curve::swap_exact_in(swapped, 1_000_000, 2_000_000, SWAP_FEE_BPS).unwrap());
+    let expected_lp =
+        curve::lp_tokens_for_deposit(deposited, out, 1_000_000 + swapped, 2_000_000 - out, 1_414_213).unwrap();
+
+    let mut accounts = zap_accounts(&pool, 0);
+    let zap = LiquidityPoolInstruction::AddLiquiditySingle { token_in: 0, amount_in: 10_000, min_lp_out: expected_lp };
+    assert_eq!(run(&mut accounts, zap), Ok(()));
+
+    // Nearly all of the input is used, and only token A moves.
+    let taken = 100_000 - accounts[5].token_amount();
+    assert_eq!(taken, swapped + deposited);
+    assert!(10_000 - taken <= 3, "left {}", 10_000 - taken);
+    assert_eq!(accounts[6].token_amount(), expected_lp);
+    assert_eq!(accounts[2].token_amount(), 1_000_000 + taken);
+    assert_eq!(accounts[3].token_amount(), 2_000_000);
+
+    let state = state(&accounts);
+    assert_eq!((state.token_a_reserve, state.token_b_reserve), (1_000_000 + taken, 2_000_000));
+    assert_eq!(state.lp_supply, 1_414_213 + expected_lp);
+}
+
+#[test]
+fn deposits_token_b_into_a_stable_pool() {
+    let pool = PoolFixture::stable(1_000_000, 1_000_000, 2_000_000, 100_000, 100);
+    let mut accounts = zap_accounts(&pool, 1);
+    let zap = LiquidityPoolInstruction::AddLiquiditySingle { token_in: 1, amount_in: 50_000, min_lp_out: 0 };
+    assert_eq!(run(&mut accounts, zap), Ok(()));
+
+    // One LP token per unit of value near 1:1, less the fee on the half swapped.
+    let minted = accounts[6].token_amount();
+    assert!((49_900..50_000).contains(&minted), "minted {minted}");
+    assert!(100_000 - accounts[5].token_amount() >= 49_997);
+}
+
+#[test]
+fn checks_the_lp_minted_and_the_token() {
+    let pool = PoolFixture::new(1_000_000, 2_000_000, 1_414_213, 100_000, 0);
+    let (swapped, out, deposited) = pool.state().zap_in(true, 10_000, NOW).unwrap();
+    let minted =
+        curve::lp_tokens_for_deposit(deposited, out, 1_000_000 + swapped, 2_000_000 - out, 1_414_213).unwrap();
+
+    let mut accounts = zap_accounts(&pool, 0);
+    let zap = LiquidityPoolInstruction::AddLiquiditySingle { token_in: 0, amount_in: 10_000, min_lp_out: minted + 1 };
+    assert_eq!(run(&mut accounts, zap), Err(LiquidityPoolError::SlippageExceeded.into()));
+
+    let zap = LiquidityPoolInstruction::AddLiquiditySingle { token_in: 2, amount_in: 10_000, min_lp_out: 0 };
+    assert_eq!(run(&mut accounts, zap), Err(LiquidityPoolError::InvalidInstruction.into()));
+}
+
+#[test]
+fn withdraws_to_one_side() {
+    let pool = PoolFixture::new(1_000_000, 2_000_000, 1_414_213, 0, 100_000);
+    let (amount_a, amount_b) = curve::withdraw_amounts(100_000, 1_000_000, 2_000_000, 1_414_213).unwrap();
+    let bought =
+        curve::swap_exact_in(amount_b, 2_000_000 - amount_b, 1_000_000 - amount_a, SWAP_FEE_BPS).unwrap();
+
+    let mut accounts = zap_accounts(&pool, 0);
+    let remove = |min_amount_out| LiquidityPoolInstruction::RemoveLiquiditySingle { token_out: 0, lp_amount: 100_000, min_amount_out };
+    assert_eq!(run(&mut accounts, remove(amount_a + bought + 1)), Err(LiquidityPoolError::SlippageExceeded.into()));
+    assert_eq!(run(&mut accounts, remove(amount_a + bought)), Ok(()));
+
+    assert_eq!(accounts[5].token_amount(), amount_a + bought);
+    assert_eq!(accounts[6].token_amount(), 0);
+    assert_eq!(accounts[3].token_amount(), 2_000_000);
+    let state = state(&accounts);
+    assert_eq!(state.token_a_reserve, accounts[2].token_amount());
+    assert_eq!(state.token_b_reserve, 2_000_000);
+    assert_eq!(state.lp_supply, 1_414_213 - 100_000);
+}
+
+#[test]
+fn a_round_trip_costs_only_fees() {
+    let pool = PoolFixture::new(1_000_000, 2_000_000, 1_414_213, 100_000, 0);
+    let mut accounts = zap_accounts(&pool, 0);
+    let zap = LiquidityPoolInstruction::AddLiquiditySingle { token_in: 0, amount_in: 10_000, min_lp_out: 0 };
+    assert_eq!(run(&mut accounts, zap), Ok(()));
+
+    let minted = accounts[6].token_amount();
+    let remove = LiquidityPoolInstruction::RemoveLiquiditySingle { token_out: 0, lp_amount: minted, min_amount_out: 0 };
+    assert_eq!(run(&mut accounts, remove), Ok(()));
+    let lost = 100_000 - accounts[5].token_amount();
+    assert!(lost > 0 && lost < 10_000 * SWAP_FEE_BPS / 10_000 + 5, "lost {lost}");
+}
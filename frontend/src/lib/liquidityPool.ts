@@ -39,9 +39,24 @@ import { BN } from 'bn.js';
 // Program ID for the deployed liquidity pool program
 const PROGRAM_ID = new PublicKey("64Kd3NVVfKLcfxXsNLEvriNSiuzGpeTaBqSLwk4vXx2Y");
 
+// Default swap fee tier in basis points (SWAP_FEE_BPS in the Rust program)
+export const SWAP_FEE_BPS = 30;
+// Registry layout (RegistryEntry::LEN and REGISTRY_PAGE_CAPACITY in the Rust program)
+const REGISTRY_ENTRY_LEN = 32 + 32 + 32 + 32 + 8 + 8;
+const REGISTRY_PAGE_CAPACITY = 32;
+
+// Layout version of PoolState (POOL_STATE_VERSION in the Rust program)
+export const POOL_STATE_VERSION = 2;
+// First bytes of every PoolState account (POOL_STATE_DISCRIMINATOR in the Rust program)
+export const POOL_STATE_DISCRIMINATOR = Uint8Array.from([247, 237, 227, 245, 215, 195, 222, 70]);
+// Zeroed bytes at the end of PoolState kept for later fields (POOL_STATE_RESERVED in the Rust program)
+export const POOL_STATE_RESERVED = 32;
+
 // Borsh schema for PoolState (must match Rust program)
 export class PoolState {
+    discriminator: Uint8Array = POOL_STATE_DISCRIMINATOR;
     is_initialized: number = 0; // Rust `bool` is treated as `u8` by borsh
+    version: number = 0;
     token_a_mint: PublicKey = PublicKey.default;
     token_b_mint: PublicKey = PublicKey.default;
     token_a_reserve: BN = new BN(0);
@@ -49,28 +64,31 @@ export class PoolState {
     lp_mint: PublicKey = PublicKey.default;
     lp_supply: BN = new BN(0);
     bump_seed: number = 0;
-
-    static LEN: number = 1 + 32 + 32 + 8 + 8 + 32 + 8 + 1;
-
-    constructor(fields?: {
-        is_initialized: number;
-        token_a_mint: PublicKey;
-        token_b_mint: PublicKey;
-        token_a_reserve: BN;
-        token_b_reserve: BN;
-        lp_mint: PublicKey;
-        lp_supply: BN;
-        bump_seed: number;
-    }) {
+    curve_type: number = 0; // 0 = ConstantProduct, 1 = Stable
+    creator: PublicKey = PublicKey.default;
+    initial_amp: BN = new BN(0);
+    target_amp: BN = new BN(0);
+    ramp_start_ts: BN = new BN(0);
+    ramp_stop_ts: BN = new BN(0);
+    price_a_cumulative_x64: BN = new BN(0);
+    price_b_cumulative_x64: BN = new BN(0);
+    last_update_ts: BN = new BN(0);
+    fee_bps: BN = new BN(0);
+    vault_a: PublicKey = PublicKey.default;
+    vault_b: PublicKey = PublicKey.default;
+    reserve_a_cumulative: BN = new BN(0);
+    reserve_b_cumulative: BN = new BN(0);
+    reserved: Uint8Array = new Uint8Array(POOL_STATE_RESERVED);
+
+    static LEN: number =
+        8 + 1 + 1 + 32 + 32 + 8 + 8 + 32 + 8 + 1 + 1 + 32 + 8 + 8 + 8 + 8 + 16 + 16 + 8 + 8 + 32 + 32 + 16 + 16 + POOL_STATE_RESERVED;
+    // Size of pools written before the layout was versioned; these must be
+    // upgraded with `migratePool` before the program accepts them.
+    static V1_LEN: number = 1 + 32 + 32 + 8 + 8 + 32 + 8 + 1;
+
+    constructor(fields?: Partial<PoolState>) {
         if (fields) {
-            this.is_initialized = fields.is_initialized;
-            this.token_a_mint = fields.token_a_mint;
-            this.token_b_mint = fields.token_b_mint;
-            this.token_a_reserve = fields.token_a_reserve;
-            this.token_b_reserve = fields.token_b_reserve;
-            this.lp_mint = fields.lp_mint;
-            this.lp_supply = fields.lp_supply;
-            this.bump_seed = fields.bump_seed;
+            Object.assign(this, fields);
         }
     }
 }
@@ -81,6 +99,8 @@ enum LiquidityPoolInstruction {
     RemoveLiquidity = 2,
     SwapAforB = 3,
     SwapBforA = 4,
+    MigratePool = 43,
+    ClosePool = 44,
 }
 
 // This is the structure for the instruction data, matching the Rust enum variants.
@@ -90,10 +110,16 @@ class InitializePoolInstructionData {
     instruction: number = LiquidityPoolInstruction.InitializePool;
     initial_amount_a: BN; // MUST match Rust struct field
     initial_amount_b: BN; // MUST match Rust struct field
+    curve_type: number; // 0 = ConstantProduct, 1 = Stable
+    amp: BN; // Must be zero unless curve_type is Stable
+    fee_bps: BN; // Must be one of the configured fee tiers
 
-    constructor(props: { initial_amount_a: BN; initial_amount_b: BN }) {
+    constructor(props: { initial_amount_a: BN; initial_amount_b: BN; curve_type: number; amp: BN; fee_bps: BN }) {
         this.initial_amount_a = props.initial_amount_a;
         this.initial_amount_b = props.initial_amount_b;
+        this.curve_type = props.curve_type;
+        this.amp = props.amp;
+        this.fee_bps = props.fee_bps;
     }
 }
 
@@ -135,6 +161,14 @@ class SwapBforAInstructionData {
     }
 }
 
+class MigratePoolInstructionData {
+    instruction: number = LiquidityPoolInstruction.MigratePool;
+}
+
+class ClosePoolInstructionData {
+    instruction: number = LiquidityPoolInstruction.ClosePool;
+}
+
 // Combined schema for all data structures
 // eslint-disable-next-line @typescript-eslint/no-explicit-any
 const LIQUIDITY_POOL_SCHEMA: Schema = new Map<any, any>([ // <-- Use Map<any, any> to fix TS error
@@ -143,7 +177,9 @@ const LIQUIDITY_POOL_SCHEMA: Schema = new Map<any, any>([ // <-- Use Map<any, an
         {
             kind: 'struct',
             fields: [
+                ['discriminator', [8]],
                 ['is_initialized', 'u8'],
+                ['version', 'u8'],
                 ['token_a_mint', 'pubkey'],
                 ['token_b_mint', 'pubkey'],
                 ['token_a_reserve', 'u64'],
@@ -151,6 +187,21 @@ const LIQUIDITY_POOL_SCHEMA: Schema = new Map<any, any>([ // <-- Use Map<any, an
                 ['lp_mint', 'pubkey'],
                 ['lp_supply', 'u64'],
                 ['bump_seed', 'u8'],
+                ['curve_type', 'u8'],
+                ['creator', 'pubkey'],
+                ['initial_amp', 'u64'],
+                ['target_amp', 'u64'],
+                ['ramp_start_ts', 'u64'],
+                ['ramp_stop_ts', 'u64'],
+                ['price_a_cumulative_x64', 'u128'],
+                ['price_b_cumulative_x64', 'u128'],
+                ['last_update_ts', 'u64'],
+                ['fee_bps', 'u64'],
+                ['vault_a', 'pubkey'],
+                ['vault_b', 'pubkey'],
+                ['reserve_a_cumulative', 'u128'],
+                ['reserve_b_cumulative', 'u128'],
+                ['reserved', [POOL_STATE_RESERVED]],
             ],
         },
     ],
@@ -160,6 +211,9 @@ const LIQUIDITY_POOL_SCHEMA: Schema = new Map<any, any>([ // <-- Use Map<any, an
         ['instruction', 'u8'],
         ['initial_amount_a', 'u64'],
         ['initial_amount_b', 'u64'],
+        ['curve_type', 'u8'],
+        ['amp', 'u64'],
+        ['fee_bps', 'u64'],
     ]
 }],
     [AddLiquidityInstructionData, {
@@ -191,6 +245,18 @@ const LIQUIDITY_POOL_SCHEMA: Schema = new Map<any, any>([ // <-- Use Map<any, an
             ['amount_b_in', 'u64'], // Use snake_case
         ]
     }],
+    [MigratePoolInstructionData, {
+        kind: 'struct',
+        fields: [
+            ['instruction', 'u8'],
+        ]
+    }],
+    [ClosePoolInstructionData, {
+        kind: 'struct',
+        fields: [
+            ['instruction', 'u8'],
+        ]
+    }],
 ]);
 
 
@@ -199,8 +265,6 @@ export class LiquidityPoolClient {
     private connection: Connection;
     private wallet: WalletContextState;
     private programId: PublicKey;
-    private poolStatePda: PublicKey | null = null;
-    private poolStateBump: number | null = null;
 
     constructor(connection: Connection, wallet: WalletContextState) {
         this.connection = connection;
@@ -208,26 +272,98 @@ export class LiquidityPoolClient {
         this.programId = PROGRAM_ID;
     }
 
-    async getPoolStatePda(tokenAMint: PublicKey, tokenBMint: PublicKey): Promise<[PublicKey, number]> {
-        if (this.poolStatePda && this.poolStateBump) {
-            return [this.poolStatePda, this.poolStateBump];
-        }
-        const [pda, bump] = await PublicKey.findProgramAddress(
+    async getPoolStatePda(
+        tokenAMint: PublicKey,
+        tokenBMint: PublicKey,
+        feeBps: number = SWAP_FEE_BPS
+    ): Promise<[PublicKey, number]> {
+        return PublicKey.findProgramAddress(
+            [
+                Buffer.from("liquidity_pool"),
+                tokenAMint.toBuffer(),
+                tokenBMint.toBuffer(),
+                new BN(feeBps).toArrayLike(Buffer, 'le', 8),
+            ],
+            this.programId
+        );
+    }
+
+    // Pools created before fee tiers sit at the address derived from their
+    // mints alone.
+    async getLegacyPoolStatePda(tokenAMint: PublicKey, tokenBMint: PublicKey): Promise<[PublicKey, number]> {
+        return PublicKey.findProgramAddress(
             [Buffer.from("liquidity_pool"), tokenAMint.toBuffer(), tokenBMint.toBuffer()],
             this.programId
         );
-        this.poolStatePda = pda;
-        this.poolStateBump = bump;
-        return [pda, bump];
     }
 
-    async fetchPoolState(tokenAMint: PublicKey, tokenBMint: PublicKey): Promise<PoolState | null> {
-        const [poolStatePda] = await this.getPoolStatePda(tokenAMint, tokenBMint);
+    async getVaultPda(poolStatePda: PublicKey, mint: PublicKey): Promise<PublicKey> {
+        const [vault] = await PublicKey.findProgramAddress(
+            [Buffer.from("vault"), poolStatePda.toBuffer(), mint.toBuffer()],
+            this.programId
+        );
+        return vault;
+    }
+
+    async getRegistryHeaderPda(): Promise<PublicKey> {
+        const [header] = await PublicKey.findProgramAddress([Buffer.from("registry")], this.programId);
+        return header;
+    }
+
+    async getRegistryPagePda(pageIndex: number): Promise<PublicKey> {
+        const pageSeed = Buffer.alloc(4);
+        pageSeed.writeUInt32LE(pageIndex);
+        const [page] = await PublicKey.findProgramAddress([Buffer.from("registry_page"), pageSeed], this.programId);
+        return page;
+    }
+
+    // Number of pools ever recorded in the registry, closed ones included.
+    async fetchRegistryPoolCount(): Promise<number> {
+        const header = await this.connection.getAccountInfo(await this.getRegistryHeaderPda());
+        if (!header || header.owner.equals(SystemProgram.programId)) {
+            return 0;
+        }
+        // discriminator: [u8; 8], is_initialized: u8, then pool_count: u64
+        return Number(header.data.readBigUInt64LE(9));
+    }
+
+    // Finds the registry page holding the entry of an open pool.
+    async findRegistryPage(poolStatePda: PublicKey): Promise<PublicKey> {
+        const pageCount = Math.ceil((await this.fetchRegistryPoolCount()) / REGISTRY_PAGE_CAPACITY);
+        for (let pageIndex = 0; pageIndex < pageCount; pageIndex++) {
+            const pagePda = await this.getRegistryPagePda(pageIndex);
+            const page = await this.connection.getAccountInfo(pagePda);
+            if (!page) {
+                continue;
+            }
+            // discriminator: [u8; 8], is_initialized: u8, page_index: u32, then
+            // the entries as a borsh Vec
+            const entryCount = page.data.readUInt32LE(13);
+            for (let i = 0; i < entryCount; i++) {
+                // The pool follows the two mints in each entry
+                const poolOffset = 17 + i * REGISTRY_ENTRY_LEN + 64;
+                if (new PublicKey(page.data.subarray(poolOffset, poolOffset + 32)).equals(poolStatePda)) {
+                    return pagePda;
+                }
+            }
+        }
+        throw new Error("Pool is not in the registry.");
+    }
+
+    async fetchPoolState(
+        tokenAMint: PublicKey,
+        tokenBMint: PublicKey,
+        feeBps: number = SWAP_FEE_BPS
+    ): Promise<PoolState | null> {
+        const [poolStatePda] = await this.getPoolStatePda(tokenAMint, tokenBMint, feeBps);
         const accountInfo = await this.connection.getAccountInfo(poolStatePda);
 
         if (!accountInfo) {
             return null;
         }
+        if (accountInfo.data.length === PoolState.V1_LEN) {
+            throw new Error("Pool uses an outdated layout; migrate it with migratePool first.");
+        }
 
         const deserializedPoolState: PoolState = deserialize(LIQUIDITY_POOL_SCHEMA, PoolState, accountInfo.data);
         return deserializedPoolState;
@@ -246,7 +382,8 @@ export class LiquidityPoolClient {
         for (const account of accounts) {
             try {
                 const deserializedPoolState: PoolState = deserialize(LIQUIDITY_POOL_SCHEMA, PoolState, account.account.data);
-                if (deserializedPoolState.is_initialized) {
+                const isPoolState = POOL_STATE_DISCRIMINATOR.every((byte, i) => deserializedPoolState.discriminator[i] === byte);
+                if (isPoolState && deserializedPoolState.is_initialized) {
                     poolStates.push(deserializedPoolState);
                 }
             } catch (error) {
@@ -261,33 +398,46 @@ export class LiquidityPoolClient {
         tokenAMint: PublicKey,
         tokenBMint: PublicKey,
         initialAmountA: number,
-        initialAmountB: number
+        initialAmountB: number,
+        feeBps: number = SWAP_FEE_BPS,
+        curveType: number = 0,
+        amp: number = 0
     ): Promise<string> {
         // --- CHECK WALLET ---
-        // We need 'signTransaction' for the 2-tx flow
         if (!this.wallet.publicKey || !this.wallet.signTransaction) { 
             throw new Error("Wallet not connected or does not support signing transactions");
         }
 
-        const [poolStatePda, bumpSeed] = await this.getPoolStatePda(tokenAMint, tokenBMint);
+        const [poolStatePda] = await this.getPoolStatePda(tokenAMint, tokenBMint, feeBps);
 
         // --- Debugging PDA Derivation ---
         console.log("--- PDA Derivation Details ---");
         console.log("Derived Pool State PDA:", poolStatePda.toBase58());
         console.log("------------------------------");
 
-        const lpMintAccount = Keypair.generate(); // This is the keypair for the NEW LP MINT
+        // The program creates the pool state, the LP mint and the vaults at
+        // their PDAs, and the initializer's LP token account from a new keypair
+        const [lpMintPda] = await PublicKey.findProgramAddress(
+            [Buffer.from("lp_mint"), poolStatePda.toBuffer()],
+            this.programId
+        );
+        const poolTokenAAccount = await this.getVaultPda(poolStatePda, tokenAMint);
+        const poolTokenBAccount = await this.getVaultPda(poolStatePda, tokenBMint);
+        const initializerLpTokenAccount = Keypair.generate();
+
+        // The new pool is recorded in the registry page its index falls in
+        const registryHeaderPda = await this.getRegistryHeaderPda();
+        const poolCount = await this.fetchRegistryPoolCount();
+        const registryPagePda = await this.getRegistryPagePda(Math.floor(poolCount / REGISTRY_PAGE_CAPACITY));
+        const [feeConfigPda] = await PublicKey.findProgramAddress([Buffer.from("fee_config")], this.programId);
 
         // --- Derive All Account Addresses ---
         const initializerTokenAAccount = await getAssociatedTokenAddress(tokenAMint, initializer);
         const initializerTokenBAccount = await getAssociatedTokenAddress(tokenBMint, initializer);
-        const initializerLpTokenAccount = await getAssociatedTokenAddress(lpMintAccount.publicKey, initializer);
-        const poolTokenAAccount = await getAssociatedTokenAddress(tokenAMint, poolStatePda, true);
-        const poolTokenBAccount = await getAssociatedTokenAddress(tokenBMint, poolStatePda, true);
 
         // Check if Pool State PDA already exists
         const existingPoolStateAccountInfo = await this.connection.getAccountInfo(poolStatePda);
-        if (existingPoolStateAccountInfo) {
+        if (existingPoolStateAccountInfo && !existingPoolStateAccountInfo.owner.equals(SystemProgram.programId)) {
             console.error("Error: Pool State PDA already exists!");
             console.error("Existing Pool State PDA Owner:", existingPoolStateAccountInfo.owner.toBase58());
             throw new Error("Pool already exists at this PDA. Please use a different pair of token mints or ensure the pool is not already initialized.");
@@ -298,7 +448,8 @@ export class LiquidityPoolClient {
         const lpMintAccountLamports = await this.connection.getMinimumBalanceForRentExemption(MintLayout.span);
         const ataRentExemption = await this.connection.getMinimumBalanceForRentExemption(AccountLayout.span);
 
-        let totalRentRequired = poolStateAccountLamports + lpMintAccountLamports;
+        // Pool state, LP mint, both vaults and the initializer's LP account
+        let totalRentRequired = poolStateAccountLamports + lpMintAccountLamports + ataRentExemption * 3;
 
         // We check for the user's ATAs first (optional but good)
         const accountInfoA = await this.connection.getAccountInfo(initializerTokenAAccount);
@@ -307,14 +458,7 @@ export class LiquidityPoolClient {
         // Add rent for ATAs if they are being created
         if (!accountInfoA) totalRentRequired += ataRentExemption;
         if (!accountInfoB) totalRentRequired += ataRentExemption;
-        // Initializer LP ATA is always created
-        totalRentRequired += ataRentExemption;
-        // Pool Token A/B ATAs are always created
-        totalRentRequired += ataRentExemption * 2;
-
-        console.log("Required lamports for Pool State Account:", poolStateAccountLamports);
-        console.log("Required lamports for LP Mint Account:", lpMintAccountLamports);
-        console.log("Required lamports for ATA rent exemption:", ataRentExemption);
+
         console.log("Total estimated rent required for new accounts:", totalRentRequired);
 
         const initializerBalance = await this.connection.getBalance(initializer);
@@ -324,141 +468,48 @@ export class LiquidityPoolClient {
             throw new Error(`Insufficient SOL. Required: ${totalRentRequired} lamports, Available: ${initializerBalance} lamports.`);
         }
 
-        // ====================================================================
-        // --- TRANSACTION 1a: Create Core Accounts (Pool State & LP Mint) ---
-        // ====================================================================
-        console.log("--- Building Transaction 1a: Create Core Accounts ---");
-        const createCoreAccountsTx = new Transaction();
-
-        // 1. Create Pool State Account (PDA)
-        console.log("Adding: Create Pool State Account (PDA)");
-        createCoreAccountsTx.add(
-            SystemProgram.createAccount({
-                fromPubkey: initializer,
-                newAccountPubkey: poolStatePda,
-                lamports: poolStateAccountLamports,
-                space: PoolState.LEN,
-                programId: this.programId,
-            })
-        );
-
-        // 2. Create LP Mint Account
-        console.log("Adding: Create LP Mint Account");
-        createCoreAccountsTx.add(
-            SystemProgram.createAccount({
-                fromPubkey: initializer,
-                newAccountPubkey: lpMintAccount.publicKey,
-                lamports: lpMintAccountLamports,
-                space: MintLayout.span,
-                programId: TOKEN_PROGRAM_ID,
-            })
-        );
-
-        // --- Sign and Send Transaction 1a ---
-        console.log("Sending Transaction 1a to wallet...");
-        const latestBlockhash1a = await this.connection.getLatestBlockhash();
-        createCoreAccountsTx.feePayer = this.wallet.publicKey;
-        createCoreAccountsTx.recentBlockhash = latestBlockhash1a.blockhash;
-        const messageV0_1a = createCoreAccountsTx.compileMessage();
-        const v0CreateCoreAccountsTx = new VersionedTransaction(messageV0_1a);
-        v0CreateCoreAccountsTx.sign([lpMintAccount]); // lpMintAccount needs to pre-sign its creation
-        const createCoreTxSignature = await this.wallet.sendTransaction(v0CreateCoreAccountsTx, this.connection, {
-            preflightCommitment: 'confirmed',
-        });
-        console.log("--- Transaction 1a Confirmed ---");
-        console.log("Signature:", createCoreTxSignature);
-        await this.connection.confirmTransaction({
-            ...latestBlockhash1a,
-            signature: createCoreTxSignature,
-        }, 'confirmed');
-        console.log("Core accounts created successfully.");
-
-        // ====================================================================
-        // --- TRANSACTION 1b: Create Associated Token Accounts ---
-        // ====================================================================
-        console.log("--- Building Transaction 1b: Create ATAs ---");
-        const createATAsTx = new Transaction();
+        const initializeTx = new Transaction();
 
-        // We check for the user's ATAs first (optional but good)
         if (!accountInfoA) {
-            console.log("Adding: Create Initializer Token A ATA");
-            createATAsTx.add(createAssociatedTokenAccountInstruction(
+            initializeTx.add(createAssociatedTokenAccountInstruction(
                 initializer, initializerTokenAAccount, initializer, tokenAMint, TOKEN_PROGRAM_ID
             ));
         }
-
         if (!accountInfoB) {
-            console.log("Adding: Create Initializer Token B ATA");
-            createATAsTx.add(createAssociatedTokenAccountInstruction(
+            initializeTx.add(createAssociatedTokenAccountInstruction(
                 initializer, initializerTokenBAccount, initializer, tokenBMint, TOKEN_PROGRAM_ID
             ));
         }
 
-        // 1. Create Initializer's LP Token ATA
-        console.log("Adding: Create Initializer LP ATA");
-        createATAsTx.add(createAssociatedTokenAccountInstruction(
-            initializer, initializerLpTokenAccount, initializer, lpMintAccount.publicKey, TOKEN_PROGRAM_ID
-        ));
-
-        // 2. Create Pool's Token A ATA
-        console.log("Adding: Create Pool Token A ATA");
-        createATAsTx.add(createAssociatedTokenAccountInstruction(
-            initializer, poolTokenAAccount, poolStatePda, tokenAMint, TOKEN_PROGRAM_ID
-        ));
-
-        // 3. Create Pool's Token B ATA
-        console.log("Adding: Create Pool Token B ATA");
-        createATAsTx.add(createAssociatedTokenAccountInstruction(
-            initializer, poolTokenBAccount, poolStatePda, tokenBMint, TOKEN_PROGRAM_ID
-        ));
-
-        // --- Sign and Send Transaction 1b ---
-        console.log("Sending Transaction 1b to wallet...");
-        const latestBlockhash1b = await this.connection.getLatestBlockhash();
-        createATAsTx.feePayer = this.wallet.publicKey;
-        createATAsTx.recentBlockhash = latestBlockhash1b.blockhash;
-        const messageV0_1b = createATAsTx.compileMessage();
-        const v0CreateATAsTx = new VersionedTransaction(messageV0_1b);
-        // No additional signers needed for ATAs if initializer is payer
-        const createATAsTxSignature = await this.wallet.sendTransaction(v0CreateATAsTx, this.connection, {
-            preflightCommitment: 'confirmed',
-        });
-        console.log("--- Transaction 1b Confirmed ---");
-        console.log("Signature:", createATAsTxSignature);
-        await this.connection.confirmTransaction({
-            ...latestBlockhash1b,
-            signature: createATAsTxSignature,
-        }, 'confirmed');
-        console.log("Associated Token Accounts created successfully.");
-
-        // ====================================================================
-        // --- TRANSACTION 2: Initialize The Pool ---
-        // ====================================================================
-        console.log("--- Building Transaction 2: Initialize Pool ---");
-        
         const instructionData = new InitializePoolInstructionData({
             initial_amount_a: new BN(initialAmountA),
             initial_amount_b: new BN(initialAmountB),
+            curve_type: curveType,
+            amp: new BN(amp),
+            fee_bps: new BN(feeBps),
         });
         const data = serialize(LIQUIDITY_POOL_SCHEMA, instructionData);
 
         const initializePoolInstructionKeys = [
-            { pubkey: initializer, isSigner: true, isWritable: false }, // 0. Initializer
+            { pubkey: initializer, isSigner: true, isWritable: true }, // 0. Initializer
             { pubkey: poolStatePda, isSigner: false, isWritable: true }, // 1. Pool State Account (PDA)
             { pubkey: tokenAMint, isSigner: false, isWritable: false }, // 2. Token A Mint
             { pubkey: tokenBMint, isSigner: false, isWritable: false }, // 3. Token B Mint
-            { pubkey: poolTokenAAccount, isSigner: false, isWritable: true }, // 4. Pool Token A Account
-            { pubkey: poolTokenBAccount, isSigner: false, isWritable: true }, // 5. Pool Token B Account
-            { pubkey: lpMintAccount.publicKey, isSigner: false, isWritable: true }, // 6. LP Mint Account
+            { pubkey: poolTokenAAccount, isSigner: false, isWritable: true }, // 4. Pool Token A Vault (PDA)
+            { pubkey: poolTokenBAccount, isSigner: false, isWritable: true }, // 5. Pool Token B Vault (PDA)
+            { pubkey: lpMintPda, isSigner: false, isWritable: true }, // 6. LP Mint (PDA)
             { pubkey: initializerTokenAAccount, isSigner: false, isWritable: true }, // 7. Initializer Token A Account
             { pubkey: initializerTokenBAccount, isSigner: false, isWritable: true }, // 8. Initializer Token B Account
-            { pubkey: initializerLpTokenAccount, isSigner: false, isWritable: true }, // 9. Initializer LP Token Account
+            { pubkey: initializerLpTokenAccount.publicKey, isSigner: true, isWritable: true }, // 9. Initializer LP Token Account (new)
             { pubkey: TOKEN_PROGRAM_ID, isSigner: false, isWritable: false }, // 10. SPL Token Program
             { pubkey: SYSVAR_RENT_PUBKEY, isSigner: false, isWritable: false }, // 11. Rent Sysvar
             { pubkey: SystemProgram.programId, isSigner: false, isWritable: false }, // 12. System Program
+            { pubkey: registryHeaderPda, isSigner: false, isWritable: true }, // 13. Registry Header (PDA)
+            { pubkey: registryPagePda, isSigner: false, isWritable: true }, // 14. Registry Page (PDA)
+            { pubkey: feeConfigPda, isSigner: false, isWritable: false }, // 15. Fee Config (PDA)
         ];
 
-        const initializeTx = new Transaction().add(
+        initializeTx.add(
             new TransactionInstruction({
                 keys: initializePoolInstructionKeys,
                 programId: this.programId,
@@ -466,23 +517,20 @@ export class LiquidityPoolClient {
             })
         );
 
-        // --- Sign and Send Transaction 2 ---
-        console.log("Sending Transaction 2 to wallet...");
+        console.log("Sending Initialize Pool transaction to wallet...");
         
         const initLatestBlockhash = await this.connection.getLatestBlockhash();
         initializeTx.feePayer = this.wallet.publicKey;
         initializeTx.recentBlockhash = initLatestBlockhash.blockhash;
         
-        // This is a simple 1-instruction tx, a v0 tx is good practice
         const initMessageV0 = initializeTx.compileMessage();
         const v0InitTx = new VersionedTransaction(initMessageV0);
-
-        // No additional signers needed for this one
+        v0InitTx.sign([initializerLpTokenAccount]); // The new LP token account signs its creation
         const initTxSignature = await this.wallet.sendTransaction(v0InitTx, this.connection, {
             preflightCommitment: 'confirmed',
         });
         
-        console.log("--- Transaction 2 Confirmed ---");
+        console.log("--- Initialize Pool Transaction Confirmed ---");
         console.log("Signature:", initTxSignature);
         await this.connection.confirmTransaction({
             ...initLatestBlockhash,
@@ -490,11 +538,96 @@ export class LiquidityPoolClient {
         }, 'confirmed');
         
         console.log("--- POOL INITIALIZED SUCCESSFULLY ---");
-        
-        // Return the signature of the *second* transaction as the result
         return initTxSignature;
     }
 
+    // Upgrades a pool written with the original PoolState layout, at the
+    // address derived from its mints alone; the payer covers the extra rent of
+    // the larger account and of the new vaults. Original pools kept their
+    // funds in vaults created from keypairs, which the pool state does not
+    // record, so their addresses must be passed in. Their balances move into
+    // new vaults at the pool's vault PDAs.
+    async migratePool(
+        payer: PublicKey,
+        tokenAMint: PublicKey,
+        tokenBMint: PublicKey,
+        oldPoolTokenAAccount: PublicKey,
+        oldPoolTokenBAccount: PublicKey
+    ): Promise<string> {
+        const [poolStatePda] = await this.getLegacyPoolStatePda(tokenAMint, tokenBMint);
+        const poolTokenAAccount = await this.getVaultPda(poolStatePda, tokenAMint);
+        const poolTokenBAccount = await this.getVaultPda(poolStatePda, tokenBMint);
+        const data = serialize(LIQUIDITY_POOL_SCHEMA, new MigratePoolInstructionData());
+
+        const transaction = new Transaction().add(
+            new TransactionInstruction({
+                keys: [
+                    { pubkey: payer, isSigner: true, isWritable: true }, // 0. Payer
+                    { pubkey: poolStatePda, isSigner: false, isWritable: true }, // 1. Pool State Account (PDA)
+                    { pubkey: SystemProgram.programId, isSigner: false, isWritable: false }, // 2. System Program
+                    { pubkey: oldPoolTokenAAccount, isSigner: false, isWritable: true }, // 3. Old Pool Token A Vault
+                    { pubkey: oldPoolTokenBAccount, isSigner: false, isWritable: true }, // 4. Old Pool Token B Vault
+                    { pubkey: tokenAMint, isSigner: false, isWritable: false }, // 5. Token A Mint
+                    { pubkey: tokenBMint, isSigner: false, isWritable: false }, // 6. Token B Mint
+                    { pubkey: poolTokenAAccount, isSigner: false, isWritable: true }, // 7. Pool Token A Vault (PDA)
+                    { pubkey: poolTokenBAccount, isSigner: false, isWritable: true }, // 8. Pool Token B Vault (PDA)
+                    { pubkey: TOKEN_PROGRAM_ID, isSigner: false, isWritable: false }, // 9. SPL Token Program
+                ],
+                programId: this.programId,
+                data: Buffer.from(data),
+            })
+        );
+        return this.wallet.sendTransaction(transaction, this.connection);
+    }
+
+    // Closes a pool once all of its LP tokens have been redeemed. What is left
+    // in the vaults goes to the creator's token accounts, and the rent of the
+    // vaults and the pool state to the creator. The pool's registry entry is
+    // marked closed; a pool with a farm cannot be closed until the farm is.
+    async closePool(
+        creator: PublicKey,
+        tokenAMint: PublicKey,
+        tokenBMint: PublicKey,
+        feeBps: number = SWAP_FEE_BPS
+    ): Promise<string> {
+        const [poolStatePda] = await this.getPoolStatePda(tokenAMint, tokenBMint, feeBps);
+        const poolState = await this.fetchPoolState(tokenAMint, tokenBMint, feeBps);
+
+        if (!poolState) {
+            throw new Error("Pool not initialized.");
+        }
+
+        const [farmPda] = await PublicKey.findProgramAddress(
+            [Buffer.from("farm"), poolStatePda.toBuffer()],
+            this.programId
+        );
+        const registryPagePda = await this.findRegistryPage(poolStatePda);
+
+        const creatorTokenAAccount = await getAssociatedTokenAddress(tokenAMint, creator);
+        const creatorTokenBAccount = await getAssociatedTokenAddress(tokenBMint, creator);
+        const data = serialize(LIQUIDITY_POOL_SCHEMA, new ClosePoolInstructionData());
+
+        const transaction = new Transaction().add(
+            new TransactionInstruction({
+                keys: [
+                    { pubkey: creator, isSigner: true, isWritable: true }, // 0. Creator
+                    { pubkey: poolStatePda, isSigner: false, isWritable: true }, // 1. Pool State Account (PDA)
+                    { pubkey: poolState.vault_a, isSigner: false, isWritable: true }, // 2. Pool Token A Vault
+                    { pubkey: poolState.vault_b, isSigner: false, isWritable: true }, // 3. Pool Token B Vault
+                    { pubkey: poolState.lp_mint, isSigner: false, isWritable: false }, // 4. LP Mint
+                    { pubkey: creatorTokenAAccount, isSigner: false, isWritable: true }, // 5. Creator Token A Account
+                    { pubkey: creatorTokenBAccount, isSigner: false, isWritable: true }, // 6. Creator Token B Account
+                    { pubkey: TOKEN_PROGRAM_ID, isSigner: false, isWritable: false }, // 7. SPL Token Program
+                    { pubkey: farmPda, isSigner: false, isWritable: false }, // 8. Farm (PDA), which must not exist
+                    { pubkey: registryPagePda, isSigner: false, isWritable: true }, // 9. Registry Page (PDA)
+                ],
+                programId: this.programId,
+                data: Buffer.from(data),
+            })
+        );
+        return this.wallet.sendTransaction(transaction, this.connection);
+    }
+
     async addLiquidity(
         provider: PublicKey,
         tokenAMint: PublicKey,
@@ -509,8 +642,8 @@ export class LiquidityPoolClient {
             throw new Error("Pool not initialized.");
         }
 
-        const poolTokenAAccount = await getAssociatedTokenAddress(tokenAMint, poolStatePda, true);
-        const poolTokenBAccount = await getAssociatedTokenAddress(tokenBMint, poolStatePda, true);
+        const poolTokenAAccount = poolState.vault_a;
+        const poolTokenBAccount = poolState.vault_b;
         const providerTokenAAccount = await getAssociatedTokenAddress(tokenAMint, provider);
         const providerTokenBAccount = await getAssociatedTokenAddress(tokenBMint, provider);
         const providerLpTokenAccount = await getAssociatedTokenAddress(poolState.lp_mint, provider);
@@ -554,8 +687,8 @@ export class LiquidityPoolClient {
             throw new Error("Pool not initialized.");
         }
 
-        const poolTokenAAccount = await getAssociatedTokenAddress(tokenAMint, poolStatePda, true);
-        const poolTokenBAccount = await getAssociatedTokenAddress(tokenBMint, poolStatePda, true);
+        const poolTokenAAccount = poolState.vault_a;
+        const poolTokenBAccount = poolState.vault_b;
         const providerTokenAAccount = await getAssociatedTokenAddress(tokenAMint, provider);
         const providerTokenBAccount = await getAssociatedTokenAddress(tokenBMint, provider);
         const providerLpTokenAccount = await getAssociatedTokenAddress(poolState.lp_mint, provider);
@@ -599,8 +732,8 @@ export class LiquidityPoolClient {
             throw new Error("Pool not initialized.");
         }
 
-        const poolTokenAAccount = await getAssociatedTokenAddress(tokenAMint, poolStatePda, true);
-        const poolTokenBAccount = await getAssociatedTokenAddress(tokenBMint, poolStatePda, true);
+        const poolTokenAAccount = poolState.vault_a;
+        const poolTokenBAccount = poolState.vault_b;
         const swapperTokenAAccount = await getAssociatedTokenAddress(tokenAMint, swapper);
         const swapperTokenBAccount = await getAssociatedTokenAddress(tokenBMint, swapper);
 
@@ -641,8 +774,8 @@ export class LiquidityPoolClient {
             throw new Error("Pool not initialized.");
         }
 
-        const poolTokenAAccount = await getAssociatedTokenAddress(tokenAMint, poolStatePda, true);
-        const poolTokenBAccount = await getAssociatedTokenAddress(tokenBMint, poolStatePda, true);
+        const poolTokenAAccount = poolState.vault_a;
+        const poolTokenBAccount = poolState.vault_b;
         const swapperTokenAAccount = await getAssociatedTokenAddress(tokenAMint, swapper);
         const swapperTokenBAccount = await getAssociatedTokenAddress(tokenBMint, swapper);
 